@@ -1,16 +1,234 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use num_complex::Complex;
 use rustfft::{Fft, FftPlanner};
 
-use super::Config;
+use super::{Config, PresetRequest, INPUT_ACTIVE_TIMEOUT};
 use crate::util::RingBuffer;
 
 pub const AUDIO_SAMPLES: usize = 512;
 pub const FFT_ATTACK: f32 = 0.5;
 pub const FFT_DECAY: f32 = 0.5;
 
+/// Lowest frequency shown in log scaled spectrum textures.
+pub const FFT_LOG_MIN_FREQ: f32 = 20.0;
+
+/// Seconds a peak is held before it starts to fall.
+pub const PEAK_HOLD: f32 = 0.5;
+
+/// Level per second a peak falls by once the hold time is over.
+pub const PEAK_DECAY: f32 = 0.5;
+
+/// Levels at or below this count as silence, which resets the peaks.
+pub const PEAK_SILENCE: f32 = 1e-4;
+
+/// Default delay of the analysis behind the input, in milliseconds.
+pub const AUDIO_LATENCY: f32 = 0.0;
+
+/// Default gain of both input channels before the analysis.
+pub const AUDIO_PRE_GAIN: f32 = 1.0;
+
+/// Default share of the last frame kept by the smoothed centroid and spread.
+pub const CENTROID_SMOOTHING: f32 = 0.8;
+
+/// Lowest frequency the pitch detection looks for, in Hz.
+///
+/// Low notes need a long window, the sample buffer must hold at least two
+/// periods of a note for it to be detected.
+pub const PITCH_MIN_FREQ: f32 = 40.0;
+
+/// Highest frequency the pitch detection looks for, in Hz.
+pub const PITCH_MAX_FREQ: f32 = 2000.0;
+
+/// Default number of seconds the onset density is averaged over.
+pub const ONSET_WINDOW: f32 = 2.0;
+
+/// Share of the spectrum which has to be new within a frame for an onset.
+const ONSET_THRESHOLD: f32 = 0.3;
+
+/// Onsets closer together than this count as one.
+const ONSET_MIN_GAP: Duration = Duration::from_millis(50);
+
+/// Dips of the YIN difference function below this count as a period.
+const YIN_THRESHOLD: f32 = 0.15;
+
+/// Seconds it takes a change of the input gain to mostly take effect.
+///
+/// Gain changes and muting fade instead of jumping, so none of the audio
+/// uniforms snap to a new value.
+const GAIN_FADE: f32 = 0.05;
+
+/// Time it takes to move the envelope parameters to another profile.
+pub const PROFILE_FADE: Duration = Duration::from_millis(250);
+
+/// Envelope parameters of the analysis, which can be switched while playing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioProfile {
+    pub attack: f32,
+    pub decay: f32,
+    pub peak_hold: f32,
+    pub peak_decay: f32,
+}
+
+impl Default for AudioProfile {
+    fn default() -> Self {
+        Self {
+            attack: FFT_ATTACK,
+            decay: FFT_DECAY,
+            peak_hold: PEAK_HOLD,
+            peak_decay: PEAK_DECAY,
+        }
+    }
+}
+
+impl AudioProfile {
+    /// Blends every parameter from `self` at `t = 0` to `other` at `t = 1`.
+    pub fn mix(&self, other: &Self, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        // exact at both ends, unlike a + (b - a) * t
+        let mix = |a: f32, b: f32| a * (1.0 - t) + b * t;
+        Self {
+            attack: mix(self.attack, other.attack),
+            decay: mix(self.decay, other.decay),
+            peak_hold: mix(self.peak_hold, other.peak_hold),
+            peak_decay: mix(self.peak_decay, other.peak_decay),
+        }
+    }
+}
+
+/// Latches the maximum level of a signal and lets it fall slowly afterwards.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PeakHold {
+    pub value: f32,
+    age: f32,
+}
+
+impl PeakHold {
+    /// Feeds the current level, `dt` seconds after the last update.
+    ///
+    /// A new maximum is held for `hold` seconds, then the peak falls by
+    /// `decay` per second, but never below the current level.
+    pub fn update(&mut self, level: f32, dt: f32, hold: f32, decay: f32) {
+        if level <= PEAK_SILENCE {
+            *self = Self::default();
+            return;
+        }
+
+        if level >= self.value {
+            self.value = level;
+            self.age = 0.0;
+            return;
+        }
+
+        let falling = (self.age + dt - hold.max(self.age)).max(0.0);
+        self.value = (self.value - decay * falling).max(level);
+        self.age += dt;
+    }
+}
+
+/// Finds onsets, where the spectrum suddenly gains energy, and keeps the recent ones.
+#[derive(Debug, Clone, Default)]
+pub struct OnsetDetector {
+    /// Power spectrum of the last frame.
+    previous: Vec<f32>,
+    /// Running average of the share of new energy per frame.
+    average_flux: f32,
+    onsets: VecDeque<Instant>,
+}
+
+impl OnsetDetector {
+    /// Feeds the power spectra of a frame, returns whether it starts an onset.
+    ///
+    /// Both spectra are summed, so mono input can pass the same one twice.
+    pub fn update(&mut self, left: &[f32], right: &[f32], now: Instant) -> bool {
+        self.previous.resize(left.len(), 0.0);
+
+        let (mut flux, mut total) = (0.0, 0.0);
+        for (k, (l, r)) in left.iter().zip(right).enumerate() {
+            // the neighbours absorb leakage which moves around with the phase of steady tones
+            let lo = k.saturating_sub(1);
+            let hi = (k + 2).min(self.previous.len());
+            let prev = self.previous[lo..hi].iter().fold(0.0f32, |a, &b| a.max(b));
+            flux += (l + r - prev).max(0.0);
+            total += l + r;
+        }
+
+        for ((prev, l), r) in self.previous.iter_mut().zip(left).zip(right) {
+            *prev = l + r;
+        }
+
+        // spectral flux relative to the power of the frame, independent of the level
+        let flux = if total > 0.0 { flux / total } else { 0.0 };
+        let threshold = ONSET_THRESHOLD.max(2.0 * self.average_flux);
+        self.average_flux = 0.9 * self.average_flux + 0.1 * flux;
+
+        let recent = self.onsets.back().map_or(false, |&last| {
+            now.saturating_duration_since(last) < ONSET_MIN_GAP
+        });
+        let onset = flux > threshold && !recent;
+        if onset {
+            self.onsets.push_back(now);
+        }
+        onset
+    }
+
+    /// Forgets the last frame, so the first sound after silence is an onset.
+    pub fn silence(&mut self) {
+        self.previous.fill(0.0);
+        self.average_flux = 0.0;
+    }
+
+    /// Onsets per second within the last `window` seconds.
+    pub fn density(&mut self, now: Instant, window: f32) -> f32 {
+        let window = window.max(1e-3);
+        while let Some(&first) = self.onsets.front() {
+            match now.saturating_duration_since(first).as_secs_f32() > window {
+                true => self.onsets.pop_front(),
+                false => break,
+            };
+        }
+        self.onsets.len() as f32 / window
+    }
+}
+
+/// Returns the names of all input devices of a host, in the order cpal lists them.
+pub fn input_device_names(host: &cpal::Host) -> Result<Vec<String>, String> {
+    let devices = host.input_devices().map_err(|e| e.to_string())?;
+    devices
+        .map(|dev| dev.name().map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Finds the input device which `audio_device` in the config selects.
+///
+/// Names only need to contain the configured string, if several do, the
+/// last one is used.
+pub fn find_input_device(names: &[String], audio_device: &str) -> Option<usize> {
+    names.iter().rposition(|name| name.contains(audio_device))
+}
+
+/// How the bins of the raw spectrum texture are spaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FftScale {
+    Linear,
+    Log,
+}
+
+/// How the bins of the raw spectrum are combined into the texels of its texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FftResample {
+    /// Each texel holds the loudest bin within its frequency range.
+    Max,
+    /// Each texel interpolates linearly between the bins around its center.
+    Linear,
+}
+
+#[derive(Debug)]
 pub enum Channels {
     None,
     Mono,
@@ -22,6 +240,13 @@ pub struct Audio {
     pub r_signal: Vec<f32>,
     pub l_raw_spectrum: Vec<f32>,
     pub r_raw_spectrum: Vec<f32>,
+    pub l_binned_spectrum: Vec<f32>,
+    pub r_binned_spectrum: Vec<f32>,
+    pub fft_scale: FftScale,
+    pub fft_resample: FftResample,
+    pub stereo_analysis: bool,
+    pub stereo_correlation: f32,
+    pub stereo_width: f32,
     pub l_spectrum: Vec<f32>,
     pub r_spectrum: Vec<f32>,
     pub l_spectrum_integrated: Vec<f32>,
@@ -45,6 +270,40 @@ pub struct Audio {
     pub bass_smooth_integrated: [f32; 3],
     pub mid_smooth_integrated: [f32; 3],
     pub high_smooth_integrated: [f32; 3],
+    pub volume_peak: [f32; 3],
+    pub bass_peak: [f32; 3],
+    pub mid_peak: [f32; 3],
+    pub high_peak: [f32; 3],
+    pub peak_hold: f32,
+    pub peak_decay: f32,
+    pub pitch_detection: bool,
+    /// Fundamental frequency of the input in Hz, kept while nothing is detected.
+    pub pitch: f32,
+    /// Nearest MIDI note of the pitch, 69 is the A above middle C.
+    pub note: f32,
+    /// How periodic the input is, from 0 for noise or silence up to 1.
+    pub pitch_confidence: f32,
+    /// Spectral centroid of both, the left and the right channel.
+    pub centroid: [f32; 3],
+    /// Spread of the spectrum around its centroid.
+    pub spread: [f32; 3],
+    pub centroid_smooth: [f32; 3],
+    pub spread_smooth: [f32; 3],
+    /// Share of the last frame the smoothed centroid and spread keep.
+    pub centroid_smoothing: f32,
+    /// Gives centroid and spread relative to the Nyquist frequency instead of in Hz.
+    pub centroid_normalized: bool,
+    /// Onsets per second, averaged over the last `density_window` seconds.
+    pub density: f32,
+    pub density_window: f32,
+    onsets: OnsetDetector,
+    peaks: [[PeakHold; 3]; 4],
+    last_peak_update: Instant,
+    /// When the input was last louder than silence.
+    last_signal: Option<Instant>,
+    latency_ms: f32,
+    /// Gain of the left and right input channels, 0 while muted.
+    gain: Arc<Mutex<[f32; 2]>>,
     l_fft: Vec<Complex<f32>>,
     r_fft: Vec<Complex<f32>>,
     l_samples: Arc<Mutex<RingBuffer<f32>>>,
@@ -54,11 +313,41 @@ pub struct Audio {
     sample_freq: usize,
     pub attack: f32,
     pub decay: f32,
+    /// Named profiles of the pipeline and the index of the active one.
+    profiles: Vec<(String, AudioProfile)>,
+    active_profile: Option<usize>,
+    /// Parameters before and after a profile switch, and when it started.
+    profile_fade: Option<(AudioProfile, AudioProfile, Instant)>,
     fft: Arc<dyn Fft<f32>>,
 }
 
+impl std::fmt::Debug for Audio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(Audio))
+            .field("size", &self.size)
+            .field("channels", &self.channels)
+            .field("sample_freq", &self.sample_freq)
+            .field("fft_scale", &self.fft_scale)
+            .field("fft_resample", &self.fft_resample)
+            .finish()
+    }
+}
+
 impl Audio {
     pub fn new(window_size: usize, config: &Config) -> Self {
+        let mut this = Self::without_input(window_size);
+
+        if let Err(err) = this.connect(config) {
+            log::error!("Error connecting to audio input device: {}", err);
+        }
+
+        this
+    }
+
+    /// Creates an analyzer which is not connected to any input device.
+    ///
+    /// All bands and spectra read as silence until `connect` is called.
+    pub fn without_input(window_size: usize) -> Self {
         let size = window_size;
         let spec_size = size / 2;
         let bands = 100;
@@ -66,7 +355,7 @@ impl Audio {
         let mut planner = FftPlanner::<f32>::new();
         let fft = planner.plan_fft_forward(size);
 
-        let mut this = Self {
+        Self {
             size,
             l_signal: vec![0.0; size],
             r_signal: vec![0.0; size],
@@ -86,8 +375,39 @@ impl Audio {
             bass_smooth_integrated: [0.0; 3],
             mid_smooth_integrated: [0.0; 3],
             high_smooth_integrated: [0.0; 3],
+            volume_peak: [0.0; 3],
+            bass_peak: [0.0; 3],
+            mid_peak: [0.0; 3],
+            high_peak: [0.0; 3],
+            peak_hold: PEAK_HOLD,
+            peak_decay: PEAK_DECAY,
+            pitch_detection: false,
+            pitch: 0.0,
+            note: 0.0,
+            pitch_confidence: 0.0,
+            centroid: [0.0; 3],
+            spread: [0.0; 3],
+            centroid_smooth: [0.0; 3],
+            spread_smooth: [0.0; 3],
+            centroid_smoothing: CENTROID_SMOOTHING,
+            centroid_normalized: false,
+            density: 0.0,
+            density_window: ONSET_WINDOW,
+            onsets: OnsetDetector::default(),
+            peaks: [[PeakHold::default(); 3]; 4],
+            last_peak_update: Instant::now(),
+            last_signal: None,
+            latency_ms: AUDIO_LATENCY,
+            gain: Arc::new(Mutex::new([AUDIO_PRE_GAIN; 2])),
             l_raw_spectrum: vec![0.0; spec_size],
             r_raw_spectrum: vec![0.0; spec_size],
+            l_binned_spectrum: vec![0.0; spec_size],
+            r_binned_spectrum: vec![0.0; spec_size],
+            fft_scale: FftScale::Linear,
+            fft_resample: FftResample::Max,
+            stereo_analysis: true,
+            stereo_correlation: 1.0,
+            stereo_width: 0.0,
             l_spectrum: vec![0.0; bands],
             r_spectrum: vec![0.0; bands],
             l_spectrum_integrated: vec![0.0; bands],
@@ -103,14 +423,11 @@ impl Audio {
             fft,
             attack: 0.5,
             decay: 0.5,
+            profiles: Vec::new(),
+            active_profile: None,
+            profile_fade: None,
             sample_freq: 0,
-        };
-
-        if let Err(err) = this.connect(config) {
-            log::error!("Error connecting to audio input device: {}", err);
         }
-
-        this
     }
 
     pub fn resize(&mut self, new_size: usize) {
@@ -126,8 +443,119 @@ impl Audio {
         self.r_fft = vec![Complex::new(0.0, 0.0); new_size];
         self.l_raw_spectrum = vec![0.0; spec_size];
         self.r_raw_spectrum = vec![0.0; spec_size];
-        *self.l_samples.lock().unwrap() = RingBuffer::new(new_size);
-        *self.r_samples.lock().unwrap() = RingBuffer::new(new_size);
+        self.reset_sample_buffers();
+    }
+
+    /// Delays the analysis behind the input by `latency_ms` milliseconds.
+    ///
+    /// The analysis cannot run ahead of the input, so negative values have
+    /// the same effect as 0.
+    pub fn set_latency(&mut self, latency_ms: f32) {
+        if self.latency_ms == latency_ms {
+            return;
+        }
+
+        if latency_ms < 0.0 {
+            log::warn!(
+                "Audio analysis cannot run ahead of the input, latency_ms {} has no effect",
+                latency_ms
+            );
+        }
+
+        self.latency_ms = latency_ms;
+        self.reset_sample_buffers();
+    }
+
+    /// Scales the left and right input before the analysis, or silences it.
+    ///
+    /// The samples fade towards the new gain over a few milliseconds.
+    pub fn set_pre_gain(&mut self, gain: [f32; 2], mute: bool) {
+        *self.gain.lock().unwrap() = match mute {
+            true => [0.0; 2],
+            false => gain,
+        };
+    }
+
+    /// Returns the envelope parameters which are currently in use.
+    pub fn profile(&self) -> AudioProfile {
+        AudioProfile {
+            attack: self.attack,
+            decay: self.decay,
+            peak_hold: self.peak_hold,
+            peak_decay: self.peak_decay,
+        }
+    }
+
+    fn apply_profile(&mut self, profile: AudioProfile) {
+        self.attack = profile.attack;
+        self.decay = profile.decay;
+        self.peak_hold = profile.peak_hold;
+        self.peak_decay = profile.peak_decay;
+    }
+
+    /// Replaces the named profiles of the previous pipeline.
+    ///
+    /// The parameters themselves are set directly, so a running switch is
+    /// cancelled.
+    pub fn set_profiles(&mut self, profiles: Vec<(String, AudioProfile)>, active: Option<usize>) {
+        self.profiles = profiles;
+        self.active_profile = active;
+        self.profile_fade = None;
+    }
+
+    /// Name of the active profile, if one was selected.
+    pub fn active_profile(&self) -> Option<&str> {
+        let index = self.active_profile?;
+        self.profiles.get(index).map(|(name, _)| name.as_str())
+    }
+
+    /// Starts moving the envelope parameters to another profile.
+    ///
+    /// The parameters are blended over `PROFILE_FADE`, so the smoothed
+    /// uniforms do not jump. Returns `false` if no profile matches.
+    pub fn select_profile(&mut self, request: &PresetRequest) -> bool {
+        let names: Vec<_> = self.profiles.iter().map(|(name, _)| name.clone()).collect();
+        let index = match request.resolve(&names, self.active_profile.unwrap_or(0)) {
+            Some(index) => index,
+            None => {
+                log::warn!("No audio profile matches request {:?}", request);
+                return false;
+            }
+        };
+
+        log::info!("Switching to audio profile {}", names[index]);
+        self.active_profile = Some(index);
+        self.profile_fade = Some((self.profile(), self.profiles[index].1, Instant::now()));
+        true
+    }
+
+    /// Advances a running profile switch.
+    fn update_profile(&mut self) {
+        let (from, to, start) = match self.profile_fade {
+            Some(fade) => fade,
+            None => return,
+        };
+
+        let t = start.elapsed().as_secs_f32() / PROFILE_FADE.as_secs_f32();
+        self.apply_profile(from.mix(&to, t));
+        if t >= 1.0 {
+            self.profile_fade = None;
+        }
+    }
+
+    /// Recreates the sample buffers, which hold the delay on top of the window.
+    fn reset_sample_buffers(&mut self) {
+        let len = self.size + latency_samples(self.latency_ms, self.sample_freq);
+        *self.l_samples.lock().unwrap() = RingBuffer::new(len);
+        *self.r_samples.lock().unwrap() = RingBuffer::new(len);
+    }
+
+    /// Sets the size, frequency scale and resampling of the raw spectrum texture.
+    pub fn set_fft_bins(&mut self, bins: usize, scale: FftScale, resample: FftResample) {
+        self.fft_scale = scale;
+        self.fft_resample = resample;
+        self.l_binned_spectrum = vec![0.0; bins];
+        self.r_binned_spectrum = vec![0.0; bins];
     }
 
     pub fn connect(&mut self, config: &Config) -> Result<(), String> {
@@ -138,14 +566,9 @@ impl Audio {
                 .default_input_device()
                 .ok_or("No input device is available".to_string()),
             Some(s) => {
-                let mut ret = None;
-                for dev in host.input_devices().unwrap() {
-                    let dev_name = dev.name().map_err(|e| e.to_string())?;
-                    if dev_name.contains(s) {
-                        ret = Some(dev);
-                    }
-                }
-                ret.ok_or(format!("Failed to find audio device {}", s))
+                let index = find_input_device(&input_device_names(&host)?, s);
+                let device = index.and_then(|index| host.input_devices().ok()?.nth(index));
+                device.ok_or(format!("Failed to find audio device {}", s))
             }
         }?;
 
@@ -185,20 +608,32 @@ impl Audio {
 
         let l_samples_p = self.l_samples.clone();
         let r_samples_p = self.r_samples.clone();
+        let gain_p = self.gain.clone();
+
+        let mut gain = *self.gain.lock().unwrap();
+        let fade = gain_fade_factor(config.sample_rate.0 as _);
 
         let input_callback = move |data: &[f32], _: &cpal::InputCallbackInfo| {
+            let target = *gain_p.lock().unwrap();
+
             {
                 let mut l_samples_lock = l_samples_p.lock().unwrap();
+                let mut g = gain[0];
                 for x in data.iter().step_by(channel_count) {
-                    l_samples_lock.push(x);
+                    g += (target[0] - g) * fade;
+                    l_samples_lock.push(&(x * g));
                 }
+                gain[0] = g;
             }
 
             if channel_count > 1 {
                 let mut r_samples_lock = r_samples_p.lock().unwrap();
+                let mut g = gain[1];
                 for x in data.iter().skip(1).step_by(channel_count) {
-                    r_samples_lock.push(x);
+                    g += (target[1] - g) * fade;
+                    r_samples_lock.push(&(x * g));
                 }
+                gain[1] = g;
             }
         };
 
@@ -219,10 +654,32 @@ impl Audio {
         let sample_freq = config.sample_rate.0;
         self.sample_freq = sample_freq as _;
 
+        // the delay depends on the sample rate of the device
+        if latency_samples(self.latency_ms, self.sample_freq) > 0 {
+            self.reset_sample_buffers();
+        }
+
         self.stream = Some(stream);
         Ok(())
     }
 
+    /// Stops the input stream, if there is one.
+    /// Whether an input stream is running and was not silent recently.
+    pub fn is_active(&self) -> bool {
+        let recent = self
+            .last_signal
+            .map_or(false, |t| t.elapsed() <= INPUT_ACTIVE_TIMEOUT);
+        self.stream.is_some() && recent
+    }
+
+    pub fn disconnect(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            if let Err(err) = stream.pause() {
+                log::warn!("Failed to pause audio stream: {}", err);
+            }
+        }
+    }
+
     pub fn update_samples(&mut self) {
         if self.stream.is_none() {
             return;
@@ -233,16 +690,16 @@ impl Audio {
         l_samples.copy_to_slice(&mut self.l_signal);
 
         // calculate volume with RMS
+        // the buffer also holds the delay, so only the window is counted
         self.volume[1] =
-            (self.l_signal.iter().map(|&x| x.powi(2)).sum::<f32>() / l_samples.size as f32).sqrt();
+            (self.l_signal.iter().map(|&x| x.powi(2)).sum::<f32>() / self.size as f32).sqrt();
 
         if let Channels::Stereo = self.channels {
             let r_samples_p = self.r_samples.clone();
             let r_samples = r_samples_p.lock().unwrap();
             r_samples.copy_to_slice(&mut self.r_signal);
-            self.volume[2] = (self.r_signal.iter().map(|&x| x.powi(2)).sum::<f32>()
-                / l_samples.size as f32)
-                .sqrt();
+            self.volume[2] =
+                (self.r_signal.iter().map(|&x| x.powi(2)).sum::<f32>() / self.size as f32).sqrt();
             self.volume[0] = (self.volume[1] + self.volume[2]) / 2.0;
         } else {
             self.volume[0] = self.volume[1];
@@ -252,16 +709,54 @@ impl Audio {
             .iter_mut()
             .zip(self.volume.iter())
             .for_each(sum_left);
+
+        if self.volume[0] > PEAK_SILENCE {
+            self.last_signal = Some(Instant::now());
+        }
+
+        // measure how different the two channels are
+        self.stereo_correlation = match self.channels {
+            Channels::Stereo if self.stereo_analysis => {
+                stereo_correlation(&self.l_signal, &self.r_signal)
+            }
+            _ => 1.0,
+        };
+        self.stereo_width = 0.5 * (1.0 - self.stereo_correlation);
+
+        if self.pitch_detection {
+            self.update_pitch();
+        }
+    }
+
+    /// Estimates the pitch of the sum of both channels.
+    fn update_pitch(&mut self) {
+        let signal: Vec<_> = match self.channels {
+            Channels::Stereo => self
+                .l_signal
+                .iter()
+                .zip(self.r_signal.iter())
+                .map(|(&l, &r)| 0.5 * (l + r))
+                .collect(),
+            _ => self.l_signal.clone(),
+        };
+
+        match detect_pitch(&signal, self.sample_freq as _) {
+            Some((pitch, confidence)) => {
+                self.pitch = pitch;
+                self.note = midi_note(pitch);
+                self.pitch_confidence = confidence;
+            }
+            None => self.pitch_confidence = 0.0,
+        }
     }
 
     pub fn update_fft(&mut self) {
+        self.update_profile();
+
         if self.stream.is_none() {
             return;
         }
 
-        let left_iter = self.l_signal.iter().map(|&x| Complex::new(x, 0.0));
-        let right_iter = self.r_signal.iter().map(|&x| Complex::new(x, 0.0));
-
         fn fill_iter<T>(slice: &mut [T], mut iter: impl ExactSizeIterator<Item = T>) {
             debug_assert!(iter.len() >= slice.len());
 
@@ -275,24 +770,118 @@ impl Audio {
             }
         }
 
-        fill_iter(&mut self.l_fft, left_iter);
-        fill_iter(&mut self.r_fft, right_iter);
+        if self.stereo_analysis {
+            let left_iter = self.l_signal.iter().map(|&x| Complex::new(x, 0.0));
+            let right_iter = self.r_signal.iter().map(|&x| Complex::new(x, 0.0));
+
+            fill_iter(&mut self.l_fft, left_iter);
+            fill_iter(&mut self.r_fft, right_iter);
 
-        self.fft.process(&mut self.l_fft);
-        self.fft.process(&mut self.r_fft);
+            self.fft.process(&mut self.l_fft);
+            self.fft.process(&mut self.r_fft);
 
-        let left_spectrum = self.l_fft.iter().map(|z| z.norm_sqr());
-        let right_spectrum = self.r_fft.iter().map(|z| z.norm_sqr());
+            let left_spectrum = self.l_fft.iter().map(|z| z.norm_sqr());
+            let right_spectrum = self.r_fft.iter().map(|z| z.norm_sqr());
 
-        fill_iter(&mut self.l_raw_spectrum, left_spectrum);
-        fill_iter(&mut self.r_raw_spectrum, right_spectrum);
+            fill_iter(&mut self.l_raw_spectrum, left_spectrum);
+            fill_iter(&mut self.r_raw_spectrum, right_spectrum);
+        } else {
+            // only run a single FFT on the sum of both channels
+            let mix = matches!(self.channels, Channels::Stereo);
+            let mono_iter = self
+                .l_signal
+                .iter()
+                .zip(self.r_signal.iter())
+                .map(|(&l, &r)| Complex::new(if mix { 0.5 * (l + r) } else { l }, 0.0));
+
+            fill_iter(&mut self.l_fft, mono_iter);
+            self.fft.process(&mut self.l_fft);
+
+            let mono_spectrum = self.l_fft.iter().map(|z| z.norm_sqr());
+            fill_iter(&mut self.l_raw_spectrum, mono_spectrum);
+            self.r_raw_spectrum.copy_from_slice(&self.l_raw_spectrum);
+        }
 
         debug_assert!(self.l_raw_spectrum.iter().all(|x| x.is_finite()));
         debug_assert!(self.r_raw_spectrum.iter().all(|x| x.is_finite()));
 
+        self.update_centroid();
+        self.update_density();
+
+        let sample_freq = self.sample_freq as f32;
+        let (scale, resample) = (self.fft_scale, self.fft_resample);
+        let (l_raw, r_raw) = (&self.l_raw_spectrum, &self.r_raw_spectrum);
+        resample_spectrum(
+            l_raw,
+            &mut self.l_binned_spectrum,
+            scale,
+            resample,
+            sample_freq,
+        );
+        resample_spectrum(
+            r_raw,
+            &mut self.r_binned_spectrum,
+            scale,
+            resample,
+            sample_freq,
+        );
+
         self.update_nice_fft();
         self.update_smooth_fft();
         self.update_bass_mid_high();
+        self.update_peaks();
+    }
+
+    /// Describes the timbre of the raw spectra by their centroid and spread.
+    fn update_centroid(&mut self) {
+        let nyquist = self.sample_freq as f32 / 2.0;
+        let bin_hz = match self.centroid_normalized {
+            true => 1.0 / self.l_raw_spectrum.len().max(1) as f32,
+            false => nyquist / self.l_raw_spectrum.len().max(1) as f32,
+        };
+
+        let (l_centroid, l_spread) = spectral_centroid(&self.l_raw_spectrum, bin_hz);
+        let (r_centroid, r_spread) = spectral_centroid(&self.r_raw_spectrum, bin_hz);
+        self.centroid = [0.5 * (l_centroid + r_centroid), l_centroid, r_centroid];
+        self.spread = [0.5 * (l_spread + r_spread), l_spread, r_spread];
+
+        let keep = self.centroid_smoothing.clamp(0.0, 1.0);
+        for k in 0..3 {
+            let smooth = &mut self.centroid_smooth[k];
+            *smooth = keep * *smooth + (1.0 - keep) * self.centroid[k];
+            let smooth = &mut self.spread_smooth[k];
+            *smooth = keep * *smooth + (1.0 - keep) * self.spread[k];
+        }
+    }
+
+    /// Counts the recent onsets of the raw spectra.
+    fn update_density(&mut self) {
+        let now = Instant::now();
+        if self.volume[0] > PEAK_SILENCE {
+            self.onsets
+                .update(&self.l_raw_spectrum, &self.r_raw_spectrum, now);
+        } else {
+            self.onsets.silence();
+        }
+        self.density = self.onsets.density(now, self.density_window);
+    }
+
+    fn update_peaks(&mut self) {
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_peak_update).as_secs_f32();
+        self.last_peak_update = now;
+
+        let levels = [self.volume, self.bass, self.mid, self.high];
+        for (peaks, levels) in self.peaks.iter_mut().zip(levels.iter()) {
+            for (peak, &level) in peaks.iter_mut().zip(levels.iter()) {
+                peak.update(level, dt, self.peak_hold, self.peak_decay);
+            }
+        }
+
+        self.volume_peak = self.peaks[0].map(|peak| peak.value);
+        self.bass_peak = self.peaks[1].map(|peak| peak.value);
+        self.mid_peak = self.peaks[2].map(|peak| peak.value);
+        self.high_peak = self.peaks[3].map(|peak| peak.value);
     }
 
     fn update_nice_fft(&mut self) {
@@ -478,6 +1067,545 @@ impl Audio {
     }
 }
 
+/// Number of samples the analysis lags behind the input.
+fn latency_samples(latency_ms: f32, sample_freq: usize) -> usize {
+    (latency_ms.max(0.0) * 1e-3 * sample_freq as f32).round() as _
+}
+
+/// Returns how far the gain moves towards its target with every sample.
+fn gain_fade_factor(sample_freq: usize) -> f32 {
+    1.0 - (-1.0 / (GAIN_FADE * sample_freq.max(1) as f32)).exp()
+}
+
+/// Estimates the fundamental frequency of a signal with the YIN algorithm.
+///
+/// Returns the frequency in Hz and a confidence between 0 and 1, or `None`
+/// if the signal is silent or too short to hold two periods of any pitch
+/// within the search range.
+fn detect_pitch(signal: &[f32], sample_freq: f32) -> Option<(f32, f32)> {
+    let window = signal.len() / 2;
+    let max_tau = ((sample_freq / PITCH_MIN_FREQ) as usize).min(window);
+    let min_tau = ((sample_freq / PITCH_MAX_FREQ) as usize).max(2);
+    if sample_freq <= 0.0 || min_tau + 2 > max_tau {
+        return None;
+    }
+
+    let energy: f32 = signal[..window].iter().map(|x| x * x).sum();
+    if (energy / window as f32).sqrt() <= PEAK_SILENCE {
+        return None;
+    }
+
+    // cumulative mean normalized difference function
+    let mut cmnd = vec![1.0; max_tau + 1];
+    let mut running_sum = 0.0;
+    for tau in 1..=max_tau {
+        let diff: f32 = signal[..window]
+            .iter()
+            .zip(signal[tau..].iter())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum();
+
+        running_sum += diff;
+        if running_sum > 0.0 {
+            cmnd[tau] = diff * tau as f32 / running_sum;
+        }
+    }
+
+    // take the first dip below the threshold, or the deepest one
+    let search = min_tau..max_tau;
+    let tau = match search.clone().find(|&tau| cmnd[tau] < YIN_THRESHOLD) {
+        Some(mut tau) => {
+            while tau + 1 < max_tau && cmnd[tau + 1] < cmnd[tau] {
+                tau += 1;
+            }
+            tau
+        }
+        None => search.min_by(|&a, &b| cmnd[a].total_cmp(&cmnd[b]))?,
+    };
+
+    // refine the period between samples
+    let (a, b, c) = (cmnd[tau - 1], cmnd[tau], cmnd[tau + 1]);
+    let curvature = a - 2.0 * b + c;
+    let shift = match curvature > f32::EPSILON {
+        true => (0.5 * (a - c) / curvature).clamp(-1.0, 1.0),
+        false => 0.0,
+    };
+
+    let period = tau as f32 + shift;
+    Some((sample_freq / period, (1.0 - b).clamp(0.0, 1.0)))
+}
+
+/// Returns the nearest MIDI note of a frequency in Hz.
+fn midi_note(freq: f32) -> f32 {
+    (69.0 + 12.0 * (freq / 440.0).log2()).round()
+}
+
 fn sum_left((acc, val): (&mut f32, &f32)) {
     *acc += val;
 }
+
+/// Computes the correlation between the left and right channel.
+///
+/// The result is 1 for identical channels, 0 for unrelated channels and
+/// -1 if one channel is the inverse of the other. Silence counts as mono.
+fn stereo_correlation(left: &[f32], right: &[f32]) -> f32 {
+    let mut lr = 0.0;
+    let mut ll = 0.0;
+    let mut rr = 0.0;
+    for (&l, &r) in left.iter().zip(right.iter()) {
+        lr += l * r;
+        ll += l * l;
+        rr += r * r;
+    }
+
+    let norm = (ll * rr).sqrt();
+    if norm <= f32::EPSILON {
+        return 1.0;
+    }
+
+    (lr / norm).clamp(-1.0, 1.0)
+}
+
+/// Computes the centroid and spread of a power spectrum.
+///
+/// Bin `k` sits at `k * bin_hz` and is weighted by its magnitude, so the
+/// results are in the unit of `bin_hz`. Silence has a centroid and spread of 0.
+fn spectral_centroid(power: &[f32], bin_hz: f32) -> (f32, f32) {
+    let mut total = 0.0;
+    let mut weighted = 0.0;
+    for (k, &p) in power.iter().enumerate() {
+        let magnitude = p.sqrt();
+        total += magnitude;
+        weighted += magnitude * k as f32;
+    }
+
+    if total <= f32::EPSILON {
+        return (0.0, 0.0);
+    }
+
+    let centroid = weighted / total;
+    let variance = power
+        .iter()
+        .enumerate()
+        .map(|(k, &p)| p.sqrt() * (k as f32 - centroid).powi(2))
+        .sum::<f32>()
+        / total;
+
+    (centroid * bin_hz, variance.sqrt() * bin_hz)
+}
+
+/// Returns the power of two which is closest to `n`, rounding ties up.
+pub fn nearest_power_of_two(n: usize) -> usize {
+    let up = n.max(1).next_power_of_two();
+    let down = up / 2;
+    match down > 0 && n - down < up - n {
+        true => down,
+        false => up,
+    }
+}
+
+/// Resamples a magnitude spectrum onto a different number of bins.
+///
+/// The input covers the frequencies from 0 Hz up to the Nyquist frequency
+/// in equally sized bins. With `FftResample::Max`, each output bin takes the
+/// maximum of all input bins within its frequency range, or interpolates if
+/// the range is too narrow to contain a full input bin. With
+/// `FftResample::Linear`, every output bin interpolates.
+fn resample_spectrum(
+    src: &[f32],
+    dst: &mut [f32],
+    scale: FftScale,
+    resample: FftResample,
+    sample_freq: f32,
+) {
+    if src.is_empty() || dst.is_empty() {
+        return;
+    }
+
+    // skip the work for the default settings
+    if scale == FftScale::Linear && src.len() == dst.len() {
+        dst.copy_from_slice(src);
+        return;
+    }
+
+    let nyquist = sample_freq / 2.0;
+    let bins = dst.len() as f32;
+
+    // position in the input spectrum at the edge of an output bin
+    let edge = |k: usize| -> f32 {
+        let t = k as f32 / bins;
+        let freq = match scale {
+            FftScale::Linear => t * nyquist,
+            FftScale::Log if nyquist > FFT_LOG_MIN_FREQ => {
+                FFT_LOG_MIN_FREQ * (nyquist / FFT_LOG_MIN_FREQ).powf(t)
+            }
+            FftScale::Log => t * nyquist,
+        };
+
+        match nyquist > 0.0 {
+            true => freq / nyquist * src.len() as f32,
+            false => t * src.len() as f32,
+        }
+    };
+
+    let last = src.len() - 1;
+    for (k, out) in dst.iter_mut().enumerate() {
+        let start = edge(k);
+        let end = edge(k + 1);
+
+        let first = start.ceil() as usize;
+        let after = (end.ceil() as usize).min(src.len());
+
+        *out = if first < after && resample == FftResample::Max {
+            src[first..after].iter().copied().fold(0.0, f32::max)
+        } else {
+            let pos = (0.5 * (start + end)).min(last as f32);
+            let i = pos.floor() as usize;
+            let j = (i + 1).min(last);
+            let t = pos - i as f32;
+            src[i] * (1.0 - t) + src[j] * t
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sine(phase: f32) -> Vec<f32> {
+        (0..1024)
+            .map(|k| (std::f32::consts::TAU * k as f32 / 64.0 + phase).sin())
+            .collect()
+    }
+
+    #[test]
+    fn correlation_identical() {
+        let signal = sine(0.0);
+        let corr = stereo_correlation(&signal, &signal);
+        assert!((corr - 1.0).abs() < 1e-4, "{}", corr);
+    }
+
+    #[test]
+    fn correlation_inverted() {
+        let left = sine(0.0);
+        let right: Vec<_> = left.iter().map(|x| -x).collect();
+        let corr = stereo_correlation(&left, &right);
+        assert!((corr + 1.0).abs() < 1e-4, "{}", corr);
+    }
+
+    #[test]
+    fn correlation_quadrature() {
+        let left = sine(0.0);
+        let right = sine(std::f32::consts::FRAC_PI_2);
+        let corr = stereo_correlation(&left, &right);
+        assert!(corr.abs() < 1e-3, "{}", corr);
+    }
+
+    #[test]
+    fn correlation_one_sided() {
+        let left = sine(0.0);
+        let right = vec![0.0; left.len()];
+        assert_eq!(stereo_correlation(&left, &right), 1.0);
+        assert_eq!(stereo_correlation(&right, &right), 1.0);
+    }
+
+    #[test]
+    fn resample_identity() {
+        let src = [1.0, 2.0, 3.0, 4.0];
+        let mut dst = [0.0; 4];
+        resample_spectrum(&src, &mut dst, FftScale::Linear, FftResample::Max, 48000.0);
+        assert_eq!(dst, src);
+    }
+
+    #[test]
+    fn resample_linear_downsample() {
+        let src = [1.0, 5.0, 2.0, 3.0, 0.0, 0.0, 7.0, 1.0];
+        let mut dst = [0.0; 4];
+        resample_spectrum(&src, &mut dst, FftScale::Linear, FftResample::Max, 48000.0);
+        assert_eq!(dst, [5.0, 3.0, 0.0, 7.0]);
+    }
+
+    #[test]
+    fn resample_log_spacing() {
+        // a single peak at 1 kHz with 1 Hz per input bin
+        let mut src = vec![0.0; 24000];
+        src[1000] = 1.0;
+
+        let mut dst = vec![0.0; 100];
+        resample_spectrum(&src, &mut dst, FftScale::Log, FftResample::Max, 48000.0);
+
+        // 1 kHz lies at log(1000 / 20) / log(24000 / 20) of the texture
+        let expected = (100.0 * (50f32).ln() / (1200f32).ln()) as usize;
+        let peak = dst
+            .iter()
+            .enumerate()
+            .fold(
+                (0, 0.0),
+                |acc, (k, &v)| if v > acc.1 { (k, v) } else { acc },
+            )
+            .0;
+
+        assert_eq!(peak, expected);
+    }
+
+    #[test]
+    fn resample_linear_interpolation() {
+        let src = [0.0, 2.0, 8.0, 6.0];
+        let mut dst = [0.0; 2];
+        resample_spectrum(
+            &src,
+            &mut dst,
+            FftScale::Linear,
+            FftResample::Linear,
+            48000.0,
+        );
+        assert_eq!(dst, [2.0, 6.0]);
+
+        // without interpolation, the loudest bin wins
+        resample_spectrum(&src, &mut dst, FftScale::Linear, FftResample::Max, 48000.0);
+        assert_eq!(dst, [2.0, 8.0]);
+    }
+
+    #[test]
+    fn power_of_two_widths() {
+        assert_eq!(nearest_power_of_two(0), 1);
+        assert_eq!(nearest_power_of_two(256), 256);
+        assert_eq!(nearest_power_of_two(300), 256);
+        assert_eq!(nearest_power_of_two(400), 512);
+        assert_eq!(nearest_power_of_two(384), 512);
+    }
+
+    fn feed(peak: &mut PeakHold, level: f32, seconds: f32) {
+        for _ in 0..(seconds * 100.0).round() as usize {
+            peak.update(level, 0.01, 0.5, 0.5);
+        }
+    }
+
+    #[test]
+    fn peak_latches_and_holds() {
+        let mut peak = PeakHold::default();
+        peak.update(1.0, 0.01, 0.5, 0.5);
+        assert_eq!(peak.value, 1.0);
+
+        feed(&mut peak, 0.1, 0.49);
+        assert_eq!(peak.value, 1.0);
+    }
+
+    #[test]
+    fn peak_decays_after_hold() {
+        let mut peak = PeakHold::default();
+        peak.update(1.0, 0.01, 0.5, 0.5);
+        feed(&mut peak, 0.1, 1.0);
+        assert!((peak.value - 0.75).abs() < 1e-3, "{}", peak.value);
+
+        // never falls below the current level
+        feed(&mut peak, 0.1, 5.0);
+        assert_eq!(peak.value, 0.1);
+
+        // a louder impulse latches again
+        peak.update(0.5, 0.01, 0.5, 0.5);
+        assert_eq!(peak.value, 0.5);
+    }
+
+    #[test]
+    fn peak_resets_on_silence() {
+        let mut peak = PeakHold::default();
+        peak.update(1.0, 0.01, 0.5, 0.5);
+        peak.update(0.0, 0.01, 0.5, 0.5);
+        assert_eq!(peak, PeakHold::default());
+    }
+
+    #[test]
+    fn latency_to_samples() {
+        assert_eq!(latency_samples(0.0, 48000), 0);
+        assert_eq!(latency_samples(20.0, 48000), 960);
+        assert_eq!(latency_samples(10.0, 44100), 441);
+        assert_eq!(latency_samples(-30.0, 48000), 0);
+    }
+
+    fn tone(freq: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|k| 0.5 * (std::f32::consts::TAU * freq * k as f32 / 48000.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn pitch_of_sine_tones() {
+        for &freq in [82.41, 110.0, 261.63, 440.0, 1046.5].iter() {
+            let (pitch, confidence) = detect_pitch(&tone(freq, 4096), 48000.0).unwrap();
+            assert!((pitch / freq - 1.0).abs() < 0.005, "{} != {}", pitch, freq);
+            assert!(confidence > 0.9, "{} at {}", confidence, freq);
+        }
+    }
+
+    #[test]
+    fn pitch_notes() {
+        assert_eq!(midi_note(440.0), 69.0);
+        assert_eq!(midi_note(261.63), 60.0);
+        assert_eq!(midi_note(452.0), 69.0);
+        assert_eq!(midi_note(880.0), 81.0);
+    }
+
+    #[test]
+    fn pitch_needs_signal() {
+        assert_eq!(detect_pitch(&[0.0; 4096], 48000.0), None);
+
+        // 40 samples are shorter than two periods of the highest note
+        assert_eq!(detect_pitch(&tone(440.0, 40), 48000.0), None);
+    }
+
+    #[test]
+    fn pitch_of_noise_is_uncertain() {
+        let mut state = 0x1234_5678_u32;
+        let noise: Vec<_> = (0..4096)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect();
+
+        let (_, confidence) = detect_pitch(&noise, 48000.0).unwrap();
+        assert!(confidence < 0.5, "{}", confidence);
+    }
+
+    #[test]
+    fn gain_fades() {
+        let fade = gain_fade_factor(48000);
+        let mut gain = 1.0;
+
+        // muting must not silence the very next sample
+        gain += (0.0 - gain) * fade;
+        assert!(gain > 0.99);
+
+        for _ in 0..(GAIN_FADE * 48000.0) as usize * 5 {
+            gain += (0.0 - gain) * fade;
+        }
+        assert!(gain < 0.01);
+    }
+
+    #[test]
+    fn input_device_matching() {
+        let names = vec![
+            "Microphone".to_string(),
+            "Line In (USB Audio)".to_string(),
+            "Line In (Mixer)".to_string(),
+        ];
+        assert_eq!(find_input_device(&names, "Microphone"), Some(0));
+        assert_eq!(find_input_device(&names, "USB"), Some(1));
+        assert_eq!(find_input_device(&names, "Line In"), Some(2));
+        assert_eq!(find_input_device(&names, "Loopback"), None);
+    }
+
+    #[test]
+    fn centroid_of_tones() {
+        // 1024 samples at 48 kHz, a pure tone at 750 Hz lands in bin 16
+        let bin_hz = 48000.0 / 1024.0;
+        let mut power = vec![0.0; 512];
+        power[16] = 256.0;
+        assert_eq!(spectral_centroid(&power, bin_hz), (750.0, 0.0));
+
+        // two tones of equal level, at 750 Hz and 1500 Hz
+        power[32] = 256.0;
+        let (centroid, spread) = spectral_centroid(&power, bin_hz);
+        assert!((centroid - 1125.0).abs() < 1e-3, "{}", centroid);
+        assert!((spread - 375.0).abs() < 1e-3, "{}", spread);
+
+        // the louder tone pulls the centroid towards it
+        power[32] = 9.0 * 256.0;
+        let (centroid, _) = spectral_centroid(&power, bin_hz);
+        assert!((centroid - 1312.5).abs() < 1e-3, "{}", centroid);
+
+        assert_eq!(spectral_centroid(&[0.0; 512], bin_hz), (0.0, 0.0));
+    }
+
+    /// Onset density at the end of six seconds of a tone with a click train on top.
+    fn click_train_density(rate: Option<f32>) -> f32 {
+        let sample_freq = 48000;
+        let fft = FftPlanner::<f32>::new().plan_fft_forward(512);
+        let signal: Vec<f32> = (0..6 * sample_freq)
+            .map(|k| {
+                let t = k as f32 / sample_freq as f32;
+                let tone = 0.3 * (std::f32::consts::TAU * 440.0 * t).sin();
+
+                // short decaying bursts, like a hi-hat
+                let click = match rate {
+                    Some(rate) => {
+                        let t = t % rate.recip();
+                        (-t / 0.02).exp() * (std::f32::consts::TAU * 3000.0 * t).sin()
+                    }
+                    None => 0.0,
+                };
+                tone + click
+            })
+            .collect();
+
+        let mut detector = OnsetDetector::default();
+        let start = Instant::now();
+        let mut density = 0.0;
+        for end in (512..signal.len()).step_by(sample_freq / 60) {
+            let mut buffer: Vec<_> = signal[end - 512..end]
+                .iter()
+                .map(|&x| Complex::new(x, 0.0))
+                .collect();
+            fft.process(&mut buffer);
+            let power: Vec<_> = buffer[..256].iter().map(|z| z.norm_sqr()).collect();
+
+            let now = start + Duration::from_secs_f32(end as f32 / sample_freq as f32);
+            detector.update(&power, &power, now);
+            density = detector.density(now, 4.0);
+        }
+        density
+    }
+
+    #[test]
+    fn onset_density() {
+        assert_eq!(click_train_density(None), 0.0);
+        for &rate in [1.0, 2.0, 4.0, 8.0].iter() {
+            let density = click_train_density(Some(rate));
+            assert!((density - rate).abs() <= 0.25, "{} {}", rate, density);
+        }
+
+        // old onsets leave the window
+        let mut detector = OnsetDetector::default();
+        let start = Instant::now();
+        assert!(detector.update(&[0.0, 4.0], &[0.0, 4.0], start));
+        assert_eq!(detector.density(start, 2.0), 0.5);
+        assert_eq!(detector.density(start + Duration::from_secs(3), 2.0), 0.0);
+    }
+
+    #[test]
+    fn profile_switch() {
+        let calm = AudioProfile {
+            attack: 0.9,
+            decay: 0.95,
+            peak_hold: 1.0,
+            peak_decay: 0.2,
+        };
+        let aggressive = AudioProfile {
+            attack: 0.1,
+            decay: 0.5,
+            peak_hold: 0.1,
+            peak_decay: 2.0,
+        };
+
+        let halfway = calm.mix(&aggressive, 0.5);
+        assert!((halfway.attack - 0.5).abs() < 1e-6);
+        assert!((halfway.peak_decay - 1.1).abs() < 1e-6);
+        assert_eq!(calm.mix(&aggressive, 2.0), aggressive);
+
+        let mut audio = Audio::without_input(64);
+        audio.set_profiles(
+            vec![("calm".into(), calm), ("aggressive".into(), aggressive)],
+            None,
+        );
+        assert!(!audio.select_profile(&PresetRequest::Name("wild".into())));
+        assert!(audio.select_profile(&PresetRequest::Index(1)));
+        assert_eq!(audio.active_profile(), Some("aggressive"));
+
+        // the parameters only move once the fade has started
+        assert_eq!(audio.profile(), AudioProfile::default());
+        std::thread::sleep(PROFILE_FADE);
+        audio.update_fft();
+        assert_eq!(audio.profile(), aggressive);
+    }
+}
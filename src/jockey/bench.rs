@@ -0,0 +1,182 @@
+use std::{
+    fmt,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use super::{
+    check::{enter_pipeline_folder, headless_context},
+    Engine,
+};
+use crate::util::FrameBuffer;
+
+/// Size of the offscreen target the benchmark renders into.
+const BENCH_SCREEN_SIZE: (u32, u32) = (1920, 1080);
+
+/// Step of the render clock between two benchmark frames.
+///
+/// Frames are rendered as fast as possible, but the shaders see a steady
+/// 60 fps, so every run renders exactly the same frames.
+const BENCH_FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+/// Timings of one stage over all frames of a benchmark.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassTiming {
+    pub label: String,
+    /// Either `"GPU"` or `"CPU"`, depending on where the time was measured.
+    pub clock: &'static str,
+    pub samples: usize,
+    pub min_ms: f32,
+    pub avg_ms: f32,
+    pub max_ms: f32,
+}
+
+impl PassTiming {
+    pub fn new(label: String, clock: &'static str, samples: &[f32]) -> Self {
+        let min_ms = samples.iter().copied().fold(f32::INFINITY, f32::min);
+        let max_ms = samples.iter().copied().fold(0.0, f32::max);
+        let avg_ms = match samples.len() {
+            0 => 0.0,
+            n => samples.iter().sum::<f32>() / n as f32,
+        };
+
+        Self {
+            label,
+            clock,
+            samples: samples.len(),
+            min_ms: if samples.is_empty() { 0.0 } else { min_ms },
+            avg_ms,
+            max_ms,
+        }
+    }
+}
+
+/// Result of `bench_pipeline`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    pub frames: u32,
+    /// Wall clock time of all frames, until the GPU finished the last one.
+    pub seconds: f32,
+    pub passes: Vec<PassTiming>,
+}
+
+impl BenchReport {
+    pub fn fps(&self) -> f32 {
+        match self.seconds {
+            s if s > 0.0 => self.frames as f32 / s,
+            _ => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} frames in {:.3} s, {:.2} fps ({:.3} ms per frame)",
+            self.frames,
+            self.seconds,
+            self.fps(),
+            1000.0 * self.seconds / self.frames.max(1) as f32
+        )?;
+
+        for (k, pass) in self.passes.iter().enumerate() {
+            match pass.samples {
+                0 => writeln!(f, "  stage {} ({}): no timings", k, pass.label)?,
+                _ => writeln!(
+                    f,
+                    "  stage {} ({}): min {:.4} ms, avg {:.4} ms, max {:.4} ms {}",
+                    k, pass.label, pass.min_ms, pass.avg_ms, pass.max_ms, pass.clock
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders a pipeline file offscreen as fast as possible and times every stage.
+///
+/// Like `check_pipeline`, paths are resolved relative to the folder of the
+/// file and audio, MIDI, OSC and NDI are never started. Nothing is presented
+/// and there is no vsync, so the frame rate is only limited by the pipeline.
+pub fn bench_pipeline(path: &Path, frames: u32) -> Result<BenchReport, String> {
+    let file_name = enter_pipeline_folder(path)?;
+
+    let events_loop = glutin::event_loop::EventLoop::new();
+    let context = headless_context(&events_loop)?;
+
+    let (width, height) = BENCH_SCREEN_SIZE;
+    let mut engine = Engine::new(|s| context.get_proc_address(s) as _, width, height);
+    engine.load_pipeline(file_name)?;
+
+    let target = FrameBuffer::new(width, height);
+    let stage_count = engine.pipeline.stages.len();
+    let mut samples = vec![Vec::with_capacity(frames as _); stage_count];
+    let mut indices = vec![0; stage_count];
+
+    let start = Instant::now();
+    for _ in 0..frames {
+        engine.update(BENCH_FRAME_TIME);
+        engine.render(target.fb_id);
+
+        // timer queries finish a few frames late, so only new results are taken
+        for (k, stage) in engine.pipeline.stages.iter().enumerate() {
+            let perf = match stage.gpu_timer {
+                Some(_) => &stage.gpu_perf,
+                None => &stage.perf,
+            };
+
+            if perf.index != indices[k] {
+                indices[k] = perf.index;
+                samples[k].push(perf.last());
+            }
+        }
+    }
+
+    unsafe {
+        gl::Finish();
+    }
+    let seconds = start.elapsed().as_secs_f32();
+
+    let passes = engine
+        .pipeline
+        .stages
+        .iter()
+        .zip(samples.iter())
+        .map(|(stage, samples)| PassTiming::new(stage.label(), stage.timing().1, samples))
+        .collect();
+
+    Ok(BenchReport {
+        frames,
+        seconds,
+        passes,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pass_statistics() {
+        let pass = PassTiming::new("screen".into(), "GPU", &[2.0, 1.0, 3.0, 2.0]);
+        assert_eq!(pass.samples, 4);
+        assert_eq!(pass.min_ms, 1.0);
+        assert_eq!(pass.avg_ms, 2.0);
+        assert_eq!(pass.max_ms, 3.0);
+
+        let empty = PassTiming::new("compute".into(), "CPU", &[]);
+        assert_eq!((empty.min_ms, empty.avg_ms, empty.max_ms), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn report_fps() {
+        let report = BenchReport {
+            frames: 500,
+            seconds: 2.0,
+            passes: Vec::new(),
+        };
+        assert_eq!(report.fps(), 250.0);
+    }
+}
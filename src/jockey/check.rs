@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use super::Pipeline;
+use crate::util::block_on;
+
+/// Screen size used to resolve render targets without a fixed resolution.
+const CHECK_SCREEN_SIZE: (u32, u32) = (1280, 720);
+
+/// Builds a pipeline file without opening a window.
+///
+/// Paths in the pipeline are resolved relative to the folder of the file,
+/// just like when the tool is started in that folder. The pipeline is built
+/// exactly as it would be on stage, so every shader is compiled and linked,
+/// but audio, MIDI, OSC and NDI are never started.
+pub fn check_pipeline(path: &Path) -> Result<(), String> {
+    let file_name = enter_pipeline_folder(path)?;
+
+    let events_loop = glutin::event_loop::EventLoop::new();
+    let context = headless_context(&events_loop)?;
+    gl::load_with(|s| context.get_proc_address(s) as _);
+
+    block_on(Pipeline::load(file_name, CHECK_SCREEN_SIZE, Vec::new())).map(|_| ())
+}
+
+/// Enters the folder of a pipeline file and returns the name of the file.
+pub(super) fn enter_pipeline_folder(path: &Path) -> Result<&Path, String> {
+    let file_name = match path.file_name() {
+        Some(name) => Path::new(name),
+        None => return Err(format!("{} is not a file", path.display())),
+    };
+
+    if let Some(folder) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::env::set_current_dir(folder)
+            .map_err(|err| format!("Failed to enter {}: {}", folder.display(), err))?;
+    }
+
+    Ok(file_name)
+}
+
+/// Creates an OpenGL context without a window and makes it current.
+pub(super) fn headless_context(
+    events_loop: &glutin::event_loop::EventLoop<()>,
+) -> Result<glutin::Context<glutin::PossiblyCurrent>, String> {
+    let size = glutin::dpi::PhysicalSize::new(1, 1);
+    let context = glutin::ContextBuilder::new()
+        .with_gl(glutin::GlRequest::Latest)
+        .build_headless(events_loop, size)
+        .map_err(|err| format!("Failed to create OpenGL context: {}", err))?;
+
+    unsafe { context.make_current() }
+        .map_err(|(_, err)| format!("Failed to activate OpenGL context: {}", err))
+}
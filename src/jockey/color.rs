@@ -0,0 +1,240 @@
+use std::ffi::CString;
+
+use gl::types::*;
+use lazy_static::lazy_static;
+
+use super::{ColorConfig, CubeLut, Dither, Tonemap, PASS_VERT, POSITION_NAME, RESOLUTION_NAME};
+use crate::util::*;
+
+const PRESENT_FRAG: &str = include_str!("shaders/present.frag");
+
+lazy_static! {
+    static ref FRAME_NAME: CString = CString::new("frame").unwrap();
+    static ref TONEMAP_NAME: CString = CString::new("tonemap").unwrap();
+    static ref SRGB_NAME: CString = CString::new("srgb").unwrap();
+    static ref LUT_NAME: CString = CString::new("lut").unwrap();
+    static ref LUT_SIZE_NAME: CString = CString::new("lut_size").unwrap();
+    static ref LUT_STRENGTH_NAME: CString = CString::new("lut_strength").unwrap();
+    static ref LUT_DOMAIN_MIN_NAME: CString = CString::new("lut_domain_min").unwrap();
+    static ref LUT_DOMAIN_MAX_NAME: CString = CString::new("lut_domain_max").unwrap();
+    static ref DITHER_NAME: CString = CString::new("dither").unwrap();
+}
+
+/// Tone maps and encodes the final frame before it reaches the screen.
+///
+/// While color handling is enabled, the decks render into a float
+/// framebuffer instead of the screen, which is then drawn to the default
+/// framebuffer in a final pass. Everything which reads the default
+/// framebuffer afterwards, like Spout and screenshots, sees the same pixels
+/// as the screen.
+pub struct ColorPass {
+    config: ColorConfig,
+    target: Option<FrameBuffer>,
+    lut: Option<(Texture3D, CubeLut)>,
+    prog_id: GLuint,
+    sh_ids: Vec<GLuint>,
+}
+
+impl std::fmt::Debug for ColorPass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(ColorPass))
+            .field("config", &self.config)
+            .field("target", &self.target)
+            .field("lut", &self.lut.as_ref().map(|(_, lut)| lut.size))
+            .finish()
+    }
+}
+
+impl ColorPass {
+    /// Creates a new color pass for the OpenGL context which is currently active.
+    ///
+    /// A LUT which fails to load is logged and left out.
+    pub fn new(mut config: ColorConfig) -> Self {
+        let lut = config
+            .lut
+            .as_ref()
+            .and_then(|path| match CubeLut::load(path) {
+                Ok(mut lut) => {
+                    log::info!("Loaded LUT {:?} of size {}", path, lut.size);
+                    let size = lut.size as u32;
+                    let texture = Texture3D::with_params(
+                        [size; 3],
+                        gl::LINEAR,
+                        gl::LINEAR,
+                        gl::CLAMP_TO_EDGE,
+                        TextureFormat::RGB32F,
+                        false,
+                        lut.data.as_ptr() as _,
+                    );
+                    // the entries live on the GPU from now on
+                    lut.data = Vec::new();
+                    Some((texture, lut))
+                }
+                Err(err) => {
+                    log::error!("{}", err);
+                    None
+                }
+            });
+
+        if lut.is_none() {
+            config.lut = None;
+        }
+
+        let sh_ids = vec![
+            compile_shader(PASS_VERT, gl::VERTEX_SHADER).unwrap(),
+            compile_shader(PRESENT_FRAG, gl::FRAGMENT_SHADER).unwrap(),
+        ];
+
+        let prog_id = link_program(&sh_ids).unwrap();
+
+        Self {
+            config,
+            target: None,
+            lut,
+            prog_id,
+            sh_ids,
+        }
+    }
+
+    /// The settings in use, including the current LUT strength.
+    pub fn config(&self) -> &ColorConfig {
+        &self.config
+    }
+
+    /// Changes how much of the graded color is mixed in, between 0 and 1.
+    pub fn set_lut_strength(&mut self, strength: f32) {
+        self.config.lut_strength = strength.clamp(0.0, 1.0);
+    }
+
+    /// Whether Spout gets the same frame as the screen, which is not the case
+    /// if only the screen is dithered.
+    pub fn spout_matches_screen(&self) -> bool {
+        self.config.dither == Dither::None || self.config.dither_spout
+    }
+
+    /// Returns the framebuffer the decks should render into.
+    ///
+    /// This is `next_fb` itself, unless color handling is enabled.
+    pub fn target(&mut self, next_fb: GLuint, width: u32, height: u32) -> GLuint {
+        if self.config.is_passthrough() {
+            self.target = None;
+            return next_fb;
+        }
+
+        let resized = match &self.target {
+            Some(target) => target.resolution()[..2] != [width.max(1), height.max(1)],
+            None => true,
+        };
+
+        if resized {
+            self.target = Some(FrameBuffer::with_params(
+                width,
+                height,
+                gl::NEAREST,
+                gl::NEAREST,
+                gl::CLAMP_TO_EDGE,
+                false,
+                true,
+            ));
+        }
+
+        self.target.as_ref().unwrap().fb_id
+    }
+
+    /// Draws the frame rendered into the target to `next_fb`.
+    ///
+    /// Without `dither`, the configured dither is left out.
+    pub fn present(&self, vao: GLuint, next_fb: GLuint, width: u32, height: u32, dither: bool) {
+        let target = match &self.target {
+            Some(target) => target,
+            None => return,
+        };
+
+        let (w, h) = (width as f32, height as f32);
+        let tonemap = match self.config.tonemap {
+            Tonemap::None => 0,
+            Tonemap::Reinhard => 1,
+            Tonemap::Aces => 2,
+        };
+        let dither = match (dither, self.config.dither) {
+            (false, _) | (_, Dither::None) => 0,
+            (true, Dither::Ordered) => 1,
+            (true, Dither::Noise) => 2,
+        };
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, next_fb);
+            gl::Viewport(0, 0, width as _, height as _);
+            gl::Disable(gl::BLEND);
+            gl::UseProgram(self.prog_id);
+            gl_debug_check!();
+
+            let res_loc = gl::GetUniformLocation(self.prog_id, RESOLUTION_NAME.as_ptr());
+            gl::Uniform4f(res_loc, w, h, w / h, h / w);
+            let tonemap_loc = gl::GetUniformLocation(self.prog_id, TONEMAP_NAME.as_ptr());
+            gl::Uniform1i(tonemap_loc, tonemap);
+            let srgb_loc = gl::GetUniformLocation(self.prog_id, SRGB_NAME.as_ptr());
+            gl::Uniform1i(srgb_loc, self.config.srgb as _);
+            let dither_loc = gl::GetUniformLocation(self.prog_id, DITHER_NAME.as_ptr());
+            gl::Uniform1i(dither_loc, dither);
+            gl_debug_check!();
+
+            // the sampler gets its own unit even without a LUT,
+            // since it must not share one with the 2D frame
+            let lut_loc = gl::GetUniformLocation(self.prog_id, LUT_NAME.as_ptr());
+            gl::Uniform1i(lut_loc, 1);
+            let lut_size_loc = gl::GetUniformLocation(self.prog_id, LUT_SIZE_NAME.as_ptr());
+            if let Some((texture, lut)) = &self.lut {
+                gl::ActiveTexture(gl::TEXTURE1);
+                texture.bind(1);
+                gl::Uniform1f(lut_size_loc, lut.size as _);
+                let strength_loc = gl::GetUniformLocation(self.prog_id, LUT_STRENGTH_NAME.as_ptr());
+                gl::Uniform1f(strength_loc, self.config.lut_strength);
+                let min_loc = gl::GetUniformLocation(self.prog_id, LUT_DOMAIN_MIN_NAME.as_ptr());
+                gl::Uniform3fv(min_loc, 1, lut.domain_min.as_ptr());
+                let max_loc = gl::GetUniformLocation(self.prog_id, LUT_DOMAIN_MAX_NAME.as_ptr());
+                gl::Uniform3fv(max_loc, 1, lut.domain_max.as_ptr());
+            } else {
+                gl::Uniform1f(lut_size_loc, 0.0);
+            }
+            gl_debug_check!();
+
+            let frame_loc = gl::GetUniformLocation(self.prog_id, FRAME_NAME.as_ptr());
+            gl::ActiveTexture(gl::TEXTURE0);
+            target.bind(0);
+            gl::Uniform1i(frame_loc, 0);
+            gl_debug_check!();
+
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vao);
+            let pos_attr = gl::GetAttribLocation(self.prog_id, POSITION_NAME.as_ptr());
+            if pos_attr != -1 {
+                gl::EnableVertexAttribArray(pos_attr as GLuint);
+                gl::VertexAttribPointer(
+                    pos_attr as GLuint,
+                    2,
+                    gl::FLOAT,
+                    gl::FALSE as GLboolean,
+                    0,
+                    std::ptr::null(),
+                );
+            }
+            gl_debug_check!();
+
+            draw_fullscreen(vao);
+        }
+    }
+}
+
+impl Drop for ColorPass {
+    fn drop(&mut self) {
+        unsafe {
+            for &id in self.sh_ids.iter() {
+                gl::DetachShader(self.prog_id, id);
+                gl::DeleteShader(id);
+            }
+
+            gl::DeleteProgram(self.prog_id);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use serde_yaml::Value;
+
+/// How the colors of an image are encoded, and how much they are brightened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageColor {
+    /// Whether the image is sRGB encoded, `None` picks sRGB for 8 bit images only.
+    pub srgb: Option<bool>,
+    /// Multiplier for the linear colors, alpha is left as it is.
+    pub exposure: f32,
+}
+
+impl Default for ImageColor {
+    fn default() -> Self {
+        Self {
+            srgb: None,
+            exposure: 1.0,
+        }
+    }
+}
+
+impl ImageColor {
+    /// Reads the `colorspace` and `exposure` fields of an image.
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let srgb = match object.get("colorspace").map(Value::as_str) {
+            Some(Some("srgb")) => Some(true),
+            Some(Some("linear")) => Some(false),
+            None => None,
+            Some(s) => {
+                return Err(format!(
+                    "Expected \"colorspace\" to be either \"srgb\" or \"linear\", got {:?}",
+                    s
+                ))
+            }
+        };
+
+        let exposure = match object.get("exposure") {
+            Some(s) => match s.as_f64() {
+                Some(x) if x > 0.0 && x.is_finite() => x as _,
+                _ => {
+                    return Err(format!(
+                        "Expected \"exposure\" to be a positive number, got {:?}",
+                        s
+                    ))
+                }
+            },
+            None => 1.0,
+        };
+
+        Ok(Self { srgb, exposure })
+    }
+
+    /// Applies the exposure to linear float RGBA pixels in place.
+    pub fn expose(&self, pixels: &mut [f32]) {
+        for px in pixels.chunks_exact_mut(4) {
+            px[..3].iter_mut().for_each(|c| *c *= self.exposure);
+        }
+    }
+
+    /// Converts 16 bit RGBA pixels to linear floats and applies the exposure.
+    pub fn linearize(&self, srgb: bool, pixels: &[u16]) -> Vec<f32> {
+        pixels
+            .chunks_exact(4)
+            .flat_map(|px| {
+                let color = |c: u16| {
+                    let c = c as f32 / u16::MAX as f32;
+                    match srgb {
+                        true => decode_srgb(c) * self.exposure,
+                        false => c * self.exposure,
+                    }
+                };
+
+                let alpha = px[3] as f32 / u16::MAX as f32;
+                [color(px[0]), color(px[1]), color(px[2]), alpha]
+            })
+            .collect()
+    }
+}
+
+/// How the alpha of an external texture is stored by its source.
+///
+/// Blending in the pipeline expects straight alpha, so premultiplied
+/// sources are converted when they are uploaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    Straight,
+    Premultiplied,
+}
+
+impl Default for AlphaMode {
+    fn default() -> Self {
+        Self::Straight
+    }
+}
+
+impl AlphaMode {
+    /// Reads the `alpha` field of an image or an external source.
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        match object.get("alpha").map(Value::as_str) {
+            Some(Some("straight")) | None => Ok(Self::Straight),
+            Some(Some("premultiplied")) => Ok(Self::Premultiplied),
+            Some(s) => Err(format!(
+                "Expected \"alpha\" to be either \"straight\" or \"premultiplied\", got {:?}",
+                s
+            )),
+        }
+    }
+
+    /// Converts 8 bit RGBA pixels to straight alpha in place.
+    pub fn convert_u8(&self, pixels: &mut [u8]) {
+        if *self == Self::Premultiplied {
+            for px in pixels.chunks_exact_mut(4) {
+                let alpha = px[3] as u32;
+                for c in &mut px[..3] {
+                    *c = unpremultiply(*c as _, alpha, u8::MAX as _) as _;
+                }
+            }
+        }
+    }
+
+    /// Converts float RGBA pixels to straight alpha in place.
+    pub fn convert_f32(&self, pixels: &mut [f32]) {
+        if *self == Self::Premultiplied {
+            for px in pixels.chunks_exact_mut(4) {
+                if px[3] > 0.0 {
+                    let alpha = px[3];
+                    px[..3].iter_mut().for_each(|c| *c /= alpha);
+                }
+            }
+        }
+    }
+
+    /// Converts 16 bit RGBA pixels to straight alpha in place.
+    pub fn convert_u16(&self, pixels: &mut [u16]) {
+        if *self == Self::Premultiplied {
+            for px in pixels.chunks_exact_mut(4) {
+                let alpha = px[3] as u32;
+                for c in &mut px[..3] {
+                    *c = unpremultiply(*c as _, alpha, u16::MAX as _) as _;
+                }
+            }
+        }
+    }
+}
+
+/// Divides a color channel by its alpha, rounding to the nearest value.
+///
+/// Fully transparent pixels keep their color, there is nothing to divide by.
+fn unpremultiply(c: u32, alpha: u32, max: u32) -> u32 {
+    match alpha {
+        0 => c,
+        _ => ((c * max + alpha / 2) / alpha).min(max),
+    }
+}
+
+/// Converts an sRGB encoded value from 0 to 1 to linear.
+pub fn decode_srgb(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_image_color() {
+        let parse = |yaml: &str| ImageColor::from_yaml(&serde_yaml::from_str(yaml).unwrap());
+
+        assert_eq!(parse("path: a.png").unwrap(), ImageColor::default());
+        assert_eq!(
+            parse("colorspace: linear\nexposure: 2").unwrap(),
+            ImageColor {
+                srgb: Some(false),
+                exposure: 2.0
+            }
+        );
+
+        assert!(parse("colorspace: rec709").is_err());
+        assert!(parse("exposure: 0").is_err());
+    }
+
+    #[test]
+    fn linearize_pixels() {
+        let color = ImageColor {
+            srgb: None,
+            exposure: 2.0,
+        };
+
+        let pixels = [u16::MAX, 0, u16::MAX / 2, u16::MAX / 2];
+        let linear = color.linearize(false, &pixels);
+        assert_eq!(linear[..2], [2.0, 0.0]);
+        assert!((linear[2] - 1.0).abs() < 1e-4);
+        assert!((linear[3] - 0.5).abs() < 1e-4);
+
+        // mid gray in sRGB is about a fifth in linear
+        let linear = color.linearize(true, &pixels);
+        assert!((linear[2] - 2.0 * 0.214).abs() < 1e-3, "{:?}", linear);
+        assert!((linear[3] - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn unpremultiply_alpha() {
+        let parse = |yaml: &str| AlphaMode::from_yaml(&serde_yaml::from_str(yaml).unwrap());
+        assert_eq!(parse("name: a").unwrap(), AlphaMode::Straight);
+        assert_eq!(
+            parse("alpha: premultiplied").unwrap(),
+            AlphaMode::Premultiplied
+        );
+        assert!(parse("alpha: multiplied").is_err());
+
+        // half transparent white, black and an invisible pixel
+        let pixels = [128, 128, 128, 128, 0, 0, 0, 128, 7, 7, 7, 0];
+        let mut straight = pixels;
+        AlphaMode::Straight.convert_u8(&mut straight);
+        assert_eq!(straight, pixels);
+
+        let mut converted = pixels;
+        AlphaMode::Premultiplied.convert_u8(&mut converted);
+        assert_eq!(converted, [255, 255, 255, 128, 0, 0, 0, 128, 7, 7, 7, 0]);
+
+        // colors brighter than the alpha are clamped
+        let mut pixels = [u16::MAX, 1000, 0, 1000];
+        AlphaMode::Premultiplied.convert_u16(&mut pixels);
+        assert_eq!(pixels, [u16::MAX, u16::MAX, 0, 1000]);
+
+        // floats are not clamped, since HDR colors may exceed 1
+        let mut pixels = [2.0, 0.25, 0.0, 0.5, 1.0, 1.0, 1.0, 0.0];
+        AlphaMode::Premultiplied.convert_f32(&mut pixels);
+        assert_eq!(pixels, [4.0, 0.5, 0.0, 0.5, 1.0, 1.0, 1.0, 0.0]);
+    }
+}
@@ -0,0 +1,389 @@
+use std::{collections::HashMap, ffi::CString};
+
+use lazy_static::lazy_static;
+use serde_yaml::Value;
+
+use super::OscUniformValue;
+
+pub const COMPOSITE_FRAG: &str = include_str!("shaders/composite.frag");
+
+lazy_static! {
+    pub static ref COMPOSITE_SOURCE_NAME: CString = CString::new("composite_source").unwrap();
+    pub static ref COMPOSITE_TRANSFORM_NAME: CString = CString::new("composite_transform").unwrap();
+    pub static ref COMPOSITE_OPACITY_NAME: CString = CString::new("composite_opacity").unwrap();
+}
+
+/// A value of a composite layer which may follow a live control.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayerParam {
+    Value(f32),
+    /// A midi slider, mapped from 0..1 onto the range.
+    Slider {
+        index: usize,
+        range: [f32; 2],
+        invert: bool,
+    },
+    /// The value of an OSC mapping, mapped from 0..1 onto the range.
+    Osc {
+        name: String,
+        range: [f32; 2],
+        invert: bool,
+        /// Used as it is until the mapping has a value.
+        default: Option<f32>,
+    },
+}
+
+impl LayerParam {
+    /// Parses either a number or a mapping with a `slider` or `osc` field.
+    ///
+    /// With `invert: true` the control reads as `1 - x` before it is mapped
+    /// onto the range. OSC params may have a `default`, which is the value
+    /// of the param itself while the mapping has none.
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        if let Some(f) = object.as_f64() {
+            return Ok(Self::Value(f as _));
+        }
+
+        if !object.is_mapping() {
+            return Err(format!(
+                "Expected a number or a mapping with \"slider\" or \"osc\", got {:?}",
+                object
+            ));
+        }
+
+        let range = match object.get("range") {
+            Some(Value::Sequence(s)) => match s.as_slice() {
+                [min, max] => match (min.as_f64(), max.as_f64()) {
+                    (Some(min), Some(max)) => [min as f32, max as f32],
+                    _ => return Err(format!("Expected \"range\" to be two numbers, got {:?}", s)),
+                },
+                _ => return Err(format!("Expected \"range\" to be two numbers, got {:?}", s)),
+            },
+            Some(s) => return Err(format!("Expected \"range\" to be two numbers, got {:?}", s)),
+            None => [0.0, 1.0],
+        };
+
+        let invert = match object.get("invert") {
+            Some(Value::Bool(b)) => *b,
+            Some(s) => {
+                return Err(format!(
+                    "Expected \"invert\" to be true or false, got {:?}",
+                    s
+                ))
+            }
+            None => false,
+        };
+
+        let default = match object.get("default") {
+            Some(s) => match s.as_f64() {
+                Some(f) => Some(f as f32),
+                None => return Err(format!("Expected \"default\" to be a number, got {:?}", s)),
+            },
+            None => None,
+        };
+
+        match (object.get("slider"), object.get("osc")) {
+            (Some(_), None) if default.is_some() => Err(
+                "Expected \"default\" only with \"osc\", sliders take it from slider_defaults"
+                    .into(),
+            ),
+            (Some(s), None) => match s.as_u64() {
+                Some(index) => Ok(Self::Slider {
+                    index: index as _,
+                    range,
+                    invert,
+                }),
+                None => Err(format!("Expected \"slider\" to be an index, got {:?}", s)),
+            },
+            (None, Some(Value::String(name))) => Ok(Self::Osc {
+                name: name.clone(),
+                range,
+                invert,
+                default,
+            }),
+            (None, Some(s)) => Err(format!(
+                "Expected \"osc\" to be the name of a mapping, got {:?}",
+                s
+            )),
+            _ => Err("Expected exactly one of \"slider\" or \"osc\"".into()),
+        }
+    }
+
+    /// Returns the current value.
+    ///
+    /// OSC mappings which have not been set use the default of the param,
+    /// without one they read as zero.
+    pub fn get(&self, sliders: &[f32], osc: &HashMap<String, OscUniformValue>) -> f32 {
+        let (value, range, invert) = match self {
+            Self::Value(f) => return *f,
+            Self::Slider {
+                index,
+                range,
+                invert,
+            } => (sliders.get(*index).copied(), range, invert),
+            Self::Osc {
+                name,
+                range,
+                invert,
+                default,
+            } => {
+                let value = osc.get(name).map(OscUniformValue::as_f32);
+                if let (None, Some(default)) = (value, default) {
+                    return *default;
+                }
+                (value, range, invert)
+            }
+        };
+
+        let value = match invert {
+            true => 1.0 - value.unwrap_or(0.0),
+            false => value.unwrap_or(0.0),
+        };
+
+        range[0] + value * (range[1] - range[0])
+    }
+}
+
+/// Places an external texture, like an NDI or Spout input, in a stage target.
+///
+/// Positions are given relative to the target, `[0, 0]` centers the layer
+/// and `[1, 0]` moves its center onto the right edge. A scale of 1 fills
+/// the whole target. The rotation is in degrees, counter-clockwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompositeLayer {
+    pub source: CString,
+    pub position: [LayerParam; 2],
+    pub scale: [LayerParam; 2],
+    pub rotation: LayerParam,
+    pub opacity: LayerParam,
+}
+
+impl CompositeLayer {
+    /// Parses the layer settings of a stage with a `composite` field.
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let source = match object.get("composite") {
+            Some(Value::String(s)) => CString::new(s.as_str()).unwrap(),
+            s => {
+                return Err(format!(
+                    "Expected \"composite\" to be the name of a texture, got {:?}",
+                    s
+                ))
+            }
+        };
+
+        let pair = |key: &str, default: f32| -> Result<[LayerParam; 2], String> {
+            match object.get(key) {
+                Some(Value::Sequence(s)) if s.len() == 2 => {
+                    Ok([LayerParam::from_yaml(&s[0])?, LayerParam::from_yaml(&s[1])?])
+                }
+                Some(Value::Sequence(s)) => Err(format!(
+                    "Expected \"{}\" to have 2 elements, got {}",
+                    key,
+                    s.len()
+                )),
+                Some(s) => {
+                    let param = LayerParam::from_yaml(s).map_err(|e| format!("{}: {}", key, e))?;
+                    Ok([param.clone(), param])
+                }
+                None => Ok([LayerParam::Value(default), LayerParam::Value(default)]),
+            }
+        };
+
+        let single = |key: &str, default: f32| -> Result<LayerParam, String> {
+            match object.get(key) {
+                Some(s) => LayerParam::from_yaml(s).map_err(|e| format!("{}: {}", key, e)),
+                None => Ok(LayerParam::Value(default)),
+            }
+        };
+
+        Ok(Self {
+            source,
+            position: pair("position", 0.0)?,
+            scale: pair("scale", 1.0)?,
+            rotation: single("rotation", 0.0)?,
+            opacity: single("opacity", 1.0)?,
+        })
+    }
+
+    /// Returns `true` if one of the parameters follows the given OSC mapping.
+    pub fn uses_osc(&self, mapping: &str) -> bool {
+        self.position
+            .iter()
+            .chain(self.scale.iter())
+            .chain([&self.rotation, &self.opacity].iter().copied())
+            .any(|param| matches!(param, LayerParam::Osc { name, .. } if name == mapping))
+    }
+
+    /// Returns the transform matrix and opacity for the current frame.
+    pub fn evaluate(
+        &self,
+        sliders: &[f32],
+        osc: &HashMap<String, OscUniformValue>,
+        aspect: f32,
+    ) -> ([f32; 9], f32) {
+        let get = |param: &LayerParam| param.get(sliders, osc);
+        let transform = layer_transform(
+            [get(&self.position[0]), get(&self.position[1])],
+            [get(&self.scale[0]), get(&self.scale[1])],
+            get(&self.rotation),
+            aspect,
+        );
+
+        (transform, get(&self.opacity).clamp(0.0, 1.0))
+    }
+}
+
+/// Builds the matrix which maps target coordinates back onto the layer.
+///
+/// Both coordinate systems go from -1 to 1. The rotation happens in pixel
+/// space, so layers keep their shape while turning on targets which are not
+/// square. The matrix is column-major, as expected by `glUniformMatrix3fv`.
+pub fn layer_transform(
+    position: [f32; 2],
+    scale: [f32; 2],
+    rotation: f32,
+    aspect: f32,
+) -> [f32; 9] {
+    // a scale of zero would divide by zero, the layer is just invisible then
+    let nonzero = |s: f32| match s.abs() < 1e-6 {
+        true => 1e-6_f32.copysign(s),
+        false => s,
+    };
+
+    let (sx, sy) = (nonzero(scale[0]) * aspect, nonzero(scale[1]));
+    let (tx, ty) = (position[0] * aspect, position[1]);
+    let (s, c) = rotation.to_radians().sin_cos();
+
+    [
+        c * aspect / sx,
+        -s * aspect / sy,
+        0.0,
+        s / sx,
+        c / sy,
+        0.0,
+        (-c * tx - s * ty) / sx,
+        (s * tx - c * ty) / sy,
+        1.0,
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn apply(m: &[f32; 9], p: [f32; 2]) -> [f32; 2] {
+        [
+            m[0] * p[0] + m[3] * p[1] + m[6],
+            m[1] * p[0] + m[4] * p[1] + m[7],
+        ]
+    }
+
+    fn assert_close(a: [f32; 2], b: [f32; 2]) {
+        assert!(
+            (a[0] - b[0]).abs() < 1e-5 && (a[1] - b[1]).abs() < 1e-5,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn identity() {
+        let m = layer_transform([0.0, 0.0], [1.0, 1.0], 0.0, 16.0 / 9.0);
+        assert_eq!(m, [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn move_and_scale() {
+        let m = layer_transform([0.5, 0.0], [0.5, 0.5], 0.0, 2.0);
+        assert_close(apply(&m, [0.5, 0.0]), [0.0, 0.0]);
+        assert_close(apply(&m, [1.0, 0.5]), [1.0, 1.0]);
+    }
+
+    #[test]
+    fn rotation_keeps_shape() {
+        // the long side of the layer points up after a quarter turn
+        let m = layer_transform([0.0, 0.0], [1.0, 1.0], 90.0, 2.0);
+        assert_close(apply(&m, [0.0, 1.0]), [0.5, 0.0]);
+        assert_close(apply(&m, [-0.5, 0.0]), [0.0, 1.0]);
+    }
+
+    #[test]
+    fn params() {
+        let yaml = r#"
+            composite: camera
+            position: [{ slider: 2, range: [-1, 1] }, 0.25]
+            scale: 0.5
+            rotation: { osc: spin, range: [0, 360] }
+        "#;
+
+        let layer = CompositeLayer::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        assert_eq!(
+            layer.scale,
+            [LayerParam::Value(0.5), LayerParam::Value(0.5)]
+        );
+        assert!(layer.uses_osc("spin"));
+        assert!(!layer.uses_osc("camera"));
+
+        let mut osc = HashMap::new();
+        osc.insert("spin".to_owned(), OscUniformValue::Float(0.5));
+        let sliders = [0.0, 0.0, 0.75];
+        assert_eq!(layer.position[0].get(&sliders, &osc), 0.5);
+        assert_eq!(layer.rotation.get(&sliders, &osc), 180.0);
+        assert_eq!(layer.opacity.get(&sliders, &osc), 1.0);
+    }
+
+    #[test]
+    fn inverted_param() {
+        let mut osc = HashMap::new();
+        osc.insert("spin".to_owned(), OscUniformValue::Float(0.5));
+        let sliders = [0.0, 0.0, 0.75];
+
+        let param =
+            |yaml: &str| LayerParam::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        assert_eq!(
+            param("{ slider: 2, invert: true }").get(&sliders, &osc),
+            0.25
+        );
+
+        // inverted before the range, unset mappings read as zero first
+        let turn = param("{ slider: 2, range: [0, 360], invert: true }");
+        assert_eq!(turn.get(&sliders, &osc), 90.0);
+        let spin = param("{ osc: spin, range: [0, 360], invert: true }");
+        assert_eq!(spin.get(&sliders, &osc), 180.0);
+        let tilt = param("{ osc: tilt, range: [-1, 1], invert: true }");
+        assert_eq!(tilt.get(&sliders, &osc), 1.0);
+    }
+
+    #[test]
+    fn param_defaults() {
+        let param =
+            |yaml: &str| LayerParam::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        let spin = param("{ osc: spin, range: [0, 360], default: 90 }");
+
+        // the default is the value of the param, not of the mapping
+        let mut osc = HashMap::new();
+        assert_eq!(spin.get(&[], &osc), 90.0);
+
+        osc.insert("spin".to_owned(), OscUniformValue::Float(0.0));
+        assert_eq!(spin.get(&[], &osc), 0.0);
+    }
+
+    #[test]
+    fn param_errors() {
+        for yaml in [
+            "{ slider: 1, osc: a }",
+            "{ range: [0, 1] }",
+            "\"fast\"",
+            "{ osc: 3 }",
+            "{ slider: 1, invert: 1 }",
+            "{ slider: 1, default: 0.5 }",
+            "{ osc: a, default: high }",
+        ]
+        .iter()
+        {
+            let value = serde_yaml::from_str(yaml).unwrap();
+            assert!(LayerParam::from_yaml(&value).is_err(), "{}", yaml);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use super::{OscUniformValue, MIDI_N};
+
+/// Comparison operators of a stage condition, longest first so `>=` is not read as `>`.
+const COMPARISONS: [(&str, Comparison); 6] = [
+    (">=", Comparison::GreaterEqual),
+    ("<=", Comparison::LessEqual),
+    ("==", Comparison::Equal),
+    ("!=", Comparison::NotEqual),
+    (">", Comparison::Greater),
+    ("<", Comparison::Less),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+}
+
+/// One side of a stage condition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Value(f32),
+    /// A midi slider, from 0 to 1.
+    Slider(usize),
+    /// The velocity or pressure of a midi button, 0 while released.
+    Button(usize),
+    /// The value of an OSC mapping, mappings which have not been set read as zero.
+    Osc(String),
+}
+
+impl Operand {
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if let Ok(f) = text.parse::<f32>() {
+            return Ok(Self::Value(f));
+        }
+
+        for &(prefix, button) in [("sliders[", false), ("buttons[", true)].iter() {
+            let index = match text.strip_prefix(prefix).and_then(|s| s.strip_suffix(']')) {
+                Some(index) => index.trim(),
+                None => continue,
+            };
+
+            return match index.parse::<usize>() {
+                Ok(index) if index < MIDI_N && button => Ok(Self::Button(index)),
+                Ok(index) if index < MIDI_N => Ok(Self::Slider(index)),
+                _ => Err(format!(
+                    "Expected an index below {} in {:?}, got {:?}",
+                    MIDI_N, text, index
+                )),
+            };
+        }
+
+        let mut chars = text.chars();
+        let valid = chars
+            .next()
+            .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        match valid {
+            true => Ok(Self::Osc(text.to_owned())),
+            false => Err(format!(
+                "Expected a number, sliders[i], buttons[i] or the name of an OSC mapping, got {:?}",
+                text
+            )),
+        }
+    }
+
+    fn get(&self, sliders: &[f32], buttons: &[f32], osc: &HashMap<String, OscUniformValue>) -> f32 {
+        match self {
+            Self::Value(f) => *f,
+            Self::Slider(index) => sliders.get(*index).copied().unwrap_or(0.0),
+            Self::Button(index) => buttons.get(*index).copied().unwrap_or(0.0),
+            Self::Osc(name) => osc.get(name).map_or(0.0, OscUniformValue::as_f32),
+        }
+    }
+}
+
+/// Decides every frame whether a stage runs, like `when: bloom > 0`.
+///
+/// A condition without a comparison, like `when: bloom`, holds while the
+/// value is not zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageCondition {
+    pub left: Operand,
+    pub comparison: Comparison,
+    pub right: Operand,
+}
+
+impl StageCondition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let split = (0..text.len()).find_map(|k| {
+            let rest = text.get(k..)?;
+            COMPARISONS
+                .iter()
+                .find(|(op, _)| rest.starts_with(op))
+                .map(|&(op, comparison)| (k, op.len(), comparison))
+        });
+
+        let (left, comparison, right) = match split {
+            Some((k, len, comparison)) => (
+                Operand::parse(&text[..k])?,
+                comparison,
+                Operand::parse(&text[k + len..])?,
+            ),
+            None => (
+                Operand::parse(text)?,
+                Comparison::NotEqual,
+                Operand::Value(0.0),
+            ),
+        };
+
+        if let (Operand::Value(_), Operand::Value(_)) = (&left, &right) {
+            return Err(format!(
+                "Expected the condition to read a slider, button or OSC mapping, got {:?}",
+                text
+            ));
+        }
+
+        Ok(Self {
+            left,
+            comparison,
+            right,
+        })
+    }
+
+    /// Names of the OSC mappings the condition reads.
+    pub fn osc_names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(&self.left)
+            .chain(std::iter::once(&self.right))
+            .filter_map(|operand| match operand {
+                Operand::Osc(name) => Some(name.as_str()),
+                _ => None,
+            })
+    }
+
+    pub fn evaluate(
+        &self,
+        sliders: &[f32],
+        buttons: &[f32],
+        osc: &HashMap<String, OscUniformValue>,
+    ) -> bool {
+        let left = self.left.get(sliders, buttons, osc);
+        let right = self.right.get(sliders, buttons, osc);
+        match self.comparison {
+            Comparison::Greater => left > right,
+            Comparison::GreaterEqual => left >= right,
+            Comparison::Less => left < right,
+            Comparison::LessEqual => left <= right,
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_conditions() {
+        let condition = StageCondition::parse("bloom > 0").unwrap();
+        assert_eq!(condition.left, Operand::Osc("bloom".into()));
+        assert_eq!(condition.comparison, Comparison::Greater);
+        assert_eq!(condition.right, Operand::Value(0.0));
+
+        let condition = StageCondition::parse("0.5<=sliders[3]").unwrap();
+        assert_eq!(condition.comparison, Comparison::LessEqual);
+        assert_eq!(condition.right, Operand::Slider(3));
+
+        let condition = StageCondition::parse("buttons[1]").unwrap();
+        assert_eq!(condition.left, Operand::Button(1));
+        assert_eq!(condition.comparison, Comparison::NotEqual);
+
+        for text in [
+            "",
+            "bloom >",
+            "1 > 0",
+            "sliders[32] > 0",
+            "bloom strength > 0",
+            "a => b",
+        ] {
+            assert!(StageCondition::parse(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn evaluate_conditions() {
+        let mut osc = HashMap::new();
+        osc.insert("bloom".to_owned(), OscUniformValue::Float(0.25));
+        let sliders = [0.0, 0.75];
+        let buttons = [1.0];
+
+        let holds = |text: &str| {
+            let condition = StageCondition::parse(text).unwrap();
+            condition.evaluate(&sliders, &buttons, &osc)
+        };
+        assert!(holds("bloom > 0"));
+        assert!(!holds("bloom >= 0.5"));
+        assert!(holds("sliders[1] > bloom"));
+        assert!(holds("buttons[0]"));
+        assert!(!holds("sliders[0]"));
+
+        // unset mappings read as zero
+        assert!(holds("glow == 0"));
+    }
+}
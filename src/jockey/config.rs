@@ -1,55 +1,883 @@
 use anyhow::{format_err, Result};
+use std::{collections::HashMap, path::PathBuf};
+
 use serde_yaml::Value;
 
+use super::{PresetRequest, MIDI_N};
+
 #[derive(Debug, Default, Clone)]
 pub struct Config {
-    pub midi_devices: Vec<String>,
+    pub midi_devices: Vec<MidiDevice>,
     pub audio_device: Option<String>,
+    pub window: WindowConfig,
+    pub outputs: Vec<OutputConfig>,
+    pub crossfade: f32,
+    pub crossfade_slider: Option<usize>,
+    /// Sliders which read as `1 - x`, for faders mounted upside down.
+    pub inverted_sliders: Vec<usize>,
+    /// Values of sliders and buttons before their first MIDI message.
+    pub slider_defaults: Vec<(usize, f32)>,
+    pub button_defaults: Vec<(usize, f32)>,
+    pub state: StateConfig,
+    pub color: ColorConfig,
+    pub flash_limiter: FlashLimiterConfig,
+    pub shader_cache: Option<PathBuf>,
+    pub program_change: ProgramChangeConfig,
+    pub midi_expression: MidiExpressionConfig,
 }
 
-impl Config {
-    pub fn load_or_default() -> Self {
-        match Self::load() {
-            Ok(config) => config,
-            Err(err) => {
-                log::warn!("Failed to load config.yaml: {err}");
-                Default::default()
+/// How finished frames are handed to the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Waits for the display refresh, frames may queue up in the driver.
+    Fifo,
+    /// Waits for the display refresh and for every frame to be shown before
+    /// the next one starts, so no frames queue up.
+    LowLatency,
+    /// Presents right away, which may tear.
+    Immediate,
+}
+
+impl PresentMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Fifo => "fifo",
+            Self::LowLatency => "low_latency",
+            Self::Immediate => "immediate",
+        }
+    }
+}
+
+/// Placement, size and presentation settings of a window.
+#[derive(Debug, Clone)]
+pub struct WindowConfig {
+    pub monitor: Option<usize>,
+    pub fullscreen: bool,
+    pub resolution: Option<[u32; 2]>,
+    /// Whether the window waits for the display refresh, follows `present_mode`.
+    pub vsync: bool,
+    pub present_mode: PresentMode,
+    pub fps_limit: Option<f32>,
+    /// Frame time in milliseconds above which the overlay reports a hitch.
+    pub hitch_threshold: Option<f32>,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            monitor: None,
+            fullscreen: false,
+            resolution: None,
+            vsync: true,
+            present_mode: PresentMode::Fifo,
+            fps_limit: None,
+            hitch_threshold: None,
+        }
+    }
+}
+
+impl WindowConfig {
+    pub fn from_yaml(object: &Value) -> Result<Self> {
+        let monitor = match object.get("monitor") {
+            Some(Value::Number(n)) => match n.as_u64() {
+                Some(n) => Some(n as _),
+                None => {
+                    return Err(format_err!(
+                        "Expected window monitor to be a positive integer, got: {:?}",
+                        n
+                    ))
+                }
+            },
+            None => None,
+            s => {
+                return Err(format_err!(
+                    "Expected window monitor to be a number, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let fullscreen = match object.get("fullscreen") {
+            Some(Value::Bool(b)) => *b,
+            None => false,
+            s => {
+                return Err(format_err!(
+                    "Expected window fullscreen to be a bool, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let resolution = match object.get("resolution") {
+            Some(Value::Sequence(xs)) => match xs.as_slice() {
+                [w, h] => match (w.as_u64(), h.as_u64()) {
+                    (Some(w), Some(h)) if w > 0 && h > 0 => Some([w as _, h as _]),
+                    _ => {
+                        return Err(format_err!(
+                            "Expected window resolution to be two positive integers, got: {:?}",
+                            xs
+                        ))
+                    }
+                },
+                _ => {
+                    return Err(format_err!(
+                        "Expected window resolution to be a list of two numbers, got: {:?}",
+                        xs
+                    ))
+                }
+            },
+            None => None,
+            s => {
+                return Err(format_err!(
+                    "Expected window resolution to be a list, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let vsync = match object.get("vsync") {
+            Some(Value::Bool(b)) => Some(*b),
+            None => None,
+            s => {
+                return Err(format_err!(
+                    "Expected window vsync to be a bool, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let present_mode = match object.get("present_mode").map(Value::as_str) {
+            Some(Some("fifo")) => PresentMode::Fifo,
+            Some(Some("low_latency")) => PresentMode::LowLatency,
+            Some(Some("immediate")) => PresentMode::Immediate,
+            None if vsync == Some(false) => PresentMode::Immediate,
+            None => PresentMode::Fifo,
+            Some(Some("mailbox")) => {
+                return Err(format_err!(
+                    "OpenGL has no mailbox present_mode, \
+                     use low_latency to keep frames from queuing up"
+                ))
+            }
+            Some(s) => {
+                return Err(format_err!(
+                    "Expected window present_mode to be fifo, low_latency or immediate, got: {:?}",
+                    s
+                ))
             }
+        };
+
+        // an explicit vsync has to agree with the present mode
+        let waits = present_mode != PresentMode::Immediate;
+        if vsync.map_or(false, |vsync| vsync != waits) {
+            return Err(format_err!(
+                "Expected window vsync to be {} for present_mode {}",
+                waits,
+                present_mode.name()
+            ));
         }
+
+        let fps_limit = match object.get("fps_limit") {
+            Some(Value::Number(n)) => match n.as_f64() {
+                Some(n) if n > 0.0 => Some(n as _),
+                _ => {
+                    return Err(format_err!(
+                        "Expected window fps_limit to be a positive number, got: {:?}",
+                        n
+                    ))
+                }
+            },
+            None => None,
+            s => {
+                return Err(format_err!(
+                    "Expected window fps_limit to be a number, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let hitch_threshold = match object.get("hitch_threshold") {
+            Some(Value::Number(n)) => match n.as_f64() {
+                Some(n) if n > 0.0 => Some(n as _),
+                _ => {
+                    return Err(format_err!(
+                        "Expected window hitch_threshold to be a positive number, got: {:?}",
+                        n
+                    ))
+                }
+            },
+            None => None,
+            s => {
+                return Err(format_err!(
+                    "Expected window hitch_threshold to be a number, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        Ok(Self {
+            monitor,
+            fullscreen,
+            resolution,
+            vsync: waits,
+            present_mode,
+            fps_limit,
+            hitch_threshold,
+        })
     }
 
-    pub fn load() -> Result<Self> {
-        let mut file_path = std::env::current_dir()?;
-        file_path.push("config.yaml");
+    /// Looks up the configured monitor, falling back to the primary monitor.
+    pub fn find_monitor<T>(
+        &self,
+        events_loop: &glutin::event_loop::EventLoop<T>,
+    ) -> Option<glutin::monitor::MonitorHandle> {
+        match self.monitor {
+            Some(index) => {
+                let monitor = events_loop.available_monitors().nth(index);
+                if monitor.is_none() {
+                    log::warn!(
+                        "Monitor {} does not exist, using primary monitor instead",
+                        index
+                    );
+                }
+                monitor.or_else(|| events_loop.primary_monitor())
+            }
+            None => events_loop.primary_monitor(),
+        }
+    }
 
-        let reader = std::fs::File::open(file_path)?;
-        let object: Value = serde_yaml::from_reader(reader)?;
+    /// Applies size and fullscreen mode to a window builder.
+    ///
+    /// The builder can not set a position, see [`WindowConfig::place`].
+    pub fn apply<T>(
+        &self,
+        mut builder: glutin::window::WindowBuilder,
+        events_loop: &glutin::event_loop::EventLoop<T>,
+    ) -> glutin::window::WindowBuilder {
+        if let Some([width, height]) = self.resolution {
+            let size = glutin::dpi::PhysicalSize::new(width, height);
+            builder = builder.with_inner_size(size);
+        }
 
-        let mut midi_devices = Vec::new();
+        if self.fullscreen {
+            let monitor = self.find_monitor(events_loop);
+            let handle = Some(glutin::window::Fullscreen::Borderless(monitor));
+            builder = builder.with_fullscreen(handle);
+        }
 
-        match object.get("midi_devices") {
-            Some(Value::Sequence(xs)) => {
-                for val in xs {
-                    match val.as_str() {
-                        Some(s) => midi_devices.push(s.to_owned()),
-                        None => {
+        builder
+    }
+
+    /// Moves a created window onto the configured monitor, unless it is fullscreen.
+    pub fn place(&self, window: &glutin::window::Window) {
+        if self.fullscreen {
+            return;
+        }
+
+        let monitor = self
+            .monitor
+            .and_then(|index| window.available_monitors().nth(index));
+        if let Some(monitor) = monitor {
+            window.set_outer_position(monitor.position());
+        }
+    }
+}
+
+/// Controls whether slider and OSC values are kept between sessions.
+#[derive(Debug, Default, Clone)]
+pub struct StateConfig {
+    pub persist: bool,
+    pub path: Option<PathBuf>,
+}
+
+impl StateConfig {
+    pub fn from_yaml(object: &Value) -> Result<Self> {
+        let persist = match object.get("persist") {
+            Some(Value::Bool(b)) => *b,
+            None => false,
+            s => {
+                return Err(format_err!(
+                    "Expected state persist to be a bool, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let path = match object.get("path") {
+            Some(Value::String(s)) => Some(PathBuf::from(s)),
+            None => None,
+            s => {
+                return Err(format_err!(
+                    "Expected state path to be a string, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        Ok(Self { persist, path })
+    }
+}
+
+/// Tone mapping curve which brings HDR values into the displayable range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemap {
+    None,
+    Reinhard,
+    Aces,
+}
+
+impl Default for Tonemap {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+/// Noise added to the final frame to hide banding of the 8 bit output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    None,
+    /// 8x8 Bayer matrix, a regular pattern.
+    Ordered,
+    /// Interleaved gradient noise, which looks less regular.
+    Noise,
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+/// Color handling of the final frame before it is presented.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorConfig {
+    pub srgb: bool,
+    pub tonemap: Tonemap,
+    /// `.cube` file used for color grading.
+    pub lut: Option<PathBuf>,
+    /// How much of the graded color is mixed in, between 0 and 1.
+    pub lut_strength: f32,
+    pub dither: Dither,
+    /// Whether the frame sent to Spout is dithered as well.
+    pub dither_spout: bool,
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        Self {
+            srgb: false,
+            tonemap: Tonemap::None,
+            lut: None,
+            lut_strength: 1.0,
+            dither: Dither::None,
+            dither_spout: true,
+        }
+    }
+}
+
+impl ColorConfig {
+    pub fn from_yaml(object: &Value) -> Result<Self> {
+        let srgb = match object.get("srgb") {
+            Some(Value::Bool(b)) => *b,
+            None => false,
+            s => {
+                return Err(format_err!(
+                    "Expected color srgb to be a bool, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let tonemap = match object.get("tonemap").map(Value::as_str) {
+            Some(Some("none")) | None => Tonemap::None,
+            Some(Some("reinhard")) => Tonemap::Reinhard,
+            Some(Some("aces")) => Tonemap::Aces,
+            _ => {
+                return Err(format_err!(
+                    "Expected color tonemap to be one of none, reinhard or aces, got: {:?}",
+                    object.get("tonemap")
+                ))
+            }
+        };
+
+        let lut = match object.get("lut") {
+            Some(Value::String(s)) => Some(PathBuf::from(s)),
+            None => None,
+            s => {
+                return Err(format_err!(
+                    "Expected color lut to be a string, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let lut_strength = match object.get("lut_strength").map(Value::as_f64) {
+            Some(Some(f)) if (0.0..=1.0).contains(&f) => f as f32,
+            None => 1.0,
+            _ => {
+                return Err(format_err!(
+                    "Expected color lut_strength to be a number between 0 and 1, got: {:?}",
+                    object.get("lut_strength")
+                ))
+            }
+        };
+
+        let dither = match object.get("dither").map(Value::as_str) {
+            Some(Some("none")) | None => Dither::None,
+            Some(Some("ordered")) => Dither::Ordered,
+            Some(Some("noise")) => Dither::Noise,
+            _ => {
+                return Err(format_err!(
+                    "Expected color dither to be one of none, ordered or noise, got: {:?}",
+                    object.get("dither")
+                ))
+            }
+        };
+
+        let dither_spout = match object.get("dither_spout") {
+            Some(Value::Bool(b)) => *b,
+            None => true,
+            s => {
+                return Err(format_err!(
+                    "Expected color dither_spout to be a bool, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        Ok(Self {
+            srgb,
+            tonemap,
+            lut,
+            lut_strength,
+            dither,
+            dither_spout,
+        })
+    }
+
+    /// Whether frames can go to the screen untouched.
+    pub fn is_passthrough(&self) -> bool {
+        !self.srgb
+            && self.tonemap == Tonemap::None
+            && self.lut.is_none()
+            && self.dither == Dither::None
+    }
+}
+
+/// Default for the fastest allowed change of the average luminance, per second.
+pub const FLASH_LIMITER_THRESHOLD: f32 = 4.0;
+
+/// Settings of the limiter which slows down full screen flashes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlashLimiterConfig {
+    pub enabled: bool,
+    /// Fastest allowed change of the average luminance, per second.
+    pub threshold: f32,
+}
+
+impl Default for FlashLimiterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: FLASH_LIMITER_THRESHOLD,
+        }
+    }
+}
+
+impl FlashLimiterConfig {
+    pub fn from_yaml(object: &Value) -> Result<Self> {
+        let enabled = match object.get("enabled") {
+            Some(Value::Bool(b)) => *b,
+            None => false,
+            s => {
+                return Err(format_err!(
+                    "Expected flash_limiter enabled to be a bool, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let threshold = match object.get("threshold") {
+            Some(s) => match s.as_f64() {
+                Some(x) if x > 0.0 => x as f32,
+                _ => {
+                    return Err(format_err!(
+                        "Expected flash_limiter threshold to be a positive number, got: {:?}",
+                        s
+                    ))
+                }
+            },
+            None => FLASH_LIMITER_THRESHOLD,
+        };
+
+        Ok(Self { enabled, threshold })
+    }
+}
+
+/// Maps MIDI program change messages to pipeline presets.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProgramChangeConfig {
+    /// Only messages on this channel are used, counting from 0.
+    pub channel: Option<u8>,
+    /// Preset of every program number. If empty, program `n` selects preset `n`.
+    pub presets: HashMap<u8, PresetRequest>,
+    /// Audio profile of program numbers, these do not switch presets.
+    pub audio_profiles: HashMap<u8, String>,
+}
+
+impl ProgramChangeConfig {
+    pub fn from_yaml(object: &Value) -> Result<Self> {
+        let channel = match object.get("channel") {
+            Some(Value::Number(n)) => match n.as_u64() {
+                Some(n) if (1..=16).contains(&n) => Some(n as u8 - 1),
+                _ => {
+                    return Err(format_err!(
+                        "Expected program_change channel to be between 1 and 16, got: {:?}",
+                        n
+                    ))
+                }
+            },
+            None => None,
+            s => {
+                return Err(format_err!(
+                    "Expected program_change channel to be a number, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let mut presets = HashMap::new();
+        match object.get("presets") {
+            Some(Value::Mapping(map)) => {
+                for (program, preset) in map {
+                    let program = match program.as_u64() {
+                        Some(n) if n < 128 => n as u8,
+                        _ => {
                             return Err(format_err!(
-                                "Expected midi_device name {:?} to be a string",
-                                val
-                            ));
+                                "Expected program number to be between 0 and 127, got: {:?}",
+                                program
+                            ))
                         }
-                    }
+                    };
+
+                    let preset = match preset {
+                        Value::Number(n) => match n.as_u64() {
+                            Some(n) => PresetRequest::Index(n as _),
+                            None => {
+                                return Err(format_err!(
+                                    "Expected preset index to be a positive integer, got: {:?}",
+                                    n
+                                ))
+                            }
+                        },
+                        Value::String(s) => PresetRequest::Name(s.clone()),
+                        s => {
+                            return Err(format_err!(
+                                "Expected preset to be an index or a file name, got: {:?}",
+                                s
+                            ))
+                        }
+                    };
+
+                    presets.insert(program, preset);
                 }
             }
             None => {}
+            Some(s) => {
+                return Err(format_err!(
+                    "Expected program_change presets to be a mapping, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let mut audio_profiles = HashMap::new();
+        match object.get("audio_profiles") {
+            Some(Value::Mapping(map)) => {
+                for (program, profile) in map {
+                    let program = match program.as_u64() {
+                        Some(n) if n < 128 => n as u8,
+                        _ => {
+                            return Err(format_err!(
+                                "Expected program number to be between 0 and 127, got: {:?}",
+                                program
+                            ))
+                        }
+                    };
+
+                    let profile = match profile {
+                        Value::String(s) => s.clone(),
+                        s => {
+                            return Err(format_err!(
+                                "Expected audio profile to be a name, got: {:?}",
+                                s
+                            ))
+                        }
+                    };
+
+                    audio_profiles.insert(program, profile);
+                }
+            }
+            None => {}
+            Some(s) => {
+                return Err(format_err!(
+                    "Expected program_change audio_profiles to be a mapping, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        Ok(Self {
+            channel,
+            presets,
+            audio_profiles,
+        })
+    }
+
+    /// Returns the audio profile a program change message selects, if any.
+    pub fn resolve_audio_profile(&self, channel: u8, program: u8) -> Option<&str> {
+        if self.channel.map_or(false, |c| c != channel) {
+            return None;
+        }
+
+        self.audio_profiles.get(&program).map(String::as_str)
+    }
+
+    /// Returns the preset a program change message selects, if any.
+    pub fn resolve(&self, channel: u8, program: u8) -> Option<PresetRequest> {
+        if self.channel.map_or(false, |c| c != channel) {
+            return None;
+        }
+
+        if self.audio_profiles.contains_key(&program) {
+            return None;
+        }
+
+        match self.presets.is_empty() {
+            true => Some(PresetRequest::Index(program as _)),
+            false => self.presets.get(&program).cloned(),
+        }
+    }
+}
+
+/// Width of the pitch bend dead zone around the center of the wheel.
+pub const PITCH_BEND_DEADZONE: f32 = 0.02;
+
+/// Maps MIDI pitch bend and aftertouch messages to uniforms and sliders.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiExpressionConfig {
+    /// Only messages on this channel are used, counting from 0.
+    pub channel: Option<u8>,
+    /// Pitch bend values closer than this to the center count as centered.
+    pub pitch_bend_deadzone: f32,
+    /// Slider which follows the pitch bend, with the center at 0.5.
+    pub pitch_bend_slider: Option<usize>,
+    /// Slider which follows the aftertouch pressure.
+    pub pressure_slider: Option<usize>,
+}
+
+impl Default for MidiExpressionConfig {
+    fn default() -> Self {
+        Self {
+            channel: None,
+            pitch_bend_deadzone: PITCH_BEND_DEADZONE,
+            pitch_bend_slider: None,
+            pressure_slider: None,
+        }
+    }
+}
+
+impl MidiExpressionConfig {
+    pub fn from_yaml(object: &Value) -> Result<Self> {
+        let channel = match object.get("channel") {
+            Some(Value::Number(n)) => match n.as_u64() {
+                Some(n) if (1..=16).contains(&n) => Some(n as u8 - 1),
+                _ => {
+                    return Err(format_err!(
+                        "Expected midi_expression channel to be between 1 and 16, got: {:?}",
+                        n
+                    ))
+                }
+            },
+            None => None,
+            s => {
+                return Err(format_err!(
+                    "Expected midi_expression channel to be a number, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let pitch_bend_deadzone = match object.get("pitch_bend_deadzone") {
+            Some(Value::Number(n)) => match n.as_f64() {
+                Some(n) if (0.0..1.0).contains(&n) => n as _,
+                _ => {
+                    return Err(format_err!(
+                        "Expected pitch_bend_deadzone to be at least 0 and below 1, got: {:?}",
+                        n
+                    ))
+                }
+            },
+            None => PITCH_BEND_DEADZONE,
             s => {
                 return Err(format_err!(
-                    "Expected midi_devices to be a list of strings, got: {:?}",
+                    "Expected pitch_bend_deadzone to be a number, got: {:?}",
                     s
                 ))
             }
         };
 
+        let slider = |name: &str| match object.get(name) {
+            Some(Value::Number(n)) => match n.as_u64() {
+                Some(n) if (n as usize) < MIDI_N => Ok(Some(n as _)),
+                _ => Err(format_err!(
+                    "Expected {} to be a slider index below {}, got: {:?}",
+                    name,
+                    MIDI_N,
+                    n
+                )),
+            },
+            None => Ok(None),
+            Some(s) => Err(format_err!(
+                "Expected {} to be a number, got: {:?}",
+                name,
+                s
+            )),
+        };
+
+        Ok(Self {
+            channel,
+            pitch_bend_deadzone,
+            pitch_bend_slider: slider("pitch_bend_slider")?,
+            pressure_slider: slider("pressure_slider")?,
+        })
+    }
+
+    /// Whether messages on this channel are used.
+    pub fn accepts(&self, channel: u8) -> bool {
+        self.channel.map_or(true, |c| c == channel)
+    }
+}
+
+/// Largest channel offset of a MIDI port, so that offset channels still fit into a byte.
+pub const MIDI_MAX_CHANNEL_OFFSET: u8 = 240;
+
+/// MIDI input ports to connect to, an entry of `midi_devices`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiDevice {
+    /// Part of the port name to look for, `None` matches every port.
+    pub name: Option<String>,
+    /// Added to the channel of every message from the port, so that devices
+    /// which send the same notes and controls can be bound separately.
+    pub channel_offset: u8,
+}
+
+impl MidiDevice {
+    pub fn from_yaml(object: &Value) -> Result<Self> {
+        let name = match object.get("name").unwrap_or(object) {
+            Value::String(s) if s == "all" => None,
+            Value::String(s) => Some(s.clone()),
+            s => {
+                return Err(format_err!(
+                    "Expected midi_device name {:?} to be a string",
+                    s
+                ))
+            }
+        };
+
+        let channel_offset = match object.get("channel_offset") {
+            Some(Value::Number(n)) => match n.as_u64() {
+                Some(n) if n <= MIDI_MAX_CHANNEL_OFFSET as u64 => n as _,
+                _ => {
+                    return Err(format_err!(
+                        "Expected channel_offset to be a number from 0 to {}, got: {:?}",
+                        MIDI_MAX_CHANNEL_OFFSET,
+                        n
+                    ))
+                }
+            },
+            None => 0,
+            s => {
+                return Err(format_err!(
+                    "Expected channel_offset to be a number, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        Ok(Self {
+            name,
+            channel_offset,
+        })
+    }
+
+    /// Parses the `midi_devices` field, which is either `all` or a list of devices.
+    ///
+    /// An empty list selects every port.
+    pub fn from_yaml_list(object: Option<&Value>) -> Result<Vec<Self>> {
+        match object {
+            Some(Value::Sequence(xs)) => xs.iter().map(Self::from_yaml).collect(),
+            Some(Value::String(s)) if s == "all" => Ok(Vec::new()),
+            None => Ok(Vec::new()),
+            s => Err(format_err!(
+                "Expected midi_devices to be \"all\" or a list of devices, got: {:?}",
+                s
+            )),
+        }
+    }
+
+    /// Whether the port with this name belongs to the device.
+    pub fn matches(&self, port_name: &str) -> bool {
+        self.name
+            .as_ref()
+            .map_or(true, |name| port_name.contains(name.as_str()))
+    }
+}
+
+/// Describes an additional output window showing a single texture.
+#[derive(Debug, Clone)]
+pub struct OutputConfig {
+    pub texture: String,
+    pub window: WindowConfig,
+}
+
+impl OutputConfig {
+    pub fn from_yaml(object: &Value) -> Result<Self> {
+        let texture = match object.get("texture").or_else(|| object.get("target")) {
+            Some(Value::String(s)) => s.clone(),
+            s => {
+                return Err(format_err!(
+                    "Expected output texture to be a string, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let window = WindowConfig::from_yaml(object)?;
+
+        Ok(Self { texture, window })
+    }
+}
+
+impl Config {
+    pub fn load_or_default() -> Self {
+        match Self::load() {
+            Ok(config) => config,
+            Err(err) => {
+                log::warn!("Failed to load config.yaml: {err}");
+                Default::default()
+            }
+        }
+    }
+
+    pub fn load() -> Result<Self> {
+        let mut file_path = std::env::current_dir()?;
+        file_path.push("config.yaml");
+
+        let reader = std::fs::File::open(file_path)?;
+        let object: Value = serde_yaml::from_reader(reader)?;
+
+        let midi_devices = MidiDevice::from_yaml_list(object.get("midi_devices"))?;
+
         let audio_device = match object.get("audio_device") {
             Some(Value::String(s)) => Some(s.clone()),
             None => None,
@@ -85,9 +913,336 @@ impl Config {
             }
         };
 
+        let window = match object.get("window") {
+            Some(object) if object.is_mapping() => WindowConfig::from_yaml(object)?,
+            None => WindowConfig::default(),
+            Some(s) => return Err(format_err!("Expected window to be a mapping, got: {:?}", s)),
+        };
+
+        let mut outputs = Vec::new();
+        match object.get("outputs") {
+            Some(Value::Sequence(xs)) => {
+                for val in xs {
+                    outputs.push(OutputConfig::from_yaml(val)?);
+                }
+            }
+            None => {}
+            Some(s) => {
+                return Err(format_err!(
+                    "Expected outputs to be a list of output windows, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let crossfade = match object.get("crossfade") {
+            Some(Value::Number(n)) => match n.as_f64() {
+                Some(n) if n >= 0.0 => n as _,
+                _ => {
+                    return Err(format_err!(
+                        "Expected crossfade to be a non-negative number, got: {:?}",
+                        n
+                    ))
+                }
+            },
+            None => 0.0,
+            Some(s) => {
+                return Err(format_err!(
+                    "Expected crossfade to be a number of seconds, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let crossfade_slider = match object.get("crossfade_slider") {
+            Some(Value::Number(n)) => match n.as_u64() {
+                Some(n) if (n as usize) < MIDI_N => Some(n as _),
+                _ => {
+                    return Err(format_err!(
+                        "Expected crossfade_slider to be a slider index below {}, got: {:?}",
+                        MIDI_N,
+                        n
+                    ))
+                }
+            },
+            None => None,
+            Some(s) => {
+                return Err(format_err!(
+                    "Expected crossfade_slider to be a number, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let inverted_sliders = parse_inverted_sliders(object.get("inverted_sliders"))?;
+        let defaults = |name: &str| parse_midi_defaults(name, object.get(name));
+        let slider_defaults = defaults("slider_defaults")?;
+        let button_defaults = defaults("button_defaults")?;
+
+        let state = match object.get("state") {
+            Some(object) if object.is_mapping() => StateConfig::from_yaml(object)?,
+            None => StateConfig::default(),
+            Some(s) => return Err(format_err!("Expected state to be a mapping, got: {:?}", s)),
+        };
+
+        let color = match object.get("color") {
+            Some(object) if object.is_mapping() => ColorConfig::from_yaml(object)?,
+            None => ColorConfig::default(),
+            Some(s) => return Err(format_err!("Expected color to be a mapping, got: {:?}", s)),
+        };
+
+        let flash_limiter = match object.get("flash_limiter") {
+            Some(object) if object.is_mapping() => FlashLimiterConfig::from_yaml(object)?,
+            None => FlashLimiterConfig::default(),
+            Some(s) => {
+                return Err(format_err!(
+                    "Expected flash_limiter to be a mapping, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let shader_cache = match object.get("shader_cache") {
+            Some(Value::String(s)) => Some(PathBuf::from(s)),
+            None => None,
+            s => {
+                return Err(format_err!(
+                    "Expected shader_cache to be a string, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let program_change = match object.get("program_change") {
+            Some(object) if object.is_mapping() => ProgramChangeConfig::from_yaml(object)?,
+            None => ProgramChangeConfig::default(),
+            Some(s) => {
+                return Err(format_err!(
+                    "Expected program_change to be a mapping, got: {:?}",
+                    s
+                ))
+            }
+        };
+
+        let midi_expression = match object.get("midi_expression") {
+            Some(object) if object.is_mapping() => MidiExpressionConfig::from_yaml(object)?,
+            None => MidiExpressionConfig::default(),
+            Some(s) => {
+                return Err(format_err!(
+                    "Expected midi_expression to be a mapping, got: {:?}",
+                    s
+                ))
+            }
+        };
+
         Ok(Self {
             midi_devices,
             audio_device,
+            window,
+            outputs,
+            crossfade,
+            crossfade_slider,
+            inverted_sliders,
+            slider_defaults,
+            button_defaults,
+            state,
+            color,
+            flash_limiter,
+            shader_cache,
+            program_change,
+            midi_expression,
         })
     }
 }
+
+/// Parses the indices of sliders which read as `1 - x`, none if missing.
+fn parse_inverted_sliders(object: Option<&Value>) -> Result<Vec<usize>> {
+    match object {
+        Some(Value::Sequence(s)) => s
+            .iter()
+            .map(|v| match v.as_u64() {
+                Some(n) if (n as usize) < MIDI_N => Ok(n as usize),
+                _ => Err(format_err!(
+                    "Expected inverted_sliders to be slider indices below {}, got: {:?}",
+                    MIDI_N,
+                    v
+                )),
+            })
+            .collect(),
+        None => Ok(Vec::new()),
+        Some(s) => Err(format_err!(
+            "Expected inverted_sliders to be a list of slider indices, got: {:?}",
+            s
+        )),
+    }
+}
+
+/// Parses a mapping from slider or button indices to their values at startup.
+fn parse_midi_defaults(name: &str, object: Option<&Value>) -> Result<Vec<(usize, f32)>> {
+    match object {
+        Some(Value::Mapping(m)) => m
+            .iter()
+            .map(|(k, v)| match (k.as_u64(), v.as_f64()) {
+                (Some(n), Some(x)) if (n as usize) < MIDI_N => Ok((n as usize, x as f32)),
+                _ => Err(format_err!(
+                    "Expected {} to map indices below {} to numbers, got: {:?}: {:?}",
+                    name,
+                    MIDI_N,
+                    k,
+                    v
+                )),
+            })
+            .collect(),
+        None => Ok(Vec::new()),
+        Some(s) => Err(format_err!(
+            "Expected {} to be a mapping from indices to numbers, got: {:?}",
+            name,
+            s
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn program_change(yaml: &str) -> ProgramChangeConfig {
+        ProgramChangeConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn program_change_presets() {
+        let config = program_change("channel: 2\npresets: { 0: 3, 5: tunnel }");
+        assert_eq!(config.resolve(1, 0), Some(PresetRequest::Index(3)));
+        assert_eq!(
+            config.resolve(1, 5),
+            Some(PresetRequest::Name("tunnel".into()))
+        );
+        assert_eq!(config.resolve(1, 1), None);
+        assert_eq!(config.resolve(0, 0), None);
+
+        // without presets, program numbers are preset indices on any channel
+        let config = program_change("{}");
+        assert_eq!(config.resolve(9, 4), Some(PresetRequest::Index(4)));
+
+        // programs of audio profiles do not switch presets
+        let config = program_change("audio_profiles: { 4: calm }");
+        assert_eq!(config.resolve(9, 4), None);
+        assert_eq!(config.resolve_audio_profile(9, 4), Some("calm"));
+        assert_eq!(config.resolve_audio_profile(9, 5), None);
+    }
+
+    #[test]
+    fn midi_expression() {
+        let parse =
+            |yaml: &str| MidiExpressionConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap());
+
+        let config = parse("channel: 3\npitch_bend_slider: 4").unwrap();
+        assert_eq!(config.channel, Some(2));
+        assert_eq!(config.pitch_bend_deadzone, PITCH_BEND_DEADZONE);
+        assert_eq!(config.pitch_bend_slider, Some(4));
+        assert_eq!(config.pressure_slider, None);
+        assert!(config.accepts(2));
+        assert!(!config.accepts(0));
+        assert!(parse("{}").unwrap().accepts(9));
+
+        assert!(parse("channel: 0").is_err());
+        assert!(parse("pitch_bend_deadzone: 1").is_err());
+        assert!(parse("pressure_slider: 32").is_err());
+    }
+
+    #[test]
+    fn inverted_sliders() {
+        let parse = |yaml: &str| parse_inverted_sliders(Some(&serde_yaml::from_str(yaml).unwrap()));
+
+        assert_eq!(parse("[0, 7]").unwrap(), vec![0, 7]);
+        assert_eq!(parse_inverted_sliders(None).unwrap(), Vec::<usize>::new());
+        assert!(parse("3").is_err());
+        assert!(parse("[32]").is_err());
+        assert!(parse("[fader]").is_err());
+    }
+
+    #[test]
+    fn midi_defaults() {
+        let parse = |yaml: &str| {
+            parse_midi_defaults(
+                "slider_defaults",
+                Some(&serde_yaml::from_str(yaml).unwrap()),
+            )
+        };
+
+        assert_eq!(parse("{ 2: 0.5, 7: 1 }").unwrap(), vec![(2, 0.5), (7, 1.0)]);
+        assert_eq!(
+            parse_midi_defaults("button_defaults", None).unwrap(),
+            vec![]
+        );
+        assert!(parse("[0.5]").is_err());
+        assert!(parse("{ 32: 0.5 }").is_err());
+        assert!(parse("{ 1: on }").is_err());
+    }
+
+    #[test]
+    fn midi_devices() {
+        let parse =
+            |yaml: &str| MidiDevice::from_yaml_list(Some(&serde_yaml::from_str(yaml).unwrap()));
+
+        assert_eq!(parse("all").unwrap(), Vec::new());
+        let devices = parse("[nanoKONTROL, {name: APC, channel_offset: 16}, all]").unwrap();
+        assert_eq!(devices[0].name.as_deref(), Some("nanoKONTROL"));
+        assert_eq!(devices[0].channel_offset, 0);
+        assert_eq!(devices[1].name.as_deref(), Some("APC"));
+        assert_eq!(devices[1].channel_offset, 16);
+        assert_eq!(devices[2].name, None);
+        assert!(devices[1].matches("APC mini 1"));
+        assert!(!devices[1].matches("nanoKONTROL2"));
+        assert!(devices[2].matches("anything"));
+
+        assert!(parse("some").is_err());
+        assert!(parse("[{name: APC, channel_offset: 241}]").is_err());
+        assert!(parse("[{channel_offset: 16}]").is_err());
+    }
+
+    #[test]
+    fn present_mode() {
+        let parse = |yaml: &str| WindowConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap());
+
+        let config = parse("{}").unwrap();
+        assert_eq!(config.present_mode, PresentMode::Fifo);
+        assert!(config.vsync);
+
+        let config = parse("vsync: false").unwrap();
+        assert_eq!(config.present_mode, PresentMode::Immediate);
+        assert!(!config.vsync);
+
+        let config = parse("present_mode: low_latency\nvsync: true").unwrap();
+        assert_eq!(config.present_mode, PresentMode::LowLatency);
+        assert!(config.vsync);
+
+        assert!(parse("present_mode: mailbox").is_err());
+        assert!(parse("present_mode: immediate\nvsync: true").is_err());
+        assert!(parse("present_mode: relaxed").is_err());
+    }
+
+    #[test]
+    fn color_lut() {
+        let parse = |yaml: &str| ColorConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap());
+
+        let config = parse("lut: grade.cube").unwrap();
+        assert_eq!(config.lut, Some(PathBuf::from("grade.cube")));
+        assert_eq!(config.lut_strength, 1.0);
+        assert!(!config.is_passthrough());
+
+        let config = parse("lut: grade.cube\nlut_strength: 0.5").unwrap();
+        assert_eq!(config.lut_strength, 0.5);
+
+        assert!(parse("lut: 3").is_err());
+        assert!(parse("lut_strength: 2").is_err());
+
+        let config = parse("dither: ordered\ndither_spout: false").unwrap();
+        assert_eq!(config.dither, Dither::Ordered);
+        assert!(!config.dither_spout);
+        assert!(!config.is_passthrough());
+        assert!(parse("dither: blue").is_err());
+        assert!(parse("{}").unwrap().is_passthrough());
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt;
+
+use cpal::traits::{DeviceTrait, HostTrait};
+
+use super::{find_input_device, input_device_names, midi_device_offset, midi_port_names, Config};
+
+/// A device or port, and whether the config in the current folder selects it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceEntry {
+    pub name: String,
+    pub selected: bool,
+}
+
+/// Everything `list_devices` found, for choosing names in the config file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceReport {
+    pub audio_inputs: Vec<DeviceEntry>,
+    pub midi_inputs: Vec<DeviceEntry>,
+    pub midi_outputs: Vec<DeviceEntry>,
+    pub monitors: Vec<DeviceEntry>,
+    /// Sections which could not be enumerated, with the reason.
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for DeviceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            ("Audio input devices (audio_device)", &self.audio_inputs),
+            ("MIDI input ports (midi_devices)", &self.midi_inputs),
+            ("MIDI output ports", &self.midi_outputs),
+            ("Monitors (window.monitor)", &self.monitors),
+        ];
+
+        for (title, entries) in sections.iter() {
+            writeln!(f, "{}:", title)?;
+            if entries.is_empty() {
+                writeln!(f, "  none")?;
+            }
+            for (k, entry) in entries.iter().enumerate() {
+                let mark = if entry.selected { " *" } else { "" };
+                writeln!(f, "  {}: {}{}", k, entry.name, mark)?;
+            }
+        }
+
+        for err in &self.errors {
+            writeln!(f, "Error: {}", err)?;
+        }
+
+        writeln!(f, "* used with the config in the current folder")
+    }
+}
+
+/// Describes a monitor by its name and size, as in the `window.monitor` list.
+pub fn describe_monitor(monitor: &glutin::monitor::MonitorHandle) -> String {
+    let size = monitor.size();
+    format!(
+        "{} ({} x {})",
+        monitor.name().unwrap_or_else(|| "<no-name>".into()),
+        size.width,
+        size.height
+    )
+}
+
+/// Enumerates audio inputs, MIDI ports and monitors without starting anything.
+///
+/// Devices are matched against the config in the current folder with the
+/// same rules the tool uses on start, so the report also shows which ones
+/// would be used.
+pub fn list_devices() -> DeviceReport {
+    let config = Config::load_or_default();
+    let mut report = DeviceReport::default();
+
+    let host = cpal::default_host();
+    match input_device_names(&host) {
+        Ok(names) => {
+            let selected = match &config.audio_device {
+                Some(s) => find_input_device(&names, s),
+                None => {
+                    let default = host.default_input_device().and_then(|dev| dev.name().ok());
+                    default.and_then(|default| names.iter().position(|name| *name == default))
+                }
+            };
+
+            report.audio_inputs = names
+                .into_iter()
+                .enumerate()
+                .map(|(k, name)| DeviceEntry {
+                    name,
+                    selected: selected == Some(k),
+                })
+                .collect();
+        }
+        Err(err) => report
+            .errors
+            .push(format!("Failed to list audio devices: {}", err)),
+    }
+
+    match midi_port_names() {
+        Ok((inputs, outputs)) => {
+            report.midi_inputs = inputs
+                .into_iter()
+                .map(|name| DeviceEntry {
+                    selected: midi_device_offset(&config.midi_devices, &name).is_some(),
+                    name,
+                })
+                .collect();
+            report.midi_outputs = outputs
+                .into_iter()
+                .map(|name| DeviceEntry {
+                    name,
+                    selected: false,
+                })
+                .collect();
+        }
+        Err(err) => report
+            .errors
+            .push(format!("Failed to list MIDI ports: {}", err)),
+    }
+
+    let events_loop = glutin::event_loop::EventLoop::new();
+    let selected = config.window.find_monitor(&events_loop);
+    report.monitors = events_loop
+        .available_monitors()
+        .map(|monitor| DeviceEntry {
+            name: describe_monitor(&monitor),
+            selected: selected.as_ref() == Some(&monitor),
+        })
+        .collect();
+
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report_format() {
+        let entry = |name: &str, selected| DeviceEntry {
+            name: name.into(),
+            selected,
+        };
+        let report = DeviceReport {
+            audio_inputs: vec![entry("Microphone", false), entry("Line In", true)],
+            midi_inputs: vec![entry("nanoKONTROL2", true)],
+            errors: vec!["Failed to list MIDI ports: no backend".into()],
+            ..Default::default()
+        };
+
+        let text = report.to_string();
+        assert!(text
+            .starts_with("Audio input devices (audio_device):\n  0: Microphone\n  1: Line In *\n"));
+        assert!(text.contains("MIDI output ports:\n  none\n"));
+        assert!(text.contains("Error: Failed to list MIDI ports: no backend\n"));
+    }
+}
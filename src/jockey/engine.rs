@@ -0,0 +1,513 @@
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use gl::types::*;
+
+use crate::{
+    jockey::*,
+    util::{block_on, set_shader_cache_dir},
+};
+
+/// A callback which feeds uniforms from the host, see [`Engine::register_uniform_source`].
+pub type UniformSource = Box<dyn Fn() -> Vec<(String, OscUniformValue)>>;
+
+/// The render engine without any windows or UI.
+///
+/// This is meant for embedding pipelines into other applications. The host
+/// owns the window and the OpenGL context, and calls `update` and `render`
+/// once per frame. Audio, OSC, NDI, Spout, color handling and the flash
+/// limiter are all off by default and can be switched on with the `with_*`
+/// methods.
+///
+/// # OpenGL context
+///
+/// The context passed to `new` must be current on the calling thread for
+/// the whole lifetime of the engine, including when it is dropped. It has to
+/// support OpenGL 4.3 for compute shaders and image load/store, and should be
+/// a core profile context. The engine changes the bound framebuffer, program,
+/// textures, viewport and blend state, so the host must not rely on any of
+/// them surviving a call to `render`.
+pub struct Engine {
+    pub pipeline: Pipeline,
+    pub beat_sync: BeatSync,
+    pub sliders: [f32; MIDI_N],
+    pub buttons: [(f32, Instant, Instant, u32); MIDI_N],
+    pub pitch_bend: f32,
+    pub pressure: f32,
+    pub crossfade: f32,
+    pub time: f32,
+    pub time_since_build: f32,
+    pub loop_origin: f32,
+    pub frame: u32,
+    pub frame_since_build: u32,
+    vao: GLuint,
+    size: (u32, u32),
+    delta: f32,
+    audio: Audio,
+    osc: OscReceiver,
+    osc_enabled: bool,
+    ndi: Option<Ndi>,
+    spout_enabled: bool,
+    spout: Option<SpoutSender>,
+    spout_receiver: Option<SpoutReceiver>,
+    /// Copy of the finished frame for Spout, with the size it was allocated with.
+    spout_texture: (GLuint, (u32, u32)),
+    color: Option<ColorPass>,
+    limiter: Option<FlashLimiter>,
+    uniform_sources: Vec<UniformSource>,
+}
+
+impl std::fmt::Debug for Engine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(Engine))
+            .field("vao", &self.vao)
+            .field("size", &self.size)
+            .field("pipeline", &self.pipeline)
+            .field("color", &self.color)
+            .field("limiter", &self.limiter)
+            .field("uniform_sources", &self.uniform_sources.len())
+            .finish()
+    }
+}
+
+impl Engine {
+    /// Creates an engine on a glutin context, see [`Engine::new`].
+    pub fn from_context(
+        context: &glutin::Context<glutin::PossiblyCurrent>,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        Self::new(|s| context.get_proc_address(s) as _, width, height)
+    }
+
+    /// Creates an engine which renders into a target of the given size.
+    ///
+    /// `loader` resolves OpenGL function names in the current context, it is
+    /// usually `|s| context.get_proc_address(s) as _`.
+    pub fn new<F>(loader: F, width: u32, height: u32) -> Self
+    where
+        F: FnMut(&'static str) -> *const c_void,
+    {
+        gl::load_with(loader);
+
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl_debug_check!();
+        }
+
+        let now = Instant::now();
+        Self {
+            pipeline: Pipeline::splash_screen(),
+            beat_sync: BeatSync::new(),
+            sliders: [0.0; MIDI_N],
+            buttons: [(0.0, now, now, 0); MIDI_N],
+            pitch_bend: 0.0,
+            pressure: 0.0,
+            crossfade: 0.0,
+            time: 0.0,
+            time_since_build: 0.0,
+            loop_origin: 0.0,
+            frame: 0,
+            frame_since_build: 0,
+            vao,
+            size: (width, height),
+            delta: 0.0,
+            audio: Audio::without_input(AUDIO_SAMPLES),
+            osc: OscReceiver::new(),
+            osc_enabled: false,
+            ndi: None,
+            spout_enabled: false,
+            spout: None,
+            spout_receiver: None,
+            spout_texture: (0, (0, 0)),
+            color: None,
+            limiter: None,
+            uniform_sources: Vec::new(),
+        }
+    }
+
+    /// Analyzes the audio input device selected in `config`.
+    pub fn with_audio(mut self, config: &Config) -> Self {
+        if let Err(err) = self.audio.connect(config) {
+            log::error!("Error connecting to audio input device: {}", err);
+        }
+
+        self
+    }
+
+    /// Listens for OSC messages on the port of the loaded pipeline.
+    pub fn with_osc(mut self) -> Self {
+        self.osc_enabled = true;
+        self
+    }
+
+    /// Receives the NDI sources requested by the loaded pipeline.
+    pub fn with_ndi(mut self) -> Self {
+        self.ndi = Some(Ndi::with_config_path(None));
+        self
+    }
+
+    /// Shares every rendered frame over Spout and receives Spout input, if the pipeline asks for it.
+    pub fn with_spout(mut self) -> Self {
+        self.spout_enabled = true;
+        self
+    }
+
+    /// Tone maps and grades every frame like the output window, with the colors of `config`.
+    pub fn with_color(mut self, config: &Config) -> Self {
+        self.color = Some(ColorPass::new(config.color.clone()));
+        self
+    }
+
+    /// Slows down full screen flashes like the output window, with the limiter of `config`.
+    pub fn with_flash_limiter(mut self, config: &Config) -> Self {
+        self.limiter = Some(FlashLimiter::new(config.flash_limiter.clone()));
+        self
+    }
+
+    /// Caches linked shader programs in `dir`, so pipelines build faster next time.
+    ///
+    /// The cache is shared by all engines in the process.
+    pub fn with_shader_cache(self, dir: impl Into<PathBuf>) -> Self {
+        set_shader_cache_dir(Some(dir.into()));
+        self
+    }
+
+    /// Builds a pipeline file and replaces the current pipeline with it.
+    ///
+    /// Paths in the pipeline are resolved relative to the current working
+    /// directory. If the build fails, the current pipeline is kept.
+    pub fn load_pipeline(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        let (pipeline, update) = block_on(Pipeline::load(path, self.size, Vec::new()))?;
+
+        self.pipeline = pipeline;
+        self.time_since_build = 0.0;
+        self.frame_since_build = 0;
+
+        self.audio.attack = update.smoothing_attack;
+        self.audio.decay = update.smoothing_decay;
+        if update.audio_samples != self.audio.size {
+            self.audio.resize(update.audio_samples);
+        }
+        self.audio
+            .set_fft_bins(update.fft_bins, update.fft_scale, update.fft_resample);
+        self.audio.stereo_analysis = update.stereo_analysis;
+        self.audio.peak_hold = update.peak_hold;
+        self.audio.peak_decay = update.peak_decay;
+        self.audio
+            .set_profiles(update.audio_profiles, update.audio_profile);
+        self.audio.set_latency(update.audio_latency);
+        self.audio
+            .set_pre_gain(update.audio_pre_gain, update.audio_mute);
+        self.audio.pitch_detection = update.pitch_detection;
+        self.audio.centroid_smoothing = update.centroid_smoothing;
+        self.audio.centroid_normalized = update.centroid_normalized;
+        self.audio.density_window = update.density_window;
+
+        if let Some(ndi) = &mut self.ndi {
+            let requests = self.pipeline.requested_ndi_sources.values();
+            if let Err(err) = ndi.connect(&requests) {
+                log::error!("Failed to connect to NDI sources: {}", err);
+            }
+        }
+
+        let osc_config = self.pipeline.osc_config.clone().unwrap_or_default();
+        if self.osc_enabled && self.pipeline.osc_config.is_some() {
+            if let Err(err) = self.osc.apply_config(&osc_config) {
+                log::error!("Failed to start OSC receiver: {}", err);
+            }
+        } else {
+            self.osc.update_type_mappings(&osc_config);
+            self.osc.seed_defaults(&osc_config);
+        }
+
+        let query = match self.osc_enabled {
+            true => self.osc.serve_query(&osc_config),
+            false => self.osc.serve_query(&OscConfig::default()),
+        };
+        if let Err(err) = query {
+            log::error!("Failed to start OSCQuery server: {}", err);
+        }
+
+        self.spout = match &self.pipeline.spout_config {
+            Some(config) if self.spout_enabled && config.enabled => {
+                let mut sender = SpoutSender::new_on_adapter(&config.sender_name, config.adapter);
+                sender.set_fps(config.fps);
+                Some(sender)
+            }
+            _ => None,
+        };
+
+        let receive = self.pipeline.spout_config.as_ref().and_then(|config| {
+            config.receive_texture.as_ref()?;
+            Some((config.receive_sender.as_deref(), config.adapter))
+        });
+        match receive {
+            Some((sender, adapter)) if self.spout_enabled => {
+                let current = self
+                    .spout_receiver
+                    .as_ref()
+                    .map(|r| (r.sender_name(), r.adapter()));
+                if current != Some((sender, adapter)) {
+                    self.spout_receiver = Some(SpoutReceiver::new_on_adapter(sender, adapter));
+                }
+            }
+            _ => self.spout_receiver = None,
+        }
+
+        Ok(())
+    }
+
+    /// Resizes all render targets which depend on the target size.
+    ///
+    /// A size of zero, as reported for minimized windows, is ignored.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        if self.size != (width, height) {
+            self.size = (width, height);
+            self.pipeline.resize_buffers(width, height);
+        }
+    }
+
+    /// Advances the clock by `dt` seconds and reads new audio and OSC values.
+    pub fn update(&mut self, dt: Duration) {
+        self.delta = dt.as_secs_f32();
+        self.time += self.delta;
+        self.time_since_build += self.delta;
+        self.frame = self.frame.wrapping_add(1);
+        self.frame_since_build = self.frame_since_build.wrapping_add(1);
+
+        self.audio.update_samples();
+        self.audio.update_fft();
+        self.osc.tick();
+
+        for source in &self.uniform_sources {
+            self.osc.provide_values(source());
+        }
+
+        if let Some(receiver) = &mut self.spout_receiver {
+            receiver.check_receiver();
+        }
+
+        for (label, enabled) in self.osc.take_pass_toggles() {
+            self.pipeline.set_stage_enabled(&label, enabled);
+        }
+
+        if self.osc.take_loop_reset() {
+            self.reset_loop();
+        }
+    }
+
+    /// Adds a callback which `update` asks for uniform values every frame.
+    ///
+    /// The values go into the same store as the OSC values, after the OSC
+    /// messages of the frame, so they win over OSC values with the same name.
+    /// Sources run in the order they were registered, later ones win. Names
+    /// without an OSC mapping are uploaded as uniforms of their own.
+    pub fn register_uniform_source(&mut self, source: UniformSource) {
+        self.uniform_sources.push(source);
+    }
+
+    /// Restarts the `loop_time` uniform from zero at the current time.
+    pub fn reset_loop(&mut self) {
+        self.loop_origin = self.time;
+    }
+
+    /// The OSC receiver, to read its values with `get_f32` and friends.
+    pub fn osc(&self) -> &OscReceiver {
+        &self.osc
+    }
+
+    /// Collects which inputs received data recently, the engine has no MIDI input.
+    fn input_activity(&self) -> InputActivity {
+        InputActivity::new(&self.audio, &self.osc, self.spout_receiver.as_ref(), None)
+    }
+
+    /// Lists the uniforms the next frame feeds to the shaders, with their sources.
+    pub fn dump_uniforms(&self) -> HashMap<String, String> {
+        let inputs = RenderInputs {
+            vao: self.vao,
+            audio: &self.audio,
+            sliders: &self.sliders,
+            buttons: &self.buttons,
+            pitch_bend: self.pitch_bend,
+            pressure: self.pressure,
+            osc: &self.osc,
+            ndi: self.ndi.as_ref(),
+            spout: self.spout_receiver.as_ref(),
+            crossfade: self.crossfade,
+            activity: self.input_activity(),
+        };
+
+        dump_uniforms(&self.pipeline, &inputs, &self.frame_uniforms())
+    }
+
+    fn frame_uniforms(&self) -> FrameUniforms {
+        let (width, height) = self.size;
+        FrameUniforms {
+            width,
+            height,
+            beat: self.beat_sync.beat(),
+            time: self.time,
+            time_since_build: self.time_since_build,
+            loop_time: loop_phase(self.time, self.loop_origin, self.pipeline.loop_period),
+            delta: self.delta,
+            frame: self.frame,
+            frame_since_build: self.frame_since_build,
+            fps: match self.delta {
+                dt if dt > 0.0 => 1.0 / dt,
+                _ => 0.0,
+            },
+            // every frame takes exactly one step of the fixed clock
+            frame_time: 1000.0 * self.delta,
+            frame_time_max: 1000.0 * self.delta,
+        }
+    }
+
+    /// Renders the pipeline into `target_fbo`, 0 being the default framebuffer.
+    pub fn render(&mut self, target_fbo: GLuint) {
+        let uniforms = self.frame_uniforms();
+        let activity = self.input_activity();
+        self.render_frame(target_fbo, &uniforms, activity);
+    }
+
+    /// Renders a recorded frame into `target_fbo`, with the inputs of the recording.
+    ///
+    /// The clock, audio, MIDI and OSC values are all taken from the snapshot,
+    /// so the frame looks like the recorded one no matter when it is rendered.
+    /// The flash limiter and the LUT strength follow the recording as well,
+    /// if they were switched on with `with_flash_limiter` and `with_color`.
+    pub fn render_snapshot(
+        &mut self,
+        target_fbo: GLuint,
+        snapshot: &FrameSnapshot,
+    ) -> Result<(), String> {
+        snapshot.restore_audio(&mut self.audio)?;
+        snapshot.restore_midi(&mut self.sliders, &mut self.buttons, Instant::now());
+        self.osc.set_values(&snapshot.controls.osc_values);
+
+        self.pitch_bend = snapshot.pitch_bend;
+        self.pressure = snapshot.pressure;
+        self.crossfade = snapshot.crossfade;
+        self.time = snapshot.time;
+        self.time_since_build = snapshot.time_since_build;
+        self.loop_origin = snapshot.loop_origin;
+        self.delta = snapshot.delta;
+        self.frame = snapshot.frame;
+        self.frame_since_build = snapshot.frame_since_build;
+
+        if let (Some(limiter), Some(enabled)) = (&mut self.limiter, snapshot.flash_limiter) {
+            limiter.set_enabled(enabled);
+        }
+        if let (Some(color), Some(strength)) = (&mut self.color, snapshot.lut_strength) {
+            color.set_lut_strength(strength);
+        }
+
+        let uniforms = FrameUniforms {
+            beat: snapshot.beat,
+            fps: snapshot.fps,
+            frame_time: snapshot.frame_time[0],
+            frame_time_max: snapshot.frame_time[1],
+            ..self.frame_uniforms()
+        };
+
+        self.render_frame(target_fbo, &uniforms, snapshot.activity);
+        Ok(())
+    }
+
+    fn render_frame(
+        &mut self,
+        target_fbo: GLuint,
+        uniforms: &FrameUniforms,
+        activity: InputActivity,
+    ) {
+        let (width, height) = self.size;
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let inputs = RenderInputs {
+            vao: self.vao,
+            audio: &self.audio,
+            sliders: &self.sliders,
+            buttons: &self.buttons,
+            pitch_bend: self.pitch_bend,
+            pressure: self.pressure,
+            osc: &self.osc,
+            ndi: self.ndi.as_ref(),
+            spout: self.spout_receiver.as_ref(),
+            crossfade: self.crossfade,
+            activity,
+        };
+
+        // the same chain of passes as the output window
+        let color_fb = match &mut self.color {
+            Some(color) => color.target(target_fbo, width, height),
+            None => target_fbo,
+        };
+        let stages_fb = match &mut self.limiter {
+            Some(limiter) => limiter.target(color_fb, width, height),
+            None => color_fb,
+        };
+
+        render_stages(&mut self.pipeline, &inputs, stages_fb, uniforms);
+
+        if let Some(limiter) = &mut self.limiter {
+            limiter.apply_with_delta(self.vao, color_fb, width, height, self.delta);
+        }
+        if let Some(color) = &self.color {
+            color.present(self.vao, target_fbo, width, height, true);
+        }
+
+        if let Some(spout) = self.spout.as_mut().filter(|spout| spout.is_due()) {
+            // the copy is kept between frames and only reallocated on resize
+            let (texture, size) = &mut self.spout_texture;
+            unsafe {
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target_fbo);
+                if *texture == 0 {
+                    gl::GenTextures(1, texture);
+                }
+                gl::BindTexture(gl::TEXTURE_2D, *texture);
+                if *size != (width, height) {
+                    *size = (width, height);
+                    gl::CopyTexImage2D(
+                        gl::TEXTURE_2D,
+                        0,
+                        gl::RGBA8,
+                        0,
+                        0,
+                        width as _,
+                        height as _,
+                        0,
+                    );
+                } else {
+                    gl::CopyTexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, 0, 0, width as _, height as _);
+                }
+                gl_debug_check!();
+
+                if let Err(err) = spout.send_texture(*texture, width, height) {
+                    log::warn!("Failed to send texture to Spout: {}", err);
+                }
+
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            }
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.spout_texture.0);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
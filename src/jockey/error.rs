@@ -0,0 +1,74 @@
+use std::io;
+
+use thiserror::Error;
+
+use crate::util::gl_error_name;
+
+/// Errors of the OSC and Spout connections.
+///
+/// The messages are meant for the log and the overlay. Functions which
+/// still report errors as strings can take these with `?`, since they
+/// convert into their message.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A socket could not be bound to its address, usually because the port is taken.
+    #[error("Failed to bind {service} to {addr}: {source}")]
+    Bind {
+        service: &'static str,
+        addr: String,
+        source: io::Error,
+    },
+    /// A bound socket could not be set up.
+    #[error("Failed to {action}: {source}")]
+    Io {
+        action: &'static str,
+        source: io::Error,
+    },
+    /// A received packet is malformed.
+    #[error("Failed to decode {0}")]
+    Decode(String),
+    /// A frame could not be shared with other applications.
+    #[error("{0}")]
+    D3D(String),
+    /// OpenGL reported an error while a frame was prepared for sharing.
+    #[error("OpenGL error {} (0x{:X}) while copying the frame", gl_error_name(*.0), .0)]
+    Gl(u32),
+    /// SpoutLibrary.dll is missing or did not provide an instance.
+    #[error("{0}")]
+    Library(String),
+    /// A config section contains an invalid value.
+    #[error("{0}")]
+    Config(String),
+}
+
+impl From<Error> for String {
+    fn from(err: Error) -> Self {
+        err.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        let err = Error::Bind {
+            service: "OSC socket",
+            addr: "127.0.0.1:9000".into(),
+            source: io::Error::new(io::ErrorKind::AddrInUse, "in use"),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Failed to bind OSC socket to 127.0.0.1:9000: in use"
+        );
+
+        let message: String = Error::Config("OSC port must be a number".into()).into();
+        assert_eq!(message, "OSC port must be a number");
+
+        assert_eq!(
+            Error::Gl(0x0502).to_string(),
+            "OpenGL error INVALID_OPERATION (0x502) while copying the frame"
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use image::codecs::hdr::HdrDecoder;
+
+/// Width and pixels of an OpenEXR image while it is read.
+type ExrRows = (usize, Vec<[f32; 4]>);
+
+/// File formats of high dynamic range images, which are loaded as floats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrFormat {
+    /// Radiance RGBE, usually `.hdr`
+    Radiance,
+    /// OpenEXR, `.exr`
+    OpenExr,
+}
+
+impl HdrFormat {
+    /// Detects the format from the first bytes of a file.
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(b"#?") {
+            Some(Self::Radiance)
+        } else if header.starts_with(&[0x76, 0x2f, 0x31, 0x01]) {
+            Some(Self::OpenExr)
+        } else {
+            None
+        }
+    }
+
+    /// Detects the format of the file at `path`, `None` for all other images.
+    pub fn of_file(path: impl AsRef<Path>) -> Option<Self> {
+        let mut header = Vec::with_capacity(4);
+        File::open(path)
+            .ok()?
+            .take(4)
+            .read_to_end(&mut header)
+            .ok()?;
+        Self::detect(&header)
+    }
+}
+
+/// Linear RGBA pixels of an HDR image, with the bottom row first like OpenGL expects.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<f32>,
+}
+
+impl HdrImage {
+    /// Decodes the image at `path`, images without alpha are opaque.
+    pub fn load(path: impl AsRef<Path>, format: HdrFormat) -> Result<Self, String> {
+        match format {
+            HdrFormat::Radiance => {
+                let file = File::open(path).map_err(|e| e.to_string())?;
+                let decoder = HdrDecoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+                let meta = decoder.metadata();
+                let rows = decoder
+                    .read_image_hdr()
+                    .map_err(|e| e.to_string())?
+                    .iter()
+                    .map(|px| [px.0[0], px.0[1], px.0[2], 1.0])
+                    .collect();
+
+                Ok(Self::from_rows(meta.width, meta.height, rows))
+            }
+            HdrFormat::OpenExr => {
+                let image = exr::prelude::read_first_rgba_layer_from_file(
+                    path,
+                    |size, _| (size.width(), vec![[0.0; 4]; size.width() * size.height()]),
+                    |(width, rows): &mut ExrRows, pos, (r, g, b, a): (f32, f32, f32, f32)| {
+                        rows[pos.y() * *width + pos.x()] = [r, g, b, a];
+                    },
+                )
+                .map_err(|e| e.to_string())?;
+
+                let size = image.layer_data.size;
+                let (_, rows) = image.layer_data.channel_data.pixels;
+                Ok(Self::from_rows(size.width() as _, size.height() as _, rows))
+            }
+        }
+    }
+
+    /// Takes the pixels from the top row down, as image files store them.
+    fn from_rows(width: u32, height: u32, rows: Vec<[f32; 4]>) -> Self {
+        let pixels = rows
+            .chunks_exact(width.max(1) as _)
+            .rev()
+            .flatten()
+            .flatten()
+            .copied()
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_formats() {
+        assert_eq!(
+            HdrFormat::detect(b"#?RADIANCE\n"),
+            Some(HdrFormat::Radiance)
+        );
+        assert_eq!(HdrFormat::detect(b"#?RGBE\n"), Some(HdrFormat::Radiance));
+        assert_eq!(
+            HdrFormat::detect(&[0x76, 0x2f, 0x31, 0x01, 2]),
+            Some(HdrFormat::OpenExr)
+        );
+        assert_eq!(HdrFormat::detect(b"\x89PNG\r\n"), None);
+        assert_eq!(HdrFormat::detect(b"#"), None);
+    }
+
+    #[test]
+    fn rows_are_flipped() {
+        let rows = vec![[1.0; 4], [2.0; 4], [3.0; 4], [4.0; 4]];
+        let image = HdrImage::from_rows(2, 2, rows);
+        assert_eq!(image.pixels[..4], [3.0; 4]);
+        assert_eq!(image.pixels[12..], [2.0; 4]);
+    }
+}
@@ -0,0 +1,256 @@
+use std::{ffi::CString, time::Instant};
+
+use gl::types::*;
+use lazy_static::lazy_static;
+
+use super::{FlashLimiterConfig, PASS_VERT, POSITION_NAME, RESOLUTION_NAME};
+use crate::util::*;
+
+const LIMIT_FRAG: &str = include_str!("shaders/limit.frag");
+
+/// Longest frame time the limiter accounts for, in seconds.
+///
+/// After a stall, the frame would otherwise be allowed to jump all at once.
+const FLASH_LIMITER_MAX_DT: f32 = 1.0 / 20.0;
+
+lazy_static! {
+    static ref FRAME_NAME: CString = CString::new("frame").unwrap();
+    static ref PREVIOUS_NAME: CString = CString::new("previous").unwrap();
+    static ref LOD_NAME: CString = CString::new("lod").unwrap();
+    static ref MAX_CHANGE_NAME: CString = CString::new("max_change").unwrap();
+}
+
+#[derive(Debug)]
+struct LimiterTargets {
+    input: FrameBuffer,
+    output: FrameBuffer,
+    previous: FrameBuffer,
+    primed: bool,
+}
+
+impl LimiterTargets {
+    fn new(width: u32, height: u32) -> Self {
+        let build = || {
+            FrameBuffer::with_params(
+                width,
+                height,
+                gl::LINEAR_MIPMAP_LINEAR,
+                gl::LINEAR,
+                gl::CLAMP_TO_EDGE,
+                true,
+                true,
+            )
+        };
+
+        Self {
+            input: build(),
+            output: build(),
+            previous: build(),
+            primed: false,
+        }
+    }
+}
+
+/// Slows down rapid changes of the overall brightness of the final frame.
+///
+/// While enabled, the decks render into a float framebuffer. The average
+/// luminance of that frame is compared to the one of the last frame which
+/// was shown, and if it changed faster than the threshold allows, the new
+/// frame is blended with the last one. This only catches full screen
+/// flashes, small flashing areas barely move the average.
+pub struct FlashLimiter {
+    pub config: FlashLimiterConfig,
+    targets: Option<LimiterTargets>,
+    last_apply: Instant,
+    prog_id: GLuint,
+    sh_ids: Vec<GLuint>,
+}
+
+impl std::fmt::Debug for FlashLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(FlashLimiter))
+            .field("config", &self.config)
+            .field("targets", &self.targets)
+            .finish()
+    }
+}
+
+impl FlashLimiter {
+    /// Creates a new limiter for the OpenGL context which is currently active.
+    pub fn new(config: FlashLimiterConfig) -> Self {
+        let sh_ids = vec![
+            compile_shader(PASS_VERT, gl::VERTEX_SHADER).unwrap(),
+            compile_shader(LIMIT_FRAG, gl::FRAGMENT_SHADER).unwrap(),
+        ];
+
+        let prog_id = link_program(&sh_ids).unwrap();
+
+        Self {
+            config,
+            targets: None,
+            last_apply: Instant::now(),
+            prog_id,
+            sh_ids,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.set_enabled(!self.config.enabled);
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if self.config.enabled != enabled {
+            log::info!(
+                "Flash limiter {}",
+                if enabled { "enabled" } else { "disabled" }
+            );
+        }
+
+        self.config.enabled = enabled;
+    }
+
+    /// Returns the framebuffer the decks should render into.
+    ///
+    /// This is `next_fb` itself, unless the limiter is enabled.
+    pub fn target(&mut self, next_fb: GLuint, width: u32, height: u32) -> GLuint {
+        if !self.config.enabled {
+            // start over from the current frame when enabled again
+            self.targets = None;
+            return next_fb;
+        }
+
+        let resized = match &self.targets {
+            Some(targets) => targets.input.resolution()[..2] != [width.max(1), height.max(1)],
+            None => true,
+        };
+
+        if resized {
+            self.targets = Some(LimiterTargets::new(width, height));
+        }
+
+        self.targets.as_ref().unwrap().input.fb_id
+    }
+
+    /// Limits the frame rendered into the target and draws it to `next_fb`.
+    pub fn apply(&mut self, vao: GLuint, next_fb: GLuint, width: u32, height: u32) {
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_apply).as_secs_f32();
+        self.apply_with_delta(vao, next_fb, width, height, dt);
+    }
+
+    /// Like `apply`, but the last frame was shown `dt` seconds ago.
+    ///
+    /// Used when frames are rendered on a clock of their own, like a replay.
+    pub fn apply_with_delta(
+        &mut self,
+        vao: GLuint,
+        next_fb: GLuint,
+        width: u32,
+        height: u32,
+        dt: f32,
+    ) {
+        self.last_apply = Instant::now();
+
+        let targets = match &mut self.targets {
+            Some(targets) => targets,
+            None => return,
+        };
+
+        // the first frame has nothing to be compared to
+        let max_change = match targets.primed {
+            true => self.config.threshold * dt.min(FLASH_LIMITER_MAX_DT),
+            false => f32::MAX,
+        };
+        targets.primed = true;
+
+        let (w, h) = (width as f32, height as f32);
+        let lod = w.max(h).max(1.0).log2().floor();
+
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, targets.input.tex_id);
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+            gl_debug_check!();
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, targets.output.fb_id);
+            gl::Viewport(0, 0, width as _, height as _);
+            gl::Disable(gl::BLEND);
+            gl::UseProgram(self.prog_id);
+            gl_debug_check!();
+
+            let res_loc = gl::GetUniformLocation(self.prog_id, RESOLUTION_NAME.as_ptr());
+            gl::Uniform4f(res_loc, w, h, w / h, h / w);
+            let lod_loc = gl::GetUniformLocation(self.prog_id, LOD_NAME.as_ptr());
+            gl::Uniform1f(lod_loc, lod);
+            let change_loc = gl::GetUniformLocation(self.prog_id, MAX_CHANGE_NAME.as_ptr());
+            gl::Uniform1f(change_loc, max_change);
+            gl_debug_check!();
+
+            let frame_loc = gl::GetUniformLocation(self.prog_id, FRAME_NAME.as_ptr());
+            gl::ActiveTexture(gl::TEXTURE0);
+            targets.input.bind(0);
+            gl::Uniform1i(frame_loc, 0);
+            let previous_loc = gl::GetUniformLocation(self.prog_id, PREVIOUS_NAME.as_ptr());
+            gl::ActiveTexture(gl::TEXTURE1);
+            targets.previous.bind(1);
+            gl::Uniform1i(previous_loc, 1);
+            gl_debug_check!();
+
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vao);
+            let pos_attr = gl::GetAttribLocation(self.prog_id, POSITION_NAME.as_ptr());
+            if pos_attr != -1 {
+                gl::EnableVertexAttribArray(pos_attr as GLuint);
+                gl::VertexAttribPointer(
+                    pos_attr as GLuint,
+                    2,
+                    gl::FLOAT,
+                    gl::FALSE as GLboolean,
+                    0,
+                    std::ptr::null(),
+                );
+            }
+            gl_debug_check!();
+
+            draw_fullscreen(vao);
+
+            // the average of the output is needed next frame
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, targets.output.tex_id);
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+            gl_debug_check!();
+
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, targets.output.fb_id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, next_fb);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                width as _,
+                height as _,
+                0,
+                0,
+                width as _,
+                height as _,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl_debug_check!();
+        }
+
+        std::mem::swap(&mut targets.output, &mut targets.previous);
+    }
+}
+
+impl Drop for FlashLimiter {
+    fn drop(&mut self) {
+        unsafe {
+            for &id in self.sh_ids.iter() {
+                gl::DetachShader(self.prog_id, id);
+                gl::DeleteShader(id);
+            }
+
+            gl::DeleteProgram(self.prog_id);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::path::Path;
+
+/// Smallest and largest edge length accepted for 3D LUTs.
+const LUT_SIZE_RANGE: std::ops::RangeInclusive<usize> = 2..=256;
+
+/// A 3D color lookup table, as stored in `.cube` files.
+///
+/// The entries are RGB triplets with red changing fastest, then green,
+/// then blue, which is also the layout of a 3D texture indexed by color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    pub size: usize,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub data: Vec<f32>,
+}
+
+impl CubeLut {
+    /// Reads and parses a `.cube` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read LUT {:?}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("Failed to parse LUT {:?}: {}", path, e))
+    }
+
+    /// Parses the contents of a `.cube` file with a 3D table.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut data = Vec::new();
+
+        for (k, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let line_nr = k + 1;
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap();
+            match keyword {
+                "TITLE" => {
+                    let rest = line["TITLE".len()..].trim();
+                    title = Some(rest.trim_matches('"').to_owned());
+                }
+                "LUT_3D_SIZE" => {
+                    let value = words.next().and_then(|s| s.parse::<usize>().ok());
+                    match value {
+                        Some(n) if LUT_SIZE_RANGE.contains(&n) => size = Some(n),
+                        _ => {
+                            return Err(format!(
+                                "Expected LUT_3D_SIZE to be between {} and {}, got {:?} in line {}",
+                                LUT_SIZE_RANGE.start(),
+                                LUT_SIZE_RANGE.end(),
+                                line,
+                                line_nr
+                            ))
+                        }
+                    }
+                }
+                "LUT_1D_SIZE" => return Err(format!("1D LUTs are not supported, got {:?}", line)),
+                "DOMAIN_MIN" => domain_min = parse_triplet(line_nr, words)?,
+                "DOMAIN_MAX" => domain_max = parse_triplet(line_nr, words)?,
+                "LUT_3D_INPUT_RANGE" => {
+                    let range: Vec<_> = words.map(str::parse::<f32>).collect();
+                    match range.as_slice() {
+                        [Ok(min), Ok(max)] => {
+                            domain_min = [*min; 3];
+                            domain_max = [*max; 3];
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected two numbers in line {}, got {:?}",
+                                line_nr, range
+                            ))
+                        }
+                    }
+                }
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    log::warn!(
+                        "Ignoring unknown LUT keyword {:?} in line {}",
+                        keyword,
+                        line_nr
+                    );
+                }
+                _ => {
+                    let entry = parse_triplet(line_nr, line.split_whitespace())?;
+                    data.extend_from_slice(&entry);
+                }
+            }
+        }
+
+        let size = match size {
+            Some(n) => n,
+            None => return Err("Expected a LUT_3D_SIZE line".to_string()),
+        };
+
+        let expected = size * size * size;
+        if data.len() != 3 * expected {
+            return Err(format!(
+                "Expected {} entries for a LUT of size {}, got {}",
+                expected,
+                size,
+                data.len() / 3
+            ));
+        }
+
+        if (0..3).any(|i| domain_min[i] >= domain_max[i]) {
+            return Err(format!(
+                "Expected DOMAIN_MIN to be below DOMAIN_MAX, got {:?} and {:?}",
+                domain_min, domain_max
+            ));
+        }
+
+        Ok(Self {
+            title,
+            size,
+            domain_min,
+            domain_max,
+            data,
+        })
+    }
+}
+
+fn parse_triplet<'a>(
+    line_nr: usize,
+    words: impl Iterator<Item = &'a str>,
+) -> Result<[f32; 3], String> {
+    let values: Vec<_> = words.map(str::parse::<f32>).collect();
+    match values.as_slice() {
+        [Ok(r), Ok(g), Ok(b)] => Ok([*r, *g, *b]),
+        _ => Err(format!(
+            "Expected three numbers in line {}, got {:?}",
+            line_nr, values
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn identity(size: usize) -> String {
+        let mut text = format!("TITLE \"identity\"\n# comment\nLUT_3D_SIZE {}\n", size);
+        let max = (size - 1) as f32;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let line =
+                        format!("{} {} {}\n", r as f32 / max, g as f32 / max, b as f32 / max);
+                    text.push_str(&line);
+                }
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn common_sizes() {
+        for &size in &[17, 33] {
+            let lut = CubeLut::parse(&identity(size)).unwrap();
+            assert_eq!(lut.title.as_deref(), Some("identity"));
+            assert_eq!(lut.size, size);
+            assert_eq!(lut.data.len(), 3 * size * size * size);
+
+            // red changes fastest
+            assert_eq!(&lut.data[3..6], &[1.0 / (size - 1) as f32, 0.0, 0.0]);
+            assert_eq!(&lut.data[lut.data.len() - 3..], &[1.0, 1.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn domain() {
+        let text = "DOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 4\nLUT_3D_SIZE 2\n".to_string()
+            + &"0 0 0\n".repeat(8);
+        let lut = CubeLut::parse(&text).unwrap();
+        assert_eq!(lut.domain_max, [2.0, 2.0, 4.0]);
+
+        let text = "DOMAIN_MAX 0 1 1\nLUT_3D_SIZE 2\n".to_string() + &"0 0 0\n".repeat(8);
+        assert!(CubeLut::parse(&text).is_err());
+    }
+
+    #[test]
+    fn invalid_files() {
+        let entries = "0 0 0\n".repeat(8);
+        assert!(CubeLut::parse(&entries).is_err());
+        assert!(CubeLut::parse(&format!("LUT_3D_SIZE 3\n{}", entries)).is_err());
+        assert!(CubeLut::parse(&format!("LUT_3D_SIZE 1\n{}", entries)).is_err());
+        assert!(CubeLut::parse(&format!("LUT_1D_SIZE 8\n{}", entries)).is_err());
+        assert!(CubeLut::parse(&format!("LUT_3D_SIZE 2\n{}0 x 0\n", entries)).is_err());
+        assert!(CubeLut::parse(&format!("LUT_3D_SIZE 2\n{}", entries)).is_ok());
+    }
+}
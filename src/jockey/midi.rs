@@ -6,24 +6,102 @@ use std::{
     time::Instant,
 };
 
-use midir::{Ignore, MidiInput, MidiInputConnection, MidiInputPort};
+use midir::{Ignore, MidiInput, MidiInputConnection, MidiInputPort, MidiOutput};
 
-use super::Config;
+use super::{
+    Config, MidiDevice, MidiExpressionConfig, PresetRequest, ProgramChangeConfig,
+    INPUT_ACTIVE_TIMEOUT,
+};
 
 pub const MIDI_N: usize = 32;
 
+/// Connection to a single MIDI input port.
+pub struct MidiPort {
+    pub name: String,
+    /// Added to the channel of every message of this port.
+    pub channel_offset: u8,
+    // dropped before the queue, so the callback never sends into a closed channel
+    _conn: MidiInputConnection<()>,
+    queue: Receiver<[u8; 3]>,
+}
+
 pub struct Midi {
-    pub conns: Vec<MidiInputConnection<()>>,
-    pub queues: Vec<Receiver<[u8; 3]>>,
+    pub ports: Vec<MidiPort>,
     pub last_button: [u8; 2],
     pub last_slider: [u8; 2],
     pub sliders: [f32; MIDI_N],
     pub buttons: [(f32, Instant, Instant, u32); MIDI_N],
     pub button_bindings: HashMap<[u8; 2], usize>,
     pub slider_bindings: HashMap<[u8; 2], usize>,
-    preferred_devices: Vec<String>,
+    /// Position of the pitch wheel from -1 to 1, zero when centered.
+    pub pitch_bend: f32,
+    /// Most recent channel or key pressure from 0 to 1.
+    pub pressure: f32,
+    program_change: ProgramChangeConfig,
+    expression: MidiExpressionConfig,
+    inverted_sliders: Vec<usize>,
+    preset_request: Option<PresetRequest>,
+    audio_profile_request: Option<PresetRequest>,
+    preferred_devices: Vec<MidiDevice>,
     config_file: Option<PathBuf>,
     port_count: usize,
+    /// When the last message of any connected device arrived.
+    last_message: Option<Instant>,
+}
+
+/// Channel offset of the MIDI input port with this name, `None` if the tool
+/// does not connect to it.
+///
+/// Without `midi_devices` in the config every port is used, otherwise only
+/// ports whose name contains one of the listed strings. The first matching
+/// device decides the offset.
+pub fn midi_device_offset(midi_devices: &[MidiDevice], name: &str) -> Option<u8> {
+    match midi_devices.is_empty() {
+        true => Some(0),
+        false => midi_devices
+            .iter()
+            .find(|device| device.matches(name))
+            .map(|device| device.channel_offset),
+    }
+}
+
+/// Converts the two data bytes of a pitch bend message to a value from -1 to 1.
+///
+/// The 14 bit value is centered at 8192, both directions reach exactly one.
+/// Values within `deadzone` of the center are zero and the rest of the range
+/// is stretched, so the wheel does not jump at the edge of the dead zone.
+pub fn pitch_bend_value(lsb: u8, msb: u8, deadzone: f32) -> f32 {
+    let raw = (((msb as i32 & 0x7F) << 7) | (lsb as i32 & 0x7F)) - 8192;
+    let value = match raw < 0 {
+        true => raw as f32 / 8192.0,
+        false => raw as f32 / 8191.0,
+    };
+
+    match value.abs() <= deadzone {
+        true => 0.0,
+        false => value.signum() * (value.abs() - deadzone) / (1.0 - deadzone),
+    }
+}
+
+/// Returns the names of all MIDI input and output ports.
+pub fn midi_port_names() -> Result<(Vec<String>, Vec<String>), String> {
+    let midi_in = MidiInput::new("Sh4derJockey").map_err(|e| e.to_string())?;
+    let midi_out = MidiOutput::new("Sh4derJockey").map_err(|e| e.to_string())?;
+
+    let inputs = midi_in
+        .ports()
+        .iter()
+        .map(|port| midi_in.port_name(port))
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    let outputs = midi_out
+        .ports()
+        .iter()
+        .map(|port| midi_out.port_name(port))
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok((inputs, outputs))
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -32,13 +110,45 @@ pub enum MessageKind {
     NoteOff { channel: u8, key: u8, _velocity: u8 },
     KeyPressure { channel: u8, key: u8, pressure: u8 },
     ControlChange { channel: u8, key: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelPressure { channel: u8, pressure: u8 },
+    PitchBend { channel: u8, lsb: u8, msb: u8 },
+}
+
+impl std::fmt::Debug for MidiPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(MidiPort))
+            .field("name", &self.name)
+            .field("channel_offset", &self.channel_offset)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for Midi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(Midi))
+            .field("ports", &self.ports)
+            .field("sliders", &self.sliders)
+            .field("pitch_bend", &self.pitch_bend)
+            .field("pressure", &self.pressure)
+            .finish()
+    }
 }
 
 impl Midi {
     pub fn new(config: &Config, base_path: Option<&Path>) -> Self {
         let now = Instant::now();
-        let sliders = [0.0; MIDI_N];
-        let buttons = [(0.0, now, now, 0); MIDI_N];
+        let mut sliders = [0.0; MIDI_N];
+        let mut buttons = [(0.0, now, now, 0); MIDI_N];
+
+        // replaced by the first message of the control
+        for &(id, value) in &config.slider_defaults {
+            sliders[id] = value;
+        }
+        for &(id, value) in &config.button_defaults {
+            buttons[id].0 = value;
+        }
+
         let mut button_bindings = HashMap::new();
         let mut slider_bindings = HashMap::new();
 
@@ -61,17 +171,24 @@ impl Midi {
         }
 
         let mut this = Self {
-            conns: Vec::new(),
-            queues: Vec::new(),
+            ports: Vec::new(),
             last_button: [0, 0],
             last_slider: [0, 0],
             sliders,
             buttons,
             button_bindings,
             slider_bindings,
+            pitch_bend: 0.0,
+            pressure: 0.0,
+            program_change: config.program_change.clone(),
+            expression: config.midi_expression.clone(),
+            inverted_sliders: config.inverted_sliders.clone(),
+            preset_request: None,
+            audio_profile_request: None,
             preferred_devices,
             config_file,
             port_count: 0,
+            last_message: None,
         };
 
         this.connect();
@@ -91,11 +208,11 @@ impl Midi {
             return;
         }
 
-        self.conns = Vec::new();
-        self.queues = Vec::new();
         self.connect();
     }
 
+    /// Connects to all matching ports which are not connected yet, and drops
+    /// the connections of ports which are gone. The other connections stay open.
     pub fn connect(&mut self) {
         let mut midi_in = match MidiInput::new("Sh4derJockey") {
             Ok(s) => s,
@@ -106,47 +223,51 @@ impl Midi {
         };
 
         midi_in.ignore(Ignore::None);
-
-        // Get an input port (read from console if multiple are available)
-        let mut in_ports = midi_in.ports();
-        if midi_in.port_count() == 0 {
+        self.port_count = midi_in.port_count();
+        if self.port_count == 0 {
             log::warn!("No midi input port found.");
-            return;
-        }
-
-        if !self.preferred_devices.is_empty() {
-            in_ports.retain(|port| {
-                self.preferred_devices
-                    .iter()
-                    .any(|pref| midi_in.port_name(port).unwrap_or_default().contains(pref))
-            });
         }
 
-        let mut conns = Vec::new();
-        let mut queues = Vec::new();
-        for in_port in in_ports.iter() {
-            match self.new_connection(in_port) {
-                Ok((conn, rx)) => {
-                    conns.push(conn);
-                    queues.push(rx);
+        let mut in_ports: Vec<_> = midi_in
+            .ports()
+            .into_iter()
+            .filter_map(|port| {
+                let name = midi_in.port_name(&port).unwrap_or_default();
+                let offset = midi_device_offset(&self.preferred_devices, &name)?;
+                Some((port, name, offset))
+            })
+            .collect();
+
+        // ports are matched by name, identical devices only by their count
+        self.ports.retain(|port| {
+            match in_ports.iter().position(|(_, name, _)| *name == port.name) {
+                Some(k) => {
+                    in_ports.remove(k);
+                    true
                 }
-
-                Err(code) => {
-                    let temp = midi_in.port_name(&in_port);
-                    let name = temp.as_deref().unwrap_or("???");
-                    log::warn!("Failed to connect to {name}: {code:?}");
+                None => {
+                    log::info!("Lost midi input port: {}", port.name);
+                    false
                 }
+            }
+        });
+
+        for (in_port, name, channel_offset) in in_ports {
+            match Self::new_connection(&in_port, &name) {
+                Ok((conn, queue)) => self.ports.push(MidiPort {
+                    name,
+                    channel_offset,
+                    _conn: conn,
+                    queue,
+                }),
+                Err(code) => log::warn!("Failed to connect to {name}: {code:?}"),
             };
         }
-
-        self.conns = conns;
-        self.queues = queues;
-        self.port_count = midi_in.port_count();
     }
 
     fn new_connection(
-        &self,
         in_port: &MidiInputPort,
+        port_name: &str,
     ) -> Result<(MidiInputConnection<()>, Receiver<[u8; 3]>), anyhow::Error> {
         let mut midi_input = match MidiInput::new("Sh4derJockey") {
             Ok(s) => s,
@@ -156,7 +277,6 @@ impl Midi {
         };
 
         midi_input.ignore(Ignore::None);
-        let port_name = midi_input.port_name(&in_port).unwrap_or_default();
         log::info!("Connecting to input port: {}", port_name);
 
         let (tx, rx) = channel();
@@ -165,12 +285,13 @@ impl Midi {
                 in_port,
                 format!("sh4der-jockey-read-input-{}", port_name).as_str(),
                 move |_, message, _| {
-                    if message.len() != 3 {
+                    // program changes only carry a single data byte
+                    if message.len() != 2 && message.len() != 3 {
                         return;
                     }
                     let mut out = [0; 3];
-                    out.copy_from_slice(message);
-                    tx.send(out).unwrap();
+                    out[..message.len()].copy_from_slice(message);
+                    let _ = tx.send(out);
                 },
                 (),
             )
@@ -179,13 +300,13 @@ impl Midi {
     }
 
     pub fn handle_input(&mut self) {
-        fn parse_msg(message: [u8; 3]) -> Option<MessageKind> {
+        fn parse_msg(message: [u8; 3], channel_offset: u8) -> Option<MessageKind> {
             let status = message[0];
             let data0 = message[1];
             let data1 = message[2];
 
             let kind_bits = 0xF0_u8 & status;
-            let channel = status & 0x0F_u8;
+            let channel = (status & 0x0F_u8).saturating_add(channel_offset);
             match kind_bits {
                 0x80 => Some(MessageKind::NoteOff {
                     channel,
@@ -211,65 +332,160 @@ impl Midi {
                     value: data1,
                 }),
 
+                0xC0 => Some(MessageKind::ProgramChange {
+                    channel,
+                    program: data0,
+                }),
+
+                0xD0 => Some(MessageKind::ChannelPressure {
+                    channel,
+                    pressure: data0,
+                }),
+
+                0xE0 => Some(MessageKind::PitchBend {
+                    channel,
+                    lsb: data0,
+                    msb: data1,
+                }),
+
                 _ => None,
             }
         }
 
-        for queue in &self.queues {
-            for message in queue.try_iter() {
-                let kind = parse_msg(message);
-                // println!("{:#02x} {} {}", message[0], message[1], message[2]);
-                // println!("{:?}", kind);
-
-                match kind {
-                    None => continue,
-
-                    Some(k) => match k {
-                        MessageKind::NoteOn {
-                            channel,
-                            key,
-                            velocity,
-                        } => {
-                            self.last_button = [channel, key];
-                            if let Some(&id) = self.button_bindings.get(&self.last_button) {
-                                self.buttons[id].0 = velocity as f32 / 127.0;
-                                self.buttons[id].1 = Instant::now();
-                                self.buttons[id].3 += 1;
-                            }
+        // messages of all ports are merged in the order of the ports
+        let messages: Vec<_> = self
+            .ports
+            .iter()
+            .flat_map(|port| port.queue.try_iter().map(move |m| (m, port.channel_offset)))
+            .collect();
+
+        for (message, channel_offset) in messages {
+            self.last_message = Some(Instant::now());
+            let kind = parse_msg(message, channel_offset);
+            // println!("{:#02x} {} {}", message[0], message[1], message[2]);
+            // println!("{:?}", kind);
+
+            match kind {
+                None => continue,
+
+                Some(k) => match k {
+                    MessageKind::NoteOn {
+                        channel,
+                        key,
+                        velocity,
+                    } => {
+                        self.last_button = [channel, key];
+                        if let Some(&id) = self.button_bindings.get(&self.last_button) {
+                            self.buttons[id].0 = velocity as f32 / 127.0;
+                            self.buttons[id].1 = Instant::now();
+                            self.buttons[id].3 += 1;
                         }
-                        MessageKind::NoteOff { channel, key, .. } => {
-                            self.last_button = [channel, key];
-                            if let Some(&id) = self.button_bindings.get(&self.last_button) {
-                                self.buttons[id].0 = 0.0;
-                                self.buttons[id].2 = Instant::now();
-                            }
+                    }
+                    MessageKind::NoteOff { channel, key, .. } => {
+                        self.last_button = [channel, key];
+                        if let Some(&id) = self.button_bindings.get(&self.last_button) {
+                            self.buttons[id].0 = 0.0;
+                            self.buttons[id].2 = Instant::now();
                         }
-                        MessageKind::KeyPressure {
-                            channel,
-                            key,
-                            pressure,
-                        } => {
-                            self.last_button = [channel, key];
-                            if let Some(&id) = self.button_bindings.get(&self.last_button) {
-                                self.buttons[id].0 = pressure as f32 / 127.0;
-                            }
+                    }
+                    MessageKind::KeyPressure {
+                        channel,
+                        key,
+                        pressure,
+                    } => {
+                        self.last_button = [channel, key];
+                        if let Some(&id) = self.button_bindings.get(&self.last_button) {
+                            self.buttons[id].0 = pressure as f32 / 127.0;
+                        }
+                        self.set_pressure(channel, pressure);
+                    }
+                    MessageKind::ChannelPressure { channel, pressure } => {
+                        self.set_pressure(channel, pressure);
+                    }
+                    MessageKind::PitchBend { channel, lsb, msb } => {
+                        if !self.expression.accepts(channel) {
+                            continue;
+                        }
+
+                        let deadzone = self.expression.pitch_bend_deadzone;
+                        self.pitch_bend = pitch_bend_value(lsb, msb, deadzone);
+                        if let Some(id) = self.expression.pitch_bend_slider {
+                            self.set_slider(id, 0.5 + 0.5 * self.pitch_bend);
+                        }
+                    }
+                    MessageKind::ControlChange {
+                        channel,
+                        key,
+                        value,
+                    } => {
+                        self.last_slider = [channel, key];
+                        if let Some(&id) = self.slider_bindings.get(&self.last_slider) {
+                            self.set_slider(id, value as f32 / 127.0);
+                        }
+                    }
+                    MessageKind::ProgramChange { channel, program } => {
+                        let config = &self.program_change;
+                        if let Some(name) = config.resolve_audio_profile(channel, program) {
+                            log::debug!("MIDI audio profile request: {:?}", name);
+                            self.audio_profile_request = Some(PresetRequest::Name(name.to_owned()));
+                            continue;
                         }
-                        MessageKind::ControlChange {
-                            channel,
-                            key,
-                            value,
-                        } => {
-                            self.last_slider = [channel, key];
-                            if let Some(&id) = self.slider_bindings.get(&self.last_slider) {
-                                self.sliders[id] = value as f32 / 127.0;
+
+                        match self.program_change.resolve(channel, program) {
+                            Some(request) => {
+                                log::debug!("MIDI preset request: {:?}", request);
+                                self.preset_request = Some(request);
                             }
+                            None => log::debug!(
+                                "Ignoring program change {} on channel {}",
+                                program,
+                                channel as u32 + 1
+                            ),
                         }
-                    },
-                }
+                    }
+                },
             }
         }
     }
 
+    /// Moves a slider to a value from 0 to 1, inverted sliders read as `1 - value`.
+    fn set_slider(&mut self, id: usize, value: f32) {
+        self.sliders[id] = match self.inverted_sliders.contains(&id) {
+            true => 1.0 - value,
+            false => value,
+        };
+    }
+
+    /// Stores the pressure of an aftertouch message, if its channel is used.
+    fn set_pressure(&mut self, channel: u8, pressure: u8) {
+        if !self.expression.accepts(channel) {
+            return;
+        }
+
+        self.pressure = pressure as f32 / 127.0;
+        if let Some(id) = self.expression.pressure_slider {
+            self.set_slider(id, self.pressure);
+        }
+    }
+
+    /// Whether a device is connected and sent a message recently.
+    pub fn is_active(&self) -> bool {
+        let recent = self
+            .last_message
+            .map_or(false, |t| t.elapsed() <= INPUT_ACTIVE_TIMEOUT);
+        !self.ports.is_empty() && recent
+    }
+
+    /// Returns the preset selected by the most recent program change, if there is one.
+    pub fn take_preset_request(&mut self) -> Option<PresetRequest> {
+        self.preset_request.take()
+    }
+
+    /// Returns the audio profile selected by the most recent program change, if there is one.
+    pub fn take_audio_profile_request(&mut self) -> Option<PresetRequest> {
+        self.audio_profile_request.take()
+    }
+
     fn store_bindings(&self) {
         let Some(path) = &self.config_file else {
             return;
@@ -323,3 +539,36 @@ impl Midi {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pitch_bend_range() {
+        assert_eq!(pitch_bend_value(0x00, 0x40, 0.0), 0.0);
+        assert_eq!(pitch_bend_value(0x00, 0x00, 0.0), -1.0);
+        assert_eq!(pitch_bend_value(0x7F, 0x7F, 0.0), 1.0);
+
+        // the dead zone swallows a wheel which does not quite return to the center
+        assert_eq!(pitch_bend_value(0x40, 0x40, 0.02), 0.0);
+        assert_eq!(pitch_bend_value(0x7F, 0x7F, 0.02), 1.0);
+        let half = pitch_bend_value(0x00, 0x60, 0.2);
+        assert!((half - (0.5 - 0.2) / 0.8).abs() < 1e-3, "{}", half);
+    }
+
+    #[test]
+    fn device_offsets() {
+        let device = |name: Option<&str>, channel_offset| MidiDevice {
+            name: name.map(str::to_owned),
+            channel_offset,
+        };
+
+        assert_eq!(midi_device_offset(&[], "APC mini"), Some(0));
+
+        let devices = [device(Some("APC"), 16), device(None, 0)];
+        assert_eq!(midi_device_offset(&devices, "APC mini"), Some(16));
+        assert_eq!(midi_device_offset(&devices, "nanoKONTROL2"), Some(0));
+        assert_eq!(midi_device_offset(&devices[..1], "nanoKONTROL2"), None);
+    }
+}
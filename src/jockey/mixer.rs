@@ -0,0 +1,262 @@
+use std::{
+    ffi::CString,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use gl::types::*;
+use lazy_static::lazy_static;
+
+use super::{Pipeline, PipelinePartial, CROSSFADE_NAME, PASS_VERT, POSITION_NAME, RESOLUTION_NAME};
+use crate::util::*;
+
+const MIX_FRAG: &str = include_str!("shaders/mix.frag");
+
+lazy_static! {
+    static ref DECK_A_NAME: CString = CString::new("deck_a").unwrap();
+    static ref DECK_B_NAME: CString = CString::new("deck_b").unwrap();
+}
+
+/// Which decks have to be rendered in the current frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeckView {
+    /// Only deck A, straight to the screen.
+    A,
+    /// Only deck B, straight to the screen.
+    B,
+    /// Both decks into their own framebuffers, mixed in a final pass.
+    Mix,
+}
+
+impl DeckView {
+    /// Picks the decks to render for a fader position.
+    ///
+    /// Both decks only run while the fader is somewhere between the ends,
+    /// so a loaded deck B costs nothing while it is faded out.
+    pub fn new(fader: f32, has_deck_b: bool) -> Self {
+        match fader {
+            _ if !has_deck_b => Self::A,
+            f if f <= 0.0 => Self::A,
+            f if f >= 1.0 => Self::B,
+            _ => Self::Mix,
+        }
+    }
+}
+
+/// A second pipeline which is mixed with the main pipeline by a crossfader.
+///
+/// The main pipeline always acts as deck A and owns all shared modules like
+/// audio, OSC, NDI and Spout. Deck B only contributes its stages.
+pub struct Mixer {
+    pub fader: f32,
+    pub slider: Option<usize>,
+    pub deck_b: Option<Pipeline>,
+    pub deck_b_file: Option<String>,
+    pub partial: Option<Pin<PipelinePartial>>,
+    loading_file: Option<String>,
+    last_slider: f32,
+    targets: Option<(FrameBuffer, FrameBuffer)>,
+    prog_id: GLuint,
+    sh_ids: Vec<GLuint>,
+}
+
+impl std::fmt::Debug for Mixer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(Mixer))
+            .field("fader", &self.fader)
+            .field("slider", &self.slider)
+            .field("deck_b", &self.deck_b)
+            .field("deck_b_file", &self.deck_b_file)
+            .field("targets", &self.targets)
+            .finish()
+    }
+}
+
+impl Mixer {
+    /// Creates a new mixer for the OpenGL context which is currently active.
+    pub fn new(slider: Option<usize>) -> Self {
+        let sh_ids = vec![
+            compile_shader(PASS_VERT, gl::VERTEX_SHADER).unwrap(),
+            compile_shader(MIX_FRAG, gl::FRAGMENT_SHADER).unwrap(),
+        ];
+
+        let prog_id = link_program(&sh_ids).unwrap();
+
+        Self {
+            fader: 0.0,
+            slider,
+            deck_b: None,
+            deck_b_file: None,
+            partial: None,
+            loading_file: None,
+            last_slider: f32::NAN,
+            targets: None,
+            prog_id,
+            sh_ids,
+        }
+    }
+
+    /// Returns the decks which have to be rendered in the current frame.
+    pub fn view(&self) -> DeckView {
+        DeckView::new(self.fader, self.deck_b.is_some())
+    }
+
+    /// Starts building a pipeline file for deck B in the background.
+    pub fn load(&mut self, file: &str, screen_size: (u32, u32), outputs: Vec<CString>) {
+        log::info!("Start building deck B from {}", file);
+        self.loading_file = Some(file.to_owned());
+        self.partial = Some(Box::pin(Pipeline::load(
+            file.to_owned(),
+            screen_size,
+            outputs,
+        )));
+    }
+
+    /// Removes deck B and frees all of its resources.
+    pub fn eject(&mut self) {
+        self.deck_b = None;
+        self.deck_b_file = None;
+        self.partial = None;
+        self.loading_file = None;
+        self.targets = None;
+    }
+
+    /// Builds deck B a little, returning an error message if the build failed.
+    pub fn update_incremental(&mut self, timeout: Duration) -> Result<(), String> {
+        let start = Instant::now();
+        while let Some(part) = self.partial.as_mut() {
+            if start.elapsed() > timeout {
+                return Ok(());
+            }
+
+            if let Some(result) = futures::FutureExt::now_or_never(part) {
+                self.partial = None;
+
+                // audio settings of deck B are ignored, deck A owns the audio module
+                let (pipeline, _) = result?;
+                self.deck_b = Some(pipeline);
+                self.deck_b_file = self.loading_file.take();
+                log::info!("Deck B is ready");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves the fader if the bound midi slider changed since the last frame.
+    pub fn follow_slider(&mut self, sliders: &[f32]) {
+        let value = match self.slider.and_then(|k| sliders.get(k)) {
+            Some(&value) => value,
+            None => return,
+        };
+
+        if value != self.last_slider {
+            self.fader = value.clamp(0.0, 1.0);
+            self.last_slider = value;
+        }
+    }
+
+    /// Returns the framebuffers both decks render into while mixing.
+    ///
+    /// The framebuffers are only kept around while both decks are visible.
+    pub fn targets(&mut self, width: u32, height: u32) -> (GLuint, GLuint) {
+        let resized = match &self.targets {
+            Some((a, _)) => a.resolution()[..2] != [width.max(1), height.max(1)],
+            None => true,
+        };
+
+        if resized {
+            self.targets = Some((
+                FrameBuffer::new(width, height),
+                FrameBuffer::new(width, height),
+            ));
+        }
+
+        let (a, b) = self.targets.as_ref().unwrap();
+        (a.fb_id, b.fb_id)
+    }
+
+    /// Frees the framebuffers of both decks.
+    pub fn release_targets(&mut self) {
+        self.targets = None;
+    }
+
+    /// Mixes both decks into `target_fb`.
+    pub fn composite(&self, vao: GLuint, target_fb: GLuint, width: u32, height: u32) {
+        let (deck_a, deck_b) = match &self.targets {
+            Some(targets) => targets,
+            None => return,
+        };
+
+        let (w, h) = (width as f32, height as f32);
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, target_fb);
+            gl::Viewport(0, 0, width as _, height as _);
+            gl::Disable(gl::BLEND);
+            gl::UseProgram(self.prog_id);
+            gl_debug_check!();
+
+            let res_loc = gl::GetUniformLocation(self.prog_id, RESOLUTION_NAME.as_ptr());
+            gl::Uniform4f(res_loc, w, h, w / h, h / w);
+            let fade_loc = gl::GetUniformLocation(self.prog_id, CROSSFADE_NAME.as_ptr());
+            gl::Uniform1f(fade_loc, self.fader);
+            gl_debug_check!();
+
+            for (k, (name, deck)) in [(&*DECK_A_NAME, deck_a), (&*DECK_B_NAME, deck_b)]
+                .iter()
+                .enumerate()
+            {
+                let loc = gl::GetUniformLocation(self.prog_id, name.as_ptr());
+                gl::ActiveTexture(gl::TEXTURE0 + k as GLenum);
+                deck.bind(k as _);
+                gl::Uniform1i(loc, k as _);
+                gl_debug_check!();
+            }
+
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vao);
+            let pos_attr = gl::GetAttribLocation(self.prog_id, POSITION_NAME.as_ptr());
+            if pos_attr != -1 {
+                gl::EnableVertexAttribArray(pos_attr as GLuint);
+                gl::VertexAttribPointer(
+                    pos_attr as GLuint,
+                    2,
+                    gl::FLOAT,
+                    gl::FALSE as GLboolean,
+                    0,
+                    std::ptr::null(),
+                );
+            }
+            gl_debug_check!();
+
+            draw_fullscreen(vao);
+        }
+    }
+}
+
+impl Drop for Mixer {
+    fn drop(&mut self) {
+        unsafe {
+            for &id in self.sh_ids.iter() {
+                gl::DetachShader(self.prog_id, id);
+                gl::DeleteShader(id);
+            }
+
+            gl::DeleteProgram(self.prog_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deck_view() {
+        assert_eq!(DeckView::new(0.5, false), DeckView::A);
+        assert_eq!(DeckView::new(0.0, true), DeckView::A);
+        assert_eq!(DeckView::new(0.5, true), DeckView::Mix);
+        assert_eq!(DeckView::new(1.0, true), DeckView::B);
+    }
+}
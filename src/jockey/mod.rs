@@ -1,46 +1,91 @@
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
     ffi::CString,
     future::Future,
     hash::{Hash, Hasher},
     io::Write,
-    mem::MaybeUninit,
-    path::{Path, PathBuf},
+    path::PathBuf,
     pin::Pin,
-    rc::Rc,
     sync::atomic::{AtomicBool, Ordering},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use gl::types::*;
 use glutin::{dpi::PhysicalSize, platform::run_return::EventLoopExtRunReturn};
 use imgui::im_str;
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
-use notify::Watcher;
 
 use crate::util::*;
 
 mod audio;
 mod beatsync;
+mod bench;
+mod check;
+mod color;
+mod colorspace;
+mod composite;
+mod condition;
 mod config;
+mod devices;
+mod engine;
+mod error;
+mod hdr;
+mod limiter;
+mod lut;
 mod midi;
+mod mixer;
 mod network;
 mod osc;
+mod oscquery;
+mod output;
+mod overlay;
+mod pacing;
 mod pipeline;
+mod preset;
+mod recorder;
+mod render;
 mod spout;
 mod stage;
+mod state;
+mod ubo;
 mod uniforms;
+mod validate;
+mod watch;
 
 pub use audio::*;
 pub use beatsync::*;
+pub use bench::*;
+pub use check::*;
+pub use color::*;
+pub use colorspace::*;
+pub use composite::*;
+pub use condition::*;
 pub use config::*;
+pub use devices::*;
+pub use engine::*;
+pub use error::*;
+pub use hdr::*;
+pub use limiter::*;
+pub use lut::*;
 pub use midi::*;
+pub use mixer::*;
 pub use network::*;
 pub use osc::*;
+pub use oscquery::*;
+pub use output::*;
+pub use overlay::*;
+pub use pacing::*;
 pub use pipeline::*;
+pub use preset::*;
+pub use recorder::*;
+pub use render::*;
 pub use spout::*;
 pub use stage::*;
+pub use state::*;
+pub use ubo::*;
 pub use uniforms::*;
+pub use validate::*;
+pub use watch::*;
 
 static mut PIPELINE_STALE: AtomicBool = AtomicBool::new(false);
 static mut PROJECT_STALE: AtomicBool = AtomicBool::new(false);
@@ -51,11 +96,24 @@ pub struct MegaContext {
     pub renderer: imgui_opengl_renderer::Renderer,
     pub vao: GLuint,
     pub vbo: GLuint,
-    pub watcher: Option<notify::RecommendedWatcher>,
+    pub watcher: Option<DependencyWatcher>,
     pub context: glutin::WindowedContext<glutin::PossiblyCurrent>,
     pub ui_context: glutin::WindowedContext<glutin::PossiblyCurrent>,
     pub events_loop: glutin::event_loop::EventLoop<()>,
     pub platform: WinitPlatform,
+    pub outputs: Vec<OutputWindow>,
+    pub overlay: Overlay,
+}
+
+impl std::fmt::Debug for MegaContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(MegaContext))
+            .field("vao", &self.vao)
+            .field("vbo", &self.vbo)
+            .field("outputs", &self.outputs)
+            .field("overlay", &self.overlay)
+            .finish()
+    }
 }
 
 /// A struct to keep the state of the tool.
@@ -67,6 +125,16 @@ pub struct Jockey {
     pub ctx: MegaContext,
     pub done: bool,
     pub frame_perf: RunningAverage<f32, 128>,
+    pub present_perf: RunningAverage<f32, 128>,
+    pub fps_limit: Option<f32>,
+    pub present_mode: PresentMode,
+    pub last_present: Instant,
+    pub last_fps_log: Instant,
+    pub frame_pacing: FramePacing,
+    /// Frames which take longer than this many milliseconds flash the overlay.
+    pub hitch_threshold: Option<f32>,
+    /// End and length of the last frame which exceeded the threshold.
+    pub last_hitch: Option<(Instant, f32)>,
     pub beat_sync: BeatSync,
     pub last_build: Instant,
     pub last_frame: Instant,
@@ -77,12 +145,33 @@ pub struct Jockey {
     pub ndi: Ndi,
     pub osc: OscReceiver,
     pub spout: Option<SpoutSender>,
+    pub spout_receiver: Option<SpoutReceiver>,
     pub pipeline_files: Vec<String>,
     pub pipeline_index: usize,
     pub pipeline: Pipeline,
     pub pipeline_partial: Option<Pin<PipelinePartial>>,
+    /// Whether a pipeline file has been built successfully since startup.
+    pub pipeline_loaded: bool,
+    pub pass_overrides: HashMap<String, bool>,
+    pub pass_overrides_file: Option<String>,
+    pub presets: PresetManager,
+    pub mixer: Mixer,
+    pub color: ColorPass,
+    pub limiter: FlashLimiter,
+    pub capture: FrameCapture,
+    pub screenshot_requested: bool,
+    pub pixel_reader: PixelReader,
+    pub pick_requested: bool,
+    /// Target label, pixel and whether to log it, of the pixel which is read back.
+    pub pick_pending: Option<(String, [u32; 2], bool)>,
+    /// Target label, pixel and color of the last pixel which was read back.
+    pub picked_pixel: Option<(String, [u32; 2], [f32; 4])>,
+    pub recorder: Option<Recorder>,
+    pub state_file: Option<PathBuf>,
     pub time: f32,
     pub time_since_build: f32,
+    pub loop_origin: f32,
+    pub last_uniforms: Option<FrameUniforms>,
     pub speed: f32,
     pub time_range: (f32, f32),
     pub custom_res: (i32, i32),
@@ -104,8 +193,69 @@ impl std::fmt::Debug for Jockey {
     }
 }
 
+/// Turns a captured frame into an image, with the top row first.
+fn capture_image(capture: CapturedFrame) -> Option<image::RgbImage> {
+    let CapturedFrame {
+        width,
+        height,
+        pixels,
+        ..
+    } = capture;
+
+    let mut img = match image::RgbImage::from_raw(width, height, pixels) {
+        Some(img) => img,
+        None => {
+            log::error!("Captured frame does not match its size");
+            return None;
+        }
+    };
+
+    image::imageops::flip_vertical_in_place(&mut img);
+    Some(img)
+}
+
+/// Encodes a captured frame as png and saves it in the cwd.
+///
+/// Encoding is slow, so it happens on a background thread.
+fn save_screenshot(capture: CapturedFrame) {
+    std::thread::spawn(move || {
+        let img = match capture_image(capture) {
+            Some(img) => img,
+            None => return,
+        };
+
+        let mut hasher = DefaultHasher::new();
+        Instant::now().hash(&mut hasher);
+        img.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let file_name = format!("frame-{}.png", hash);
+        match img.save(&file_name) {
+            Ok(()) => log::info!("Saved screenshot {}", file_name),
+            Err(err) => log::error!("Failed to save screenshot {}: {}", file_name, err),
+        }
+    });
+}
+
 static CONFIG_ENV: &'static str = "SH4DER_DIR";
 
+/// How long `Jockey::shutdown` waits for each background thread.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long the project files have to be left alone before a reload starts.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Default name of the state file in the config folder.
+///
+/// Not a `.yaml` file, so it is never mistaken for a pipeline.
+const STATE_FILE_NAME: &str = "jockey-state.dat";
+
+/// How long a frame takes at least while the main window has no area to render to.
+const MINIMIZED_SLEEP: Duration = Duration::from_millis(16);
+
+/// How long the overlay highlights a frame which exceeded the hitch threshold.
+const HITCH_FLASH: Duration = Duration::from_millis(500);
+
 fn config_folder_path() -> Option<PathBuf> {
     // Fetch config folder path from enviroment variable
     if let Some(path) = std::env::var_os(CONFIG_ENV) {
@@ -222,14 +372,21 @@ impl Jockey {
 
         // Set up winit for OpenGL stuff
         let context_builder = glutin::ContextBuilder::new()
-            .with_vsync(true)
+            .with_vsync(config.window.vsync)
             .with_gl(request);
 
+        // report available monitors
+        for (k, monitor) in events_loop.available_monitors().enumerate() {
+            log::info!("Monitor {}: {}", k, describe_monitor(&monitor));
+        }
+
         let window_builder = glutin::window::WindowBuilder::new()
             .with_inner_size(glutin::dpi::LogicalSize::new(1280.0, 720.0))
             .with_resizable(true)
             .with_title("Sh4derJockey");
 
+        let window_builder = config.window.apply(window_builder, &events_loop);
+
         #[cfg(target_os = "windows")]
         let window_builder = glutin::platform::windows::WindowBuilderExtWindows::with_drag_and_drop(
             window_builder,
@@ -239,6 +396,7 @@ impl Jockey {
         let built_context = context_builder
             .build_windowed(window_builder, &events_loop)
             .expect("Failed to create windowed context");
+        config.window.place(built_context.window());
 
         let context = unsafe {
             built_context
@@ -249,6 +407,9 @@ impl Jockey {
         let prog_addr = |s| context.get_proc_address(s) as _;
         gl::load_with(prog_addr);
 
+        // the overlay renderer lives in the main context
+        let overlay = Overlay::new(&mut imgui, prog_addr);
+
         // setup OpenGL
         let mut vao = 0;
         let mut vbo = 0;
@@ -259,6 +420,21 @@ impl Jockey {
             gl_debug_check!();
         }
 
+        // create additional output windows
+        let mut outputs = Vec::new();
+        for output_config in config.outputs.iter() {
+            match OutputWindow::new(output_config, &events_loop, context.context()) {
+                Ok(output) => outputs.push(output),
+                Err(err) => log::error!("{}", err),
+            }
+        }
+
+        let context = unsafe {
+            context
+                .make_current()
+                .expect("Failed to activate windowed context")
+        };
+
         let frame_perf = RunningAverage::new();
 
         let ctx = MegaContext {
@@ -271,20 +447,71 @@ impl Jockey {
             ui_context,
             events_loop,
             platform,
+            outputs,
+            overlay,
         };
 
         let pipeline = Pipeline::splash_screen();
-        let midi = Midi::new(&config, config_folder_path.as_deref());
+        let presets = PresetManager::new(config.crossfade);
+        let mixer = Mixer::new(config.crossfade_slider);
+        let color = ColorPass::new(config.color.clone());
+        let limiter = FlashLimiter::new(config.flash_limiter.clone());
+        set_shader_cache_dir(config.shader_cache.clone());
+        let capture = FrameCapture::new();
+        let pixel_reader = PixelReader::new();
+        let mut midi = Midi::new(&config, config_folder_path.as_deref());
         let ndi = Ndi::with_config_path(config_folder_path.clone());
         let osc = OscReceiver::new();
 
+        // restore control values before the first pipeline seeds its defaults
+        let state_file = match &config.state {
+            StateConfig { persist: false, .. } => None,
+            StateConfig {
+                path: Some(path), ..
+            } => Some(path.clone()),
+            StateConfig { path: None, .. } => {
+                let path = config_folder_path
+                    .as_ref()
+                    .map(|base| base.join(STATE_FILE_NAME));
+                if path.is_none() {
+                    log::error!(
+                        "No config folder found, please set a state path to persist control values"
+                    );
+                }
+                path
+            }
+        };
+
+        if let Some(state) = state_file.as_deref().and_then(SavedState::load) {
+            state.restore(&mut midi, &osc);
+        }
+
         let console = "No pipeline has been built yet".into();
 
+        let fps_limit = match config.window.fps_limit {
+            Some(fps) => format!(", limited to {} fps", fps),
+            None => String::new(),
+        };
+        log::info!(
+            "Presenting frames with {} (vsync {}){}",
+            config.window.present_mode.name(),
+            if config.window.vsync { "on" } else { "off" },
+            fps_limit
+        );
+
         let now = Instant::now();
         let mut this = Self {
             ctx,
             done: false,
             frame_perf,
+            present_perf: RunningAverage::new(),
+            fps_limit: config.window.fps_limit,
+            present_mode: config.window.present_mode,
+            last_present: now,
+            last_fps_log: now,
+            frame_pacing: FramePacing::new(),
+            hitch_threshold: config.window.hitch_threshold,
+            last_hitch: None,
             beat_sync: BeatSync::new(),
             last_build: now,
             last_frame: now,
@@ -295,12 +522,30 @@ impl Jockey {
             ndi,
             osc,
             spout: None,
+            spout_receiver: None,
             pipeline_files: Vec::new(),
             pipeline,
             pipeline_index: 0,
             pipeline_partial: None,
+            pipeline_loaded: false,
+            pass_overrides: HashMap::new(),
+            pass_overrides_file: None,
+            presets,
+            mixer,
+            color,
+            limiter,
+            capture,
+            screenshot_requested: false,
+            pixel_reader,
+            pick_requested: false,
+            pick_pending: None,
+            picked_pixel: None,
+            recorder: None,
+            state_file,
             time: 0.0,
             time_since_build: 0.0,
+            loop_origin: 0.0,
+            last_uniforms: None,
             speed: 1.0,
             time_range: (0.0, 60.0),
             custom_res: (512, 512),
@@ -393,6 +638,9 @@ impl Jockey {
             .filter(|s| s != "config.yaml")
             .collect();
 
+        // sort files so preset indices do not depend on the file system
+        self.pipeline_files.sort();
+
         log::info!("Found pipeline files: {:?}", &self.pipeline_files);
 
         // override pipeline index, if the user has no choice
@@ -409,11 +657,93 @@ impl Jockey {
             }
         };
 
-        let screen_size = self.ctx.context.window().inner_size();
-        let screen_size = (screen_size.width as u32, screen_size.height as u32);
+        let screen_size = self.screen_size();
 
         log::info!("Start building pipeline");
-        self.pipeline_partial = Some(Box::pin(Pipeline::load(path.to_owned(), screen_size)));
+        self.pipeline_partial = Some(Box::pin(Pipeline::load(
+            path.to_owned(),
+            screen_size,
+            self.output_textures(),
+        )));
+    }
+
+    /// Starts building a pipeline file for deck B of the mixer.
+    pub fn load_deck_b(&mut self, file: &str) {
+        let screen_size = self.screen_size();
+        let output_textures = self.output_textures();
+        self.mixer.load(file, screen_size, output_textures);
+    }
+
+    /// Releases all GPU resources and rebuilds them from the current settings.
+    ///
+    /// The textures, framebuffers and programs of both decks and of the final
+    /// passes are deleted and created again, and the pipeline is rebuilt from
+    /// its files. Windows, MIDI, audio, NDI and OSC stay connected, and all
+    /// control values are kept. This recovers from a driver hiccup or from
+    /// GL state which went bad during a long session, without a restart.
+    pub fn reinit_gl(&mut self) {
+        log::info!("Reinitializing all OpenGL resources");
+
+        // the splash screen is shown until the rebuild is done, and the
+        // fallback pipeline if it fails
+        self.pipeline_partial = None;
+        self.pipeline = Pipeline::splash_screen();
+        self.pipeline_loaded = false;
+        self.spout = None;
+        self.spout_receiver = None;
+        Cache::clear();
+
+        let deck_b_file = self.mixer.deck_b_file.clone();
+        let mut mixer = Mixer::new(self.mixer.slider);
+        mixer.fader = self.mixer.fader;
+        self.mixer = mixer;
+        self.presets = PresetManager::new(self.presets.crossfade);
+        self.color = ColorPass::new(self.color.config().clone());
+        self.limiter = FlashLimiter::new(self.limiter.config.clone());
+        self.pixel_reader = PixelReader::new();
+        self.pick_pending = None;
+
+        // recordings count the frames of the capture, so it stays while one runs
+        if self.recorder.is_none() {
+            self.capture = FrameCapture::new();
+        }
+
+        unsafe {
+            gl::DeleteBuffers(1, &self.ctx.vbo);
+            gl::DeleteVertexArrays(1, &self.ctx.vao);
+            gl::GenVertexArrays(1, &mut self.ctx.vao);
+            gl::GenBuffers(1, &mut self.ctx.vbo);
+            gl_debug_check!();
+        }
+
+        self.update_pipeline();
+        if let Some(file) = deck_b_file {
+            self.load_deck_b(&file);
+        }
+
+        self.last_build = Instant::now();
+        self.time_since_build = 0.0;
+        self.frame_since_build = 0;
+    }
+
+    /// Returns the size of the main window, which is at least 1 x 1.
+    ///
+    /// Pipelines may be built while the window is minimized, in which case
+    /// their buffers are resized once the window is restored.
+    fn screen_size(&self) -> (u32, u32) {
+        let size = self.ctx.context.window().inner_size();
+        (size.width.max(1), size.height.max(1))
+    }
+
+    /// Returns the names of all textures shown in output windows.
+    ///
+    /// These textures must not be optimized away when building a pipeline.
+    fn output_textures(&self) -> Vec<CString> {
+        self.ctx
+            .outputs
+            .iter()
+            .map(|output| output.texture.clone())
+            .collect()
     }
 
     fn update_pipeline_incremental(&mut self, timeout: Duration) {
@@ -427,90 +757,171 @@ impl Jockey {
                 self.pipeline_partial = None;
 
                 // set waker on current working directory
-                self.ctx.watcher = Some({
-                    let event_fn = |_| unsafe { PIPELINE_STALE.store(true, Ordering::Release) };
-                    let mut watcher = notify::recommended_watcher(event_fn).unwrap();
-                    watcher
-                        .watch(Path::new("."), notify::RecursiveMode::Recursive)
-                        .unwrap();
-
-                    watcher
-                });
+                if self.ctx.watcher.is_none() {
+                    match DependencyWatcher::new() {
+                        Ok(watcher) => self.ctx.watcher = Some(watcher),
+                        Err(err) => log::error!("Failed to watch project folder: {}", err),
+                    }
+                }
 
                 // unwrap pipeline build result
                 let (new_pipeline, update) = match result {
                     Ok(t) => t,
                     Err(err) => {
+                        // the files of a broken build are unknown, so any change may fix it
+                        if let Some(watcher) = self.ctx.watcher.as_mut() {
+                            watcher.set_dependencies(None);
+                        }
+
                         self.console = format!("Failed to build pipeline:\n{}", err);
                         log::error!("{}", &self.console);
+
+                        // keep the splash screen from hiding that nothing could be loaded
+                        if !self.pipeline_loaded {
+                            log::warn!("Showing the fallback pipeline until the error is fixed");
+                            self.pipeline = Pipeline::fallback();
+                        }
                         return;
                     }
                 };
 
-                // set new pipeline
-                self.pipeline = new_pipeline;
-
-                // log build time
-                let build_time = self.last_build.elapsed().as_secs_f64();
-                self.console = format!("Build pipeline over a span of {}s", build_time);
-                log::info!("{}", &self.console);
+                if let Some(watcher) = self.ctx.watcher.as_mut() {
+                    watcher.set_dependencies(Some(&new_pipeline.dependencies));
+                }
 
-                // toggle blend modes
-                unsafe {
-                    match self.pipeline.blending {
-                        true => gl::Enable(gl::BLEND),
-                        false => gl::Disable(gl::BLEND),
-                    }
-                    gl_debug_check!();
+                match self.presets.offer(new_pipeline, update) {
+                    Some((new_pipeline, update)) => self.apply_pipeline(new_pipeline, update),
+                    None => log::info!("Pipeline is ready, switching at the end of the frame"),
                 }
+            }
+        }
+    }
+
+    /// Replaces the current pipeline and reconfigures all modules which depend on it.
+    ///
+    /// The old pipeline is dropped here, which frees all of its GPU resources.
+    /// Audio, MIDI and OSC keep running, so their state carries over.
+    fn apply_pipeline(&mut self, new_pipeline: Pipeline, update: UpdateRequest) {
+        // set new pipeline
+        self.pipeline = new_pipeline;
+        self.pipeline_loaded = true;
+
+        // stage toggles survive rebuilds, but not switching to another file
+        let file = self.pipeline_files.get(self.pipeline_index).cloned();
+        if file != self.pass_overrides_file {
+            self.pass_overrides.clear();
+            self.pass_overrides_file = file;
+        }
+        for (label, &enabled) in self.pass_overrides.iter() {
+            self.pipeline.set_stage_enabled(label, enabled);
+        }
 
-                // copy audio configs
-                self.audio.attack = update.smoothing_attack;
-                self.audio.decay = update.smoothing_decay;
-                if update.audio_samples != self.audio.size {
-                    self.audio.resize(update.audio_samples);
+        // log build time
+        let build_time = self.last_build.elapsed().as_secs_f64();
+        self.console = format!("Build pipeline over a span of {}s", build_time);
+        log::info!("{}", &self.console);
+
+        for warning in self.pipeline.warnings.iter() {
+            self.console.push_str("\nWarning: ");
+            self.console.push_str(warning);
+        }
+
+        // toggle blend modes
+        unsafe {
+            match self.pipeline.blending {
+                true => gl::Enable(gl::BLEND),
+                false => gl::Disable(gl::BLEND),
+            }
+            gl_debug_check!();
+        }
+
+        // copy audio configs
+        self.audio.attack = update.smoothing_attack;
+        self.audio.decay = update.smoothing_decay;
+        if update.audio_samples != self.audio.size {
+            self.audio.resize(update.audio_samples);
+        }
+        self.audio
+            .set_fft_bins(update.fft_bins, update.fft_scale, update.fft_resample);
+        self.audio.stereo_analysis = update.stereo_analysis;
+        self.audio.peak_hold = update.peak_hold;
+        self.audio.peak_decay = update.peak_decay;
+        self.audio
+            .set_profiles(update.audio_profiles, update.audio_profile);
+        self.audio.set_latency(update.audio_latency);
+        self.audio
+            .set_pre_gain(update.audio_pre_gain, update.audio_mute);
+        self.audio.pitch_detection = update.pitch_detection;
+        self.audio.centroid_smoothing = update.centroid_smoothing;
+        self.audio.centroid_normalized = update.centroid_normalized;
+        self.audio.density_window = update.density_window;
+
+        // update ndi module
+        let requests = self.pipeline.requested_ndi_sources.values();
+        if let Err(err) = self.ndi.connect(&requests) {
+            log::error!("Failed to connect to NDI sources: {}", err);
+        }
+
+        // update osc module
+        match &self.pipeline.osc_config {
+            Some(osc_config) => {
+                if let Err(err) = self.osc.apply_config(osc_config) {
+                    log::error!("Failed to start OSC receiver: {}", err);
+                    self.console = format!("OSC Error: {}", err);
+                } else {
+                    log::info!(
+                        "OSC receiver active on port {} with {} mappings",
+                        osc_config.port,
+                        osc_config.mappings.len()
+                    );
                 }
 
-                // update ndi module
-                let requests = self.pipeline.requested_ndi_sources.values();
-                if let Err(err) = self.ndi.connect(&requests) {
-                    log::error!("Failed to connect to NDI sources: {}", err);
+                if let Err(err) = self.osc.serve_query(osc_config) {
+                    log::error!("Failed to start OSCQuery server: {}", err);
+                    self.console = format!("OSCQuery Error: {}", err);
                 }
+            }
+            None => {
+                // keep listening, so `/preset` messages still arrive
+                // after switching to a pipeline without an osc config
+                self.osc.update_type_mappings(&OscConfig::default());
+                self.osc.serve_query(&OscConfig::default()).ok();
+            }
+        }
 
-                // update osc module
-                match &self.pipeline.osc_config {
-                    Some(osc_config) => {
-                        // Update type mappings first
-                        self.osc.update_type_mappings(osc_config);
-
-                        if let Err(err) = self.osc.start(osc_config.port) {
-                            log::error!("Failed to start OSC receiver: {}", err);
-                            self.console = format!("OSC Error: {}", err);
-                        } else {
-                            log::info!("OSC receiver active on port {} with {} mappings",
-                                osc_config.port, osc_config.mappings.len());
-                        }
-                    }
-                    None => {
-                        self.osc.stop();
-                    }
+        // update spout module
+        match &self.pipeline.spout_config {
+            Some(spout_config) if spout_config.enabled => {
+                let mut sender =
+                    SpoutSender::new_on_adapter(&spout_config.sender_name, spout_config.adapter);
+                sender.set_fps(spout_config.fps);
+                log::info!("Spout sender '{}' initialized", spout_config.sender_name);
+                self.spout = Some(sender);
+            }
+            _ => {
+                if self.spout.is_some() {
+                    log::info!("Spout sender disabled");
                 }
+                self.spout = None;
+            }
+        }
 
-                // update spout module
-                match &self.pipeline.spout_config {
-                    Some(spout_config) if spout_config.enabled => {
-                        let sender = SpoutSender::new(&spout_config.sender_name);
-                        log::info!("Spout sender '{}' initialized", spout_config.sender_name);
-                        self.spout = Some(sender);
-                    }
-                    _ => {
-                        if self.spout.is_some() {
-                            log::info!("Spout sender disabled");
-                        }
-                        self.spout = None;
-                    }
+        // keep the receiver connected across rebuilds if the sender stays the same
+        let receive = self.pipeline.spout_config.as_ref().and_then(|config| {
+            config.receive_texture.as_ref()?;
+            Some((config.receive_sender.as_deref(), config.adapter))
+        });
+        match receive {
+            Some((sender, adapter)) => {
+                let current = self
+                    .spout_receiver
+                    .as_ref()
+                    .map(|r| (r.sender_name(), r.adapter()));
+                if current != Some((sender, adapter)) {
+                    self.spout_receiver = Some(SpoutReceiver::new_on_adapter(sender, adapter));
                 }
             }
+            None => self.spout_receiver = None,
         }
     }
 
@@ -537,6 +948,12 @@ impl Jockey {
                 drop(audio);
                 Audio::new(AUDIO_SAMPLES, &config)
             });
+
+            self.presets.crossfade = config.crossfade;
+            self.mixer.slider = config.crossfade_slider;
+
+            // the project folder changed, watch the new one after the next build
+            self.ctx.watcher = None;
         }
 
         let platform = &mut self.ctx.platform;
@@ -545,18 +962,43 @@ impl Jockey {
         let window = self.ctx.context.window();
         let ui_window = self.ctx.ui_context.window();
         let pipeline = &mut self.pipeline;
+        let deck_b = &mut self.mixer.deck_b;
         let alt_pressed = &mut self.alt_pressed;
+        let overlay = &mut self.ctx.overlay;
         let mut done = false;
 
         self.midi.check_connections();
         self.midi.handle_input();
 
         let mut take_screenshot = false;
-        let mut do_update_pipeline = unsafe { PIPELINE_STALE.swap(false, Ordering::AcqRel) }
-            && self.last_build.elapsed().as_millis() > 300;
+        let mut toggle_limiter = false;
+        let mut log_uniforms = false;
+        let mut toggle_recording = false;
+        let mut pick_pixel = false;
+        let mut reinit_gl = self.osc.take_reinit();
+        let mut preset_request = None;
+        // changes made while a build just started are kept for later
+        let can_rebuild = self.last_build.elapsed().as_millis() > 300;
+        let files_changed = match &self.ctx.watcher {
+            Some(watcher) => can_rebuild && watcher.poll(WATCH_DEBOUNCE),
+            None => false,
+        };
+
+        // a reload over OSC waits like file changes do
+        let remote_reload = can_rebuild && self.osc.take_reload();
+
+        let stale = unsafe { PIPELINE_STALE.swap(false, Ordering::AcqRel) };
+        let mut do_update_pipeline = (stale || files_changed || remote_reload) && can_rebuild;
 
         let main_id = self.ctx.context.window().id();
         let ui_id = ui_window.id();
+        let output_ids: Vec<_> = self
+            .ctx
+            .outputs
+            .iter()
+            .map(|output| output.context.window().id())
+            .collect();
+        let mut closed_outputs = Vec::new();
 
         events_loop.run_return(|e, _window_target, cf| {
             match e {
@@ -569,12 +1011,42 @@ impl Jockey {
                     }
 
                     match event {
+                        glutin::event::WindowEvent::CloseRequested
+                            if output_ids.contains(&window_id) =>
+                        {
+                            closed_outputs.push(window_id)
+                        }
+
                         glutin::event::WindowEvent::CloseRequested => done = true,
 
-                        glutin::event::WindowEvent::Resized(size) if window_id == main_id => {
+                        // minimizing reports a size of zero, the buffers are kept
+                        // until the window comes back and reports its real size
+                        glutin::event::WindowEvent::Resized(size)
+                            if window_id == main_id && size.width > 0 && size.height > 0 =>
+                        {
                             let width = size.width as u32;
                             let height = size.height as u32;
                             pipeline.resize_buffers(width, height);
+                            if let Some(deck_b) = deck_b {
+                                deck_b.resize_buffers(width, height);
+                            }
+                        }
+
+                        glutin::event::WindowEvent::CursorMoved { position, .. }
+                            if window_id == main_id =>
+                        {
+                            overlay.cursor = [position.x as f32, position.y as f32];
+                        }
+
+                        glutin::event::WindowEvent::CursorLeft { .. } if window_id == main_id => {
+                            overlay.cursor = [f32::MIN; 2];
+                        }
+
+                        glutin::event::WindowEvent::MouseInput { state, button, .. }
+                            if window_id == main_id
+                                && *button == glutin::event::MouseButton::Left =>
+                        {
+                            overlay.mouse_down = *state == glutin::event::ElementState::Pressed;
                         }
 
                         #[allow(deprecated)]
@@ -592,6 +1064,11 @@ impl Jockey {
                                     do_update_pipeline = true;
                                 }
 
+                                // recreate all GPU resources
+                                if ctrl && shift && !(alt || logo) {
+                                    reinit_gl = true;
+                                }
+
                                 // toggle fullscreen mode
                                 if alt && !(shift || ctrl || logo) && window.id() == window_id {
                                     if window.fullscreen().is_some() {
@@ -608,6 +1085,72 @@ impl Jockey {
                                 }
                             }
 
+                            // toggle on-screen overlay
+                            if Some(glutin::event::VirtualKeyCode::F1) == input.virtual_keycode
+                                && input.state == glutin::event::ElementState::Pressed
+                                && !(shift || ctrl || alt || logo)
+                            {
+                                overlay.visible = !overlay.visible;
+                            }
+
+                            // toggle flash limiter
+                            if Some(glutin::event::VirtualKeyCode::F2) == input.virtual_keycode
+                                && input.state == glutin::event::ElementState::Pressed
+                                && !(shift || ctrl || alt || logo)
+                            {
+                                toggle_limiter = true;
+                            }
+
+                            // write the current uniforms to the log
+                            if Some(glutin::event::VirtualKeyCode::F3) == input.virtual_keycode
+                                && input.state == glutin::event::ElementState::Pressed
+                                && !(shift || ctrl || alt || logo)
+                            {
+                                log_uniforms = true;
+                            }
+
+                            // start or stop recording frames with their uniforms
+                            if Some(glutin::event::VirtualKeyCode::F4) == input.virtual_keycode
+                                && input.state == glutin::event::ElementState::Pressed
+                                && !(shift || ctrl || alt || logo)
+                            {
+                                toggle_recording = true;
+                            }
+
+                            // read the color under the cursor
+                            if Some(glutin::event::VirtualKeyCode::F5) == input.virtual_keycode
+                                && input.state == glutin::event::ElementState::Pressed
+                                && !(shift || ctrl || alt || logo)
+                            {
+                                pick_pixel = true;
+                            }
+
+                            // switch between pipeline files
+                            if input.state == glutin::event::ElementState::Pressed
+                                && ctrl
+                                && !(shift || alt || logo)
+                            {
+                                use glutin::event::VirtualKeyCode::*;
+                                let request = match input.virtual_keycode {
+                                    Some(Key1) => Some(PresetRequest::Index(0)),
+                                    Some(Key2) => Some(PresetRequest::Index(1)),
+                                    Some(Key3) => Some(PresetRequest::Index(2)),
+                                    Some(Key4) => Some(PresetRequest::Index(3)),
+                                    Some(Key5) => Some(PresetRequest::Index(4)),
+                                    Some(Key6) => Some(PresetRequest::Index(5)),
+                                    Some(Key7) => Some(PresetRequest::Index(6)),
+                                    Some(Key8) => Some(PresetRequest::Index(7)),
+                                    Some(Key9) => Some(PresetRequest::Index(8)),
+                                    Some(PageDown) => Some(PresetRequest::Offset(1)),
+                                    Some(PageUp) => Some(PresetRequest::Offset(-1)),
+                                    _ => None,
+                                };
+
+                                if request.is_some() {
+                                    preset_request = request;
+                                }
+                            }
+
                             if Some(glutin::event::VirtualKeyCode::S) == input.virtual_keycode
                                 && input.state == glutin::event::ElementState::Pressed
                             {
@@ -627,12 +1170,44 @@ impl Jockey {
 
         self.done = done;
 
+        // closing an output window only closes that window
+        self.ctx
+            .outputs
+            .retain(|output| !closed_outputs.contains(&output.context.window().id()));
+
         if take_screenshot {
             self.save_frame();
         }
 
-        // live shader reloading hype
-        if do_update_pipeline {
+        if toggle_limiter {
+            self.limiter.toggle();
+        }
+
+        if log_uniforms {
+            self.log_uniforms();
+        }
+
+        if toggle_recording {
+            self.toggle_recording();
+        }
+
+        if pick_pixel {
+            self.pick_pixel();
+        }
+
+        // switch presets by keyboard, midi or osc
+        let preset_request = preset_request
+            .or_else(|| self.midi.take_preset_request())
+            .or_else(|| self.osc.take_preset_request());
+
+        if let Some(request) = preset_request {
+            do_update_pipeline |= self.select_preset(&request);
+        }
+
+        // a reset rebuilds the pipeline anyway
+        if reinit_gl {
+            self.reinit_gl();
+        } else if do_update_pipeline {
             self.update_pipeline();
             self.last_build = Instant::now();
             self.time_since_build = 0.0;
@@ -640,6 +1215,28 @@ impl Jockey {
         }
     }
 
+    /// Selects another pipeline file and marks the next build as a preset switch.
+    ///
+    /// Returns `true` if the pipeline needs to be rebuilt.
+    pub fn select_preset(&mut self, request: &PresetRequest) -> bool {
+        let index = match request.resolve(&self.pipeline_files, self.pipeline_index) {
+            Some(index) => index,
+            None => {
+                log::warn!("No pipeline file matches preset request {:?}", request);
+                return false;
+            }
+        };
+
+        if index == self.pipeline_index && self.pipeline_partial.is_none() {
+            return false;
+        }
+
+        log::info!("Switching to preset {}", &self.pipeline_files[index]);
+        self.pipeline_index = index;
+        self.presets.begin_switch();
+        true
+    }
+
     /// Does all the OpenGL magic.
     ///
     /// This function iterates over all stages in the pipeline and renders
@@ -652,6 +1249,10 @@ impl Jockey {
 
         // build pipeline a little
         self.update_pipeline_incremental(Duration::from_micros(50));
+        if let Err(err) = self.mixer.update_incremental(Duration::from_micros(50)) {
+            self.console = format!("Failed to build deck B:\n{}", err);
+            log::error!("{}", &self.console);
+        }
 
         // compute uniforms
         let screen_size = self.ctx.context.window().inner_size();
@@ -663,494 +1264,579 @@ impl Jockey {
         let delta = self.speed * now.duration_since(self.last_frame).as_secs_f32();
         let frame = self.frame;
         let frame_since_build = self.frame_since_build;
+        let fps = match self.present_perf.get() {
+            ms if ms > 0.0 => 1000.0 / ms,
+            _ => 0.0,
+        };
+        let (frame_time, frame_time_max) = self.frame_pacing.stats();
         self.time += delta;
         self.time_since_build += delta;
         self.last_frame = now;
         self.frame = self.frame.wrapping_add(1);
         self.frame_since_build = self.frame_since_build.wrapping_add(1);
 
-        {
-            // update audio samples texture
-            self.audio.update_samples();
-            self.audio.update_fft();
-
-            fn audio_tex_update(
-                buffers: &mut HashMap<CString, Rc<dyn Texture>>,
-                name: &CString,
-                left: &[f32],
-                right: &[f32],
-            ) {
-                if let Some(tex) = buffers.get_mut(name) {
-                    unsafe {
-                        alloca::with_slice(left.len() + right.len(), |buffer| {
-                            let buffer = &mut *(buffer as *mut [MaybeUninit<f32>] as *mut _);
-
-                            interlace(left, right, buffer);
-                            Rc::get_mut(tex)
-                                .unwrap()
-                                .as_any_mut()
-                                .downcast_mut::<Texture1D>()
-                                .unwrap()
-                                .write(buffer.as_ptr() as _);
-                        })
-                    }
-                }
+
+        // update audio samples
+        self.audio.update_samples();
+        self.audio.update_fft();
+
+        // apply OSC values which arrived since the last frame
+        self.osc.tick();
+
+        // fetch the latest frame of the spout input
+        if let Some(receiver) = &mut self.spout_receiver {
+            receiver.check_receiver();
+        }
+
+        // switch stages on or off
+        for (label, enabled) in self.osc.take_pass_toggles() {
+            let mut found = self.pipeline.set_stage_enabled(&label, enabled);
+            if let Some(deck_b) = &mut self.mixer.deck_b {
+                found |= deck_b.set_stage_enabled(&label, enabled);
             }
 
-            for (tex_name, src_name) in self.pipeline.requested_ndi_sources.iter() {
-                let tex = self.pipeline.buffers.get_mut(tex_name).unwrap();
-                let tex = Rc::get_mut(tex)
-                    .unwrap()
-                    .as_any_mut()
-                    .downcast_mut::<Texture2D>()
-                    .unwrap();
-                self.ndi.update_texture(src_name, tex);
+            if !found {
+                log::warn!("No stage with target {:?} to switch on or off", label);
             }
+            self.pass_overrides.insert(label, enabled);
+        }
 
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SAMPLES_NAME,
-                &self.audio.l_signal,
-                &self.audio.r_signal,
-            );
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SPECTRUM_RAW_NAME,
-                &self.audio.l_raw_spectrum,
-                &self.audio.r_raw_spectrum,
-            );
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SPECTRUM_NAME,
-                &self.audio.l_spectrum,
-                &self.audio.r_spectrum,
-            );
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SPECTRUM_SMOOTH_NAME,
-                &self.audio.l_spectrum_smooth,
-                &self.audio.r_spectrum_smooth,
-            );
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SPECTRUM_SMOOTH_INTEGRATED_NAME,
-                &self.audio.l_spectrum_smooth_integrated,
-                &self.audio.r_spectrum_smooth_integrated,
-            );
-            audio_tex_update(
-                &mut self.pipeline.buffers,
-                &SPECTRUM_INTEGRATED_NAME,
-                &self.audio.l_spectrum_integrated,
-                &self.audio.r_spectrum_integrated,
-            );
+        // move the crossfader
+        self.mixer.follow_slider(&self.midi.sliders);
+        if let Some(fader) = self.osc.take_crossfade() {
+            self.mixer.fader = fader;
         }
 
-        // render all shader stages
-        for (pass_num, stage) in self.pipeline.stages.iter_mut().enumerate() {
-            let stage_start = Instant::now();
+        if let Some(enabled) = self.osc.take_flash_limiter() {
+            self.limiter.set_enabled(enabled);
+        }
 
-            // skip stage if target is never used
-            if !matches!(stage.kind, StageKind::Comp { .. }) {
-                if let Some(name) = &stage.target {
-                    if self.pipeline.buffers.get(name).is_none() {
-                        continue;
-                    }
-                }
+        if let Some(strength) = self.osc.take_lut_strength() {
+            self.color.set_lut_strength(strength);
+        }
+
+        // switch audio profiles by midi or osc
+        let profile_request = self
+            .midi
+            .take_audio_profile_request()
+            .or_else(|| self.osc.take_audio_profile_request());
+        if let Some(request) = profile_request {
+            self.audio.select_profile(&request);
+        }
+
+        if self.osc.take_loop_reset() {
+            self.loop_origin = time;
+        }
+
+        // nothing can be drawn to the main window while it is minimized, but audio
+        // and OSC above still have to keep up, and output windows keep showing the
+        // pipeline at the size of the last frame
+        let minimized = width == 0 || height == 0;
+        let (width, height) = match self.last_uniforms {
+            Some(last) if minimized => (last.width, last.height),
+            _ => (width, height),
+        };
+        if minimized && (self.ctx.outputs.is_empty() || width == 0 || height == 0) {
+            std::thread::sleep(MINIMIZED_SLEEP);
+            return;
+        }
+
+        let uniforms = FrameUniforms {
+            width,
+            height,
+            beat,
+            time,
+            time_since_build,
+            loop_time: loop_phase(time, self.loop_origin, self.pipeline.loop_period),
+            delta,
+            frame,
+            frame_since_build,
+            fps,
+            frame_time,
+            frame_time_max,
+        };
+        self.last_uniforms = Some(uniforms);
+
+        // without the vsync of the main window, output windows are paced by the clock
+        if minimized {
+            self.render_pipeline(0, &uniforms);
+            for output in self.ctx.outputs.iter_mut() {
+                output.draw(&self.pipeline.buffers);
             }
 
-            // get size of the render target
-            let target_res = match stage.resolution() {
-                Some(s) => s,
-                _ => [width, height, 0],
-            };
+            take_mut::take(&mut self.ctx.context, |s| unsafe {
+                s.make_current().unwrap()
+            });
+
+            std::thread::sleep(MINIMIZED_SLEEP.saturating_sub(now.elapsed()));
+            return;
+        }
 
+        // render the visible decks
+        let color_fb = self.color.target(0, width, height);
+        let screen_fb = self.limiter.target(color_fb, width, height);
+        match self.mixer.view() {
+            DeckView::A => {
+                self.mixer.release_targets();
+                self.render_pipeline(screen_fb, &uniforms);
+            }
+            DeckView::B => {
+                self.mixer.release_targets();
+                self.render_deck_b(screen_fb, &uniforms);
+            }
+            DeckView::Mix => {
+                let (deck_a_fb, deck_b_fb) = self.mixer.targets(width, height);
+                self.render_pipeline(deck_a_fb, &uniforms);
+                self.render_deck_b(deck_b_fb, &uniforms);
+                self.mixer.composite(self.ctx.vao, screen_fb, width, height);
+            }
+        }
+
+        // slow down full screen flashes
+        self.limiter.apply(self.ctx.vao, color_fb, width, height);
+
+        // tone map and encode the frame, without dither if only the screen gets it
+        let spout_due = self.spout.as_ref().map_or(false, SpoutSender::is_due);
+        let redither = spout_due && !self.color.spout_matches_screen();
+        self.color
+            .present(self.ctx.vao, 0, width, height, !redither);
+
+        // fade out the last frame of the previous preset
+        self.presets.draw_fade(self.ctx.vao, width, height);
+
+        // send the finished frame to Spout, before the overlay is drawn on top
+        if let Some(spout) = self.spout.as_mut().filter(|spout| spout.is_due()) {
+            // Spout needs a texture, so copy the back buffer of the window into one.
+            // The copy reads the default framebuffer explicitly, so stages or
+            // passes which leave another framebuffer bound can't skip the frame.
             unsafe {
-                // Use shader program
-                gl::UseProgram(stage.prog_id);
+                let mut temp_texture: GLuint = 0;
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+                gl::ReadBuffer(gl::BACK);
+                gl::GenTextures(1, &mut temp_texture);
+                gl::BindTexture(gl::TEXTURE_2D, temp_texture);
+                gl::CopyTexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    gl::RGBA8,
+                    0,
+                    0,
+                    width as GLint,
+                    height as GLint,
+                    0,
+                );
                 gl_debug_check!();
 
-                {
-                    // Add time, beat, resolution and volume
-                    let r_loc = gl::GetUniformLocation(stage.prog_id, R_NAME.as_ptr());
-                    let k_loc = gl::GetUniformLocation(stage.prog_id, K_NAME.as_ptr());
-                    let res_loc = gl::GetUniformLocation(stage.prog_id, RESOLUTION_NAME.as_ptr());
-                    let pass_loc = gl::GetUniformLocation(stage.prog_id, PASS_INDEX_NAME.as_ptr());
-                    let time_loc = gl::GetUniformLocation(stage.prog_id, TIME_NAME.as_ptr());
-                    let time_since_build_loc =
-                        gl::GetUniformLocation(stage.prog_id, TIME_SINCE_BUILD_NAME.as_ptr());
-                    let frame_loc =
-                        gl::GetUniformLocation(stage.prog_id, FRAME_COUNT_NAME.as_ptr());
-                    let frame_since_build_loc =
-                        gl::GetUniformLocation(stage.prog_id, FRAME_COUNT_SINCE_BUILD_NAME.as_ptr());
-                    let delta_loc = gl::GetUniformLocation(stage.prog_id, TIME_DELTA_NAME.as_ptr());
-                    let beat_loc = gl::GetUniformLocation(stage.prog_id, BEAT_NAME.as_ptr());
-                    let volume_loc = gl::GetUniformLocation(stage.prog_id, VOLUME_NAME.as_ptr());
-                    let volume_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, VOLUME_INTEGRATED_NAME.as_ptr());
-                    let bass_loc = gl::GetUniformLocation(stage.prog_id, BASS_NAME.as_ptr());
-                    let mid_loc = gl::GetUniformLocation(stage.prog_id, MID_NAME.as_ptr());
-                    let high_loc = gl::GetUniformLocation(stage.prog_id, HIGH_NAME.as_ptr());
-                    let smooth_bass_loc =
-                        gl::GetUniformLocation(stage.prog_id, BASS_SMOOTH_NAME.as_ptr());
-                    let smooth_mid_loc =
-                        gl::GetUniformLocation(stage.prog_id, MID_SMOOTH_NAME.as_ptr());
-                    let smooth_high_loc =
-                        gl::GetUniformLocation(stage.prog_id, HIGH_SMOOTH_NAME.as_ptr());
-
-                    let bass_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, BASS_INTEGRATED_NAME.as_ptr());
-                    let mid_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, MID_INTEGRATED_NAME.as_ptr());
-                    let high_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, HIGH_INTEGRATED_NAME.as_ptr());
-                    let smooth_bass_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, BASS_SMOOTH_INTEGRATED_NAME.as_ptr());
-                    let smooth_mid_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, MID_SMOOTH_INTEGRATED_NAME.as_ptr());
-                    let smooth_high_integrated_loc =
-                        gl::GetUniformLocation(stage.prog_id, HIGH_SMOOTH_INTEGRATED_NAME.as_ptr());
-
-                    gl::Uniform4f(
-                        res_loc,
-                        target_res[0] as f32,
-                        target_res[1] as f32,
-                        target_res[0] as f32 / target_res[1] as f32, // x/y
-                        target_res[1] as f32 / target_res[0] as f32, // x/y
-                    );
-                    gl::Uniform3f(r_loc, target_res[0] as _, target_res[1] as _, time);
-                    gl::Uniform3f(
-                        volume_loc,
-                        self.audio.volume[0], // average L/R
-                        self.audio.volume[1], // L
-                        self.audio.volume[2], // R
-                    );
-                    gl::Uniform3f(
-                        bass_loc,
-                        self.audio.bass[0],
-                        self.audio.bass[1],
-                        self.audio.bass[2],
-                    );
-                    gl::Uniform3f(
-                        mid_loc,
-                        self.audio.mid[0],
-                        self.audio.mid[1],
-                        self.audio.mid[2],
-                    );
-                    gl::Uniform3f(
-                        high_loc,
-                        self.audio.high[0],
-                        self.audio.high[1],
-                        self.audio.high[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_bass_loc,
-                        self.audio.bass_smooth[0],
-                        self.audio.bass_smooth[1],
-                        self.audio.bass_smooth[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_mid_loc,
-                        self.audio.mid_smooth[0],
-                        self.audio.mid_smooth[1],
-                        self.audio.mid_smooth[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_high_loc,
-                        self.audio.high_smooth[0],
-                        self.audio.high_smooth[1],
-                        self.audio.high_smooth[2],
-                    );
-                    gl::Uniform3f(
-                        volume_integrated_loc,
-                        self.audio.volume_integrated[0], // average L/R
-                        self.audio.volume_integrated[1], // L
-                        self.audio.volume_integrated[2], // R
-                    );
-                    gl::Uniform3f(
-                        bass_integrated_loc,
-                        self.audio.bass_integrated[0],
-                        self.audio.bass_integrated[1],
-                        self.audio.bass_integrated[2],
-                    );
-                    gl::Uniform3f(
-                        mid_integrated_loc,
-                        self.audio.mid_integrated[0],
-                        self.audio.mid_integrated[1],
-                        self.audio.mid_integrated[2],
-                    );
-                    gl::Uniform3f(
-                        high_integrated_loc,
-                        self.audio.high_integrated[0],
-                        self.audio.high_integrated[1],
-                        self.audio.high_integrated[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_bass_integrated_loc,
-                        self.audio.bass_smooth_integrated[0],
-                        self.audio.bass_smooth_integrated[1],
-                        self.audio.bass_smooth_integrated[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_mid_integrated_loc,
-                        self.audio.mid_smooth_integrated[0],
-                        self.audio.mid_smooth_integrated[1],
-                        self.audio.mid_smooth_integrated[2],
-                    );
-                    gl::Uniform3f(
-                        smooth_high_integrated_loc,
-                        self.audio.high_smooth_integrated[0],
-                        self.audio.high_smooth_integrated[1],
-                        self.audio.high_smooth_integrated[2],
-                    );
-                    gl::Uniform2i(k_loc, pass_num as _, frame as _);
-                    gl::Uniform1i(pass_loc, pass_num as _);
-                    gl::Uniform1i(frame_loc, frame as _);
-                    gl::Uniform1i(frame_since_build_loc, frame_since_build as _);
-                    gl::Uniform1f(time_loc, time);
-                    gl::Uniform1f(time_since_build_loc, time_since_build);
-                    gl::Uniform1f(beat_loc, beat);
-                    gl::Uniform1f(delta_loc, delta);
-                    gl_debug_check!();
+                if let Err(err) = spout.send_texture(temp_texture, width, height) {
+                    log::warn!("Failed to send texture to Spout: {}", err);
                 }
 
-                {
-                    // Add sliders and buttons
-                    let s_loc = gl::GetUniformLocation(stage.prog_id, SLIDERS_NAME.as_ptr());
-                    let b_loc = gl::GetUniformLocation(stage.prog_id, BUTTONS_NAME.as_ptr());
-
-                    let mut buttons = [0.0; 4 * MIDI_N];
-                    for (k, button) in self.midi.buttons.iter().enumerate() {
-                        buttons[k * 4 + 0] = button.0;
-                        buttons[k * 4 + 1] = button.1.elapsed().as_secs_f32();
-                        buttons[k * 4 + 2] = button.2.elapsed().as_secs_f32();
-                        buttons[k * 4 + 3] = button.3 as f32;
-                    }
+                gl::DeleteTextures(1, &temp_texture);
+            }
+        }
 
-                    gl::Uniform1fv(s_loc, self.midi.sliders.len() as _, &self.midi.sliders as _);
-                    gl::Uniform4fv(b_loc, self.midi.buttons.len() as _, &buttons as _);
-                    gl_debug_check!();
-                }
+        // Spout has its copy, draw the dithered frame for the screen
+        if redither {
+            self.color.present(self.ctx.vao, 0, width, height, true);
+            self.presets.draw_fade(self.ctx.vao, width, height);
+        }
 
-                // Add OSC uniforms
-                if let Some(osc_config) = &self.pipeline.osc_config {
-                    let osc_values = self.osc.get_all_values();
-                    for (uniform_name, mapping) in &osc_config.mappings {
-                        if let Some(value) = osc_values.get(&mapping.address) {
-                            if let Ok(uniform_cstr) = std::ffi::CString::new(uniform_name.as_str()) {
-                                let loc = gl::GetUniformLocation(stage.prog_id, uniform_cstr.as_ptr());
-                                if loc != -1 {
-                                    match value {
-                                        OscUniformValue::Float(f) => {
-                                            gl::Uniform1f(loc, *f);
-                                        }
-                                        OscUniformValue::Int(i) => {
-                                            gl::Uniform1i(loc, *i);
-                                        }
-                                        OscUniformValue::Bool(b) => {
-                                            gl::Uniform1i(loc, if *b { 1 } else { 0 });
-                                        }
-                                    }
-                                    gl_debug_check!();
-                                }
-                            } else {
-                                log::warn!("Invalid uniform name for OSC mapping: {}", uniform_name);
-                            }
-                        }
-                    }
-                }
+        unsafe {
+            match self.pipeline.blending {
+                true => gl::Enable(gl::BLEND),
+                false => gl::Disable(gl::BLEND),
+            }
+            gl_debug_check!();
+        }
 
-                // Add custom uniforms
-                for (name, uniform) in &stage.unis {
-                    let loc = gl::GetUniformLocation(stage.prog_id, name.as_ptr());
-                    uniform.bind(loc);
-                    gl_debug_check!();
-                }
+        // keep the last frame of the outgoing pipeline for the crossfade
+        self.presets.capture(width, height);
+
+        // remember what went into the frame, to render it again later
+        let mut record_frame = false;
+        let activity = self.input_activity();
+        if let Some(recorder) = self.recorder.as_mut().filter(|r| r.is_active()) {
+            let inputs = RenderInputs {
+                vao: self.ctx.vao,
+                audio: &self.audio,
+                sliders: &self.midi.sliders,
+                buttons: &self.midi.buttons,
+                pitch_bend: self.midi.pitch_bend,
+                pressure: self.midi.pressure,
+                osc: &self.osc,
+                ndi: Some(&self.ndi),
+                spout: self.spout_receiver.as_ref(),
+                crossfade: self.mixer.fader,
+                activity,
+            };
 
-                // Add vertex count uniform
-                if let StageKind::Vert { count, .. } = stage.kind {
-                    let loc = gl::GetUniformLocation(stage.prog_id, VERTEX_COUNT_NAME.as_ptr());
-                    gl::Uniform1i(loc, count as _);
-                    gl_debug_check!();
-                }
+            let snapshot = FrameSnapshot {
+                flash_limiter: Some(self.limiter.config.enabled),
+                lut_strength: Some(self.color.config().lut_strength),
+                ..FrameSnapshot::capture(&inputs, &uniforms, self.loop_origin)
+            };
+            recorder.add_frame(self.capture.frame(), &snapshot);
+            record_frame = true;
+        }
+
+        // read back screenshots without stalling the gpu
+        if std::mem::take(&mut self.screenshot_requested) || record_frame {
+            self.capture.request(width, height);
+        }
+        for capture in self.capture.finish_frame() {
+            let capture = match &mut self.recorder {
+                Some(recorder) => recorder.take_capture(capture),
+                None => Some(capture),
+            };
+
+            if let Some(capture) = capture {
+                save_screenshot(capture);
+            }
+        }
+        if self.recorder.as_ref().map_or(false, Recorder::is_finished) {
+            self.recorder = None;
+        }
+
+        // read back picked colors without stalling the gpu
+        self.update_picker(width, height);
+
+        // the overlay is drawn after the frame was sent,
+        // so it never shows up in the shared texture
+        if self.ctx.overlay.visible {
+            self.draw_overlay();
+        }
 
-                // Add and bind uniform texture dependencies
-                for (k, name) in stage.deps.iter().enumerate() {
-                    let tex = self.pipeline.buffers.get(name).unwrap();
-                    let loc = gl::GetUniformLocation(stage.prog_id, name.as_ptr());
-                    debug_assert_ne!(loc, -1);
+        // present textures in output windows, before the main window, which is
+        // the only one waiting for vsync
+        for output in self.ctx.outputs.iter_mut() {
+            output.draw(&self.pipeline.buffers);
+        }
 
-                    gl::ActiveTexture(gl::TEXTURE0 + k as GLenum);
-                    gl_debug_check!();
+        take_mut::take(&mut self.ctx.context, |s| unsafe {
+            s.make_current().unwrap()
+        });
 
-                    tex.bind(k as _);
-                    gl_debug_check!();
+        if let Err(err) = self.ctx.context.swap_buffers() {
+            log::warn!("Failed to swap buffers of main window: {}", err);
+        }
 
-                    gl::Uniform1i(loc, k as _);
-                    gl_debug_check!();
+        // wait until the frame is shown, so the next one starts with fresh input
+        if self.present_mode == PresentMode::LowLatency {
+            unsafe { gl::Finish() };
+        }
 
-                    let name_len = name.as_bytes().len();
-                    let res_loc = alloca::with_bytes_zeroed(name_len + 5, |res_name| {
-                        res_name[..name_len].copy_from_slice(name.as_bytes());
-                        res_name[name_len..].copy_from_slice("_res\0".as_bytes());
+        // switch presets at the frame boundary
+        if let Some((new_pipeline, update)) = self.presets.take_ready() {
+            self.apply_pipeline(new_pipeline, update);
+        }
 
-                        gl::GetUniformLocation(stage.prog_id, res_name.as_ptr() as _)
-                    });
+        self.limit_frame_rate();
+    }
 
-                    let res = tex.resolution();
-                    gl_debug_check!();
+    /// Renders all stages of the current pipeline.
+    ///
+    /// Stages without a target draw into `screen_fb`, which is the default
+    /// framebuffer unless the pipeline is one of two decks being mixed.
+    fn render_pipeline(&mut self, screen_fb: GLuint, uniforms: &FrameUniforms) {
+        let inputs = RenderInputs {
+            vao: self.ctx.vao,
+            audio: &self.audio,
+            sliders: &self.midi.sliders,
+            buttons: &self.midi.buttons,
+            pitch_bend: self.midi.pitch_bend,
+            pressure: self.midi.pressure,
+            osc: &self.osc,
+            ndi: Some(&self.ndi),
+            spout: self.spout_receiver.as_ref(),
+            crossfade: self.mixer.fader,
+            activity: self.input_activity(),
+        };
 
-                    gl::Uniform4f(
-                        res_loc,
-                        res[0] as _,
-                        res[1] as _,
-                        res[2] as _,
-                        res[0] as f32 / res[1] as f32,
-                    );
-                    gl_debug_check!();
-                }
-            }
+        render_stages(&mut self.pipeline, &inputs, screen_fb, uniforms);
+    }
 
-            match &stage.kind {
-                StageKind::Comp { dispatch, .. } => unsafe {
-                    gl::DispatchCompute(dispatch[0], dispatch[1], dispatch[2]);
-                    gl::MemoryBarrier(
-                        gl::TEXTURE_UPDATE_BARRIER_BIT
-                            | gl::TEXTURE_FETCH_BARRIER_BIT
-                            | gl::SHADER_IMAGE_ACCESS_BARRIER_BIT,
-                    );
-                    gl_debug_check!();
-                },
-                _ => unsafe {
-                    debug_assert_eq!(target_res[2], 0);
-
-                    // get render target id
-                    let (target_tex, target_fb) = if let Some(name) = &stage.target {
-                        let tex = self.pipeline.buffers.get(name).unwrap();
-                        let tex_id = tex.texture_id();
-                        let fb_id = tex
-                            .framebuffer_id()
-                            .expect("Render target should be a framebuffer");
-                        (tex_id, fb_id)
-                    } else {
-                        (0, 0) // The screen is always id=0
-                    };
+    /// Collects which inputs are running and received data recently.
+    fn input_activity(&self) -> InputActivity {
+        InputActivity::new(
+            &self.audio,
+            &self.osc,
+            self.spout_receiver.as_ref(),
+            Some(&self.midi),
+        )
+    }
 
-                    // Specify render target
-                    gl::BindFramebuffer(gl::FRAMEBUFFER, target_fb);
-                    gl::Viewport(0, 0, target_res[0] as _, target_res[1] as _);
-                    gl_debug_check!();
-
-                    // Specify fragment shader color output
-                    gl::BindFragDataLocation(stage.prog_id, 0, OUT_COLOR_NAME.as_ptr());
-                    gl_debug_check!();
-
-                    // Specify the layout of the vertex data
-                    let pos_attr = gl::GetAttribLocation(stage.prog_id, POSITION_NAME.as_ptr());
-                    if pos_attr != -1 {
-                        gl_debug_check!();
-                        gl::EnableVertexAttribArray(pos_attr as GLuint);
-                        gl_debug_check!();
-                        gl::VertexAttribPointer(
-                            pos_attr as GLuint,
-                            2,
-                            gl::FLOAT,
-                            gl::FALSE as GLboolean,
-                            0,
-                            std::ptr::null(),
-                        );
-                    }
-                    gl_debug_check!();
+    /// Lists the uniforms of the last frame of deck A with their sources.
+    ///
+    /// Returns an empty map until the first frame was rendered.
+    pub fn dump_uniforms(&self) -> HashMap<String, String> {
+        let uniforms = match &self.last_uniforms {
+            Some(uniforms) => uniforms,
+            None => return HashMap::new(),
+        };
 
-                    // Set blend mode
-                    if self.pipeline.blending {
-                        let (src, dst) = stage.blend.unwrap_or((gl::ONE, gl::ZERO));
-                        gl::BlendFunc(src, dst);
-                        gl_debug_check!();
-                    }
+        let inputs = RenderInputs {
+            vao: self.ctx.vao,
+            audio: &self.audio,
+            sliders: &self.midi.sliders,
+            buttons: &self.midi.buttons,
+            pitch_bend: self.midi.pitch_bend,
+            pressure: self.midi.pressure,
+            osc: &self.osc,
+            ndi: Some(&self.ndi),
+            spout: self.spout_receiver.as_ref(),
+            crossfade: self.mixer.fader,
+            activity: self.input_activity(),
+        };
 
-                    // Draw stuff
-                    if let StageKind::Vert {
-                        count,
-                        mode,
-                        thickness,
-                        ..
-                    } = stage.kind
-                    {
-                        gl::ClearColor(0.0, 0.0, 0.0, 0.0);
-                        gl::Clear(gl::COLOR_BUFFER_BIT);
-                        gl_debug_check!();
-
-                        gl::PointSize(thickness);
-                        gl::LineWidth(thickness);
-                        gl_debug_check!();
-
-                        draw_vertices(self.ctx.vao, count, mode);
-                        gl_debug_check!();
-                    } else {
-                        draw_fullscreen(self.ctx.vao);
-                        gl_debug_check!();
-                    }
+        dump_uniforms(&self.pipeline, &inputs, uniforms)
+    }
 
-                    // Generate mip maps
-                    // don't do it for the screen buffer
-                    if target_tex != 0 {
-                        gl::BindTexture(gl::TEXTURE_2D, target_tex);
-                        gl::GenerateMipmap(gl::TEXTURE_2D);
-                        gl_debug_check!();
-                    }
+    /// Writes the uniforms of the last frame to the log, sorted by name.
+    fn log_uniforms(&self) {
+        let dump: BTreeMap<_, _> = self.dump_uniforms().into_iter().collect();
+        let lines: Vec<_> = dump
+            .iter()
+            .map(|(name, value)| format!("  {} = {}", name, value))
+            .collect();
 
-                    // swap buffers
-                    if let Some(name) = &stage.target {
-                        self.pipeline.buffers.get(name).unwrap().swap();
-                    }
-                },
+        log::info!("Uniforms of the last frame:\n{}", lines.join("\n"));
+    }
+
+    /// Renders deck B by swapping it in as the current pipeline for a moment.
+    fn render_deck_b(&mut self, screen_fb: GLuint, uniforms: &FrameUniforms) {
+        let mut deck_b = match self.mixer.deck_b.take() {
+            Some(deck_b) => deck_b,
+            None => return,
+        };
+
+        std::mem::swap(&mut self.pipeline, &mut deck_b);
+        self.render_pipeline(screen_fb, uniforms);
+        std::mem::swap(&mut self.pipeline, &mut deck_b);
+
+        self.mixer.deck_b = Some(deck_b);
+    }
+
+    /// Waits until the frame time of the configured fps limit has passed.
+    ///
+    /// This uses the real clock rather than the render clock, so the limit
+    /// still applies while the timeline is paused or scrubbed.
+    fn limit_frame_rate(&mut self) {
+        if let Some(fps_limit) = self.fps_limit {
+            let target = Duration::from_secs_f32(1.0 / fps_limit);
+
+            // sleep for most of the remaining time and spin for the rest,
+            // because sleeping is not precise enough on most platforms
+            let spin = Duration::from_millis(2);
+            let elapsed = self.last_present.elapsed();
+            if elapsed + spin < target {
+                std::thread::sleep(target - elapsed - spin);
             }
 
-            // log render time
-            let stage_time = stage_start.elapsed().as_secs_f32();
-            stage.perf.push(1000.0 * stage_time);
+            while self.last_present.elapsed() < target {
+                std::hint::spin_loop();
+            }
         }
 
-        // Send texture to Spout if enabled
-        if let Some(spout) = &mut self.spout {
-            log::trace!("Spout sender is active, attempting to send frame");
-            // Get the current framebuffer's color attachment (texture ID 0 is the default framebuffer)
-            // We need to copy from the default framebuffer to send via Spout
-            unsafe {
-                let mut current_fbo: GLint = 0;
-                gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut current_fbo);
-                log::trace!("Current FBO: {}", current_fbo);
-
-                if current_fbo == 0 {
-                    // We're rendering to the default framebuffer, create a texture from it
-                    let mut temp_texture: GLuint = 0;
-                    gl::GenTextures(1, &mut temp_texture);
-                    gl::BindTexture(gl::TEXTURE_2D, temp_texture);
-                    gl::CopyTexImage2D(
-                        gl::TEXTURE_2D,
-                        0,
-                        gl::RGBA8,
-                        0,
-                        0,
-                        width as GLint,
-                        height as GLint,
-                        0,
-                    );
+        let now = Instant::now();
+        let present_ms = 1000.0 * now.duration_since(self.last_present).as_secs_f32();
+        self.present_perf.push(present_ms);
+        self.frame_pacing.push(now, present_ms);
+        self.last_present = now;
+
+        if self
+            .hitch_threshold
+            .map_or(false, |threshold| present_ms > threshold)
+        {
+            self.last_hitch = Some((now, present_ms));
+        }
 
-                    if let Err(err) = spout.send_texture(temp_texture, width, height) {
-                        log::warn!("Failed to send texture to Spout: {}", err);
-                    }
+        if self.last_fps_log.elapsed() > Duration::from_secs(5) {
+            let avg_ms = self.present_perf.get();
+            let (pacing_avg, pacing_max) = self.frame_pacing.stats();
+            log::info!(
+                "FPS: {:.2} ({:.2} ms), last second: {:.2} ms average, {:.2} ms max",
+                1000.0 / avg_ms,
+                avg_ms,
+                pacing_avg,
+                pacing_max
+            );
+            self.last_fps_log = now;
 
-                    gl::DeleteTextures(1, &temp_texture);
-                } else {
-                    log::debug!("Spout: Not rendering to default framebuffer (FBO: {})", current_fbo);
-                }
+            for (k, stage) in self.pipeline.stages.iter().enumerate() {
+                let (stage_ms, clock) = stage.timing();
+                log::debug!(
+                    "Stage {} ({}): {:.4} ms {}",
+                    k,
+                    stage.label(),
+                    stage_ms,
+                    clock
+                );
             }
-        } else {
-            log::trace!("Spout sender is not active");
         }
+    }
+
+    /// Draws the on-screen overlay on top of the current frame.
+    fn draw_overlay(&mut self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl_debug_check!();
+        }
+
+        let active_inputs = self.input_activity().names();
+        let overlay = &mut self.ctx.overlay;
+        let imgui = &mut self.ctx.imgui;
+        let window = self.ctx.context.window();
+        let audio = &self.audio;
+        let midi = &mut self.midi;
+        let osc_values = self.osc.get_all_values();
+        let osc_stats = self.osc.stats();
+        let osc_connected = self.osc.is_connected();
+        let picked = self.picked_pixel.clone();
+        let osc_config = self.pipeline.osc_config.as_ref();
+        let spout_status = self
+            .spout
+            .as_ref()
+            .map(|spout| (spout.name(), spout.status()));
+        let console = &self.console;
+        let limiter_enabled = self.limiter.config.enabled;
+        let stages = &self.pipeline.stages;
+        let fps = match self.present_perf.get() {
+            ms if ms > 0.0 => 1000.0 / ms,
+            _ => 0.0,
+        };
+        let (frame_time, frame_time_max) = self.frame_pacing.stats();
+        let hitch = self
+            .last_hitch
+            .filter(|(at, _)| at.elapsed() < HITCH_FLASH)
+            .map(|(_, ms)| ms);
+        let time = self.time;
+        let frame = self.frame;
+        let beat = self.beat_sync.beat();
+        let size = window.inner_size();
+
+        overlay.draw(imgui, window, |ui| {
+            let window = imgui::Window::new(im_str!("Overlay"))
+                .position([8.0, 8.0], imgui::Condition::FirstUseEver)
+                .size([320.0, 480.0], imgui::Condition::FirstUseEver)
+                .bg_alpha(0.6)
+                .begin(ui);
+
+            if let Some(window) = window {
+                ui.text(format!("FPS: {:.2}", fps));
+                ui.text(format!(
+                    "frame time: {:.2} ms, max {:.2} ms",
+                    frame_time, frame_time_max
+                ));
+                if let Some(ms) = hitch {
+                    ui.text_colored([1.0, 0.2, 0.2, 1.0], format!("hitch: {:.2} ms", ms));
+                }
+                ui.text(format!("resolution: {} x {}", size.width, size.height));
+                ui.text(format!("time: {:.2}", time));
+                ui.text(format!("frame_count: {}", frame));
+                ui.text(format!("beat: {:.2}", beat));
+                match active_inputs.is_empty() {
+                    true => ui.text("active inputs: none"),
+                    false => ui.text(format!("active inputs: {}", active_inputs.join(", "))),
+                }
+                if limiter_enabled {
+                    ui.text("flash limiter: on");
+                }
+                if let Some((label, [x, y], [r, g, b, a])) = &picked {
+                    ui.text(format!(
+                        "pick {} at {}, {}: {:.3} {:.3} {:.3} {:.3}",
+                        label, x, y, r, g, b, a
+                    ));
+                }
+
+                ui.separator();
+                for (k, stage) in stages.iter().enumerate() {
+                    let (stage_ms, clock) = stage.timing();
+                    ui.text(format!(
+                        "stage {} ({}): {:.3} ms {}",
+                        k,
+                        stage.label(),
+                        stage_ms,
+                        clock
+                    ));
+                }
+
+                ui.separator();
+                let bands = [
+                    (im_str!("volume"), audio.volume[0]),
+                    (im_str!("bass"), audio.bass[0]),
+                    (im_str!("mid"), audio.mid[0]),
+                    (im_str!("high"), audio.high[0]),
+                ];
+                for &(name, value) in bands.iter() {
+                    imgui::ProgressBar::new(value.clamp(0.0, 1.0))
+                        .overlay_text(name)
+                        .build(ui);
+                }
+
+                ui.separator();
+                ui.text(format!(
+                    "MIDI last button: {:?}, last slider: {:?}",
+                    midi.last_button, midi.last_slider
+                ));
+                for k in 0..midi.sliders.len() {
+                    let mut buffer = [0_u8; 16];
+                    write!(buffer.as_mut(), "slider{}\0", k).unwrap();
+                    let cstr = unsafe { std::ffi::CStr::from_bytes_with_nul_unchecked(&buffer) };
+                    let ims = unsafe { imgui::ImStr::from_cstr_unchecked(cstr) };
+                    let slider = &mut midi.sliders[k];
+                    imgui::Slider::new(ims).range(0.0..=1.0).build(ui, slider);
+                }
+
+                ui.separator();
+                ui.text(format!(
+                    "OSC packets: {} received, {} decoded, {} errored",
+                    osc_stats.received, osc_stats.decoded, osc_stats.errored
+                ));
+                match osc_connected {
+                    Some(true) => ui.text("OSC controller: connected"),
+                    Some(false) => ui.text("OSC controller: no heartbeat"),
+                    None => (),
+                }
+                if osc_values.is_empty() {
+                    ui.text("No OSC values received");
+                } else {
+                    let mut osc_values: Vec<_> = osc_values.iter().collect();
+                    osc_values.sort_by(|a, b| a.0.cmp(b.0));
+                    for (name, value) in osc_values {
+                        let mode = osc_config
+                            .and_then(|config| config.mappings.get(name))
+                            .and_then(|mapping| mapping.mode_name(value));
+
+                        match mode {
+                            Some(mode) => ui.text(format!("{}: {:?} ({})", name, value, mode)),
+                            None => ui.text(format!("{}: {:?}", name, value)),
+                        }
+                    }
+                }
+
+                if let Some((name, status)) = spout_status {
+                    ui.separator();
+                    let error = status.last_error.as_deref().unwrap_or_default();
+                    if status.unavailable {
+                        ui.text(format!("Spout '{}': unavailable, {}", name, error));
+                    } else if status.gave_up {
+                        ui.text(format!("Spout '{}': gave up, {}", name, error));
+                    } else if status.healthy {
+                        ui.text(format!("Spout '{}': sending", name));
+                    } else {
+                        ui.text(format!(
+                            "Spout '{}': {} failures in a row, {}",
+                            name, status.failures, error
+                        ));
+                    }
+                }
+
+                ui.separator();
+                ui.text(console);
 
-        self.ctx.context.swap_buffers().unwrap();
+                window.end();
+            }
+        });
     }
 
     /// Wrapper function for all the imgui stuff.
@@ -1186,6 +1872,7 @@ impl Jockey {
             imgui::sys::igDockSpaceOverViewport(viewport, flags, window_class);
         }
 
+        let mut load_deck_b = None;
         if let Some(window) = imgui::Window::new(im_str!("Pipelines")).begin(&ui) {
             if ui.button_with_size(im_str!("Select project folder"), [0.0; 2]) {
                 std::thread::spawn(|| {
@@ -1215,12 +1902,38 @@ impl Jockey {
                         let ims = unsafe { imgui::ImStr::from_cstr_unchecked(&cst) };
                         if ui.button_with_size(ims, [256.0, 18.0]) {
                             self.pipeline_index = k;
+                            self.presets.begin_switch();
                             unsafe { PIPELINE_STALE.store(true, Ordering::Release) }
                         }
+
+                        // load the file into deck B
+                        ui.same_line();
+                        let token = ui.push_id(k as i32);
+                        if ui.button_with_size(im_str!("B"), [18.0, 18.0]) {
+                            load_deck_b = Some(file.clone());
+                        }
+                        token.pop();
                     }
                 }
             }
 
+            ui.separator();
+            match &self.mixer.deck_b_file {
+                Some(file) => ui.text(format!("deck B: {}", file)),
+                None => ui.text("deck B: empty"),
+            }
+
+            if self.mixer.deck_b.is_some() {
+                ui.same_line();
+                if ui.small_button(im_str!("eject")) {
+                    self.mixer.eject();
+                }
+            }
+
+            imgui::Slider::new(im_str!("crossfade"))
+                .range(0.0..=1.0)
+                .build(&ui, &mut self.mixer.fader);
+
             window.end();
         }
 
@@ -1338,6 +2051,7 @@ impl Jockey {
             ui.same_line();
             if ui.button_with_size(im_str!("Reset"), [64.0, 18.0]) {
                 self.time = 0.0;
+                self.loop_origin = 0.0;
                 self.frame = 0;
             }
 
@@ -1480,15 +2194,19 @@ impl Jockey {
 
             let mut stage_sum_ms = 0.0;
             for (k, stage) in self.pipeline.stages.iter().enumerate() {
-                let stage_ms = stage.perf.get();
+                let (stage_ms, clock) = stage.timing();
                 stage_sum_ms += stage_ms;
+                let state = if stage.enabled { "" } else { " [off]" };
                 if let Some(tex_name) = stage.target.as_ref() {
                     ui.text(format!(
-                        "Stage {}: {:.4} ms (-> {:?})",
-                        k, stage_ms, tex_name
+                        "Stage {}: {:.4} ms {} (-> {:?}){}",
+                        k, stage_ms, clock, tex_name, state
                     ));
                 } else {
-                    ui.text(format!("Stage {}: {:.4} ms", k, stage_ms));
+                    ui.text(format!(
+                        "Stage {}: {:.4} ms {}{}",
+                        k, stage_ms, clock, state
+                    ));
                 }
             }
 
@@ -1514,40 +2232,164 @@ impl Jockey {
         // render and swap buffers
         self.ctx.renderer.render(ui);
         self.ctx.ui_context.swap_buffers().unwrap();
+
+        if let Some(file) = load_deck_b {
+            self.load_deck_b(&file);
+        }
+    }
+
+    /// Stops all subsystems before the program exits.
+    ///
+    /// Waiting on background threads is bounded by `SHUTDOWN_TIMEOUT`, so a
+    /// hanging thread cannot keep the process alive. Threads that did not
+    /// finish in time are detached and logged.
+    pub fn shutdown(&mut self) {
+        log::info!("Shutting down...");
+
+        if let Some(path) = &self.state_file {
+            SavedState::capture(&self.midi, &self.osc).store(path);
+        }
+
+        let osc_start = Instant::now();
+        if !self.osc.shutdown(SHUTDOWN_TIMEOUT) {
+            log::warn!(
+                "OSC receiver thread did not stop within {:?}, detaching it",
+                SHUTDOWN_TIMEOUT
+            );
+        } else {
+            log::debug!("OSC receiver stopped in {:?}", osc_start.elapsed());
+        }
+
+        // spout owns textures, so the main context has to be current
+        if let Some(mut spout) = self.spout.take() {
+            take_mut::take(&mut self.ctx.context, |s| unsafe {
+                s.make_current().unwrap()
+            });
+
+            spout.release();
+        }
+
+        let audio_start = Instant::now();
+        self.audio.disconnect();
+        let audio_time = audio_start.elapsed();
+        if audio_time > SHUTDOWN_TIMEOUT {
+            log::warn!("Stopping the audio stream took {:?}", audio_time);
+        }
+
+        // stop compiling a pipeline nobody is going to see
+        self.pipeline_partial = None;
+
+        log::info!("Shutdown complete");
     }
 
+    /// Requests a screenshot of the next frame.
+    ///
+    /// The pixels are read back asynchronously and saved in the cwd
+    /// `CAPTURE_LATENCY` frames later.
     pub fn save_frame(&mut self) {
-        take_mut::take(&mut self.ctx.context, |s| unsafe {
-            s.make_current().unwrap()
-        });
+        self.screenshot_requested = true;
+    }
 
-        let screen_size = self.ctx.context.window().inner_size();
-        let (width, height) = (screen_size.width as u32, screen_size.height as u32);
+    /// Requests the color under the cursor, which is logged once it was read back.
+    pub fn pick_pixel(&mut self) {
+        self.pick_requested = true;
+    }
 
-        let mut img = image::ImageBuffer::<image::Rgb<u8>, Vec<u8>>::new(width, height);
-        let data = img.as_flat_samples_mut().as_mut_slice().as_mut_ptr();
+    /// Reads back the pixel under the cursor once it was requested, and the
+    /// `pick_at` pixel of the pipeline on every other frame.
+    fn update_picker(&mut self, width: u32, height: u32) {
+        if let Some(color) = self.pixel_reader.poll() {
+            if let Some((label, pixel, log)) = self.pick_pending.take() {
+                if log {
+                    let [r, g, b, a] = color;
+                    log::info!("Color of {} at {:?}: {} {} {} {}", label, pixel, r, g, b, a);
+                }
+                self.picked_pixel = Some((label, pixel, color));
+            }
+        }
 
-        unsafe {
-            gl::ReadnPixels(
-                0,
-                0,
-                width as _,
-                height as _,
-                gl::RGB,
-                gl::UNSIGNED_BYTE,
-                (3 * width * height) as _,
-                data as _,
-            );
+        if self.pixel_reader.is_busy() {
+            return;
+        }
+
+        let target = self
+            .pipeline
+            .pick_target
+            .as_ref()
+            .and_then(|name| self.pipeline.buffers.get(name))
+            .cloned();
+        let [w, h] = match &target {
+            Some(tex) => {
+                let [w, h, _] = tex.resolution();
+                [w.max(1), h.max(1)]
+            }
+            None => [width.max(1), height.max(1)],
+        };
+
+        let cursor = std::mem::take(&mut self.pick_requested);
+        let pixel = match (cursor, self.pipeline.pick_at) {
+            (true, _) => {
+                // the cursor counts from the top left of the window
+                let [x, y] = self.ctx.overlay.cursor;
+                if x < 0.0 || y < 0.0 {
+                    log::warn!("Move the cursor over the window to pick a color");
+                    return;
+                }
+
+                let x = (x / width as f32 * w as f32) as u32;
+                let y = (y / height as f32 * h as f32) as u32;
+                [x.min(w - 1), h - 1 - y.min(h - 1)]
+            }
+            (false, Some([x, y])) => [x.min(w - 1), y.min(h - 1)],
+            (false, None) => return,
+        };
+
+        match target {
+            Some(tex) => {
+                // the last rendered frame has been swapped to the front,
+                // swap it back to read it through the framebuffer
+                tex.swap();
+                let fb_id = tex
+                    .framebuffer_id()
+                    .expect("Pick target should be a framebuffer");
+                self.pixel_reader.request(fb_id, pixel[0], pixel[1]);
+                tex.swap();
+            }
+            None => {
+                self.pixel_reader.request(0, pixel[0], pixel[1]);
+            }
         }
 
-        image::imageops::flip_vertical_in_place(&mut img);
+        let label = match &self.pipeline.pick_target {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => "screen".into(),
+        };
+        self.pick_pending = Some((label, pixel, cursor));
+    }
 
-        let mut hasher = DefaultHasher::new();
-        Instant::now().hash(&mut hasher);
-        img.hash(&mut hasher);
-        let hash = hasher.finish();
+    /// Starts recording every frame together with its uniforms, or stops recording.
+    ///
+    /// Recordings are saved in a new folder in the cwd. See `replay_recording`
+    /// for rendering them again.
+    pub fn toggle_recording(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            if recorder.is_active() {
+                recorder.stop();
+                return;
+            }
 
-        let file_name = format!("frame-{}.png", hash);
-        img.save(file_name).unwrap();
+            log::warn!("The last recording is still being saved");
+            return;
+        }
+
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        match Recorder::start(PathBuf::from(format!("recording-{}", stamp))) {
+            Ok(recorder) => self.recorder = Some(recorder),
+            Err(err) => log::error!("Failed to start recording: {}", err),
+        }
     }
 }
@@ -211,9 +211,10 @@ impl Ndi {
         Ok(())
     }
 
-    pub fn update_texture(&self, tex_name: &String, tex: &mut Texture2D) {
+    pub fn update_texture(&self, tex_name: &String, alpha: AlphaMode, tex: &mut Texture2D) {
         if let Some(video) = self.videos.get(tex_name) {
-            let video = video.lock().unwrap().to_rgba8();
+            let mut video = video.lock().unwrap().to_rgba8();
+            alpha.convert_u8(&mut video);
             if tex.resolution() != [video.width(), video.height(), 0] {
                 *tex = Texture2D::with_params(
                     [video.width(), video.height()],
@@ -1,19 +1,286 @@
 use std::{
-    collections::HashMap,
+    cmp::Reverse,
+    collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet},
     convert::TryInto,
+    ffi::CString,
     net::UdpSocket,
-    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use rosc::{OscMessage, OscPacket, OscType};
+use gl::types::*;
+use rosc::{OscBundle, OscMessage, OscPacket, OscTime, OscType};
+
+use super::{Error, OscNamespace, OscQueryServer, PresetRequest, INPUT_ACTIVE_TIMEOUT};
+
+/// Messages sent to this address switch to another pipeline file.
+pub const PRESET_ADDRESS: &str = "/preset";
+/// Messages sent to this address move the crossfader between the two decks.
+pub const CROSSFADE_ADDRESS: &str = "/crossfade";
+/// Messages sent to this address restart the `loop_time` uniform.
+pub const LOOP_RESET_ADDRESS: &str = "/loop/reset";
+/// Messages sent to this address switch the flash limiter on or off.
+pub const FLASH_LIMITER_ADDRESS: &str = "/flash_limiter";
+/// Messages sent to this address change how strongly the color LUT is applied.
+pub const LUT_STRENGTH_ADDRESS: &str = "/color/lut_strength";
+/// Messages sent to this address switch to another audio profile.
+pub const AUDIO_PROFILE_ADDRESS: &str = "/audio/profile";
+/// Messages sent to `/pass/<label>/enable` switch stages on or off.
+pub const PASS_ADDRESS_PREFIX: &str = "/pass/";
+const PASS_ADDRESS_SUFFIX: &str = "/enable";
+/// Default address of messages which rebuild the pipeline, if remote reloads are allowed.
+pub const RELOAD_ADDRESS: &str = "/reload";
+/// Messages sent to this address recreate all GPU resources, if remote reloads are allowed.
+pub const REINIT_ADDRESS: &str = "/reinit";
+
+/// Default time without a heartbeat after which the controller counts as disconnected.
+pub const OSC_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Address a controller sends to at a regular interval to show that it is alive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscHeartbeat {
+    pub address: String,
+    pub timeout: Duration,
+}
+
+/// Requests which control the tool itself rather than a uniform.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OscControls {
+    pub preset: Option<PresetRequest>,
+    pub crossfade: Option<f32>,
+    pub flash_limiter: Option<bool>,
+    pub lut_strength: Option<f32>,
+    pub audio_profile: Option<PresetRequest>,
+    pub loop_reset: bool,
+    /// Stage labels and whether they should be enabled, in order of arrival.
+    pub passes: Vec<(String, bool)>,
+    /// The expected heartbeat and when it was last received.
+    pub heartbeat: Option<(OscHeartbeat, Option<Instant>)>,
+    /// Address which rebuilds the pipeline, if remote reloads are allowed.
+    pub reload_address: Option<String>,
+    pub reload: bool,
+    pub reinit: bool,
+}
+
+/// Returns the stage label of an address like `/pass/<label>/enable`.
+pub fn parse_pass_address(address: &str) -> Option<&str> {
+    address
+        .strip_prefix(PASS_ADDRESS_PREFIX)?
+        .strip_suffix(PASS_ADDRESS_SUFFIX)
+        .filter(|label| !label.is_empty() && !label.contains('/'))
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum OscDataType {
     Float,
     Int,
     Bool,
+    /// An int which selects one of the named modes, by index or by name.
+    Mode(Vec<String>),
+    /// A float kept at full precision, uploaded as a `float` unless the uniform is a `double`.
+    Double,
+    /// An int kept at full precision, uploaded as a clamped `int`.
+    Long,
+    /// An orientation, uploaded as a `mat3` or `mat4` rotation matrix.
+    Rotation(OscRotation),
+}
+
+impl OscDataType {
+    /// Whether values of this type can be uploaded to a uniform of the given kind as they are.
+    ///
+    /// Floats are uploaded with `glUniform1f`, everything else with
+    /// `glUniform1i`. Bool uniforms accept both.
+    pub fn accepts_uniform(&self, kind: GLenum) -> bool {
+        match self {
+            Self::Float => matches!(kind, gl::FLOAT | gl::BOOL),
+            Self::Double => matches!(kind, gl::FLOAT | gl::DOUBLE | gl::BOOL),
+            Self::Int | Self::Long | Self::Bool | Self::Mode(_) => {
+                matches!(kind, gl::INT | gl::BOOL)
+            }
+            Self::Rotation(_) => matches!(kind, gl::FLOAT_MAT3 | gl::FLOAT_MAT4),
+        }
+    }
+
+    /// Name of the type in the config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Float => "float",
+            Self::Int => "int",
+            Self::Bool => "bool",
+            Self::Mode(_) => "modes",
+            Self::Double => "double",
+            Self::Long => "long",
+            Self::Rotation(OscRotation::Quaternion { .. }) => "quaternion",
+            Self::Rotation(OscRotation::Euler { .. }) => "euler",
+        }
+    }
+}
+
+/// How the arguments of a rotation mapping describe an orientation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OscRotation {
+    /// Four arguments `x, y, z, w`, or `w, x, y, z` with `w_first`.
+    Quaternion { w_first: bool },
+    /// Angles about the x, y and z axis, applied in the order of `order`.
+    Euler { order: [usize; 3], degrees: bool },
+}
+
+impl OscRotation {
+    /// Number of arguments a message of this rotation carries.
+    pub fn arg_count(&self) -> usize {
+        match self {
+            Self::Quaternion { .. } => 4,
+            Self::Euler { .. } => 3,
+        }
+    }
+
+    /// Converts the arguments of a message to a unit quaternion `[x, y, z, w]`.
+    ///
+    /// Quaternions are normalized, since senders rarely keep them at unit
+    /// length. Returns `None` for too few arguments or a rotation which has
+    /// no direction, like an all zero quaternion.
+    pub fn quaternion(&self, args: &[f32]) -> Option<[f32; 4]> {
+        if args.len() < self.arg_count() || !args.iter().all(|x| x.is_finite()) {
+            return None;
+        }
+
+        let q = match *self {
+            Self::Quaternion { w_first: false } => [args[0], args[1], args[2], args[3]],
+            Self::Quaternion { w_first: true } => [args[1], args[2], args[3], args[0]],
+            Self::Euler { order, degrees } => {
+                let mut q = [0.0, 0.0, 0.0, 1.0];
+                for &axis in order.iter() {
+                    let angle = match degrees {
+                        true => args[axis].to_radians(),
+                        false => args[axis],
+                    };
+
+                    // each rotation is applied after the ones before it
+                    let (sin, cos) = (0.5 * angle).sin_cos();
+                    let mut step = [0.0, 0.0, 0.0, cos];
+                    step[axis] = sin;
+                    q = quaternion_product(step, q);
+                }
+                q
+            }
+        };
+
+        let length = q.iter().map(|x| x * x).sum::<f32>().sqrt();
+        match length.is_finite() && length > 1e-6 {
+            true => Some([q[0] / length, q[1] / length, q[2] / length, q[3] / length]),
+            false => None,
+        }
+    }
+}
+
+/// Hamilton product of two quaternions `[x, y, z, w]`, the rotation `b` followed by `a`.
+fn quaternion_product(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    let [ax, ay, az, aw] = a;
+    let [bx, by, bz, bw] = b;
+    [
+        aw * bx + ax * bw + ay * bz - az * by,
+        aw * by - ax * bz + ay * bw + az * bx,
+        aw * bz + ax * by - ay * bx + az * bw,
+        aw * bw - ax * bx - ay * by - az * bz,
+    ]
+}
+
+/// Column-major 3x3 matrix of the rotation by a unit quaternion `[x, y, z, w]`.
+pub fn rotation_matrix(q: [f32; 4]) -> [f32; 9] {
+    let [x, y, z, w] = q;
+    [
+        1.0 - 2.0 * (y * y + z * z),
+        2.0 * (x * y + z * w),
+        2.0 * (x * z - y * w),
+        2.0 * (x * y - z * w),
+        1.0 - 2.0 * (x * x + z * z),
+        2.0 * (y * z + x * w),
+        2.0 * (x * z + y * w),
+        2.0 * (y * z - x * w),
+        1.0 - 2.0 * (x * x + y * y),
+    ]
+}
+
+/// Uploads the rotation of mapping `name` to the `mat3` or `mat4` at `loc`.
+///
+/// The rotation is stored as the quaternion elements `name[0]` to `name[3]`,
+/// until the first message arrives the identity is uploaded.
+pub fn upload_osc_rotation(
+    loc: GLint,
+    values: &HashMap<String, OscUniformValue>,
+    name: &str,
+    kind: GLenum,
+) {
+    let mut q = [0.0, 0.0, 0.0, 1.0];
+    let elements: Option<Vec<f32>> = (0..4)
+        .map(|k| {
+            values
+                .get(&array_element_name(name, k))
+                .map(OscUniformValue::as_f32)
+        })
+        .collect();
+    if let Some(elements) = elements {
+        q.copy_from_slice(&elements);
+    }
+
+    let m = rotation_matrix(q);
+    unsafe {
+        match kind {
+            gl::FLOAT_MAT4 => {
+                #[rustfmt::skip]
+                let m4 = [
+                    m[0], m[1], m[2], 0.0,
+                    m[3], m[4], m[5], 0.0,
+                    m[6], m[7], m[8], 0.0,
+                    0.0, 0.0, 0.0, 1.0,
+                ];
+                gl::UniformMatrix4fv(loc, 1, gl::FALSE, m4.as_ptr());
+            }
+            _ => gl::UniformMatrix3fv(loc, 1, gl::FALSE, m.as_ptr()),
+        }
+        gl_debug_check!();
+    }
+}
+
+/// Scalar uniform kinds which OSC values can be converted to.
+pub const OSC_COERCIBLE_KINDS: [GLenum; 5] =
+    [gl::FLOAT, gl::DOUBLE, gl::INT, gl::UNSIGNED_INT, gl::BOOL];
+
+/// Uploads an OSC value to the uniform at `loc` of the program in use.
+///
+/// With a `kind`, the value is converted to that uniform type first,
+/// otherwise it is uploaded as the type it was received as.
+pub fn upload_osc_value(loc: GLint, value: &OscUniformValue, kind: Option<GLenum>) {
+    let number = value.as_f64();
+    let clamp_int = |x: f64| x.round().clamp(i32::MIN as f64, i32::MAX as f64) as GLint;
+
+    unsafe {
+        match (kind, value) {
+            (Some(gl::FLOAT), _) => gl::Uniform1f(loc, number as _),
+            (Some(gl::DOUBLE), _) => gl::Uniform1d(loc, number),
+            (Some(gl::INT), _) => gl::Uniform1i(loc, clamp_int(number)),
+            (Some(gl::UNSIGNED_INT), _) => {
+                gl::Uniform1ui(loc, number.round().clamp(0.0, u32::MAX as f64) as _)
+            }
+            (Some(gl::BOOL), _) => gl::Uniform1i(loc, (number != 0.0) as _),
+            (_, OscUniformValue::Float(f)) => gl::Uniform1f(loc, *f),
+            (_, OscUniformValue::Double(d)) => gl::Uniform1f(loc, *d as _),
+            (_, OscUniformValue::Int(i)) => gl::Uniform1i(loc, *i),
+            (_, OscUniformValue::Long(l)) => gl::Uniform1i(loc, clamp_int(*l as _)),
+            (_, OscUniformValue::Bool(b)) => gl::Uniform1i(loc, *b as _),
+        }
+        gl_debug_check!();
+    }
+}
+
+/// Clamps a mode index into the list of modes.
+fn mode_index(modes: &[String], index: i64) -> OscUniformValue {
+    OscUniformValue::Int(index.clamp(0, modes.len() as i64 - 1) as i32)
 }
 
 impl Default for OscDataType {
@@ -26,6 +293,100 @@ impl Default for OscDataType {
 pub struct OscMapping {
     pub address: String,
     pub data_type: OscDataType,
+    pub default: Option<OscUniformValue>,
+    /// Name of the uniform, without an array index.
+    pub uniform: String,
+    /// Element of the uniform array, if the mapping targets `name[index]`.
+    pub index: Option<usize>,
+    /// Length of the uniform array, if one message fills all of it with its arguments.
+    pub size: Option<usize>,
+    pub filter: OscFilter,
+    /// Display name for control surfaces, not used while rendering.
+    pub label: Option<String>,
+    /// Name of the group the control is listed under, not used while rendering.
+    pub group: Option<String>,
+}
+
+impl OscMapping {
+    /// Whether the values of the mapping are uploaded as part of a uniform array.
+    pub fn writes_array(&self) -> bool {
+        self.index.is_some() || self.size.is_some()
+    }
+
+    /// Returns the name of the mode a value selects, for mappings with modes.
+    pub fn mode_name(&self, value: &OscUniformValue) -> Option<&str> {
+        match (&self.data_type, value) {
+            (OscDataType::Mode(modes), OscUniformValue::Int(i)) => {
+                modes.get(*i as usize).map(String::as_str)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Cleans up incoming values of a mapping before they are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OscFilter {
+    /// Changes smaller than this, relative to the stored value, are ignored.
+    pub deadzone: f32,
+    /// Snaps values to multiples of this step.
+    pub quantize: Option<f32>,
+    /// Flips floats to `1 - x` and negates bools, before quantization.
+    pub invert: bool,
+}
+
+impl OscFilter {
+    /// Returns the value to store, or `None` if the stored value should be kept.
+    ///
+    /// Bools are only inverted, never quantized or held back. Ints have no
+    /// range to be inverted within, so they are never inverted.
+    pub fn apply(
+        &self,
+        value: OscUniformValue,
+        last: Option<&OscUniformValue>,
+    ) -> Option<OscUniformValue> {
+        let value = match (value, self.invert) {
+            (OscUniformValue::Float(f), true) => OscUniformValue::Float(1.0 - f),
+            (OscUniformValue::Double(d), true) => OscUniformValue::Double(1.0 - d),
+            (OscUniformValue::Bool(b), true) => OscUniformValue::Bool(!b),
+            (value, _) => value,
+        };
+
+        let value = match (value, self.quantize) {
+            (OscUniformValue::Float(f), Some(step)) => {
+                OscUniformValue::Float((f / step).round() * step)
+            }
+            (OscUniformValue::Double(d), Some(step)) => {
+                let step = step as f64;
+                OscUniformValue::Double((d / step).round() * step)
+            }
+            (OscUniformValue::Int(i), Some(step)) => {
+                OscUniformValue::Int(((i as f32 / step).round() * step).round() as i32)
+            }
+            (OscUniformValue::Long(l), Some(step)) => {
+                let step = step as f64;
+                OscUniformValue::Long(((l as f64 / step).round() * step).round() as i64)
+            }
+            (value, _) => value,
+        };
+
+        let change = match (&value, last) {
+            (OscUniformValue::Float(f), Some(OscUniformValue::Float(last))) => (f - last).abs(),
+            (OscUniformValue::Double(d), Some(OscUniformValue::Double(last))) => {
+                (d - last).abs() as f32
+            }
+            (OscUniformValue::Int(i), Some(OscUniformValue::Int(last))) => (i - last).abs() as f32,
+            (OscUniformValue::Long(l), Some(OscUniformValue::Long(last))) => {
+                (*l as f64 - *last as f64).abs() as f32
+            }
+            _ => return Some(value),
+        };
+
+        match change < self.deadzone {
+            true => None,
+            false => Some(value),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -34,21 +395,193 @@ pub struct OscValue {
     pub address: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum OscUniformValue {
     Float(f32),
     Int(i32),
     Bool(bool),
+    Double(f64),
+    Long(i64),
+}
+
+impl OscUniformValue {
+    /// The value as a float, bools read as 0 or 1.
+    pub fn as_f32(&self) -> f32 {
+        self.as_f64() as f32
+    }
+
+    /// The value as a double, which keeps doubles and most longs exact.
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            Self::Float(f) => f as f64,
+            Self::Int(i) => i as f64,
+            Self::Bool(b) => b as u8 as f64,
+            Self::Double(d) => d,
+            Self::Long(l) => l as f64,
+        }
+    }
+
+    /// The value as an int, floats are rounded and bools read as 0 or 1.
+    pub fn as_i32(&self) -> i32 {
+        match *self {
+            Self::Float(f) => f.round() as i32,
+            Self::Int(i) => i,
+            Self::Bool(b) => b as i32,
+            Self::Double(d) => d.round() as i32,
+            Self::Long(l) => l as i32,
+        }
+    }
+
+    /// The value as a bool, which is true for anything but 0.
+    pub fn as_bool(&self) -> bool {
+        match *self {
+            Self::Bool(b) => b,
+            _ => self.as_f64() != 0.0,
+        }
+    }
+}
+
+impl std::fmt::Display for OscUniformValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Float(x) => write!(f, "{}", x),
+            Self::Int(i) => write!(f, "{}", i),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Double(d) => write!(f, "{}", d),
+            Self::Long(l) => write!(f, "{}", l),
+        }
+    }
+}
+
+/// Minimum time between two log messages about packets which failed to decode.
+const DECODE_ERROR_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of bytes of a broken packet which are included in the log.
+const DECODE_ERROR_LOG_BYTES: usize = 16;
+
+/// Packet counters of an OSC receiver, see [`OscReceiver::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OscStats {
+    /// Datagrams received on the socket.
+    pub received: u64,
+    /// Datagrams which were valid OSC packets.
+    pub decoded: u64,
+    /// Datagrams which failed to decode and were dropped.
+    pub errored: u64,
+}
+
+#[derive(Debug, Default)]
+struct OscCounters {
+    received: AtomicU64,
+    decoded: AtomicU64,
+    errored: AtomicU64,
+}
+
+/// Seconds between the NTP epoch (1900) used by OSC time tags and the unix epoch.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Longest time the scheduler sleeps before checking whether it should stop.
+const SCHEDULER_POLL: Duration = Duration::from_millis(100);
+
+/// Converts an OSC time tag into system time.
+///
+/// Returns `None` for the special tag which means "immediately".
+pub fn timetag_to_system_time(tag: OscTime) -> Option<SystemTime> {
+    if tag.seconds == 0 && tag.fractional == 1 {
+        return None;
+    }
+
+    // tags before 1970 are long overdue anyway
+    let seconds = (tag.seconds as u64).saturating_sub(NTP_UNIX_OFFSET);
+    let nanos = ((tag.fractional as u64 * 1_000_000_000) >> 32) as u32;
+    Some(UNIX_EPOCH + Duration::new(seconds, nanos))
+}
+
+/// The contents of a bundle whose time tag lies in the future.
+///
+/// Bundles are ordered by their due time first and the order in which the
+/// scheduler received them second, so bundles with equal tags keep their order.
+#[derive(Debug)]
+struct ScheduledBundle {
+    due: SystemTime,
+    arrival: u64,
+    content: Vec<OscPacket>,
+}
+
+impl PartialEq for ScheduledBundle {
+    fn eq(&self, other: &Self) -> bool {
+        (self.due, self.arrival) == (other.due, other.arrival)
+    }
+}
+
+impl Eq for ScheduledBundle {}
+
+impl PartialOrd for ScheduledBundle {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScheduledBundle {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.due, self.arrival).cmp(&(other.due, other.arrival))
+    }
+}
+
+/// The latest value received for an address since the last frame.
+#[derive(Debug, Clone, PartialEq)]
+struct Coalesced {
+    value: OscUniformValue,
+    /// Set if the address was `true` at any point, even if it is `false` now.
+    pulse: bool,
+}
+
+/// Values received since the last frame, keyed by the name they are stored under.
+type OscBatch = HashMap<String, Coalesced>;
+
+/// The mappings of every address, as mapping name, type and array size, sorted by name.
+type OscTargets = HashMap<String, Vec<(String, OscDataType, Option<usize>)>>;
+
+/// Name the values of an array mapping are stored under, one per element.
+pub fn array_element_name(name: &str, index: usize) -> String {
+    format!("{}[{}]", name, index)
+}
+
+/// Adds a value to a batch, replacing older values under the same name.
+fn coalesce(batch: &mut OscBatch, address: String, incoming: Coalesced) {
+    match batch.entry(address) {
+        Entry::Occupied(mut entry) => {
+            let entry = entry.get_mut();
+            entry.pulse |= incoming.pulse;
+            entry.value = incoming.value;
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(incoming);
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct OscReceiver {
     socket: Option<UdpSocket>,
     values: Arc<Mutex<HashMap<String, OscUniformValue>>>,
-    thread_handle: Option<thread::JoinHandle<()>>,
+    inbox: Arc<Mutex<OscBatch>>,
+    releases: Vec<String>,
+    counters: Arc<OscCounters>,
+    thread_handles: Vec<thread::JoinHandle<()>>,
     running: Arc<AtomicBool>,
     current_port: Option<u16>,
-    type_mappings: Arc<Mutex<HashMap<String, OscDataType>>>,
+    type_mappings: Arc<Mutex<OscTargets>>,
+    filters: Arc<Mutex<HashMap<String, OscFilter>>>,
+    controls: Arc<Mutex<OscControls>>,
+    /// Whether the heartbeat arrived in time, as of the last tick.
+    connected: Option<bool>,
+    /// Packets received up to the last tick, and when their number last grew.
+    received_at_tick: u64,
+    last_received: Option<Instant>,
+    query: Option<OscQueryServer>,
+    /// Names written by custom uniform sources, which may have no mapping.
+    provided: HashSet<String>,
 }
 
 impl OscReceiver {
@@ -56,23 +589,172 @@ impl OscReceiver {
         Self {
             socket: None,
             values: Arc::new(Mutex::new(HashMap::new())),
-            thread_handle: None,
+            inbox: Arc::new(Mutex::new(HashMap::new())),
+            releases: Vec::new(),
+            counters: Arc::new(OscCounters::default()),
+            thread_handles: Vec::new(),
             running: Arc::new(AtomicBool::new(false)),
             current_port: None,
             type_mappings: Arc::new(Mutex::new(HashMap::new())),
+            filters: Arc::new(Mutex::new(HashMap::new())),
+            controls: Arc::new(Mutex::new(OscControls::default())),
+            connected: None,
+            received_at_tick: 0,
+            last_received: None,
+            query: None,
+            provided: HashSet::new(),
         }
     }
 
+    /// Tells the receiver which mappings each address feeds.
+    ///
+    /// Values of mapped addresses are stored under the names of their
+    /// mappings, so a single address can drive several uniforms, each with
+    /// its own type and filter. Values of unmapped addresses are stored as
+    /// floats under the address itself.
     pub fn update_type_mappings(&self, config: &OscConfig) {
         if let Ok(mut mappings) = self.type_mappings.lock() {
             mappings.clear();
-            for (_, mapping) in &config.mappings {
-                mappings.insert(mapping.address.clone(), mapping.data_type.clone());
+            for (name, mapping) in &config.mappings {
+                mappings.entry(mapping.address.clone()).or_default().push((
+                    name.clone(),
+                    mapping.data_type.clone(),
+                    mapping.size,
+                ));
+            }
+
+            for targets in mappings.values_mut() {
+                targets.sort_by(|a, b| a.0.cmp(&b.0));
+            }
+        }
+
+        if let Ok(mut filters) = self.filters.lock() {
+            filters.clear();
+            for (name, mapping) in &config.mappings {
+                if mapping.filter != OscFilter::default() {
+                    filters.insert(name.clone(), mapping.filter);
+                }
+            }
+        }
+
+        if let Ok(mut controls) = self.controls.lock() {
+            // a reload keeps the connection, unless the address changed
+            let address = config.heartbeat.as_ref().map(|h| &h.address);
+            let last = match &controls.heartbeat {
+                Some((heartbeat, last)) if Some(&heartbeat.address) == address => *last,
+                _ => None,
+            };
+            controls.heartbeat = config.heartbeat.clone().map(|heartbeat| (heartbeat, last));
+            controls.reload_address = config.reload_address.clone();
+        }
+    }
+
+    /// Seeds the value store with the default values of all mappings.
+    ///
+    /// Mappings which already received a value are left untouched, so
+    /// reloading a pipeline does not reset values sent by a controller.
+    pub fn seed_defaults(&self, config: &OscConfig) {
+        if let Ok(mut values) = self.values.lock() {
+            for (name, mapping) in &config.mappings {
+                let default = match &mapping.default {
+                    Some(default) => default,
+                    None => continue,
+                };
+
+                match mapping.size {
+                    Some(size) => {
+                        for k in 0..size {
+                            let element = array_element_name(name, k);
+                            values.entry(element).or_insert_with(|| default.clone());
+                        }
+                    }
+                    None => {
+                        values
+                            .entry(name.clone())
+                            .or_insert_with(|| default.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Seeds the value store with values from a previous session.
+    ///
+    /// Like defaults, these only fill addresses which did not receive a value yet.
+    pub fn seed_values(&self, seed: &HashMap<String, OscUniformValue>) {
+        if let Ok(mut values) = self.values.lock() {
+            for (address, value) in seed {
+                values
+                    .entry(address.clone())
+                    .or_insert_with(|| value.clone());
+            }
+        }
+    }
+
+    /// Replaces all values in the store, as when replaying a recording.
+    pub fn set_values(&self, new_values: &HashMap<String, OscUniformValue>) {
+        if let Ok(mut values) = self.values.lock() {
+            *values = new_values.clone();
+        }
+    }
+
+    /// Writes values of a custom uniform source into the store.
+    ///
+    /// They replace whatever OSC sent for the same name, without going
+    /// through its filters. Names without a mapping are remembered, so they
+    /// are uploaded as uniforms of their own.
+    pub fn provide_values(&mut self, provided: Vec<(String, OscUniformValue)>) {
+        if let Ok(mut values) = self.values.lock() {
+            for (name, value) in provided {
+                if !self.provided.contains(&name) {
+                    self.provided.insert(name.clone());
+                }
+                values.insert(name, value);
+            }
+        }
+    }
+
+    /// Names which were ever written by a custom uniform source.
+    pub fn provided_names(&self) -> &HashSet<String> {
+        &self.provided
+    }
+
+    /// Takes over the mappings and defaults of a config and listens on its port.
+    ///
+    /// The value store is kept, so moving to another port does not reset
+    /// values sent by a controller. Only mappings without a value yet get
+    /// their defaults.
+    pub fn apply_config(&mut self, config: &OscConfig) -> Result<(), Error> {
+        self.update_type_mappings(config);
+        self.seed_defaults(config);
+        self.start(config.port)
+    }
+
+    /// Starts, updates or stops the OSCQuery server to match the config.
+    pub fn serve_query(&mut self, config: &OscConfig) -> Result<(), Error> {
+        let port = match config.query_port {
+            Some(port) => port,
+            None => {
+                self.query = None;
+                return Ok(());
+            }
+        };
+
+        let namespace = OscNamespace::new(config);
+        match &self.query {
+            Some(query) if query.port() == port => query.set_namespace(namespace),
+            _ => {
+                // free the old port first, in case the new one is taken
+                self.query = None;
+                let values = Arc::clone(&self.values);
+                self.query = Some(OscQueryServer::start(port, namespace, values)?);
             }
         }
+
+        Ok(())
     }
 
-    pub fn start(&mut self, port: u16) -> Result<(), String> {
+    pub fn start(&mut self, port: u16) -> Result<(), Error> {
         // Don't restart if already running on the same port
         if self.current_port == Some(port) && self.running.load(Ordering::Relaxed) {
             return Ok(());
@@ -83,31 +765,138 @@ impl OscReceiver {
         }
 
         let addr = format!("127.0.0.1:{}", port);
-        let socket = UdpSocket::bind(&addr)
-            .map_err(|e| format!("Failed to bind OSC socket to {}: {}", addr, e))?;
+        let socket = UdpSocket::bind(&addr).map_err(|source| Error::Bind {
+            service: "OSC socket",
+            addr: addr.clone(),
+            source,
+        })?;
 
         socket
             .set_read_timeout(Some(Duration::from_millis(100)))
-            .map_err(|e| format!("Failed to set socket timeout: {}", e))?;
+            .map_err(|source| Error::Io {
+                action: "set socket timeout",
+                source,
+            })?;
 
-        let values = Arc::clone(&self.values);
+        let inbox = Arc::clone(&self.inbox);
+        let counters = Arc::clone(&self.counters);
         let running = Arc::clone(&self.running);
         let type_mappings = Arc::clone(&self.type_mappings);
-        let socket_clone = socket
-            .try_clone()
-            .map_err(|e| format!("Failed to clone socket: {}", e))?;
+        let controls = Arc::clone(&self.controls);
+        let socket_clone = socket.try_clone().map_err(|source| Error::Io {
+            action: "clone socket",
+            source,
+        })?;
 
         running.store(true, Ordering::Relaxed);
 
+        let (scheduler, schedule) = mpsc::channel::<ScheduledBundle>();
+        let scheduler_handle = {
+            let inbox = Arc::clone(&inbox);
+            let running = Arc::clone(&running);
+            let type_mappings = Arc::clone(&type_mappings);
+            let controls = Arc::clone(&controls);
+            let scheduler = scheduler.clone();
+
+            thread::spawn(move || {
+                let mut queue = BinaryHeap::<Reverse<ScheduledBundle>>::new();
+                let mut arrivals = 0;
+                let mut batch = OscBatch::new();
+
+                while running.load(Ordering::Relaxed) {
+                    let timeout = match queue.peek() {
+                        Some(Reverse(next)) => next
+                            .due
+                            .duration_since(SystemTime::now())
+                            .unwrap_or_default()
+                            .min(SCHEDULER_POLL),
+                        None => SCHEDULER_POLL,
+                    };
+
+                    match schedule.recv_timeout(timeout) {
+                        Ok(mut bundle) => {
+                            bundle.arrival = arrivals;
+                            arrivals += 1;
+                            queue.push(Reverse(bundle));
+                        }
+                        Err(RecvTimeoutError::Timeout) => (),
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+
+                    let now = SystemTime::now();
+                    while matches!(queue.peek(), Some(Reverse(next)) if next.due <= now) {
+                        let Reverse(bundle) = queue.pop().unwrap();
+                        for packet in bundle.content {
+                            Self::process_packet(
+                                &mut batch,
+                                &type_mappings,
+                                &controls,
+                                &scheduler,
+                                packet,
+                            );
+                        }
+                    }
+
+                    // scheduled values are rare, so waiting for the lock is fine
+                    Self::publish(&inbox, &mut batch, true);
+                }
+
+                if !queue.is_empty() {
+                    log::debug!("Dropped {} scheduled OSC bundles", queue.len());
+                }
+                log::debug!("OSC scheduler thread stopped");
+            })
+        };
+
         let handle = thread::spawn(move || {
             let mut buf = [0u8; rosc::decoder::MTU];
+            let mut batch = OscBatch::new();
+            let mut last_error_log: Option<Instant> = None;
+            let mut unreported_errors = 0;
 
             while running.load(Ordering::Relaxed) {
                 match socket_clone.recv_from(&mut buf) {
-                    Ok((size, _addr)) => {
-                        if let Ok((_remaining, packet)) = rosc::decoder::decode_udp(&buf[..size]) {
-                            Self::process_packet(&values, &type_mappings, packet);
+                    Ok((size, addr)) => {
+                        counters.received.fetch_add(1, Ordering::Relaxed);
+                        match rosc::decoder::decode_udp(&buf[..size]) {
+                            Ok((_remaining, packet)) => {
+                                counters.decoded.fetch_add(1, Ordering::Relaxed);
+                                Self::process_packet(
+                                    &mut batch,
+                                    &type_mappings,
+                                    &controls,
+                                    &scheduler,
+                                    packet,
+                                );
+                            }
+                            Err(err) => {
+                                counters.errored.fetch_add(1, Ordering::Relaxed);
+                                unreported_errors += 1;
+
+                                // a broken sender can easily flood the log
+                                let due = last_error_log.map_or(true, |time| {
+                                    time.elapsed() >= DECODE_ERROR_LOG_INTERVAL
+                                });
+                                if due {
+                                    let head = &buf[..size.min(DECODE_ERROR_LOG_BYTES)];
+                                    let err = Error::Decode(format!(
+                                        "OSC packet of {} bytes from {}: {:?}",
+                                        size, addr, err
+                                    ));
+                                    log::warn!(
+                                        "{}, starts with {:02x?} ({} failed since last report)",
+                                        err,
+                                        head,
+                                        unreported_errors
+                                    );
+                                    last_error_log = Some(Instant::now());
+                                    unreported_errors = 0;
+                                }
+                            }
                         }
+
+                        // never block the network, the batch is retried with the next packet
+                        Self::publish(&inbox, &mut batch, false);
                     }
                     Err(e) => {
                         if e.kind() != std::io::ErrorKind::WouldBlock &&
@@ -115,6 +904,9 @@ impl OscReceiver {
                             log::warn!("OSC receive error: {}", e);
                             break;
                         }
+
+                        // the network went quiet, so nothing new is coming to flush the batch
+                        Self::publish(&inbox, &mut batch, true);
                     }
                 }
             }
@@ -122,7 +914,7 @@ impl OscReceiver {
         });
 
         self.socket = Some(socket);
-        self.thread_handle = Some(handle);
+        self.thread_handles = vec![handle, scheduler_handle];
         self.current_port = Some(port);
 
         log::info!("OSC receiver started on port {}", port);
@@ -136,7 +928,7 @@ impl OscReceiver {
             drop(socket);
         }
 
-        if let Some(handle) = self.thread_handle.take() {
+        for handle in self.thread_handles.drain(..) {
             if let Err(e) = handle.join() {
                 log::warn!("Failed to join OSC receiver thread: {:?}", e);
             }
@@ -146,68 +938,326 @@ impl OscReceiver {
         log::info!("OSC receiver stopped");
     }
 
+    /// Stops the receiver and scheduler threads, waiting at most `timeout` for them to exit.
+    ///
+    /// Returns `false` if the threads did not finish in time. In that case
+    /// they are detached and left to exit on their own.
+    pub fn shutdown(&mut self, timeout: Duration) -> bool {
+        self.running.store(false, Ordering::Relaxed);
+        self.socket = None;
+        self.current_port = None;
+        self.query = None;
+
+        let handles = std::mem::take(&mut self.thread_handles);
+
+        let deadline = Instant::now() + timeout;
+        while !handles.iter().all(|handle| handle.is_finished()) {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        for handle in handles {
+            if let Err(e) = handle.join() {
+                log::warn!("Failed to join OSC receiver thread: {:?}", e);
+            }
+        }
+
+        true
+    }
+
+    /// Moves a batch of received values into the inbox of the render thread.
+    ///
+    /// Unless `wait` is set, this gives up right away if the render thread
+    /// holds the lock, keeping the values in the batch for the next attempt.
+    fn publish(inbox: &Mutex<OscBatch>, batch: &mut OscBatch, wait: bool) {
+        if batch.is_empty() {
+            return;
+        }
+
+        let inbox = match wait {
+            true => inbox.lock().ok(),
+            false => inbox.try_lock().ok(),
+        };
+
+        if let Some(mut inbox) = inbox {
+            for (address, value) in batch.drain() {
+                coalesce(&mut inbox, address, value);
+            }
+        }
+    }
+
+    /// Applies a packet, or hands it to the scheduler if it is a bundle
+    /// which is not due yet.
     fn process_packet(
-        values: &Arc<Mutex<HashMap<String, OscUniformValue>>>,
-        type_mappings: &Arc<Mutex<HashMap<String, OscDataType>>>,
+        batch: &mut OscBatch,
+        type_mappings: &Arc<Mutex<OscTargets>>,
+        controls: &Arc<Mutex<OscControls>>,
+        scheduler: &Sender<ScheduledBundle>,
         packet: OscPacket,
     ) {
         match packet {
             OscPacket::Message(msg) => {
-                Self::process_message(values, type_mappings, msg);
+                Self::process_message(batch, type_mappings, controls, msg);
             }
-            OscPacket::Bundle(bundle) => {
-                for packet in bundle.content {
-                    Self::process_packet(values, type_mappings, packet);
+            OscPacket::Bundle(OscBundle { timetag, content }) => {
+                if let Some(due) = timetag_to_system_time(timetag) {
+                    if due > SystemTime::now() {
+                        log::trace!("Scheduled OSC bundle for {:?}", due);
+                        let bundle = ScheduledBundle {
+                            due,
+                            arrival: 0,
+                            content,
+                        };
+
+                        if scheduler.send(bundle).is_err() {
+                            log::warn!("OSC scheduler is gone, dropping bundle");
+                        }
+                        return;
+                    }
+                }
+
+                for packet in content {
+                    Self::process_packet(batch, type_mappings, controls, scheduler, packet);
                 }
             }
         }
     }
 
     fn process_message(
-        values: &Arc<Mutex<HashMap<String, OscUniformValue>>>,
-        type_mappings: &Arc<Mutex<HashMap<String, OscDataType>>>,
+        batch: &mut OscBatch,
+        type_mappings: &Arc<Mutex<OscTargets>>,
+        controls: &Arc<Mutex<OscControls>>,
         msg: OscMessage,
     ) {
-        if msg.args.is_empty() {
-            return;
-        }
+        // neither a heartbeat nor a reload needs arguments
+        if let Ok(mut controls) = controls.lock() {
+            if let Some((heartbeat, last)) = &mut controls.heartbeat {
+                if heartbeat.address == msg.addr {
+                    *last = Some(Instant::now());
+                    return;
+                }
+            }
 
-        // Get the expected data type for this address
-        let expected_type = type_mappings
-            .lock()
-            .ok()
-            .and_then(|mappings| mappings.get(&msg.addr).cloned())
-            .unwrap_or(OscDataType::Float); // Default to Float
+            if controls.reload_address.as_ref() == Some(&msg.addr) {
+                log::info!("Pipeline reload requested over OSC");
+                controls.reload = true;
+                return;
+            }
 
-        // Convert the OSC value based on the expected type
-        let value = match Self::convert_osc_value(&msg.args[0], &expected_type) {
-            Some(v) => v,
-            None => {
-                log::warn!("Failed to convert OSC value at {} to {:?}", msg.addr, expected_type);
+            if controls.reload_address.is_some() && msg.addr == REINIT_ADDRESS {
+                log::info!("OpenGL reset requested over OSC");
+                controls.reinit = true;
                 return;
             }
-        };
+        }
 
-        if let Ok(mut values_map) = values.lock() {
-            log::debug!("OSC received: {} = {:?} (as {:?})", msg.addr, value, expected_type);
-            values_map.insert(msg.addr, value);
-        } else {
-            log::warn!("Failed to lock OSC values map");
+        // a reset needs no arguments
+        if msg.addr == LOOP_RESET_ADDRESS {
+            if let Ok(mut controls) = controls.lock() {
+                controls.loop_reset = true;
+            }
+            return;
         }
-    }
 
-    fn convert_osc_value(osc_arg: &OscType, target_type: &OscDataType) -> Option<OscUniformValue> {
-        match target_type {
-            OscDataType::Float => match osc_arg {
-                OscType::Float(f) => Some(OscUniformValue::Float(*f)),
-                OscType::Double(d) => Some(OscUniformValue::Float(*d as f32)),
-                OscType::Int(i) => Some(OscUniformValue::Float(*i as f32)),
-                OscType::Long(l) => Some(OscUniformValue::Float(*l as f32)),
-                OscType::Bool(b) => Some(OscUniformValue::Float(if *b { 1.0 } else { 0.0 })),
-                _ => None,
-            },
-            OscDataType::Int => match osc_arg {
-                OscType::Int(i) => Some(OscUniformValue::Int(*i)),
+        if msg.args.is_empty() {
+            return;
+        }
+
+        if msg.addr == PRESET_ADDRESS {
+            match PresetRequest::from_osc(&msg.args[0]) {
+                Some(request) => {
+                    log::debug!("OSC preset request: {:?}", request);
+                    if let Ok(mut controls) = controls.lock() {
+                        controls.preset = Some(request);
+                    }
+                }
+                None => log::warn!("Invalid OSC preset request: {:?}", msg.args[0]),
+            }
+            return;
+        }
+
+        if msg.addr == CROSSFADE_ADDRESS {
+            match Self::convert_osc_value(&msg.args[0], &OscDataType::Float) {
+                Some(OscUniformValue::Float(f)) => {
+                    if let Ok(mut controls) = controls.lock() {
+                        controls.crossfade = Some(f.clamp(0.0, 1.0));
+                    }
+                }
+                _ => log::warn!("Invalid OSC crossfade value: {:?}", msg.args[0]),
+            }
+            return;
+        }
+
+        if msg.addr == FLASH_LIMITER_ADDRESS {
+            match Self::convert_osc_value(&msg.args[0], &OscDataType::Bool) {
+                Some(OscUniformValue::Bool(enabled)) => {
+                    if let Ok(mut controls) = controls.lock() {
+                        controls.flash_limiter = Some(enabled);
+                    }
+                }
+                _ => log::warn!("Invalid OSC flash limiter value: {:?}", msg.args[0]),
+            }
+            return;
+        }
+
+        if msg.addr == LUT_STRENGTH_ADDRESS {
+            match Self::convert_osc_value(&msg.args[0], &OscDataType::Float) {
+                Some(OscUniformValue::Float(f)) => {
+                    if let Ok(mut controls) = controls.lock() {
+                        controls.lut_strength = Some(f.clamp(0.0, 1.0));
+                    }
+                }
+                _ => log::warn!("Invalid OSC LUT strength value: {:?}", msg.args[0]),
+            }
+            return;
+        }
+
+        if msg.addr == AUDIO_PROFILE_ADDRESS {
+            match PresetRequest::from_osc(&msg.args[0]) {
+                Some(request) => {
+                    if let Ok(mut controls) = controls.lock() {
+                        controls.audio_profile = Some(request);
+                    }
+                }
+                None => log::warn!("Invalid OSC audio profile request: {:?}", msg.args[0]),
+            }
+            return;
+        }
+
+        if let Some(label) = parse_pass_address(&msg.addr) {
+            match Self::convert_osc_value(&msg.args[0], &OscDataType::Bool) {
+                Some(OscUniformValue::Bool(enabled)) => {
+                    if let Ok(mut controls) = controls.lock() {
+                        controls.passes.push((label.to_owned(), enabled));
+                    }
+                }
+                _ => log::warn!("Invalid OSC pass toggle: {:?}", msg.args[0]),
+            }
+            return;
+        }
+
+        // Get the mappings of this address, unmapped addresses are stored as floats
+        let targets = type_mappings
+            .lock()
+            .ok()
+            .and_then(|mappings| mappings.get(&msg.addr).cloned())
+            .unwrap_or_else(|| vec![(msg.addr.clone(), OscDataType::Float, None)]);
+
+        // every mapping converts the original value by itself
+        for (name, expected_type, size) in targets {
+            // rotations take all arguments at once and are stored as a quaternion
+            if let OscDataType::Rotation(rotation) = &expected_type {
+                let args: Vec<f32> = msg
+                    .args
+                    .iter()
+                    .filter_map(
+                        |arg| match Self::convert_osc_value(arg, &OscDataType::Float) {
+                            Some(OscUniformValue::Float(f)) => Some(f),
+                            _ => None,
+                        },
+                    )
+                    .collect();
+
+                match rotation.quaternion(&args) {
+                    Some(q) => {
+                        for (k, &value) in q.iter().enumerate() {
+                            let value = OscUniformValue::Float(value);
+                            let element = array_element_name(&name, k);
+                            coalesce(
+                                batch,
+                                element,
+                                Coalesced {
+                                    value,
+                                    pulse: false,
+                                },
+                            );
+                        }
+                    }
+                    None => log::warn!(
+                        "OSC message at {} is not a valid {}: {:?}",
+                        msg.addr,
+                        expected_type.name(),
+                        msg.args
+                    ),
+                }
+                continue;
+            }
+
+            // array mappings take one element per argument, missing ones are zero
+            if let Some(size) = size {
+                if msg.args.len() != size {
+                    log::warn!(
+                        "OSC message at {} has {} arguments, but {} has {} elements",
+                        msg.addr,
+                        msg.args.len(),
+                        name,
+                        size
+                    );
+                }
+
+                let zero = OscType::Int(0);
+                for k in 0..size {
+                    let arg = msg.args.get(k).unwrap_or(&zero);
+                    let value = match Self::convert_osc_value(arg, &expected_type) {
+                        Some(v) => v,
+                        None => {
+                            log::warn!(
+                                "Failed to convert OSC value at {} to {:?}",
+                                msg.addr,
+                                expected_type
+                            );
+                            continue;
+                        }
+                    };
+
+                    let pulse = value == OscUniformValue::Bool(true);
+                    coalesce(
+                        batch,
+                        array_element_name(&name, k),
+                        Coalesced { value, pulse },
+                    );
+                }
+                continue;
+            }
+
+            let value = match Self::convert_osc_value(&msg.args[0], &expected_type) {
+                Some(v) => v,
+                None => {
+                    log::warn!(
+                        "Failed to convert OSC value at {} to {:?}",
+                        msg.addr,
+                        expected_type
+                    );
+                    continue;
+                }
+            };
+
+            log::trace!(
+                "OSC received: {} = {:?} (as {:?} for {})",
+                msg.addr,
+                value,
+                expected_type,
+                name
+            );
+            let pulse = value == OscUniformValue::Bool(true);
+            coalesce(batch, name, Coalesced { value, pulse });
+        }
+    }
+
+    fn convert_osc_value(osc_arg: &OscType, target_type: &OscDataType) -> Option<OscUniformValue> {
+        match target_type {
+            OscDataType::Float => match osc_arg {
+                OscType::Float(f) => Some(OscUniformValue::Float(*f)),
+                OscType::Double(d) => Some(OscUniformValue::Float(*d as f32)),
+                OscType::Int(i) => Some(OscUniformValue::Float(*i as f32)),
+                OscType::Long(l) => Some(OscUniformValue::Float(*l as f32)),
+                OscType::Bool(b) => Some(OscUniformValue::Float(if *b { 1.0 } else { 0.0 })),
+                _ => None,
+            },
+            OscDataType::Int => match osc_arg {
+                OscType::Int(i) => Some(OscUniformValue::Int(*i)),
                 OscType::Long(l) => Some(OscUniformValue::Int(*l as i32)),
                 OscType::Float(f) => Some(OscUniformValue::Int(f.round() as i32)),
                 OscType::Double(d) => Some(OscUniformValue::Int(d.round() as i32)),
@@ -222,11 +1272,266 @@ impl OscReceiver {
                 OscType::Double(d) => Some(OscUniformValue::Bool(*d != 0.0)),
                 _ => None,
             },
+            OscDataType::Double => match osc_arg {
+                OscType::Double(d) => Some(OscUniformValue::Double(*d)),
+                OscType::Float(f) => Some(OscUniformValue::Double(*f as f64)),
+                OscType::Long(l) => Some(OscUniformValue::Double(*l as f64)),
+                OscType::Int(i) => Some(OscUniformValue::Double(*i as f64)),
+                OscType::Bool(b) => Some(OscUniformValue::Double(if *b { 1.0 } else { 0.0 })),
+                _ => None,
+            },
+            OscDataType::Long => match osc_arg {
+                OscType::Long(l) => Some(OscUniformValue::Long(*l)),
+                OscType::Int(i) => Some(OscUniformValue::Long(*i as i64)),
+                OscType::Float(f) => Some(OscUniformValue::Long(f.round() as i64)),
+                OscType::Double(d) => Some(OscUniformValue::Long(d.round() as i64)),
+                OscType::Bool(b) => Some(OscUniformValue::Long(if *b { 1 } else { 0 })),
+                _ => None,
+            },
+            OscDataType::Mode(modes) => match osc_arg {
+                OscType::String(s) => {
+                    let index = modes.iter().position(|mode| mode == s)?;
+                    Some(mode_index(modes, index as _))
+                }
+                OscType::Int(i) => Some(mode_index(modes, *i as _)),
+                OscType::Long(l) => Some(mode_index(modes, *l)),
+                OscType::Float(f) => Some(mode_index(modes, f.round() as _)),
+                OscType::Double(d) => Some(mode_index(modes, d.round() as _)),
+                _ => None,
+            },
+            // a single argument is never a whole rotation
+            OscDataType::Rotation(_) => None,
         }
     }
 
-    pub fn get_value(&self, address: &str) -> Option<OscUniformValue> {
-        self.values.lock().ok()?.get(address).cloned()
+    /// Applies all values received since the last call.
+    ///
+    /// This is called once per frame, so the network rate does not affect
+    /// the render loop. A bool value which went `true` and back to `false`
+    /// within a single frame reads as `true` for one frame, so short pulses
+    /// are never lost.
+    pub fn tick(&mut self) {
+        self.update_connection();
+
+        // any packet counts as activity, even one without a mapping
+        let received = self.counters.received.load(Ordering::Relaxed);
+        if received != self.received_at_tick {
+            self.received_at_tick = received;
+            self.last_received = Some(Instant::now());
+        }
+
+        let batch = match self.inbox.lock() {
+            Ok(mut inbox) => std::mem::take(&mut *inbox),
+            Err(_) => return,
+        };
+
+        if batch.is_empty() && self.releases.is_empty() {
+            return;
+        }
+
+        let mut values = match self.values.lock() {
+            Ok(values) => values,
+            Err(_) => return,
+        };
+
+        let filters = self.filters.lock().map(|f| f.clone()).unwrap_or_default();
+        let inverted = |name: &str, b: bool| {
+            let invert = filters.get(name).map_or(false, |f| f.invert);
+            OscUniformValue::Bool(b != invert)
+        };
+
+        for name in self.releases.drain(..) {
+            // a newer value replaces the release
+            if !batch.contains_key(&name) {
+                let value = inverted(&name, false);
+                values.insert(name, value);
+            }
+        }
+
+        for (name, Coalesced { value, pulse }) in batch {
+            if pulse && value == OscUniformValue::Bool(false) {
+                values.insert(name.clone(), inverted(&name, true));
+                self.releases.push(name);
+                continue;
+            }
+
+            // inversion, deadzone and quantization only look at the latest value of a frame
+            let value = match filters.get(&name) {
+                Some(filter) => match filter.apply(value, values.get(&name)) {
+                    Some(value) => value,
+                    None => continue,
+                },
+                None => value,
+            };
+
+            values.insert(name, value);
+        }
+    }
+
+    /// Applies a packet as if it had arrived over the network, followed by a `tick`.
+    ///
+    /// Time tags are ignored, every bundle is applied right away. This does
+    /// not need a socket, so mappings can be tested deterministically.
+    pub fn inject(&mut self, packet: OscPacket) {
+        let mut batch = OscBatch::new();
+        let mut packets = vec![packet];
+
+        while let Some(packet) = packets.pop() {
+            match packet {
+                OscPacket::Message(msg) => {
+                    Self::process_message(&mut batch, &self.type_mappings, &self.controls, msg);
+                }
+                OscPacket::Bundle(bundle) => packets.extend(bundle.content.into_iter().rev()),
+            }
+        }
+
+        Self::publish(&self.inbox, &mut batch, true);
+        self.tick();
+    }
+
+    /// Returns how many packets were received, decoded and dropped so far.
+    pub fn stats(&self) -> OscStats {
+        OscStats {
+            received: self.counters.received.load(Ordering::Relaxed),
+            decoded: self.counters.decoded.load(Ordering::Relaxed),
+            errored: self.counters.errored.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the value of a mapping, or of an unmapped address.
+    pub fn get_value(&self, name: &str) -> Option<OscUniformValue> {
+        self.values.lock().ok()?.get(name).cloned()
+    }
+
+    /// Returns a value as a float, whatever type it was stored as.
+    pub fn get_f32(&self, name: &str) -> Option<f32> {
+        self.get_value(name).map(|value| value.as_f32())
+    }
+
+    /// Returns a value as an int, whatever type it was stored as.
+    pub fn get_i32(&self, name: &str) -> Option<i32> {
+        self.get_value(name).map(|value| value.as_i32())
+    }
+
+    /// Returns a value as a bool, whatever type it was stored as.
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.get_value(name).map(|value| value.as_bool())
+    }
+
+    /// Returns the first four elements of an array mapping as floats.
+    ///
+    /// Missing elements are 0, and a single value fills all four, like `vec4(x)`
+    /// does in GLSL. Returns `None` if neither exists.
+    pub fn get_vec4(&self, name: &str) -> Option<[f32; 4]> {
+        let values = self.values.lock().ok()?;
+        let elements: Vec<_> = (0..4)
+            .map(|k| values.get(&array_element_name(name, k)))
+            .collect();
+
+        if elements.iter().all(Option::is_none) {
+            return values.get(name).map(|value| [value.as_f32(); 4]);
+        }
+
+        let mut vec = [0.0; 4];
+        for (x, element) in vec.iter_mut().zip(elements) {
+            *x = element.map_or(0.0, OscUniformValue::as_f32);
+        }
+        Some(vec)
+    }
+
+    /// Returns the most recent `/preset` request, if there is one.
+    pub fn take_preset_request(&self) -> Option<PresetRequest> {
+        self.controls.lock().ok()?.preset.take()
+    }
+
+    /// Checks whether the heartbeat arrived within its timeout.
+    fn update_connection(&mut self) {
+        let connected = match self.controls.lock() {
+            Ok(controls) => match &controls.heartbeat {
+                Some((heartbeat, last)) => {
+                    Some(last.map_or(false, |last| last.elapsed() <= heartbeat.timeout))
+                }
+                None => None,
+            },
+            Err(_) => return,
+        };
+
+        match (self.connected, connected) {
+            (Some(false), Some(true)) | (None, Some(true)) => {
+                log::info!("OSC controller connected")
+            }
+            (Some(true), Some(false)) => log::warn!("OSC controller stopped sending its heartbeat"),
+            _ => (),
+        }
+
+        self.connected = connected;
+    }
+
+    /// Whether the receiver is listening and got a packet recently.
+    pub fn is_active(&self) -> bool {
+        let recent = self
+            .last_received
+            .map_or(false, |t| t.elapsed() <= INPUT_ACTIVE_TIMEOUT);
+        self.running.load(Ordering::Relaxed) && recent
+    }
+
+    /// Whether the controller sent its heartbeat in time.
+    ///
+    /// Returns `None` if no heartbeat is configured.
+    pub fn is_connected(&self) -> Option<bool> {
+        self.connected
+    }
+
+    /// Returns the most recent `/crossfade` value, if there is one.
+    pub fn take_crossfade(&self) -> Option<f32> {
+        self.controls.lock().ok()?.crossfade.take()
+    }
+
+    /// Returns `true` once after a `/loop/reset` message arrived.
+    pub fn take_loop_reset(&self) -> bool {
+        match self.controls.lock() {
+            Ok(mut controls) => std::mem::take(&mut controls.loop_reset),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns `true` once after a message arrived at the reload address.
+    pub fn take_reload(&self) -> bool {
+        match self.controls.lock() {
+            Ok(mut controls) => std::mem::take(&mut controls.reload),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns `true` once after a message arrived at `/reinit`.
+    pub fn take_reinit(&self) -> bool {
+        match self.controls.lock() {
+            Ok(mut controls) => std::mem::take(&mut controls.reinit),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the most recent `/flash_limiter` value, if there is one.
+    pub fn take_flash_limiter(&self) -> Option<bool> {
+        self.controls.lock().ok()?.flash_limiter.take()
+    }
+
+    /// Returns the most recent `/color/lut_strength` value, if there is one.
+    pub fn take_lut_strength(&self) -> Option<f32> {
+        self.controls.lock().ok()?.lut_strength.take()
+    }
+
+    /// Returns the most recent `/audio/profile` request, if there is one.
+    pub fn take_audio_profile_request(&self) -> Option<PresetRequest> {
+        self.controls.lock().ok()?.audio_profile.take()
+    }
+
+    /// Returns all `/pass/<label>/enable` toggles since the last call.
+    pub fn take_pass_toggles(&self) -> Vec<(String, bool)> {
+        match self.controls.lock() {
+            Ok(mut controls) => std::mem::take(&mut controls.passes),
+            Err(_) => Vec::new(),
+        }
     }
 
     pub fn get_all_values(&self) -> HashMap<String, OscUniformValue> {
@@ -243,6 +1548,13 @@ impl Drop for OscReceiver {
 #[derive(Debug, Clone)]
 pub struct OscConfig {
     pub port: u16,
+    /// Port of the OSCQuery HTTP server, which is off unless this is set.
+    pub query_port: Option<u16>,
+    /// Convert values to the type of their uniform, if the mapping declares another one.
+    pub coerce: bool,
+    pub heartbeat: Option<OscHeartbeat>,
+    /// Address which rebuilds the pipeline, `None` unless `allow_remote_reload` is set.
+    pub reload_address: Option<String>,
     pub mappings: HashMap<String, OscMapping>,
 }
 
@@ -250,13 +1562,21 @@ impl Default for OscConfig {
     fn default() -> Self {
         Self {
             port: 9000,
+            query_port: None,
+            coerce: false,
+            heartbeat: None,
+            reload_address: None,
             mappings: HashMap::new(),
         }
     }
 }
 
 impl OscConfig {
-    pub fn from_yaml(value: &serde_yaml::Value) -> Result<Self, String> {
+    pub fn from_yaml(value: &serde_yaml::Value) -> Result<Self, Error> {
+        Self::parse(value).map_err(Error::Config)
+    }
+
+    fn parse(value: &serde_yaml::Value) -> Result<Self, String> {
         let mut config = Self::default();
 
         if let Some(port) = value.get("port") {
@@ -266,6 +1586,48 @@ impl OscConfig {
                 .map_err(|_| "OSC port must be between 0 and 65535")?;
         }
 
+        if let Some(port) = value.get("query_port") {
+            config.query_port = Some(
+                port.as_u64()
+                    .ok_or("OSC query port must be a number")?
+                    .try_into()
+                    .map_err(|_| "OSC query port must be between 0 and 65535")?,
+            );
+        }
+
+        if let Some(coerce) = value.get("coerce") {
+            config.coerce = coerce.as_bool().ok_or("OSC coerce must be a boolean")?;
+        }
+
+        if let Some(heartbeat) = value.get("heartbeat") {
+            config.heartbeat = Some(Self::parse_heartbeat(heartbeat)?);
+        }
+
+        // anyone on the network could trigger a reload, so it has to be enabled
+        let allow_reload = match value.get("allow_remote_reload") {
+            Some(allow) => allow
+                .as_bool()
+                .ok_or("OSC 'allow_remote_reload' must be a boolean")?,
+            None => false,
+        };
+
+        let reload_address = match value.get("reload_address") {
+            Some(address) => match address.as_str() {
+                Some(address) if address.starts_with('/') => address.to_owned(),
+                _ => {
+                    return Err(format!(
+                        "OSC 'reload_address' must start with '/', got {:?}",
+                        address
+                    ))
+                }
+            },
+            None => RELOAD_ADDRESS.to_owned(),
+        };
+
+        if allow_reload {
+            config.reload_address = Some(reload_address);
+        }
+
         if let Some(mappings) = value.get("mappings") {
             if let Some(mappings_obj) = mappings.as_mapping() {
                 for (key, val) in mappings_obj {
@@ -273,14 +1635,24 @@ impl OscConfig {
                         .ok_or("OSC mapping key must be a string")?
                         .to_string();
 
+                    let (uniform, index) = parse_uniform_target(&key_str)?;
+                    let uniform = uniform.to_string();
+
                     let mapping = match val {
                         // Simple string format: "uniform_name": "/osc/address"
                         serde_yaml::Value::String(address) => {
                             OscMapping {
                                 address: address.clone(),
                                 data_type: OscDataType::default(), // Float
+                                default: None,
+                                uniform,
+                                index,
+                                size: None,
+                                filter: OscFilter::default(),
+                                label: None,
+                                group: None,
                             }
-                        },
+                        }
                         // Extended format: "uniform_name": { "address": "/osc/address", "type": "float" }
                         serde_yaml::Value::Mapping(map) => {
                             let address = map.get(&serde_yaml::Value::String("address".to_string()))
@@ -293,12 +1665,102 @@ impl OscConfig {
                                 Some("float") => OscDataType::Float,
                                 Some("int") => OscDataType::Int,
                                 Some("bool") => OscDataType::Bool,
-                                Some(other) => return Err(format!("Unknown OSC data type: {}", other)),
+                                Some("double") => OscDataType::Double,
+                                Some("long") => OscDataType::Long,
+                                Some(kind @ ("quaternion" | "euler")) => OscDataType::Rotation(
+                                    Self::parse_rotation(map, kind)
+                                        .map_err(|e| format!("{} in OSC mapping {}", e, key_str))?,
+                                ),
+                                Some(other) => {
+                                    return Err(format!("Unknown OSC data type: {}", other))
+                                }
                                 None => OscDataType::default(), // Float
                             };
 
-                            OscMapping { address, data_type }
-                        },
+                            let data_type =
+                                match map.get(&serde_yaml::Value::String("modes".to_string())) {
+                                    Some(v) => {
+                                        if map.contains_key(&serde_yaml::Value::String(
+                                            "type".to_string(),
+                                        )) && data_type != OscDataType::Int
+                                        {
+                                            return Err(format!(
+                                                "OSC 'modes' require type int in OSC mapping {}",
+                                                key_str
+                                            ));
+                                        }
+
+                                        OscDataType::Mode(Self::parse_modes(v).map_err(|e| {
+                                            format!("{} in OSC mapping {}", e, key_str)
+                                        })?)
+                                    }
+                                    None => data_type,
+                                };
+
+                            let default =
+                                match map.get(&serde_yaml::Value::String("default".to_string())) {
+                                    Some(v) => Some(
+                                        Self::parse_default(v, &data_type).ok_or_else(|| {
+                                            format!(
+                                                "Invalid default value for OSC mapping {}: {:?}",
+                                                key_str, v
+                                            )
+                                        })?,
+                                    ),
+                                    None => None,
+                                };
+
+                            let filter = Self::parse_filter(map, &data_type)
+                                .map_err(|e| format!("{} in OSC mapping {}", e, key_str))?;
+
+                            let text = |field: &str| match map
+                                .get(&serde_yaml::Value::String(field.to_string()))
+                            {
+                                Some(serde_yaml::Value::String(s)) => Ok(Some(s.clone())),
+                                Some(v) => Err(format!(
+                                    "OSC '{}' must be a string in OSC mapping {}, got {:?}",
+                                    field, key_str, v
+                                )),
+                                None => Ok(None),
+                            };
+
+                            let label = text("label")?;
+                            let group = text("group")?;
+
+                            let size = match map.get(&serde_yaml::Value::String("array".to_string())) {
+                                Some(v) => match v.as_u64() {
+                                    Some(n) if n > 0 => Some(n as usize),
+                                    _ => return Err(format!("OSC 'array' must be a positive number of elements in OSC mapping {}, got {:?}", key_str, v)),
+                                },
+                                None => None,
+                            };
+
+                            if size.is_some() && index.is_some() {
+                                return Err(format!("OSC 'array' can not be used with an element index in OSC mapping {}", key_str));
+                            }
+
+                            if matches!(data_type, OscDataType::Rotation(_))
+                                && (size.is_some() || index.is_some())
+                            {
+                                return Err(format!("OSC rotations can not be arrays or array elements in OSC mapping {}", key_str));
+                            }
+
+                            if size.is_some() && filter != OscFilter::default() {
+                                return Err(format!("OSC 'deadzone', 'quantize' and 'invert' do not apply to arrays in OSC mapping {}", key_str));
+                            }
+
+                            OscMapping {
+                                address,
+                                data_type,
+                                default,
+                                uniform,
+                                index,
+                                size,
+                                filter,
+                                label,
+                                group,
+                            }
+                        }
                         _ => return Err("OSC mapping value must be a string or object".to_string()),
                     };
 
@@ -309,4 +1771,1270 @@ impl OscConfig {
 
         Ok(config)
     }
-}
\ No newline at end of file
+
+    fn parse_heartbeat(value: &serde_yaml::Value) -> Result<OscHeartbeat, String> {
+        // either just the address, or an object with address and timeout
+        let (address, timeout) = match value {
+            serde_yaml::Value::String(address) => (Some(address.as_str()), None),
+            serde_yaml::Value::Mapping(_) => (
+                value.get("address").and_then(|v| v.as_str()),
+                value.get("timeout"),
+            ),
+            _ => (None, None),
+        };
+
+        let address = match address {
+            Some(address) if address.starts_with('/') => address.to_owned(),
+            _ => {
+                return Err(format!(
+                    "OSC 'heartbeat' must have an address starting with '/', got {:?}",
+                    value
+                ))
+            }
+        };
+
+        let timeout = match timeout.map(|v| v.as_f64()) {
+            None => OSC_HEARTBEAT_TIMEOUT,
+            Some(Some(secs)) if secs > 0.0 && secs.is_finite() => Duration::from_secs_f64(secs),
+            Some(_) => {
+                return Err(format!(
+                    "OSC heartbeat 'timeout' must be a positive number of seconds, got {:?}",
+                    timeout
+                ))
+            }
+        };
+
+        Ok(OscHeartbeat { address, timeout })
+    }
+
+    fn parse_filter(
+        map: &serde_yaml::Mapping,
+        data_type: &OscDataType,
+    ) -> Result<OscFilter, String> {
+        let get = |key: &str| map.get(&serde_yaml::Value::String(key.to_string()));
+        let mut filter = OscFilter::default();
+
+        if let Some(v) = get("deadzone") {
+            filter.deadzone = match v.as_f64() {
+                Some(x) if x >= 0.0 => x as f32,
+                _ => {
+                    return Err(format!(
+                        "OSC 'deadzone' must be a non-negative number, got {:?}",
+                        v
+                    ))
+                }
+            };
+        }
+
+        if let Some(v) = get("quantize") {
+            filter.quantize = match v.as_f64() {
+                Some(x) if x > 0.0 => Some(x as f32),
+                _ => {
+                    return Err(format!(
+                        "OSC 'quantize' must be a positive number, got {:?}",
+                        v
+                    ))
+                }
+            };
+        }
+
+        if let Some(v) = get("invert") {
+            filter.invert = v
+                .as_bool()
+                .ok_or_else(|| format!("OSC 'invert' must be true or false, got {:?}", v))?;
+        }
+
+        if matches!(
+            data_type,
+            OscDataType::Bool | OscDataType::Mode(_) | OscDataType::Rotation(_)
+        ) && (filter.deadzone != 0.0 || filter.quantize.is_some())
+        {
+            return Err(
+                "OSC 'deadzone' and 'quantize' do not apply to bools, modes and rotations"
+                    .to_string(),
+            );
+        }
+
+        if !matches!(
+            data_type,
+            OscDataType::Float | OscDataType::Double | OscDataType::Bool
+        ) && filter.invert
+        {
+            return Err("OSC 'invert' only applies to floats and bools".to_string());
+        }
+
+        Ok(filter)
+    }
+
+    fn parse_rotation(map: &serde_yaml::Mapping, kind: &str) -> Result<OscRotation, String> {
+        let get = |key: &str| map.get(&serde_yaml::Value::String(key.to_string()));
+        let order = match get("order") {
+            Some(v) => Some(
+                v.as_str()
+                    .ok_or_else(|| format!("OSC 'order' must be a string, got {:?}", v))?,
+            ),
+            None => None,
+        };
+
+        if kind == "quaternion" {
+            if get("degrees").is_some() {
+                return Err("OSC 'degrees' only applies to euler angles".to_string());
+            }
+
+            return match order {
+                Some("xyzw") | None => Ok(OscRotation::Quaternion { w_first: false }),
+                Some("wxyz") => Ok(OscRotation::Quaternion { w_first: true }),
+                Some(s) => Err(format!(
+                    "OSC quaternion 'order' must be xyzw or wxyz, got {:?}",
+                    s
+                )),
+            };
+        }
+
+        let degrees = match get("degrees") {
+            Some(v) => v
+                .as_bool()
+                .ok_or_else(|| format!("OSC 'degrees' must be a boolean, got {:?}", v))?,
+            None => false,
+        };
+
+        let order = order.unwrap_or("xyz");
+        let axes: Vec<usize> = order.chars().filter_map(|c| "xyz".find(c)).collect();
+        match axes.as_slice() {
+            &[a, b, c] if order.len() == 3 && a != b && b != c && a != c => {
+                Ok(OscRotation::Euler {
+                    order: [a, b, c],
+                    degrees,
+                })
+            }
+            _ => Err(format!(
+                "OSC euler 'order' must name each of the axes x, y and z once, got {:?}",
+                order
+            )),
+        }
+    }
+
+    fn parse_modes(value: &serde_yaml::Value) -> Result<Vec<String>, String> {
+        let modes = match value.as_sequence() {
+            Some(seq) if !seq.is_empty() => seq,
+            _ => {
+                return Err(format!(
+                    "OSC 'modes' must be a non-empty list of names, got {:?}",
+                    value
+                ))
+            }
+        };
+
+        let mut names = Vec::with_capacity(modes.len());
+        for mode in modes {
+            match mode.as_str() {
+                Some(name) if names.iter().any(|n| n == name) => {
+                    return Err(format!("OSC mode {:?} is listed more than once", name))
+                }
+                Some(name) => names.push(name.to_owned()),
+                None => {
+                    return Err(format!(
+                        "OSC 'modes' must be a list of names, got {:?}",
+                        mode
+                    ))
+                }
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn parse_default(
+        value: &serde_yaml::Value,
+        data_type: &OscDataType,
+    ) -> Option<OscUniformValue> {
+        match data_type {
+            OscDataType::Float => value.as_f64().map(|f| OscUniformValue::Float(f as f32)),
+            OscDataType::Int => value.as_i64().map(|i| OscUniformValue::Int(i as i32)),
+            OscDataType::Bool => value.as_bool().map(OscUniformValue::Bool),
+            OscDataType::Double => value.as_f64().map(OscUniformValue::Double),
+            OscDataType::Long => value.as_i64().map(OscUniformValue::Long),
+            OscDataType::Mode(modes) => match value {
+                serde_yaml::Value::String(s) => {
+                    let index = modes.iter().position(|mode| mode == s)?;
+                    Some(mode_index(modes, index as _))
+                }
+                _ => value.as_i64().map(|i| mode_index(modes, i)),
+            },
+            OscDataType::Rotation(_) => None,
+        }
+    }
+}
+
+/// Splits a mapping target like `grid[5]` into the uniform name and the array index.
+pub fn parse_uniform_target(target: &str) -> Result<(&str, Option<usize>), Error> {
+    let (name, rest) = match target.split_once('[') {
+        Some(parts) => parts,
+        None => return Ok((target, None)),
+    };
+
+    match rest.strip_suffix(']').map(str::parse) {
+        Some(Ok(index)) if !name.is_empty() => Ok((name, Some(index))),
+        _ => Err(Error::Config(format!(
+            "Expected OSC mapping target to be \"name\" or \"name[index]\", got {:?}",
+            target
+        ))),
+    }
+}
+
+/// CPU-side copy of a uniform array which is written element-wise by OSC mappings.
+///
+/// Each stage keeps the last array it received, so the array is only
+/// uploaded to a stage after one of its elements changed.
+#[derive(Debug, Clone)]
+pub struct OscArray {
+    pub name: CString,
+    pub kind: GLenum,
+    pub values: Vec<f32>,
+    /// Array index and mapping name of every mapped element.
+    pub elements: Vec<(usize, String)>,
+    version: u64,
+    uploaded: Vec<Option<u64>>,
+}
+
+impl OscArray {
+    pub fn new(
+        name: CString,
+        kind: GLenum,
+        size: usize,
+        elements: Vec<(usize, String)>,
+        stage_count: usize,
+    ) -> Self {
+        Self {
+            name,
+            kind,
+            values: vec![0.0; size],
+            elements,
+            version: 0,
+            uploaded: vec![None; stage_count],
+        }
+    }
+
+    /// Copies the received values into the array.
+    ///
+    /// Returns `true` if any element changed.
+    pub fn update(&mut self, values: &HashMap<String, OscUniformValue>) -> bool {
+        let mut changed = false;
+        for (index, name) in self.elements.iter() {
+            let value = match values.get(name) {
+                Some(value) => value.as_f32(),
+                None => continue,
+            };
+
+            if let Some(element) = self.values.get_mut(*index) {
+                if *element != value {
+                    *element = value;
+                    changed = true;
+                }
+            }
+        }
+
+        if changed {
+            self.version += 1;
+        }
+
+        changed
+    }
+
+    /// Uploads the array to the program of a stage, if the stage is out of date.
+    ///
+    /// The program has to be in use.
+    pub fn upload(&mut self, stage_index: usize, prog_id: GLuint) {
+        let uploaded = match self.uploaded.get_mut(stage_index) {
+            Some(uploaded) => uploaded,
+            None => return,
+        };
+
+        if *uploaded == Some(self.version) {
+            return;
+        }
+
+        *uploaded = Some(self.version);
+
+        unsafe {
+            let loc = gl::GetUniformLocation(prog_id, self.name.as_ptr());
+            if loc == -1 {
+                return;
+            }
+
+            match self.kind {
+                gl::FLOAT => gl::Uniform1fv(loc, self.values.len() as _, self.values.as_ptr()),
+                _ => {
+                    let values: Vec<GLint> = self.values.iter().map(|&v| v as _).collect();
+                    gl::Uniform1iv(loc, values.len() as _, values.as_ptr());
+                }
+            }
+            gl_debug_check!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> OscConfig {
+        let yaml = r#"
+            port: 9001
+            mappings:
+              brightness:
+                address: "/fx/brightness"
+                type: float
+                default: 0.75
+              scene:
+                address: "/fx/scene"
+                type: int
+                default: 3
+              strobe:
+                address: "/fx/strobe"
+                type: bool
+                default: true
+              hue: "/fx/hue"
+        "#;
+
+        let value = serde_yaml::from_str(yaml).unwrap();
+        OscConfig::from_yaml(&value).unwrap()
+    }
+
+    /// Asks the OS for a port which is free right now.
+    fn free_port() -> u16 {
+        UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[test]
+    fn port_rebind_keeps_values() {
+        let mut config = config();
+        config.port = free_port();
+
+        let mut receiver = OscReceiver::new();
+        receiver.apply_config(&config).unwrap();
+        receiver.inject(OscPacket::Message(OscMessage {
+            addr: "/fx/brightness".into(),
+            args: vec![OscType::Float(0.25)],
+        }));
+        assert_eq!(receiver.get_f32("brightness"), Some(0.25));
+
+        let old_port = config.port;
+        config.port = free_port();
+        receiver.apply_config(&config).unwrap();
+
+        // values sent before the reload are not reset to their defaults
+        assert_eq!(receiver.get_f32("brightness"), Some(0.25));
+        assert_eq!(receiver.get_i32("scene"), Some(3));
+        assert!(UdpSocket::bind(("127.0.0.1", old_port)).is_ok());
+
+        let packet = OscPacket::Message(OscMessage {
+            addr: "/fx/scene".into(),
+            args: vec![OscType::Int(7)],
+        });
+        let bytes = rosc::encoder::encode(&packet).unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.send_to(&bytes, ("127.0.0.1", config.port)).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while receiver.get_i32("scene") != Some(7) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+            receiver.tick();
+        }
+        assert_eq!(receiver.get_i32("scene"), Some(7));
+        assert_eq!(receiver.get_f32("brightness"), Some(0.25));
+
+        receiver.stop();
+    }
+
+    #[test]
+    fn uniform_targets() {
+        assert!(matches!(parse_uniform_target("hue"), Ok(("hue", None))));
+        assert!(matches!(
+            parse_uniform_target("grid[5]"),
+            Ok(("grid", Some(5)))
+        ));
+        assert!(matches!(
+            parse_uniform_target("grid["),
+            Err(Error::Config(_))
+        ));
+        assert!(parse_uniform_target("grid[-1]").is_err());
+        assert!(parse_uniform_target("[3]").is_err());
+    }
+
+    #[test]
+    fn array_updates() {
+        let elements = vec![(1, "grid[1]".to_string()), (3, "grid[3]".to_string())];
+        let mut array = OscArray::new(CString::new("grid").unwrap(), gl::FLOAT, 4, elements, 1);
+
+        let mut values = HashMap::new();
+        assert!(!array.update(&values));
+
+        values.insert("grid[3]".to_string(), OscUniformValue::Float(0.5));
+        values.insert("grid[2]".to_string(), OscUniformValue::Float(1.0));
+        assert!(array.update(&values));
+        assert_eq!(array.values, vec![0.0, 0.0, 0.0, 0.5]);
+
+        // nothing changed since the last update
+        assert!(!array.update(&values));
+    }
+
+    #[test]
+    fn array_messages() {
+        let yaml = r#"
+            mappings:
+              steps:
+                address: "/steps"
+                array: 4
+        "#;
+
+        let config = OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        assert_eq!(config.mappings["steps"].size, Some(4));
+
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+
+        let mut send = |args: Vec<f32>| {
+            receiver.inject(OscPacket::Message(OscMessage {
+                addr: "/steps".to_string(),
+                args: args.into_iter().map(OscType::Float).collect(),
+            }));
+            (0..4)
+                .map(|k| receiver.get_f32(&array_element_name("steps", k)))
+                .collect::<Vec<_>>()
+        };
+
+        let steps = send(vec![1.0, 0.5, 0.25, 0.0]);
+        assert_eq!(steps, vec![Some(1.0), Some(0.5), Some(0.25), Some(0.0)]);
+
+        // extra arguments are dropped and missing ones read as zero
+        let steps = send(vec![0.5, 0.5, 0.5, 0.5, 0.5]);
+        assert_eq!(steps, vec![Some(0.5); 4]);
+        let steps = send(vec![1.0]);
+        assert_eq!(steps, vec![Some(1.0), Some(0.0), Some(0.0), Some(0.0)]);
+
+        let invalid = [
+            "{ address: /steps, array: 0 }",
+            "{ address: /steps, array: 4, deadzone: 0.1 }",
+        ];
+        for mapping in invalid {
+            let yaml = format!("mappings: {{ steps: {} }}", mapping);
+            assert!(OscConfig::from_yaml(&serde_yaml::from_str(&yaml).unwrap()).is_err());
+        }
+
+        let yaml = "mappings: { \"steps[1]\": { address: /steps, array: 4 } }";
+        assert!(OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap()).is_err());
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let config = config();
+        let default = |name: &str| config.mappings[name].default.clone();
+
+        assert_eq!(default("brightness"), Some(OscUniformValue::Float(0.75)));
+        assert_eq!(default("scene"), Some(OscUniformValue::Int(3)));
+        assert_eq!(default("strobe"), Some(OscUniformValue::Bool(true)));
+        assert_eq!(default("hue"), None);
+    }
+
+    #[test]
+    fn parse_labels() {
+        let yaml = r#"
+            mappings:
+              hue: { address: "/fx/hue", label: "Hue", group: "Color", color: red }
+              scene: "/fx/scene"
+        "#;
+
+        let value = serde_yaml::from_str(yaml).unwrap();
+        let config = OscConfig::from_yaml(&value).unwrap();
+        assert_eq!(config.mappings["hue"].label.as_deref(), Some("Hue"));
+        assert_eq!(config.mappings["hue"].group.as_deref(), Some("Color"));
+        assert_eq!(config.mappings["scene"].label, None);
+
+        let yaml = r#"
+            mappings:
+              hue: { address: "/fx/hue", group: 3 }
+        "#;
+
+        let value = serde_yaml::from_str(yaml).unwrap();
+        assert!(OscConfig::from_yaml(&value).is_err());
+    }
+
+    #[test]
+    fn parse_invalid_default() {
+        let yaml = r#"
+            mappings:
+              scene:
+                address: "/fx/scene"
+                type: int
+                default: "three"
+        "#;
+
+        let value = serde_yaml::from_str(yaml).unwrap();
+        assert!(OscConfig::from_yaml(&value).is_err());
+    }
+
+    #[test]
+    fn defaults_before_first_packet() {
+        let config = config();
+        let receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+        receiver.seed_defaults(&config);
+
+        assert_eq!(
+            receiver.get_value("brightness"),
+            Some(OscUniformValue::Float(0.75))
+        );
+        assert_eq!(receiver.get_value("scene"), Some(OscUniformValue::Int(3)));
+        assert_eq!(
+            receiver.get_value("strobe"),
+            Some(OscUniformValue::Bool(true))
+        );
+        assert_eq!(receiver.get_value("hue"), None);
+    }
+
+    #[test]
+    fn typed_getters() {
+        let receiver = OscReceiver::new();
+        let values = [
+            ("fader", OscUniformValue::Double(2.6)),
+            ("strobe", OscUniformValue::Bool(true)),
+            ("scene", OscUniformValue::Long(0)),
+            ("color[0]", OscUniformValue::Float(0.5)),
+            ("color[2]", OscUniformValue::Int(1)),
+        ];
+        receiver.set_values(
+            &values
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+        );
+
+        assert_eq!(receiver.get_f32("fader"), Some(2.6));
+        assert_eq!(receiver.get_i32("fader"), Some(3));
+        assert_eq!(receiver.get_f32("strobe"), Some(1.0));
+        assert_eq!(receiver.get_i32("strobe"), Some(1));
+        assert_eq!(receiver.get_bool("scene"), Some(false));
+        assert_eq!(receiver.get_bool("fader"), Some(true));
+        assert_eq!(receiver.get_f32("hue"), None);
+
+        assert_eq!(receiver.get_vec4("color"), Some([0.5, 0.0, 1.0, 0.0]));
+        assert_eq!(receiver.get_vec4("fader"), Some([2.6; 4]));
+        assert_eq!(receiver.get_vec4("hue"), None);
+    }
+
+    #[test]
+    fn defaults_overwritten_by_packet() {
+        let config = config();
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+        receiver.seed_defaults(&config);
+
+        let msg = OscMessage {
+            addr: "/fx/brightness".to_string(),
+            args: vec![OscType::Float(0.25)],
+        };
+        receiver.inject(OscPacket::Message(msg));
+
+        assert_eq!(
+            receiver.get_value("brightness"),
+            Some(OscUniformValue::Float(0.25))
+        );
+
+        // seeding again must not reset the received value
+        receiver.seed_defaults(&config);
+        assert_eq!(
+            receiver.get_value("brightness"),
+            Some(OscUniformValue::Float(0.25))
+        );
+    }
+
+    #[test]
+    fn type_conversion() {
+        let config = config();
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+
+        let message = |addr: &str, arg: OscType| {
+            OscPacket::Message(OscMessage {
+                addr: addr.to_string(),
+                args: vec![arg],
+            })
+        };
+
+        receiver.inject(OscPacket::Bundle(OscBundle {
+            timetag: OscTime {
+                seconds: 0,
+                fractional: 1,
+            },
+            content: vec![
+                message("/fx/brightness", OscType::Int(1)),
+                message("/fx/scene", OscType::Float(2.6)),
+                message("/fx/strobe", OscType::Int(0)),
+                message("/unmapped", OscType::Double(0.5)),
+            ],
+        }));
+
+        assert_eq!(
+            receiver.get_value("brightness"),
+            Some(OscUniformValue::Float(1.0))
+        );
+        assert_eq!(receiver.get_value("scene"), Some(OscUniformValue::Int(3)));
+        assert_eq!(
+            receiver.get_value("strobe"),
+            Some(OscUniformValue::Bool(false))
+        );
+        assert_eq!(
+            receiver.get_value("/unmapped"),
+            Some(OscUniformValue::Float(0.5))
+        );
+
+        // strings have no numeric value
+        receiver.inject(message("/fx/scene", OscType::String("four".to_string())));
+        assert_eq!(receiver.get_value("scene"), Some(OscUniformValue::Int(3)));
+    }
+
+    #[test]
+    fn full_precision() {
+        let yaml = r#"
+            mappings:
+              stamp: { address: "/stamp", type: long, default: 5000000000 }
+              clock: { address: "/clock", type: double }
+        "#;
+        let config = OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        assert_eq!(
+            config.mappings["stamp"].default,
+            Some(OscUniformValue::Long(5_000_000_000))
+        );
+
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+        let mut send = |addr: &str, arg: OscType| {
+            receiver.inject(OscPacket::Message(OscMessage {
+                addr: addr.to_string(),
+                args: vec![arg],
+            }));
+            receiver.get_all_values()
+        };
+
+        let stamp = (1_i64 << 53) + 1;
+        let values = send("/stamp", OscType::Long(stamp));
+        assert_eq!(values["stamp"], OscUniformValue::Long(stamp));
+        let values = send("/clock", OscType::Double(1234567.891));
+        assert_eq!(values["clock"], OscUniformValue::Double(1234567.891));
+        let values = send("/clock", OscType::Int(2));
+        assert_eq!(values["clock"], OscUniformValue::Double(2.0));
+
+        assert!(OscDataType::Double.accepts_uniform(gl::DOUBLE));
+        assert!(OscDataType::Long.accepts_uniform(gl::INT));
+        assert_eq!(OscUniformValue::Long(-3).as_f32(), -3.0);
+    }
+
+    #[test]
+    fn modes() {
+        let yaml = r#"
+            mappings:
+              mode:
+                address: "/mode"
+                modes: [calm, build, drop]
+                default: build
+        "#;
+
+        let value = serde_yaml::from_str(yaml).unwrap();
+        let config = OscConfig::from_yaml(&value).unwrap();
+        let mapping = &config.mappings["mode"];
+        assert_eq!(mapping.default, Some(OscUniformValue::Int(1)));
+        assert_eq!(mapping.mode_name(&OscUniformValue::Int(2)), Some("drop"));
+
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+
+        let message = |arg: OscType| {
+            OscPacket::Message(OscMessage {
+                addr: "/mode".to_string(),
+                args: vec![arg],
+            })
+        };
+
+        receiver.inject(message(OscType::String("drop".to_string())));
+        assert_eq!(receiver.get_value("mode"), Some(OscUniformValue::Int(2)));
+
+        receiver.inject(message(OscType::Float(0.8)));
+        assert_eq!(receiver.get_value("mode"), Some(OscUniformValue::Int(1)));
+
+        // out of range indices are clamped, unknown names are ignored
+        receiver.inject(message(OscType::Int(7)));
+        assert_eq!(receiver.get_value("mode"), Some(OscUniformValue::Int(2)));
+        receiver.inject(message(OscType::Int(-3)));
+        assert_eq!(receiver.get_value("mode"), Some(OscUniformValue::Int(0)));
+        receiver.inject(message(OscType::String("chill".to_string())));
+        assert_eq!(receiver.get_value("mode"), Some(OscUniformValue::Int(0)));
+
+        for yaml in [
+            "mappings: { mode: { address: /mode, modes: [] } }",
+            "mappings: { mode: { address: /mode, modes: [a, a] } }",
+            "mappings: { mode: { address: /mode, modes: [a, b], type: float } }",
+            "mappings: { mode: { address: /mode, modes: [a, b], quantize: 1 } }",
+        ]
+        .iter()
+        {
+            let value = serde_yaml::from_str(yaml).unwrap();
+            assert!(OscConfig::from_yaml(&value).is_err(), "{}", yaml);
+        }
+    }
+
+    #[test]
+    fn uniform_types() {
+        assert!(OscDataType::Float.accepts_uniform(gl::FLOAT));
+        assert!(OscDataType::Float.accepts_uniform(gl::BOOL));
+        assert!(!OscDataType::Float.accepts_uniform(gl::INT));
+        assert!(OscDataType::Int.accepts_uniform(gl::INT));
+        assert!(!OscDataType::Int.accepts_uniform(gl::FLOAT));
+        assert!(!OscDataType::Bool.accepts_uniform(gl::FLOAT_VEC2));
+        assert!(OscDataType::Mode(vec!["a".into()]).accepts_uniform(gl::INT));
+
+        let config = OscConfig::from_yaml(&serde_yaml::from_str("coerce: true").unwrap()).unwrap();
+        assert!(config.coerce);
+        assert!(!OscConfig::default().coerce);
+        assert!(OscConfig::from_yaml(&serde_yaml::from_str("coerce: 1").unwrap()).is_err());
+    }
+
+    #[test]
+    fn rotations() {
+        let yaml = r#"
+            mappings:
+              camera:
+                address: "/camera/quat"
+                type: quaternion
+                order: wxyz
+              spin:
+                address: "/spin"
+                type: euler
+                order: zyx
+                degrees: true
+        "#;
+
+        let value = serde_yaml::from_str(yaml).unwrap();
+        let config = OscConfig::from_yaml(&value).unwrap();
+        let spin = match &config.mappings["spin"].data_type {
+            OscDataType::Rotation(rotation) => *rotation,
+            other => panic!("Expected a rotation, got {:?}", other),
+        };
+        assert_eq!(spin.arg_count(), 3);
+        assert!(config.mappings["camera"]
+            .data_type
+            .accepts_uniform(gl::FLOAT_MAT4));
+        assert!(!config.mappings["camera"]
+            .data_type
+            .accepts_uniform(gl::FLOAT_VEC4));
+
+        // a quarter turn around z maps x onto y
+        let q = spin.quaternion(&[0.0, 0.0, 90.0]).unwrap();
+        let m = rotation_matrix(q);
+        for (a, b) in m
+            .iter()
+            .zip(&[0.0, 1.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 1.0])
+        {
+            assert!((a - b).abs() < 1e-6, "{:?}", m);
+        }
+
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+        receiver.inject(OscPacket::Message(OscMessage {
+            addr: "/camera/quat".into(),
+            args: vec![
+                OscType::Float(2.0),
+                OscType::Float(0.0),
+                OscType::Int(0),
+                OscType::Float(0.0),
+            ],
+        }));
+        assert_eq!(receiver.get_vec4("camera"), Some([0.0, 0.0, 0.0, 1.0]));
+
+        // degenerate rotations keep the last value
+        receiver.inject(OscPacket::Message(OscMessage {
+            addr: "/camera/quat".into(),
+            args: vec![OscType::Float(0.0); 4],
+        }));
+        assert_eq!(receiver.get_vec4("camera"), Some([0.0, 0.0, 0.0, 1.0]));
+
+        for yaml in [
+            "mappings: { r: { address: /r, type: euler, order: xxy } }",
+            "mappings: { r: { address: /r, type: quaternion, order: zyx } }",
+            "mappings: { r: { address: /r, type: quaternion, degrees: true } }",
+            "mappings: { r: { address: /r, type: euler, degrees: 1 } }",
+            "mappings: { r: { address: /r, type: euler, deadzone: 0.1 } }",
+            "mappings: { \"r[1]\": { address: /r, type: euler } }",
+        ]
+        .iter()
+        {
+            let value = serde_yaml::from_str(yaml).unwrap();
+            assert!(OscConfig::from_yaml(&value).is_err(), "{}", yaml);
+        }
+    }
+
+    #[test]
+    fn heartbeat() {
+        let yaml = "heartbeat: { address: \"/alive\", timeout: 0.05 }";
+        let config = OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+        let beat = || {
+            OscPacket::Message(OscMessage {
+                addr: "/alive".to_string(),
+                args: vec![],
+            })
+        };
+
+        // nothing received yet
+        receiver.tick();
+        assert_eq!(receiver.is_connected(), Some(false));
+
+        receiver.inject(beat());
+        assert_eq!(receiver.is_connected(), Some(true));
+        assert_eq!(receiver.get_value("/alive"), None);
+
+        // a reload keeps the connection
+        receiver.update_type_mappings(&config);
+        receiver.tick();
+        assert_eq!(receiver.is_connected(), Some(true));
+
+        thread::sleep(Duration::from_millis(60));
+        receiver.tick();
+        assert_eq!(receiver.is_connected(), Some(false));
+
+        receiver.inject(beat());
+        assert_eq!(receiver.is_connected(), Some(true));
+
+        receiver.update_type_mappings(&OscConfig::default());
+        receiver.tick();
+        assert_eq!(receiver.is_connected(), None);
+
+        let parse = |yaml: &str| OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap());
+        let config = parse("heartbeat: \"/alive\"").unwrap();
+        assert_eq!(config.heartbeat.unwrap().timeout, OSC_HEARTBEAT_TIMEOUT);
+        assert!(parse("heartbeat: alive").is_err());
+        assert!(parse("heartbeat: { address: \"/alive\", timeout: 0 }").is_err());
+    }
+
+    #[test]
+    fn remote_reload() {
+        let parse = |yaml: &str| OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap());
+        let reload = |address: &str| {
+            OscPacket::Message(OscMessage {
+                addr: address.to_string(),
+                args: vec![],
+            })
+        };
+
+        // reloads have to be allowed explicitly
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&parse("port: 9000").unwrap());
+        receiver.inject(reload(RELOAD_ADDRESS));
+        receiver.inject(reload(REINIT_ADDRESS));
+        assert!(!receiver.take_reload());
+        assert!(!receiver.take_reinit());
+
+        receiver.update_type_mappings(&parse("allow_remote_reload: true").unwrap());
+        receiver.inject(reload(RELOAD_ADDRESS));
+        assert!(receiver.take_reload());
+        assert!(!receiver.take_reload());
+        assert!(!receiver.take_reinit());
+
+        receiver.inject(reload(REINIT_ADDRESS));
+        assert!(receiver.take_reinit());
+        assert!(!receiver.take_reinit());
+        assert!(!receiver.take_reload());
+
+        let config = parse("{ allow_remote_reload: true, reload_address: \"/vj/rebuild\" }");
+        receiver.update_type_mappings(&config.unwrap());
+        receiver.inject(reload(RELOAD_ADDRESS));
+        assert!(!receiver.take_reload());
+        receiver.inject(reload("/vj/rebuild"));
+        assert!(receiver.take_reload());
+
+        assert!(parse("allow_remote_reload: yes please").is_err());
+        assert!(parse("reload_address: reload").is_err());
+    }
+
+    #[test]
+    fn preset_request() {
+        let mut receiver = OscReceiver::new();
+        let msg = OscMessage {
+            addr: PRESET_ADDRESS.to_string(),
+            args: vec![OscType::Int(3)],
+        };
+        receiver.inject(OscPacket::Message(msg));
+
+        assert_eq!(
+            receiver.take_preset_request(),
+            Some(PresetRequest::Index(3))
+        );
+        assert_eq!(receiver.take_preset_request(), None);
+        assert_eq!(receiver.get_value(PRESET_ADDRESS), None);
+
+        let msg = OscMessage {
+            addr: AUDIO_PROFILE_ADDRESS.to_string(),
+            args: vec![OscType::String("calm".into())],
+        };
+        receiver.inject(OscPacket::Message(msg));
+
+        let request = receiver.take_audio_profile_request();
+        assert_eq!(request, Some(PresetRequest::Name("calm".into())));
+        assert_eq!(receiver.take_preset_request(), None);
+    }
+
+    #[test]
+    fn timetags() {
+        let immediate = OscTime {
+            seconds: 0,
+            fractional: 1,
+        };
+        assert_eq!(timetag_to_system_time(immediate), None);
+
+        let half_past = OscTime {
+            seconds: NTP_UNIX_OFFSET as u32 + 10,
+            fractional: 1 << 31,
+        };
+        assert_eq!(
+            timetag_to_system_time(half_past),
+            Some(UNIX_EPOCH + Duration::from_millis(10_500))
+        );
+    }
+
+    #[test]
+    fn future_bundles_are_scheduled() {
+        let mut receiver = OscReceiver::new();
+        let (scheduler, schedule) = mpsc::channel();
+
+        let bundle = |seconds: u64| {
+            let due = SystemTime::now() + Duration::from_secs(seconds);
+            let since_epoch = due.duration_since(UNIX_EPOCH).unwrap();
+            OscPacket::Bundle(OscBundle {
+                timetag: OscTime {
+                    seconds: (since_epoch.as_secs() + NTP_UNIX_OFFSET) as u32,
+                    fractional: 0,
+                },
+                content: vec![OscPacket::Message(OscMessage {
+                    addr: format!("/in/{}", seconds),
+                    args: vec![OscType::Float(1.0)],
+                })],
+            })
+        };
+
+        // bundles which are due are applied right away
+        let mut batch = OscBatch::new();
+        for packet in vec![bundle(0), bundle(60)] {
+            OscReceiver::process_packet(
+                &mut batch,
+                &receiver.type_mappings,
+                &receiver.controls,
+                &scheduler,
+                packet,
+            );
+        }
+
+        OscReceiver::publish(&receiver.inbox, &mut batch, true);
+        receiver.tick();
+
+        assert_eq!(
+            receiver.get_value("/in/0"),
+            Some(OscUniformValue::Float(1.0))
+        );
+        assert_eq!(receiver.get_value("/in/60"), None);
+
+        let scheduled = schedule.try_recv().unwrap();
+        assert_eq!(scheduled.content.len(), 1);
+        assert!(schedule.try_recv().is_err());
+    }
+
+    #[test]
+    fn crossfade_request() {
+        let mut receiver = OscReceiver::new();
+        for value in vec![OscType::Float(0.25), OscType::Int(3)] {
+            let msg = OscMessage {
+                addr: CROSSFADE_ADDRESS.to_string(),
+                args: vec![value],
+            };
+            receiver.inject(OscPacket::Message(msg));
+        }
+
+        // only the latest value is kept, clamped to the fader range
+        assert_eq!(receiver.take_crossfade(), Some(1.0));
+        assert_eq!(receiver.take_crossfade(), None);
+    }
+
+    #[test]
+    fn pass_toggles() {
+        assert_eq!(parse_pass_address("/pass/glitch/enable"), Some("glitch"));
+        assert_eq!(parse_pass_address("/pass//enable"), None);
+        assert_eq!(parse_pass_address("/pass/a/b/enable"), None);
+        assert_eq!(parse_pass_address("/pass/glitch"), None);
+
+        let mut receiver = OscReceiver::new();
+        for value in vec![OscType::Int(0), OscType::Bool(true)] {
+            let msg = OscMessage {
+                addr: "/pass/glitch/enable".to_string(),
+                args: vec![value],
+            };
+            receiver.inject(OscPacket::Message(msg));
+        }
+
+        // toggles are not uniform values, and every one of them is kept
+        assert_eq!(receiver.get_value("/pass/glitch/enable"), None);
+        assert_eq!(
+            receiver.take_pass_toggles(),
+            vec![("glitch".to_owned(), false), ("glitch".to_owned(), true)]
+        );
+        assert!(receiver.take_pass_toggles().is_empty());
+    }
+
+    #[test]
+    fn deadzone_and_quantize() {
+        let yaml = r#"
+            mappings:
+              fader: { address: "/fader", deadzone: 0.05 }
+              steps: { address: "/steps", quantize: 0.25 }
+              scene: { address: "/scene", type: int, quantize: 4 }
+        "#;
+        let config = OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+
+        let mut send = |name: &str, arg: OscType| {
+            let msg = OscMessage {
+                addr: format!("/{}", name),
+                args: vec![arg],
+            };
+            receiver.inject(OscPacket::Message(msg));
+            receiver.get_value(name)
+        };
+
+        // the deadzone is relative to the stored value, not to zero
+        assert_eq!(
+            send("fader", OscType::Float(0.5)),
+            Some(OscUniformValue::Float(0.5))
+        );
+        assert_eq!(
+            send("fader", OscType::Float(0.53)),
+            Some(OscUniformValue::Float(0.5))
+        );
+        assert_eq!(
+            send("fader", OscType::Float(0.47)),
+            Some(OscUniformValue::Float(0.5))
+        );
+        assert_eq!(
+            send("fader", OscType::Float(0.6)),
+            Some(OscUniformValue::Float(0.6))
+        );
+
+        assert_eq!(
+            send("steps", OscType::Float(0.3)),
+            Some(OscUniformValue::Float(0.25))
+        );
+        assert_eq!(
+            send("steps", OscType::Float(0.9)),
+            Some(OscUniformValue::Float(1.0))
+        );
+        assert_eq!(
+            send("scene", OscType::Int(7)),
+            Some(OscUniformValue::Int(8))
+        );
+
+        let yaml = r#"mappings: { strobe: { address: "/strobe", type: bool, deadzone: 0.1 } }"#;
+        assert!(OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap()).is_err());
+    }
+
+    #[test]
+    fn inverted_mappings() {
+        let yaml = r#"
+            mappings:
+              fader: { address: "/fader", invert: true }
+              depth: { address: "/depth", type: double, invert: true }
+              gate: { address: "/gate", type: bool, invert: true }
+              snap: { address: "/snap", invert: true, quantize: 0.5 }
+        "#;
+        let config = OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        assert!(config.mappings["fader"].filter.invert);
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+
+        let mut send = |name: &str, arg: OscType| {
+            let msg = OscMessage {
+                addr: format!("/{}", name),
+                args: vec![arg],
+            };
+            receiver.inject(OscPacket::Message(msg));
+            receiver.get_value(name)
+        };
+
+        assert_eq!(
+            send("fader", OscType::Float(0.25)),
+            Some(OscUniformValue::Float(0.75))
+        );
+        assert_eq!(
+            send("depth", OscType::Double(0.125)),
+            Some(OscUniformValue::Double(0.875))
+        );
+        assert_eq!(
+            send("gate", OscType::Bool(true)),
+            Some(OscUniformValue::Bool(false))
+        );
+        assert_eq!(
+            send("gate", OscType::Bool(false)),
+            Some(OscUniformValue::Bool(true))
+        );
+
+        // inverted first, then snapped
+        assert_eq!(
+            send("snap", OscType::Float(0.3)),
+            Some(OscUniformValue::Float(0.5))
+        );
+        assert_eq!(
+            send("snap", OscType::Float(0.1)),
+            Some(OscUniformValue::Float(1.0))
+        );
+
+        for yaml in [
+            r#"mappings: { a: { address: "/a", invert: 1 } }"#,
+            r#"mappings: { a: { address: "/a", type: int, invert: true } }"#,
+            r#"mappings: { a: { address: "/a", modes: [x, y], invert: true } }"#,
+            r#"mappings: { a: { address: "/a", type: euler, invert: true } }"#,
+            r#"mappings: { a: { address: "/a", array: 4, invert: true } }"#,
+        ] {
+            assert!(
+                OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap()).is_err(),
+                "{}",
+                yaml
+            );
+        }
+    }
+
+    #[test]
+    fn one_address_many_uniforms() {
+        let yaml = r#"
+            mappings:
+              level: "/fader"
+              steps: { address: "/fader", type: int, quantize: 2 }
+              gate: { address: "/fader", type: bool }
+        "#;
+        let config = OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap();
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+
+        let msg = OscMessage {
+            addr: "/fader".to_string(),
+            args: vec![OscType::Float(2.6)],
+        };
+        receiver.inject(OscPacket::Message(msg));
+
+        assert_eq!(
+            receiver.get_value("level"),
+            Some(OscUniformValue::Float(2.6))
+        );
+        assert_eq!(receiver.get_value("steps"), Some(OscUniformValue::Int(4)));
+        assert_eq!(
+            receiver.get_value("gate"),
+            Some(OscUniformValue::Bool(true))
+        );
+        assert_eq!(receiver.get_value("/fader"), None);
+    }
+
+    #[test]
+    fn values_are_coalesced() {
+        let mut receiver = OscReceiver::new();
+        let mut batch = OscBatch::new();
+        for k in 0..1000 {
+            let msg = OscMessage {
+                addr: "/fader".to_string(),
+                args: vec![OscType::Float(k as f32)],
+            };
+            OscReceiver::process_message(
+                &mut batch,
+                &receiver.type_mappings,
+                &receiver.controls,
+                msg,
+            );
+        }
+
+        assert_eq!(batch.len(), 1);
+        OscReceiver::publish(&receiver.inbox, &mut batch, true);
+        assert!(batch.is_empty());
+
+        // nothing is visible before the next frame
+        assert_eq!(receiver.get_value("/fader"), None);
+        receiver.tick();
+        assert_eq!(
+            receiver.get_value("/fader"),
+            Some(OscUniformValue::Float(999.0))
+        );
+    }
+
+    #[test]
+    fn pulses_survive_coalescing() {
+        let config = config();
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+
+        let strobe = |on: bool| OscMessage {
+            addr: "/fx/strobe".to_string(),
+            args: vec![OscType::Bool(on)],
+        };
+
+        // on and off within a single frame
+        let mut batch = OscBatch::new();
+        for msg in vec![strobe(true), strobe(false)] {
+            OscReceiver::process_message(
+                &mut batch,
+                &receiver.type_mappings,
+                &receiver.controls,
+                msg,
+            );
+        }
+        OscReceiver::publish(&receiver.inbox, &mut batch, true);
+
+        receiver.tick();
+        assert_eq!(
+            receiver.get_value("strobe"),
+            Some(OscUniformValue::Bool(true))
+        );
+        receiver.tick();
+        assert_eq!(
+            receiver.get_value("strobe"),
+            Some(OscUniformValue::Bool(false))
+        );
+    }
+
+    #[test]
+    fn provided_values_replace_osc() {
+        let config = config();
+        let mut receiver = OscReceiver::new();
+        receiver.update_type_mappings(&config);
+        receiver.inject(OscPacket::Message(OscMessage {
+            addr: "/fx/brightness".into(),
+            args: vec![OscType::Float(0.25)],
+        }));
+
+        receiver.provide_values(vec![
+            ("brightness".into(), OscUniformValue::Float(0.75)),
+            ("custom".into(), OscUniformValue::Int(4)),
+        ]);
+        assert_eq!(receiver.get_f32("brightness"), Some(0.75));
+        assert_eq!(receiver.get_i32("custom"), Some(4));
+
+        let mut names: Vec<_> = receiver.provided_names().iter().cloned().collect();
+        names.sort();
+        assert_eq!(names, ["brightness", "custom"]);
+
+        // newer OSC messages win until the source runs again
+        receiver.inject(OscPacket::Message(OscMessage {
+            addr: "/fx/brightness".into(),
+            args: vec![OscType::Float(0.5)],
+        }));
+        assert_eq!(receiver.get_f32("brightness"), Some(0.5));
+    }
+}
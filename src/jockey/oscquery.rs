@@ -0,0 +1,380 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use super::{Error, OscConfig, OscDataType, OscRotation, OscUniformValue};
+
+/// Name reported to OSCQuery clients.
+const OSC_QUERY_NAME: &str = "Sh4derJockey";
+
+/// How often the server checks whether it should stop while no client connects.
+const OSC_QUERY_POLL: Duration = Duration::from_millis(100);
+
+/// Upper bound for the size of a request header.
+const OSC_QUERY_MAX_REQUEST: usize = 8192;
+
+/// The OSC addresses of a pipeline, as served to OSCQuery clients.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OscNamespace {
+    pub osc_port: u16,
+    /// Type, default value and mapping name of every address.
+    pub params: BTreeMap<String, (OscDataType, Option<OscUniformValue>, String)>,
+}
+
+impl OscNamespace {
+    pub fn new(config: &OscConfig) -> Self {
+        let mut mappings: Vec<_> = config.mappings.iter().collect();
+        mappings.sort_by(|a, b| a.0.cmp(b.0));
+
+        // an address with several mappings is described by the first one
+        let mut params = BTreeMap::new();
+        for (name, m) in mappings {
+            params
+                .entry(m.address.clone())
+                .or_insert_with(|| (m.data_type.clone(), m.default.clone(), name.clone()));
+        }
+
+        Self {
+            osc_port: config.port,
+            params,
+        }
+    }
+
+    /// Describes the server itself, as requested by `?HOST_INFO`.
+    pub fn host_info_json(&self) -> String {
+        format!(
+            "{{\"NAME\":{},\"OSC_PORT\":{},\"OSC_TRANSPORT\":\"UDP\",\"EXTENSIONS\":{{\"ACCESS\":true,\"VALUE\":true}}}}",
+            json_string(OSC_QUERY_NAME),
+            self.osc_port
+        )
+    }
+
+    /// Describes the node at `path` and everything below it.
+    ///
+    /// Current values are taken from `values` where available, and fall back
+    /// to the defaults of the mappings. Returns `None` for unknown paths.
+    pub fn node_json(
+        &self,
+        path: &str,
+        values: &HashMap<String, OscUniformValue>,
+    ) -> Option<String> {
+        let path = match path.trim_end_matches('/') {
+            "" => "/",
+            path => path,
+        };
+
+        let prefix = match path {
+            "/" => "/".to_owned(),
+            path => format!("{}/", path),
+        };
+
+        let leaf = self.params.get(path);
+        let children: BTreeSet<_> = self
+            .params
+            .keys()
+            .filter_map(|address| address.strip_prefix(prefix.as_str()))
+            .filter_map(|rest| rest.split('/').next())
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        if leaf.is_none() && children.is_empty() && path != "/" {
+            return None;
+        }
+
+        let mut fields = vec![format!("\"FULL_PATH\":{}", json_string(path))];
+
+        if let Some((data_type, default, name)) = leaf {
+            let type_tag = match data_type {
+                OscDataType::Float => "f",
+                OscDataType::Int | OscDataType::Mode(_) => "i",
+                OscDataType::Bool => "T",
+                OscDataType::Double => "d",
+                OscDataType::Long => "h",
+                OscDataType::Rotation(OscRotation::Quaternion { .. }) => "ffff",
+                OscDataType::Rotation(OscRotation::Euler { .. }) => "fff",
+            };
+
+            // the receiver only listens, so values can be written but not read back
+            fields.push(format!("\"TYPE\":\"{}\"", type_tag));
+            fields.push("\"ACCESS\":2".to_owned());
+
+            if let Some(value) = values.get(name).or_else(|| default.as_ref()) {
+                fields.push(format!("\"VALUE\":[{}]", json_value(value)));
+            }
+        }
+
+        if !children.is_empty() {
+            let contents: Vec<_> = children
+                .iter()
+                .map(|name| {
+                    let node = self.node_json(&format!("{}{}", prefix, name), values)?;
+                    Some(format!("{}:{}", json_string(name), node))
+                })
+                .collect::<Option<_>>()?;
+
+            fields.push(format!("\"CONTENTS\":{{{}}}", contents.join(",")));
+        }
+
+        Some(format!("{{{}}}", fields.join(",")))
+    }
+}
+
+/// A minimal OSCQuery server, which lets controllers discover the OSC
+/// addresses of the current pipeline.
+///
+/// Only plain HTTP `GET` requests for the namespace and `?HOST_INFO` are
+/// answered. Websockets and value streaming are not supported.
+#[derive(Debug)]
+pub struct OscQueryServer {
+    port: u16,
+    namespace: Arc<Mutex<OscNamespace>>,
+    running: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl OscQueryServer {
+    /// Starts serving `namespace` over HTTP on `127.0.0.1:port`.
+    pub fn start(
+        port: u16,
+        namespace: OscNamespace,
+        values: Arc<Mutex<HashMap<String, OscUniformValue>>>,
+    ) -> Result<Self, Error> {
+        let addr = format!("127.0.0.1:{}", port);
+        let listener = TcpListener::bind(&addr).map_err(|source| Error::Bind {
+            service: "OSCQuery server",
+            addr: addr.clone(),
+            source,
+        })?;
+
+        listener.set_nonblocking(true).map_err(|source| Error::Io {
+            action: "configure OSCQuery server",
+            source,
+        })?;
+
+        let namespace = Arc::new(Mutex::new(namespace));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let handle = {
+            let namespace = Arc::clone(&namespace);
+            let running = Arc::clone(&running);
+
+            thread::spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            if let Err(err) = Self::respond(stream, &namespace, &values) {
+                                log::debug!("Failed to answer OSCQuery request: {}", err);
+                            }
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                            thread::sleep(OSC_QUERY_POLL);
+                        }
+                        Err(e) => {
+                            log::warn!("OSCQuery server error: {}", e);
+                            break;
+                        }
+                    }
+                }
+                log::debug!("OSCQuery server thread stopped");
+            })
+        };
+
+        log::info!("OSCQuery server started on port {}", port);
+        Ok(Self {
+            port,
+            namespace,
+            running,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Replaces the addresses served to clients.
+    pub fn set_namespace(&self, namespace: OscNamespace) {
+        if let Ok(mut current) = self.namespace.lock() {
+            *current = namespace;
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        if let Some(handle) = self.handle.take() {
+            if let Err(e) = handle.join() {
+                log::warn!("Failed to join OSCQuery server thread: {:?}", e);
+            }
+            log::info!("OSCQuery server stopped");
+        }
+    }
+
+    fn respond(
+        mut stream: TcpStream,
+        namespace: &Mutex<OscNamespace>,
+        values: &Mutex<HashMap<String, OscUniformValue>>,
+    ) -> std::io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = stream.read(&mut buf)?;
+            if n == 0 || request.len() > OSC_QUERY_MAX_REQUEST {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+
+        let request = String::from_utf8_lossy(&request);
+        let (status, body) = match parse_request(&request) {
+            Some(("GET", target)) => {
+                let namespace = namespace.lock().map(|ns| ns.clone()).unwrap_or_default();
+                let values = values.lock().map(|vs| vs.clone()).unwrap_or_default();
+                let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+                match query {
+                    "HOST_INFO" => ("200 OK", namespace.host_info_json()),
+                    "" => match namespace.node_json(path, &values) {
+                        Some(json) => ("200 OK", json),
+                        None => ("404 Not Found", "{}".to_owned()),
+                    },
+                    _ => ("400 Bad Request", "{}".to_owned()),
+                }
+            }
+            Some(_) => ("405 Method Not Allowed", "{}".to_owned()),
+            None => ("400 Bad Request", "{}".to_owned()),
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        stream.flush()
+    }
+}
+
+impl Drop for OscQueryServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Splits the request line of an HTTP request into method and target.
+fn parse_request(request: &str) -> Option<(&str, &str)> {
+    let mut parts = request.lines().next()?.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?;
+    match parts.next() {
+        Some(version) if version.starts_with("HTTP/") => Some((method, target)),
+        _ => None,
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_value(value: &OscUniformValue) -> String {
+    match value {
+        OscUniformValue::Float(f) if f.is_finite() => f.to_string(),
+        OscUniformValue::Float(_) => "null".to_owned(),
+        OscUniformValue::Double(d) if d.is_finite() => d.to_string(),
+        OscUniformValue::Double(_) => "null".to_owned(),
+        OscUniformValue::Int(i) => i.to_string(),
+        OscUniformValue::Long(l) => l.to_string(),
+        OscUniformValue::Bool(b) => b.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn namespace() -> OscNamespace {
+        let yaml = r#"
+            port: 9000
+            mappings:
+              hue: "/fx/hue"
+              scene: { address: "/fx/scene", type: int, default: 2 }
+              strobe: { address: "/strobe", type: bool }
+        "#;
+        OscNamespace::new(&OscConfig::from_yaml(&serde_yaml::from_str(yaml).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn namespace_tree() {
+        let json = namespace().node_json("/", &HashMap::new()).unwrap();
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"FULL_PATH":"/","CONTENTS":{"#,
+                r#""fx":{"FULL_PATH":"/fx","CONTENTS":{"#,
+                r#""hue":{"FULL_PATH":"/fx/hue","TYPE":"f","ACCESS":2},"#,
+                r#""scene":{"FULL_PATH":"/fx/scene","TYPE":"i","ACCESS":2,"VALUE":[2]}}},"#,
+                r#""strobe":{"FULL_PATH":"/strobe","TYPE":"T","ACCESS":2}}}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn current_values() {
+        let mut values = HashMap::new();
+        values.insert("hue".to_owned(), OscUniformValue::Float(0.5));
+        values.insert("scene".to_owned(), OscUniformValue::Int(7));
+
+        let ns = namespace();
+        assert_eq!(
+            ns.node_json("/fx/hue", &values).unwrap(),
+            r#"{"FULL_PATH":"/fx/hue","TYPE":"f","ACCESS":2,"VALUE":[0.5]}"#
+        );
+        assert_eq!(
+            ns.node_json("/fx/scene/", &values).unwrap(),
+            r#"{"FULL_PATH":"/fx/scene","TYPE":"i","ACCESS":2,"VALUE":[7]}"#
+        );
+        assert_eq!(ns.node_json("/fx/nope", &values), None);
+        assert_eq!(ns.node_json("/f", &values), None);
+    }
+
+    #[test]
+    fn host_info() {
+        assert_eq!(
+            namespace().host_info_json(),
+            r#"{"NAME":"Sh4derJockey","OSC_PORT":9000,"OSC_TRANSPORT":"UDP","EXTENSIONS":{"ACCESS":true,"VALUE":true}}"#
+        );
+    }
+
+    #[test]
+    fn request_line() {
+        assert_eq!(
+            parse_request("GET /fx?HOST_INFO HTTP/1.1\r\nHost: x\r\n\r\n"),
+            Some(("GET", "/fx?HOST_INFO"))
+        );
+        assert_eq!(parse_request("GET /fx\r\n"), None);
+        assert_eq!(parse_request(""), None);
+    }
+}
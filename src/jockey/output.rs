@@ -0,0 +1,193 @@
+use std::{collections::HashMap, ffi::CString, rc::Rc};
+
+use gl::types::*;
+use glutin::event_loop::EventLoop;
+use lazy_static::lazy_static;
+
+use super::{OutputConfig, PASS_VERT, POSITION_NAME, RESOLUTION_NAME};
+use crate::util::*;
+
+const BLIT_FRAG: &str = include_str!("shaders/blit.frag");
+
+lazy_static! {
+    static ref TEX_NAME: CString = CString::new("tex").unwrap();
+}
+
+/// An additional window which presents a single texture of the pipeline.
+///
+/// The context of every output window shares its objects with the main
+/// context, so render targets can be sampled directly. Vertex arrays are
+/// not shared between contexts, which is why each window keeps its own.
+/// Buffers are shared, so the vertex buffer gets a name of its own.
+///
+/// Output windows never wait for vsync, the main window swaps right after
+/// them and waits for the display refresh once per frame.
+pub struct OutputWindow {
+    pub context: glutin::WindowedContext<glutin::PossiblyCurrent>,
+    pub texture: CString,
+    pub vao: GLuint,
+    pub vbo: GLuint,
+    pub prog_id: GLuint,
+    sh_ids: Vec<GLuint>,
+}
+
+impl std::fmt::Debug for OutputWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(OutputWindow))
+            .field("texture", &self.texture)
+            .field("vao", &self.vao)
+            .field("vbo", &self.vbo)
+            .field("prog_id", &self.prog_id)
+            .finish()
+    }
+}
+
+impl OutputWindow {
+    /// Creates a new output window.
+    ///
+    /// This leaves the context of the new window current, so the caller is
+    /// responsible for switching back to the main context afterwards.
+    pub fn new(
+        config: &OutputConfig,
+        events_loop: &EventLoop<()>,
+        shared: &glutin::Context<glutin::PossiblyCurrent>,
+    ) -> Result<Self, String> {
+        let texture = CString::new(config.texture.as_str())
+            .map_err(|_| format!("Invalid output texture name {:?}", config.texture))?;
+
+        let window_builder = glutin::window::WindowBuilder::new()
+            .with_resizable(true)
+            .with_title(format!("Sh4derJockey - {}", config.texture));
+
+        let window_builder = config.window.apply(window_builder, events_loop);
+
+        let built_context = glutin::ContextBuilder::new()
+            .with_vsync(false)
+            .with_gl(glutin::GlRequest::Latest)
+            .with_shared_lists(shared)
+            .build_windowed(window_builder, events_loop)
+            .map_err(|e| format!("Failed to create output window: {}", e))?;
+        config.window.place(built_context.window());
+
+        let context = unsafe {
+            built_context
+                .make_current()
+                .map_err(|(_, e)| format!("Failed to activate output window: {}", e))?
+        };
+
+        let sh_ids = vec![
+            compile_shader(PASS_VERT, gl::VERTEX_SHADER)?,
+            compile_shader(BLIT_FRAG, gl::FRAGMENT_SHADER)?,
+        ];
+        let prog_id = link_program(&sh_ids)?;
+
+        let mut vao = 0;
+        let mut vbo = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl_debug_check!();
+
+            let data_size = FULLSCREEN_RECT.len() * std::mem::size_of::<GLfloat>();
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                data_size as _,
+                FULLSCREEN_RECT.as_ptr() as _,
+                gl::STATIC_DRAW,
+            );
+            gl_debug_check!();
+        }
+
+        log::info!("Created output window for texture {:?}", texture);
+
+        Ok(Self {
+            context,
+            texture,
+            vao,
+            vbo,
+            prog_id,
+            sh_ids,
+        })
+    }
+
+    /// Draws the requested texture to the window and swaps its buffers.
+    ///
+    /// The window is cleared to black if the pipeline does not contain
+    /// a texture with the requested name.
+    pub fn draw(&mut self, buffers: &HashMap<CString, Rc<dyn Texture>>) {
+        take_mut::take(&mut self.context, |s| unsafe { s.make_current().unwrap() });
+
+        let size = self.context.window().inner_size();
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        let (width, height) = (size.width as f32, size.height as f32);
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, size.width as _, size.height as _);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl_debug_check!();
+
+            if let Some(tex) = buffers.get(&self.texture) {
+                gl::UseProgram(self.prog_id);
+                gl_debug_check!();
+
+                let res_loc = gl::GetUniformLocation(self.prog_id, RESOLUTION_NAME.as_ptr());
+                gl::Uniform4f(res_loc, width, height, width / height, height / width);
+                gl_debug_check!();
+
+                let tex_loc = gl::GetUniformLocation(self.prog_id, TEX_NAME.as_ptr());
+                gl::ActiveTexture(gl::TEXTURE0);
+                tex.bind(0);
+                gl::Uniform1i(tex_loc, 0);
+                gl_debug_check!();
+
+                gl::BindVertexArray(self.vao);
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+                let pos_attr = gl::GetAttribLocation(self.prog_id, POSITION_NAME.as_ptr());
+                if pos_attr != -1 {
+                    gl::EnableVertexAttribArray(pos_attr as GLuint);
+                    gl::VertexAttribPointer(
+                        pos_attr as GLuint,
+                        2,
+                        gl::FLOAT,
+                        gl::FALSE as GLboolean,
+                        0,
+                        std::ptr::null(),
+                    );
+                }
+                gl_debug_check!();
+
+                let vert_count = FULLSCREEN_RECT.len() as GLsizei / 2;
+                gl::DrawArrays(gl::TRIANGLES, 0, vert_count);
+                gl_debug_check!();
+            }
+        }
+
+        if let Err(err) = self.context.swap_buffers() {
+            log::warn!("Failed to swap buffers of output window: {}", err);
+        }
+    }
+}
+
+impl Drop for OutputWindow {
+    fn drop(&mut self) {
+        take_mut::take(&mut self.context, |s| unsafe { s.make_current().unwrap() });
+
+        unsafe {
+            for &id in self.sh_ids.iter() {
+                gl::DetachShader(self.prog_id, id);
+                gl::DeleteShader(id);
+            }
+
+            gl::DeleteProgram(self.prog_id);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
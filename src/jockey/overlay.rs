@@ -0,0 +1,111 @@
+use std::time::Instant;
+
+use glutin::window::Window;
+
+/// A lightweight imgui overlay which is drawn on top of the main window.
+///
+/// Imgui only allows a single active context at a time, so the overlay
+/// keeps its own context suspended and swaps it in while drawing. The
+/// renderer lives in the main OpenGL context, which is why the overlay
+/// needs a renderer of its own instead of sharing the one of the
+/// control panel.
+pub struct Overlay {
+    imgui: Option<imgui::SuspendedContext>,
+    renderer: imgui_opengl_renderer::Renderer,
+    last_frame: Instant,
+    pub visible: bool,
+    pub cursor: [f32; 2],
+    pub mouse_down: bool,
+}
+
+impl std::fmt::Debug for Overlay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(Overlay))
+            .field("visible", &self.visible)
+            .field("cursor", &self.cursor)
+            .field("mouse_down", &self.mouse_down)
+            .finish()
+    }
+}
+
+impl Overlay {
+    /// Creates a new overlay for the OpenGL context which is currently active.
+    pub fn new<F>(main: &mut imgui::Context, load_fn: F) -> Self
+    where
+        F: FnMut(&'static str) -> *const std::ffi::c_void,
+    {
+        let mut imgui = Some(imgui::SuspendedContext::create());
+        let renderer = Self::with_context(main, &mut imgui, |ctx| {
+            ctx.set_ini_filename(None);
+            imgui_opengl_renderer::Renderer::new(ctx, load_fn)
+        });
+
+        Self {
+            imgui,
+            renderer,
+            last_frame: Instant::now(),
+            visible: false,
+            cursor: [f32::MIN; 2],
+            mouse_down: false,
+        }
+    }
+
+    /// Runs `f` with the overlay context active and the main context suspended.
+    fn with_context<F, R>(
+        main: &mut imgui::Context,
+        overlay: &mut Option<imgui::SuspendedContext>,
+        f: F,
+    ) -> R
+    where
+        F: FnOnce(&mut imgui::Context) -> R,
+    {
+        let mut result = None;
+        take_mut::take(main, |main| {
+            let suspended_main = main.suspend();
+
+            let mut ctx = match overlay.take().unwrap().activate() {
+                Ok(ctx) => ctx,
+                Err(_) => panic!("Failed to activate overlay context"),
+            };
+
+            result = Some(f(&mut ctx));
+            *overlay = Some(ctx.suspend());
+
+            match suspended_main.activate() {
+                Ok(main) => main,
+                Err(_) => panic!("Failed to reactivate imgui context"),
+            }
+        });
+
+        result.unwrap()
+    }
+
+    /// Builds a frame with `f` and renders it into the active framebuffer.
+    ///
+    /// The main OpenGL context must be current when calling this.
+    pub fn draw<F>(&mut self, main: &mut imgui::Context, window: &Window, f: F)
+    where
+        F: FnOnce(&imgui::Ui),
+    {
+        let now = Instant::now();
+        let delta_time = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+
+        let size = window.inner_size();
+        let cursor = self.cursor;
+        let mouse_down = self.mouse_down;
+        let renderer = &self.renderer;
+
+        Self::with_context(main, &mut self.imgui, |ctx| {
+            let io = ctx.io_mut();
+            io.display_size = [size.width as f32, size.height as f32];
+            io.delta_time = delta_time.max(1e-6);
+            io.mouse_pos = cursor;
+            io.mouse_down[0] = mouse_down;
+
+            let ui = ctx.frame();
+            f(&ui);
+            renderer.render(ui);
+        });
+    }
+}
@@ -0,0 +1,65 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Length of the window the frame time statistics are taken over.
+pub const FRAME_PACING_WINDOW: Duration = Duration::from_secs(1);
+
+/// Frame times of the last second, to spot stutter which an average hides.
+#[derive(Debug, Clone, Default)]
+pub struct FramePacing {
+    frames: VecDeque<(Instant, f32)>,
+}
+
+impl FramePacing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a frame which took `ms` milliseconds and ended at `now`.
+    pub fn push(&mut self, now: Instant, ms: f32) {
+        self.frames.push_back((now, ms));
+        while let Some(&(end, _)) = self.frames.front() {
+            if now.duration_since(end) <= FRAME_PACING_WINDOW {
+                break;
+            }
+            self.frames.pop_front();
+        }
+    }
+
+    /// Average and longest frame time of the last second in milliseconds.
+    ///
+    /// Both are zero before the first frame.
+    pub fn stats(&self) -> (f32, f32) {
+        if self.frames.is_empty() {
+            return (0.0, 0.0);
+        }
+
+        let sum: f32 = self.frames.iter().map(|&(_, ms)| ms).sum();
+        let max = self.frames.iter().map(|&(_, ms)| ms).fold(0.0, f32::max);
+        (sum / self.frames.len() as f32, max)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn window_of_one_second() {
+        let start = Instant::now();
+        let mut pacing = FramePacing::new();
+        assert_eq!(pacing.stats(), (0.0, 0.0));
+
+        pacing.push(start, 50.0);
+        for k in 1..=4 {
+            pacing.push(start + Duration::from_millis(250 * k), 10.0);
+        }
+        assert_eq!(pacing.stats(), (18.0, 50.0));
+
+        // the hitch leaves the window after a second
+        pacing.push(start + Duration::from_millis(1100), 10.0);
+        assert_eq!(pacing.stats(), (10.0, 10.0));
+    }
+}
@@ -1,16 +1,21 @@
 use std::{
     collections::{HashMap, HashSet},
     ffi::CString,
-    path::Path,
+    path::{Path, PathBuf},
     rc::Rc,
+    sync::OnceLock,
 };
 
 use async_std::task::yield_now;
+use gl::types::*;
 use serde_yaml::Value;
 
 use super::uniforms::*;
 use crate::{jockey::*, util::Cache};
 
+/// Default period of the `loop_time` uniform in seconds.
+pub const LOOP_PERIOD: f32 = 8.0;
+
 /// Type alias for box containing a partially build pipeline
 pub type PipelinePartial = Box<dyn Future<Output = Result<(Pipeline, UpdateRequest), String>>>;
 
@@ -19,6 +24,23 @@ pub struct UpdateRequest {
     pub audio_samples: usize,
     pub smoothing_attack: f32,
     pub smoothing_decay: f32,
+    pub fft_bins: usize,
+    pub fft_scale: FftScale,
+    pub fft_resample: FftResample,
+    pub stereo_analysis: bool,
+    pub peak_hold: f32,
+    pub peak_decay: f32,
+    /// Named envelope profiles, in the order they are listed.
+    pub audio_profiles: Vec<(String, AudioProfile)>,
+    /// Index of the profile the pipeline starts with.
+    pub audio_profile: Option<usize>,
+    pub audio_latency: f32,
+    pub audio_pre_gain: [f32; 2],
+    pub audio_mute: bool,
+    pub pitch_detection: bool,
+    pub centroid_smoothing: f32,
+    pub centroid_normalized: bool,
+    pub density_window: f32,
 }
 
 impl Default for UpdateRequest {
@@ -27,6 +49,130 @@ impl Default for UpdateRequest {
             audio_samples: AUDIO_SAMPLES,
             smoothing_attack: FFT_ATTACK,
             smoothing_decay: FFT_DECAY,
+            fft_bins: AUDIO_SAMPLES / 2,
+            fft_scale: FftScale::Linear,
+            fft_resample: FftResample::Max,
+            stereo_analysis: true,
+            peak_hold: PEAK_HOLD,
+            peak_decay: PEAK_DECAY,
+            audio_profiles: Vec::new(),
+            audio_profile: None,
+            audio_latency: AUDIO_LATENCY,
+            audio_pre_gain: [AUDIO_PRE_GAIN; 2],
+            audio_mute: false,
+            pitch_detection: false,
+            centroid_smoothing: CENTROID_SMOOTHING,
+            centroid_normalized: false,
+            density_window: ONSET_WINDOW,
+        }
+    }
+}
+
+/// Texture which holds the final image of the last frame.
+///
+/// This is the image of the pipeline itself, before the crossfade with the
+/// other deck, the flash limiter and the color pass.
+#[derive(Debug, Clone)]
+pub struct PreviousFrame {
+    pub name: CString,
+    /// Color of the texture until the first frame was rendered.
+    pub clear_color: [f32; 4],
+    pub builder: TextureBuilder,
+}
+
+impl PreviousFrame {
+    /// Creates the texture at the size of the screen, filled with the clear color.
+    pub fn build(&self, screen_size: (u32, u32)) -> Rc<dyn Texture> {
+        let tex = self.builder.build_framebuffer(screen_size);
+        let [r, g, b, a] = self.clear_color;
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, tex.fb_id);
+            gl::ClearColor(r, g, b, a);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl_debug_check!();
+
+            if self.builder.mipmap {
+                gl::BindTexture(gl::TEXTURE_2D, tex.tex_id);
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+                gl_debug_check!();
+            }
+        }
+
+        tex
+    }
+}
+
+/// An entry of the `images` section, parsed without reading the file.
+#[derive(Debug, Clone)]
+pub struct ImageConfig {
+    pub path: String,
+    pub name: CString,
+    pub builder: TextureBuilder,
+    pub color: ImageColor,
+    pub alpha: AlphaMode,
+    /// Texture format of HDR images, only allowed for `.hdr` and `.exr` files.
+    pub format: Option<TextureFormat>,
+}
+
+impl ImageConfig {
+    /// Parses an image, the paths of the errors are relative to the entry.
+    pub fn from_yaml(object: &Value) -> Result<Self, Vec<ConfigError>> {
+        if !object.is_mapping() {
+            let err = format!("Expected a mapping, got {:?}", object);
+            return Err(vec![ConfigError::new("", err)]);
+        }
+
+        let mut errors = Vec::new();
+        let mut string = |key: &str| match object.get(key) {
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(s) => {
+                let err = format!("Expected a string, got {:?}", s);
+                errors.push(ConfigError::new(key, err));
+                None
+            }
+            None => {
+                errors.push(ConfigError::new(key, "Required field not found"));
+                None
+            }
+        };
+
+        let path = string("path");
+        let name = string("name");
+        let builder = TextureBuilder::parse(object, false, false)
+            .map_err(|err| errors.push(ConfigError::new("", err)))
+            .ok();
+        let color = ImageColor::from_yaml(object)
+            .map_err(|err| errors.push(ConfigError::new("", err)))
+            .ok();
+        let alpha = AlphaMode::from_yaml(object)
+            .map_err(|err| errors.push(ConfigError::new("", err)))
+            .ok();
+
+        let format = match object.get("format") {
+            Some(s) => match s.as_str() {
+                Some("rgba16f") => Some(TextureFormat::RGBA16F),
+                Some("rgba32f") => Some(TextureFormat::RGBA32F),
+                _ => {
+                    let err = format!("Expected either \"rgba16f\" or \"rgba32f\", got {:?}", s);
+                    errors.push(ConfigError::new("format", err));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        match (path, name, builder, color, alpha, errors.is_empty()) {
+            (Some(path), Some(name), Some(builder), Some(color), Some(alpha), true) => Ok(Self {
+                path,
+                name: CString::new(name).unwrap(),
+                builder,
+                color,
+                alpha,
+                format,
+            }),
+            _ => Err(errors),
         }
     }
 }
@@ -41,18 +187,60 @@ pub struct Pipeline {
     pub stages: Vec<Stage>,
     pub buffers: HashMap<CString, Rc<dyn Texture>>,
     pub requested_ndi_sources: HashMap<CString, String>,
+    /// How the NDI sources store alpha, by texture name.
+    pub ndi_alpha: HashMap<CString, AlphaMode>,
+    pub requested_spout_texture: Option<CString>,
+    /// Texture which receives Spout frames before they are fitted into a
+    /// receive texture of a fixed size.
+    pub spout_fit: Option<(SpoutFit, Texture2D)>,
+    /// Texture which is presented on the screen instead of the stages drawing to it.
+    pub output: Option<CString>,
+    /// Color the final target is cleared to before the stages draw.
+    pub background: Option<[f32; 4]>,
+    /// Texture the final image is copied into after all stages ran.
+    pub previous_frame: Option<PreviousFrame>,
     pub osc_config: Option<OscConfig>,
+    pub osc_arrays: Vec<OscArray>,
+    pub source_uniforms: SourceUniforms,
+    /// Blocks of `layout(std140)` uniforms which are filled from the pipeline file.
+    pub uniform_blocks: Vec<UniformBlock>,
+    /// Uniform kinds which OSC mappings are converted to before they are uploaded.
+    pub osc_coercions: HashMap<String, GLenum>,
     pub spout_config: Option<SpoutConfig>,
+    pub loop_period: f32,
+    /// Seed of the `seed` and `pass_seed` uniforms and of the noise texture.
+    pub seed: u32,
+    /// Whether to check for OpenGL errors after every stage.
+    pub gl_checks: bool,
+    /// Target the color picker reads, `None` reads the screen.
+    pub pick_target: Option<CString>,
+    /// Pixel of the pick target which is read on every frame.
+    pub pick_at: Option<[u32; 2]>,
+    /// Stage index and code of every OpenGL error which was logged already.
+    pub reported_gl_errors: HashSet<(usize, GLenum)>,
     pub blending: bool,
+    pub warnings: Vec<String>,
+    /// All files the pipeline was built from, relative to the project folder.
+    pub dependencies: HashSet<PathBuf>,
 }
 
 impl Pipeline {
     pub fn splash_screen() -> Self {
         const SPLASH_FRAG: &str = include_str!("shaders/splash.frag");
+        Self::built_in(SPLASH_FRAG)
+    }
 
+    /// Returns the pipeline shown while no pipeline file could be built.
+    pub fn fallback() -> Self {
+        const FALLBACK_FRAG: &str = include_str!("shaders/fallback.frag");
+        Self::built_in(FALLBACK_FRAG)
+    }
+
+    /// Creates a pipeline with a single stage which draws to the screen.
+    fn built_in(frag: &str) -> Self {
         let sh_ids = vec![
             compile_shader(PASS_VERT, gl::VERTEX_SHADER).unwrap(),
-            compile_shader(SPLASH_FRAG, gl::FRAGMENT_SHADER).unwrap(),
+            compile_shader(frag, gl::FRAGMENT_SHADER).unwrap(),
         ];
 
         let prog_id = link_program(&sh_ids).unwrap();
@@ -60,39 +248,73 @@ impl Pipeline {
         let stages = vec![Stage {
             prog_id,
             target: None,
+            attachments: Vec::new(),
             kind: StageKind::Frag {},
             sh_ids,
             deps: Vec::new(),
             unis: HashMap::new(),
+            resolution_kind: gl::FLOAT_VEC4,
             blend: None,
+            clear: None,
+            viewport: None,
+            enabled: true,
+            when: None,
+            when_disabled: DisabledOutput::Keep,
             perf: RunningAverage::new(),
+            gpu_perf: RunningAverage::new(),
+            gpu_timer: None,
             builder: TextureBuilder::new(),
+            sources: Vec::new(),
         }];
 
         Self {
             stages,
             buffers: HashMap::new(),
             requested_ndi_sources: HashMap::new(),
+            ndi_alpha: HashMap::new(),
+            requested_spout_texture: None,
+            spout_fit: None,
+            output: None,
+            background: None,
+            previous_frame: None,
             osc_config: None,
+            osc_arrays: Vec::new(),
+            source_uniforms: SourceUniforms::default(),
+            uniform_blocks: Vec::new(),
+            osc_coercions: HashMap::new(),
             spout_config: None,
+            loop_period: LOOP_PERIOD,
+            seed: 0,
+            gl_checks: false,
+            pick_target: None,
+            pick_at: None,
+            reported_gl_errors: HashSet::new(),
             blending: false,
+            warnings: Vec::new(),
+            dependencies: HashSet::new(),
         }
     }
 
+    /// Loads a pipeline from a file.
+    ///
+    /// Textures listed in `outputs` are kept alive even if no stage samples
+    /// them, so they can be presented in additional output windows.
     pub async fn load(
         path: impl AsRef<Path>,
         screen_size: (u32, u32),
+        outputs: Vec<CString>,
     ) -> Result<(Self, UpdateRequest), String> {
         let empty_cache = HashMap::new();
-        Pipeline::from_file_with_cache(path, screen_size, &empty_cache).await
+        Pipeline::from_file_with_cache(path, screen_size, outputs, &empty_cache).await
     }
 
     async fn from_file_with_cache(
         path: impl AsRef<Path>,
         screen_size: (u32, u32),
+        outputs: Vec<CString>,
         cache: &HashMap<CString, Rc<dyn Texture>>,
     ) -> Result<(Self, UpdateRequest), String> {
-        let reader = match std::fs::File::open(path) {
+        let reader = match std::fs::File::open(&path) {
             Ok(s) => s,
             Err(e) => return Err(e.to_string()),
         };
@@ -102,14 +324,26 @@ impl Pipeline {
             Err(e) => return Err(e.to_string()),
         };
 
-        Pipeline::from_yaml_with_cache(object, screen_size, cache).await
+        let (mut pipeline, update) =
+            Pipeline::from_yaml_with_cache(object, screen_size, outputs, cache).await?;
+
+        pipeline.dependencies.insert(path.as_ref().to_path_buf());
+        Ok((pipeline, update))
     }
 
     async fn from_yaml_with_cache(
         object: Value,
         screen_size: (u32, u32),
+        outputs: Vec<CString>,
         cache: &HashMap<CString, Rc<dyn Texture>>,
     ) -> Result<(Self, UpdateRequest), String> {
+        // report all structural errors at once
+        let errors = validate_pipeline(&object);
+        if !errors.is_empty() {
+            let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+            return Err(messages.join("\n"));
+        }
+
         let mut buffers = HashMap::<CString, Rc<dyn Texture>>::new();
         yield_now().await;
 
@@ -127,6 +361,21 @@ impl Pipeline {
             audio_samples,
             smoothing_attack,
             smoothing_decay,
+            fft_bins,
+            fft_scale,
+            fft_resample,
+            stereo_analysis,
+            peak_hold,
+            peak_decay,
+            audio_profiles,
+            audio_profile,
+            audio_latency,
+            audio_pre_gain,
+            audio_mute,
+            pitch_detection,
+            centroid_smoothing,
+            centroid_normalized,
+            density_window,
         ) = match object.get("audio") {
             None => (
                 TextureBuilder::new(),
@@ -138,6 +387,21 @@ impl Pipeline {
                 AUDIO_SAMPLES,
                 FFT_ATTACK,
                 FFT_DECAY,
+                AUDIO_SAMPLES / 2,
+                FftScale::Linear,
+                FftResample::Max,
+                true,
+                PEAK_HOLD,
+                PEAK_DECAY,
+                Vec::new(),
+                None,
+                AUDIO_LATENCY,
+                [AUDIO_PRE_GAIN; 2],
+                false,
+                false,
+                CENTROID_SMOOTHING,
+                false,
+                ONSET_WINDOW,
             ),
             Some(object) => {
                 let audio_samples = match object.get("audio_samples") {
@@ -159,25 +423,153 @@ impl Pipeline {
                     }
                 };
 
-                let attack = match object.get("attack") {
-                    None => FFT_ATTACK,
+                // envelope of the smoothed spectrum and the peaks, the
+                // pipeline may start with one of its named profiles instead
+                let base = parse_audio_profile(object, AudioProfile::default())?;
+                let (audio_profiles, audio_profile) = parse_audio_profiles(object, base)?;
+                let profile = match audio_profile {
+                    Some(index) => audio_profiles[index].1,
+                    None => base,
+                };
+
+                // resampling of the raw spectrum
+                let fft = object.get("fft");
+                let fft_bins = parse_fft_width(fft, audio_samples)?;
+                let fft_scale = match fft.and_then(|fft| fft.get("scale")) {
+                    None => FftScale::Linear,
+                    Some(s) => match s.as_str() {
+                        Some("linear") => FftScale::Linear,
+                        Some("log") => FftScale::Log,
+                        _ => {
+                            return Err(format!(
+                                "Expected \"scale\" to be either \"linear\" or \"log\", got {:?}",
+                                s
+                            ))
+                        }
+                    },
+                };
+                let fft_resample = match fft.and_then(|fft| fft.get("resample")) {
+                    None => FftResample::Max,
+                    Some(s) => match s.as_str() {
+                        Some("max") => FftResample::Max,
+                        Some("linear") => FftResample::Linear,
+                        _ => {
+                            return Err(format!(
+                            "Expected \"resample\" to be either \"max\" or \"linear\", got {:?}",
+                            s
+                        ))
+                        }
+                    },
+                };
+
+                // analyze both channels or only their sum
+                let stereo_analysis = match object.get("channels") {
+                    None => true,
+                    Some(s) => match s.as_str() {
+                        Some("stereo") => true,
+                        Some("mono") => false,
+                        _ => {
+                            return Err(format!(
+                            "Expected \"channels\" to be either \"mono\" or \"stereo\", got {:?}",
+                            s
+                        ))
+                        }
+                    },
+                };
+
+                // delay of the analysis behind the input
+                let audio_latency = match object.get("latency_ms") {
+                    None => AUDIO_LATENCY,
                     Some(s) => match s.as_f64() {
                         Some(s) => s as _,
                         _ => {
                             return Err(format!(
-                                "Expected \"smoothing\" to be a float, got {:?}",
+                                "Expected \"latency_ms\" to be a float, got {:?}",
                                 s
                             ))
                         }
                     },
                 };
-                let decay = match object.get("decay") {
-                    None => FFT_DECAY,
+
+                // gain of the input before the analysis, for both or each channel
+                let audio_pre_gain = match object.get("pre_gain") {
+                    None => [AUDIO_PRE_GAIN; 2],
+                    Some(Value::Sequence(seq)) => match seq.as_slice() {
+                        [l, r] => match (l.as_f64(), r.as_f64()) {
+                            (Some(l), Some(r)) if l >= 0.0 && r >= 0.0 => [l as _, r as _],
+                            _ => {
+                                return Err(format!(
+                                    "Expected \"pre_gain\" to be non-negative floats, got {:?}",
+                                    seq
+                                ))
+                            }
+                        },
+                        _ => {
+                            return Err(format!(
+                                "Expected \"pre_gain\" to have one gain for each channel, got {:?}",
+                                seq
+                            ))
+                        }
+                    },
                     Some(s) => match s.as_f64() {
-                        Some(s) => s as _,
+                        Some(s) if s >= 0.0 => [s as _; 2],
                         _ => {
                             return Err(format!(
-                                "Expected \"smoothing\" to be a float, got {:?}",
+                                "Expected \"pre_gain\" to be a non-negative float, got {:?}",
+                                s
+                            ))
+                        }
+                    },
+                };
+                let audio_mute = match object.get("mute") {
+                    None => false,
+                    Some(s) => match s.as_bool() {
+                        Some(b) => b,
+                        _ => return Err(format!("Expected \"mute\" to be a bool, got {:?}", s)),
+                    },
+                };
+
+                // pitch detection costs some time on the CPU, so it is opt-in
+                let pitch_detection = match object.get("pitch") {
+                    None => false,
+                    Some(s) => match s.as_bool() {
+                        Some(b) => b,
+                        _ => return Err(format!("Expected \"pitch\" to be a bool, got {:?}", s)),
+                    },
+                };
+
+                // timbre descriptors of the raw spectrum
+                let centroid = object.get("centroid");
+                let centroid_smoothing = match centroid.and_then(|c| c.get("smoothing")) {
+                    None => CENTROID_SMOOTHING,
+                    Some(s) => match s.as_f64() {
+                        Some(s) if (0.0..=1.0).contains(&s) => s as _,
+                        _ => {
+                            return Err(format!(
+                                "Expected \"smoothing\" to be a float between 0 and 1, got {:?}",
+                                s
+                            ))
+                        }
+                    },
+                };
+                let centroid_normalized = match centroid.and_then(|c| c.get("normalize")) {
+                    None => false,
+                    Some(s) => match s.as_bool() {
+                        Some(b) => b,
+                        _ => {
+                            return Err(format!("Expected \"normalize\" to be a bool, got {:?}", s))
+                        }
+                    },
+                };
+
+                // seconds the onset density is averaged over
+                let density_window = match object.get("onsets").and_then(|o| o.get("window")) {
+                    None => ONSET_WINDOW,
+                    Some(s) => match s.as_f64() {
+                        Some(s) if s > 0.0 => s as _,
+                        _ => {
+                            return Err(format!(
+                                "Expected \"window\" to be a positive float, got {:?}",
                                 s
                             ))
                         }
@@ -218,8 +610,23 @@ impl Pipeline {
                     spectrum_integrated_opts,
                     spectrum_smooth_integrated_opts,
                     audio_samples,
-                    attack,
-                    decay,
+                    profile.attack,
+                    profile.decay,
+                    fft_bins,
+                    fft_scale,
+                    fft_resample,
+                    stereo_analysis,
+                    profile.peak_hold,
+                    profile.peak_decay,
+                    audio_profiles,
+                    audio_profile,
+                    audio_latency,
+                    audio_pre_gain,
+                    audio_mute,
+                    pitch_detection,
+                    centroid_smoothing,
+                    centroid_normalized,
+                    density_window,
                 )
             }
         };
@@ -230,7 +637,7 @@ impl Pipeline {
             .set_float(true);
 
         raw_spectrum_opts
-            .set_resolution(vec![(audio_samples / 2) as _; 1])
+            .set_resolution(vec![fft_bins as _; 1])
             .set_channels(2)
             .set_float(true);
 
@@ -276,12 +683,13 @@ impl Pipeline {
             spectrum_smooth_integrated_opts.build_texture(),
         );
 
+        let seed = parse_seed(&object)?;
         {
-            // add noise texture
+            // add noise texture, which only depends on the seed
             let noise_name = NOISE_NAME.clone();
             let noise = match cache.get(&noise_name) {
                 Some(old) => Rc::clone(old),
-                None => Rc::new(make_noise()),
+                None => Rc::new(make_noise(seed)),
             };
             buffers.insert(noise_name, noise);
         }
@@ -294,12 +702,32 @@ impl Pipeline {
             None => None,
         };
 
+        // parse uniform blocks section
+        let uniform_blocks = parse_uniform_blocks(&object)?;
+
         // parse Spout section
         let spout_config = match object.get("spout") {
             Some(spout_obj) => Some(SpoutConfig::from_yaml(spout_obj)?),
             None => None,
         };
 
+        if let Some(config) = &spout_config {
+            if config.enabled && config.require_library && !spout_library_available() {
+                return Err("Spout requires SpoutLibrary.dll, but it could not be loaded".into());
+            }
+        }
+
+        let loop_period = parse_loop_period(&object)?;
+        let gl_checks = parse_gl_checks(&object)?;
+        let (pick_target, pick_at) = parse_pick(&object)?;
+        let mut pick_target = match pick_target {
+            Some(name) => Some(
+                CString::new(name.as_str())
+                    .map_err(|_| format!("Invalid pick target {:?}", name))?,
+            ),
+            None => None,
+        };
+
         // parse images section
         let images = match object.get("images") {
             Some(Value::Sequence(s)) => s.clone(),
@@ -308,18 +736,18 @@ impl Pipeline {
         };
 
         // parse images
+        let mut dependencies = HashSet::new();
+        let mut hdr_images = HashSet::new();
         for object in images {
-            let path = match object.get("path") {
-                Some(Value::String(s)) => s,
-                s => {
-                    return Err(format!("Expected \"path\" to be a string, got {:?}", s));
-                }
-            };
-
-            let name = match object.get("name") {
-                Some(Value::String(s)) => CString::new(s.as_str()).unwrap(),
-                s => return Err(format!("Expected \"name\" to be a string, got {:?}", s)),
-            };
+            let ImageConfig {
+                path,
+                name,
+                mut builder,
+                color,
+                alpha,
+                format,
+            } = ImageConfig::from_yaml(&object)
+                .map_err(|errors| format!("Invalid image, {}", errors[0]))?;
 
             // check if name is already in use
             if buffers.get(&name).is_some() {
@@ -329,10 +757,78 @@ impl Pipeline {
                 ));
             }
 
+            dependencies.insert(PathBuf::from(&path));
+
+            // HDR images are float textures with mipmaps, for blurry lookups of environments
+            let hdr_format = HdrFormat::of_file(&path);
+            let hdr_texture_format = match (hdr_format, format) {
+                (_, None) => TextureFormat::RGBA16F,
+                (Some(_), Some(format)) => format,
+                (None, Some(_)) => {
+                    return Err(format!(
+                        "Expected \"format\" only for .hdr and .exr images, {:?} is neither",
+                        path
+                    ))
+                }
+            };
+            if hdr_format.is_some() {
+                if color.srgb == Some(true) {
+                    return Err(format!("HDR image {:?} is always linear, got sRGB", name));
+                }
+
+                builder.mipmap = true;
+                builder.min_filter = match builder.mag_filter {
+                    gl::NEAREST => gl::NEAREST_MIPMAP_NEAREST,
+                    _ => gl::LINEAR_MIPMAP_LINEAR,
+                };
+                hdr_images.insert(name.clone());
+            }
+
+            let key = format!(
+                "{}#{:x}:{:x}:{:x}:{:?}:{}:{:?}:{:?}",
+                path,
+                builder.wrap_mode,
+                builder.min_filter,
+                builder.mag_filter,
+                color.srgb,
+                color.exposure,
+                alpha,
+                hdr_texture_format
+            );
+
             // fetch texture from global cache
-            let tex = match Cache::fetch(path) {
-                Some(cached_tex) => cached_tex,
-                None => {
+            let tex = match (Cache::fetch(&key), hdr_format) {
+                (Some(cached_tex), _) => cached_tex,
+                (None, Some(hdr)) => {
+                    let mut image = HdrImage::load(&path, hdr).map_err(|e| {
+                        format!("Failed to decode HDR image {:?} at {:?}: {}", name, path, e)
+                    })?;
+                    async_std::task::yield_now().await;
+
+                    alpha.convert_f32(&mut image.pixels);
+                    color.expose(&mut image.pixels);
+                    let texture = Texture2D::with_params(
+                        [image.width, image.height],
+                        builder.min_filter,
+                        builder.mag_filter,
+                        builder.wrap_mode,
+                        hdr_texture_format,
+                        builder.mipmap,
+                        image.pixels.as_ptr() as _,
+                    );
+                    unsafe {
+                        gl::BindTexture(gl::TEXTURE_2D, texture.id);
+                        gl::GenerateMipmap(gl::TEXTURE_2D);
+                        gl_debug_check!();
+                    }
+
+                    let tex: Rc<dyn Texture> = Rc::new(texture);
+                    async_std::task::yield_now().await;
+
+                    Cache::store(key, Rc::clone(&tex));
+                    tex
+                }
+                (None, None) => {
                     let reader = image::io::Reader::open(&path)
                         .map_err(|_| format!("Failed to open image {:?} at {:?}", name, path))?;
                     async_std::task::yield_now().await;
@@ -342,15 +838,33 @@ impl Pipeline {
                         .map_err(|_| format!("Failed to decode image {:?} at {:?}", name, path))?;
                     async_std::task::yield_now().await;
 
-                    let image = dyn_image.flipv().to_rgba8();
+                    let dyn_image = dyn_image.flipv();
                     async_std::task::yield_now().await;
 
-                    let mut builder = TextureBuilder::parse(&object, false, false)?;
-                    builder.resolution = vec![image.width(), image.height()];
-                    let tex = builder.build_texture_with_data(image.as_raw().as_ptr() as _);
+                    // 8 bit images are sRGB unless stated otherwise, which the
+                    // texture format decodes, everything else is converted here
+                    let color_type = dyn_image.color();
+                    let eight_bit = color_type.bytes_per_pixel() == color_type.channel_count();
+                    let srgb = color.srgb.unwrap_or(eight_bit);
+                    let tex = if eight_bit && color.exposure == 1.0 {
+                        let mut image = dyn_image.to_rgba8();
+                        alpha.convert_u8(&mut image);
+                        builder.resolution = vec![image.width(), image.height()];
+                        builder
+                            .set_srgb(srgb)
+                            .build_texture_with_data(image.as_raw().as_ptr() as _)
+                    } else {
+                        let mut image = dyn_image.to_rgba16();
+                        alpha.convert_u16(&mut image);
+                        builder.resolution = vec![image.width(), image.height()];
+                        let pixels = color.linearize(srgb, image.as_raw());
+                        builder
+                            .set_float(true)
+                            .build_texture_with_data(pixels.as_ptr() as _)
+                    };
                     async_std::task::yield_now().await;
 
-                    Cache::store(path.clone(), Rc::clone(&tex));
+                    Cache::store(key, Rc::clone(&tex));
                     tex
                 }
             };
@@ -372,6 +886,7 @@ impl Pipeline {
         };
 
         let mut requested_ndi_sources = HashMap::new();
+        let mut ndi_alpha = HashMap::new();
         for src in ndi_sources {
             let source = match src.get("source") {
                 Some(Value::String(s)) => s.clone(),
@@ -405,9 +920,74 @@ impl Pipeline {
                 .build_texture();
 
             requested_ndi_sources.insert(name.clone(), source);
+            ndi_alpha.insert(name.clone(), AlphaMode::from_yaml(&src)?);
+            buffers.insert(name, tex);
+        }
+
+        // texture for frames received over Spout, black until a sender connects
+        let mut requested_spout_texture = None;
+        let mut spout_fit = None;
+        if let Some(name) = spout_config
+            .as_ref()
+            .and_then(|c| c.receive_texture.as_ref())
+        {
+            let name = CString::new(name.as_str()).unwrap();
+            if buffers.get(&name).is_some() {
+                return Err(format!(
+                    "Texture {:?} already exists, please try a different name",
+                    name
+                ));
+            }
+
+            let receive = &object["spout"]["receive"];
+            // zero bits are black in both 8 bit and float textures
+            let black = [0_f32; 4];
+            let mut builder = TextureBuilder::parse(receive, false, true)?;
+            let staging_format = match builder.float {
+                true => TextureFormat::RGBA32F,
+                false => TextureFormat::RGBA8,
+            };
+
+            let config = spout_config.as_ref().unwrap();
+            let tex: Rc<dyn Texture> = match config.receive_size {
+                // frames are fitted into the texture with a blit every frame
+                Some([width, height]) => {
+                    let staging = Texture2D::with_params(
+                        [1, 1],
+                        gl::LINEAR,
+                        gl::LINEAR,
+                        gl::CLAMP_TO_EDGE,
+                        staging_format,
+                        false,
+                        black.as_ptr() as _,
+                    );
+                    spout_fit = Some((config.receive_fit, staging));
+                    builder
+                        .set_resolution(vec![width, height])
+                        .build_framebuffer((width, height))
+                }
+                None => builder
+                    .set_resolution(vec![1, 1])
+                    .build_texture_with_data(black.as_ptr() as _),
+            };
+
+            requested_spout_texture = Some(name.clone());
             buffers.insert(name, tex);
         }
 
+        // texture with the final image of the last frame, for feedback over the whole pipeline
+        let mut previous_frame = parse_previous_frame(&object)?;
+        if let Some(frame) = &previous_frame {
+            if buffers.get(&frame.name).is_some() {
+                return Err(format!(
+                    "Texture {:?} already exists, please try a different name",
+                    frame.name
+                ));
+            }
+
+            buffers.insert(frame.name.clone(), frame.build(screen_size));
+        }
+
         // parse stages section
         let passes = match object.get("stages") {
             Some(Value::Sequence(s)) => s.clone(),
@@ -415,10 +995,28 @@ impl Pipeline {
             s => return Err(format!("Expected \"stages\" to be an array, got {:?}", s)),
         };
 
+        let output = parse_output(&object)?.map(|name| CString::new(name).unwrap());
+        let mut background = parse_background(&object)?;
+
+        // the background shader is a stage which draws first, into the final target
+        let mut background_stage = None;
+        if let Some(path) = parse_background_shader(&object)? {
+            let mut pass = serde_yaml::Mapping::new();
+            pass.insert("fs".into(), path.into());
+            if let Some(name) = &output {
+                pass.insert("target".into(), name.to_string_lossy().as_ref().into());
+            }
+
+            let stage = Stage::from_yaml(Value::Mapping(pass))?;
+            dependencies.extend(stage.files().map(PathBuf::from));
+            background_stage = Some(stage);
+        }
+
         // parse stages
         let mut stages = Vec::with_capacity(passes.len());
         for pass in passes {
             let stage = Stage::from_yaml(pass)?;
+            dependencies.extend(stage.files().map(PathBuf::from));
             stages.push(stage);
             yield_now().await;
         }
@@ -426,51 +1024,99 @@ impl Pipeline {
         // create render targets for stages
         let mut res_map = HashMap::new();
         for stage in stages.iter() {
-            let target = match &stage.target {
-                Some(s) => s,
-                None => continue,
-            };
+            for target in stage.target.iter().chain(stage.attachments.iter()) {
+                // check if target exists already
+                let stage_res = stage.resolution();
+                if buffers.contains_key(target) {
+                    if let Some(&buffer_res) = res_map.get(target.as_c_str()) {
+                        // compare against previous stages
+                        if buffer_res != stage_res {
+                            return Err(format!(
+                                "Texture {:?} already has a different resolution",
+                                target
+                            ));
+                        }
 
-            // check if target exists already
-            let stage_res = stage.resolution();
-            if buffers.contains_key(target) {
-                if let Some(&buffer_res) = res_map.get(target.as_c_str()) {
-                    // compare against previous stages
-                    if buffer_res != stage_res {
+                        // don't create the same target twice
+                        continue;
+                    } else {
                         return Err(format!(
-                            "Texture {:?} already has a different resolution",
+                            "Target {:?} is already loaded as an image or build-in texture",
                             target
                         ));
                     }
+                }
 
-                    // don't create the same target twice
-                    continue;
-                } else {
+                // record specified stage resolution
+                res_map.insert(target.as_c_str(), stage_res);
+
+                // create textures
+                let texture: Rc<dyn Texture> = match stage.kind {
+                    StageKind::Frag { .. }
+                    | StageKind::Vert { .. }
+                    | StageKind::Composite { .. } => {
+                        stage.builder.build_double_framebuffer(screen_size)
+                    }
+                    StageKind::Comp { .. } => stage.builder.build_image(),
+                };
+
+                // insert texture into hashmap
+                buffers.insert(target.clone(), texture);
+            }
+
+            yield_now().await;
+        }
+
+        // the background stage creates no texture, it takes over the settings of the output
+        let has_background_stage = background_stage.is_some();
+        if let Some(mut stage) = background_stage {
+            let writer = stages.iter().find(|writer| {
+                let mut targets = writer.target.iter().chain(writer.attachments.iter());
+                output
+                    .as_ref()
+                    .map_or(false, |name| targets.any(|t| t == name))
+            });
+            if let Some(writer) = writer {
+                stage.builder = writer.builder.clone();
+            }
+
+            stages.insert(0, stage);
+        }
+
+        // viewports of fixed size targets have to fit,
+        // targets which follow the window size are clipped while rendering
+        for stage in stages.iter() {
+            if let (Some([x, y, w, h]), Some([width, height, _])) =
+                (stage.viewport, stage.resolution())
+            {
+                if x as u64 + w as u64 > width as u64 || y as u64 + h as u64 > height as u64 {
                     return Err(format!(
-                        "Target {:?} is already loaded as an image or build-in texture",
-                        target
+                        "Viewport {:?} of stage {} does not fit into its target of {}x{} pixels",
+                        [x, y, w, h],
+                        stage.label(),
+                        width,
+                        height
                     ));
                 }
             }
+        }
 
-            // record specified stage resolution
-            res_map.insert(target.as_c_str(), stage_res);
-
-            // create textures
-            let texture: Rc<dyn Texture> = match stage.kind {
-                StageKind::Frag { .. } | StageKind::Vert { .. } => {
-                    stage.builder.build_double_framebuffer(screen_size)
+        // compute uniform dependencies
+        let mut used_buffers = HashSet::new();
+        for stage in stages.iter() {
+            if let StageKind::Composite { layer } = &stage.kind {
+                if !buffers.contains_key(&layer.source) {
+                    return Err(format!(
+                        "Composite stage {} uses texture {:?}, which does not exist",
+                        stage.label(),
+                        layer.source
+                    ));
                 }
-                StageKind::Comp { .. } => stage.builder.build_image(),
-            };
 
-            // insert texture into hashmap
-            buffers.insert(target.clone(), texture);
-            yield_now().await;
+                used_buffers.insert(layer.source.clone());
+            }
         }
 
-        // compute uniform dependencies
-        let mut used_buffers = HashSet::new();
         for stage in stages.iter_mut() {
             for tex_name in buffers.keys() {
                 // try to locate the uniform in the program
@@ -486,6 +1132,287 @@ impl Pipeline {
             yield_now().await;
         }
 
+        // check for uniforms which are set, but never read
+        let mut warnings = Vec::new();
+        let mut active = Vec::with_capacity(stages.len());
+        let mut declared = HashMap::new();
+        for stage in stages.iter_mut() {
+            let mut names = HashSet::new();
+            for uniform in active_uniforms(stage.prog_id) {
+                // resolution may be declared as either vec3 or vec4
+                if uniform.name.as_bytes() == RESOLUTION_NAME.as_bytes() {
+                    stage.resolution_kind = uniform.kind;
+                }
+
+                // remember the largest declaration of every uniform
+                let declaration = declared
+                    .entry(uniform.name.clone())
+                    .or_insert((uniform.kind, 0));
+                declaration.1 = declaration.1.max(uniform.size);
+
+                names.insert(uniform.name);
+            }
+
+            active.push(names);
+        }
+
+        // HDR colors only survive a float sampler, integer samplers would read garbage
+        for name in hdr_images.iter() {
+            let name = name.to_string_lossy();
+            if let Some(&(kind, _)) = declared.get(name.as_ref()) {
+                if kind != gl::SAMPLER_2D {
+                    return Err(format!(
+                        "Expected HDR image {:?} to be sampled with a sampler2D, got {}",
+                        name,
+                        glsl_type_name(kind)
+                    ));
+                }
+            }
+        }
+
+        for (k, stage) in stages.iter().enumerate() {
+            for name in stage.unis.keys() {
+                let name = name.to_string_lossy();
+                if !active[k].contains(name.as_ref()) {
+                    warnings.push(format!(
+                        "Uniform {:?} of stage {} is not used by the shader, it may be misspelled or optimized out",
+                        name, k
+                    ));
+                }
+            }
+        }
+
+        // connect uniform blocks to the stages which declare them
+        for block in uniform_blocks.iter() {
+            let mut used = false;
+            for (k, stage) in stages.iter().enumerate() {
+                used |= block
+                    .attach(stage.prog_id)
+                    .map_err(|e| format!("{} in stage {}", e, k))?;
+            }
+
+            if !used {
+                warnings.push(format!(
+                    "Uniform block {:?} is not used by any stage, it may be misspelled",
+                    block.name
+                ));
+            }
+
+            for name in block.osc_names() {
+                let mapped = osc_config.as_ref();
+                if !mapped.map_or(false, |config| config.mappings.contains_key(name)) {
+                    warnings.push(format!(
+                        "Uniform block {:?} follows {:?}, which is not an OSC mapping",
+                        block.name, name
+                    ));
+                }
+            }
+        }
+
+        if let Some(osc_config) = &osc_config {
+            for (name, mapping) in osc_config.mappings.iter() {
+                let drives_layer = stages.iter().any(|stage| {
+                    matches!(&stage.kind, StageKind::Composite { layer } if layer.uses_osc(name))
+                });
+                let drives_condition = stages.iter().any(|stage| {
+                    let when = stage.when.as_ref();
+                    when.map_or(false, |when| when.osc_names().any(|n| n == name))
+                });
+
+                let drives_block = uniform_blocks.iter().any(|block| block.uses_osc(name));
+
+                if !drives_layer
+                    && !drives_condition
+                    && !drives_block
+                    && !active.iter().any(|names| names.contains(&mapping.uniform))
+                {
+                    warnings.push(format!(
+                        "OSC mapping {:?} targets uniform {:?}, which is not used by any stage",
+                        mapping.address, name
+                    ));
+                }
+            }
+        }
+
+        // conditions can only follow values which have an OSC mapping
+        for (k, stage) in stages.iter().enumerate() {
+            let names = stage.when.iter().flat_map(|when| when.osc_names());
+            for name in names {
+                let mapped = osc_config.as_ref();
+                if !mapped.map_or(false, |config| config.mappings.contains_key(name)) {
+                    warnings.push(format!(
+                        "Condition of stage {} reads {:?}, which is not an OSC mapping and stays 0",
+                        k, name
+                    ));
+                }
+            }
+        }
+
+        // feedback passes read the final frame back, the background would wipe it
+        let has_background = background.is_some() || has_background_stage;
+        if let (true, Some(name)) = (has_background, &output) {
+            if let Some(k) = stages.iter().position(|stage| stage.deps.contains(name)) {
+                warnings.push(format!(
+                    "Background is skipped, since stage {} reads the output {:?} as feedback",
+                    k, name
+                ));
+
+                background = None;
+                if has_background_stage {
+                    stages[0].enabled = false;
+                }
+            }
+        }
+
+        // the output covers everything drawn to the screen
+        if let Some(output) = &output {
+            for (k, stage) in stages.iter().enumerate() {
+                if stage.target.is_none() {
+                    warnings.push(format!(
+                        "Stage {} draws to the screen, which is skipped since {:?} is the output",
+                        k, output
+                    ));
+                }
+            }
+        }
+
+        // check that mappings declare the type of the uniform they drive
+        let mut osc_coercions = HashMap::new();
+        if let Some(osc_config) = &osc_config {
+            let mut mismatches = Vec::new();
+            for (name, mapping) in osc_config.mappings.iter() {
+                // array elements are converted to the type of their array anyway
+                if mapping.writes_array() {
+                    continue;
+                }
+
+                let kind = match declared.get(&mapping.uniform) {
+                    Some(&(kind, _)) => kind,
+                    None => continue,
+                };
+
+                if mapping.data_type.accepts_uniform(kind) {
+                    // doubles only keep their precision if they are uploaded as such,
+                    // and rotations are uploaded as the matrix the shader declares
+                    if matches!(kind, gl::DOUBLE | gl::FLOAT_MAT3 | gl::FLOAT_MAT4) {
+                        osc_coercions.insert(name.clone(), kind);
+                    }
+                    continue;
+                }
+
+                let coercible = OSC_COERCIBLE_KINDS.contains(&kind);
+                if osc_config.coerce && coercible {
+                    osc_coercions.insert(name.clone(), kind);
+                }
+
+                let action = match (osc_config.coerce, coercible) {
+                    (true, true) => "values are converted",
+                    (false, true) => "set `coerce: true` to convert its values",
+                    (_, false) => "it can not be set over OSC",
+                };
+
+                mismatches.push(format!(
+                    "{:?} is declared as {} but is a {} in the shader, {}",
+                    name,
+                    mapping.data_type.name(),
+                    glsl_type_name(kind),
+                    action
+                ));
+            }
+
+            if !mismatches.is_empty() {
+                mismatches.sort();
+                warnings.push(format!(
+                    "OSC mappings do not match their uniforms: {}",
+                    mismatches.join("; ")
+                ));
+            }
+        }
+
+        // the color picker reads the screen if its target is not a framebuffer
+        if let Some(name) = &pick_target {
+            let readable = buffers.get(name).and_then(|tex| tex.framebuffer_id());
+            if readable.is_none() {
+                warnings.push(format!(
+                    "Pick target {:?} is not the target of a stage, picking the screen instead",
+                    name
+                ));
+                pick_target = None;
+            }
+        }
+
+        for warning in warnings.iter() {
+            log::warn!("{}", warning);
+        }
+
+        // collect uniform arrays which are written element-wise over OSC
+        let mut osc_arrays = Vec::new();
+        if let Some(osc_config) = &osc_config {
+            let mut elements = HashMap::<&str, Vec<_>>::new();
+            for (key, mapping) in osc_config.mappings.iter() {
+                // a mapping of the whole array fills it from the arguments of one message
+                let indices: Vec<_> = match (mapping.index, mapping.size) {
+                    (Some(index), _) => vec![(index, key.clone())],
+                    (None, Some(size)) => {
+                        (0..size).map(|k| (k, array_element_name(key, k))).collect()
+                    }
+                    (None, None) => continue,
+                };
+
+                elements
+                    .entry(mapping.uniform.as_str())
+                    .or_default()
+                    .extend(indices);
+            }
+
+            for (name, elements) in elements {
+                // unused uniforms have already been reported above
+                let (kind, size) = match declared.get(name) {
+                    Some(&declaration) => declaration,
+                    None => continue,
+                };
+
+                if !matches!(kind, gl::FLOAT | gl::INT | gl::BOOL) {
+                    return Err(format!(
+                        "Expected OSC mapped uniform {:?} to be an array of floats, ints or bools",
+                        name
+                    ));
+                }
+
+                for (index, key) in elements.iter() {
+                    if *index >= size as usize {
+                        return Err(format!(
+                            "OSC mapping {:?} is out of range, {:?} only has {} elements",
+                            key, name, size
+                        ));
+                    }
+                }
+
+                let c_name = CString::new(name)
+                    .map_err(|_| format!("Invalid uniform name in OSC mapping {:?}", name))?;
+                osc_arrays.push(OscArray::new(
+                    c_name,
+                    kind,
+                    size as _,
+                    elements,
+                    stages.len(),
+                ));
+            }
+        }
+
+        // keep textures which are shown in output windows
+        used_buffers.extend(outputs);
+        used_buffers.extend(output.iter().cloned());
+        used_buffers.extend(pick_target.iter().cloned());
+
+        // stages with multiple targets need all of them to draw
+        for stage in stages.iter() {
+            let targets = || stage.target.iter().chain(stage.attachments.iter());
+            if targets().any(|name| used_buffers.contains(name)) {
+                used_buffers.extend(targets().cloned());
+            }
+        }
+
         // check for blend modes
         let blending = stages.iter().any(|s| s.blend.is_some());
 
@@ -494,6 +1421,13 @@ impl Pipeline {
             let needed = used_buffers.contains(name);
             if !needed {
                 requested_ndi_sources.remove(name);
+                if requested_spout_texture.as_ref() == Some(name) {
+                    requested_spout_texture = None;
+                    spout_fit = None;
+                }
+                if previous_frame.as_ref().map(|frame| &frame.name) == Some(name) {
+                    previous_frame = None;
+                }
             }
             needed
         });
@@ -503,38 +1437,540 @@ impl Pipeline {
                 stages,
                 buffers,
                 requested_ndi_sources,
+                ndi_alpha,
+                requested_spout_texture,
+                spout_fit,
+                output,
+                background,
+                previous_frame,
                 osc_config,
+                osc_arrays,
+                source_uniforms: SourceUniforms::default(),
+                uniform_blocks,
+                osc_coercions,
                 spout_config,
+                loop_period,
+                seed,
+                gl_checks,
+                pick_target,
+                pick_at,
+                reported_gl_errors: HashSet::new(),
                 blending,
+                warnings,
+                dependencies,
             },
             UpdateRequest {
                 audio_samples,
                 smoothing_attack,
                 smoothing_decay,
+                fft_bins,
+                fft_scale,
+                fft_resample,
+                stereo_analysis,
+                peak_hold,
+                peak_decay,
+                audio_profiles,
+                audio_profile,
+                audio_latency,
+                audio_pre_gain,
+                audio_mute,
+                pitch_detection,
+                centroid_smoothing,
+                centroid_normalized,
+                density_window,
             },
         ))
     }
 
+    /// Switches all stages with the given label on or off.
+    ///
+    /// Returns `false` if no stage has this label.
+    pub fn set_stage_enabled(&mut self, label: &str, enabled: bool) -> bool {
+        let mut found = false;
+        for stage in self
+            .stages
+            .iter_mut()
+            .filter(|stage| stage.label() == label)
+        {
+            stage.enabled = enabled;
+            found = true;
+        }
+
+        found
+    }
+
     pub fn resize_buffers(&mut self, width: u32, height: u32) {
         for stage in self.stages.iter() {
             if !stage.builder.resolution.is_empty() {
                 continue;
             }
 
-            if !matches!(stage.kind, StageKind::Frag { .. } | StageKind::Vert { .. }) {
+            if matches!(stage.kind, StageKind::Comp { .. }) {
                 panic!("なに the fuck?")
             }
 
-            // get name of stage render target
-            let name = match &stage.target {
-                Some(s) => s.clone(),
-                _ => continue,
-            };
+            // get names of stage render targets
+            for name in stage.target.iter().chain(stage.attachments.iter()) {
+                self.buffers.insert(
+                    name.clone(),
+                    stage.builder.build_double_framebuffer((width, height)),
+                );
+            }
+        }
 
-            self.buffers.insert(
-                name,
-                stage.builder.build_double_framebuffer((width, height)),
-            );
+        // the last frame had another size, so it starts over from the clear color
+        if let Some(frame) = &self.previous_frame {
+            self.buffers
+                .insert(frame.name.clone(), frame.build((width, height)));
+        }
+    }
+}
+
+/// Reads from the `debug` section whether OpenGL errors are checked after every stage.
+pub fn parse_gl_checks(object: &Value) -> Result<bool, String> {
+    let section = match object.get("debug") {
+        Some(section) if section.is_mapping() => section,
+        Some(s) => return Err(format!("Expected \"debug\" to be a mapping, got {:?}", s)),
+        None => return Ok(false),
+    };
+
+    match section.get("gl_checks") {
+        Some(Value::Bool(b)) => Ok(*b),
+        Some(s) => Err(format!("Expected \"gl_checks\" to be a bool, got {:?}", s)),
+        None => Ok(false),
+    }
+}
+
+/// Reads from the `debug` section which target the color picker reads, and
+/// which pixel of it is read on every frame.
+pub fn parse_pick(object: &Value) -> Result<(Option<String>, Option<[u32; 2]>), String> {
+    let section = match object.get("debug") {
+        Some(section) if section.is_mapping() => section,
+        _ => return Ok((None, None)),
+    };
+
+    let target = match section.get("pick") {
+        Some(Value::String(s)) if s == "screen" => None,
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(s) => return Err(format!("Expected \"pick\" to be a string, got {:?}", s)),
+        None => None,
+    };
+
+    let at = match section.get("pick_at") {
+        Some(Value::Sequence(xs)) => match xs.as_slice() {
+            [x, y] => match (x.as_u64(), y.as_u64()) {
+                (Some(x), Some(y)) => Some([x as _, y as _]),
+                _ => None,
+            },
+            _ => None,
+        },
+        None => return Ok((target, None)),
+        _ => None,
+    };
+
+    match at {
+        Some(at) => Ok((target, Some(at))),
+        None => Err(format!(
+            "Expected \"pick_at\" to be a pixel [x, y], got {:?}",
+            section.get("pick_at")
+        )),
+    }
+}
+
+/// Reads the name of the texture which is presented on the screen.
+///
+/// The name has to be the target of a stage which renders a color texture,
+/// images written by compute shaders can not be presented.
+pub fn parse_output(object: &Value) -> Result<Option<String>, String> {
+    let name = match object.get("output") {
+        Some(Value::String(s)) => s,
+        Some(s) => return Err(format!("Expected \"output\" to be a string, got {:?}", s)),
+        None => return Ok(None),
+    };
+
+    let stages = object.get("stages").and_then(Value::as_sequence);
+    let writer = stages.into_iter().flatten().find(|stage| {
+        let target = stage.get("target").into_iter();
+        let targets = stage.get("targets").and_then(Value::as_sequence);
+        target
+            .chain(targets.into_iter().flatten())
+            .any(|target| target.as_str() == Some(name.as_str()))
+    });
+
+    match writer {
+        Some(stage) if stage.get("cs").is_some() => Err(format!(
+            "Expected \"output\" to be a color texture, {:?} is written by a compute shader",
+            name
+        )),
+        Some(_) => Ok(Some(name.clone())),
+        None => Err(format!(
+            "Expected \"output\" to name the target of a stage, got {:?}",
+            name
+        )),
+    }
+}
+
+/// Reads the color which the final target is cleared to before every frame.
+pub fn parse_background(object: &Value) -> Result<Option<[f32; 4]>, String> {
+    let s = match object.get("background") {
+        Some(Value::Sequence(s)) => s,
+        Some(s) => {
+            return Err(format!(
+                "Expected \"background\" to be a list of numbers, got {:?}",
+                s
+            ))
         }
+        None => return Ok(None),
+    };
+
+    let color: Vec<_> = s
+        .iter()
+        .filter_map(Value::as_f64)
+        .map(|x| x as f32)
+        .collect();
+    match color.as_slice() {
+        &[r, g, b, a] if s.len() == 4 => Ok(Some([r, g, b, a])),
+        &[r, g, b] if s.len() == 3 => Ok(Some([r, g, b, 1.0])),
+        _ => Err(format!(
+            "Expected \"background\" to be a list of 3 or 4 numbers, got {:?}",
+            s
+        )),
+    }
+}
+
+/// Reads the texture which the final image of every frame is copied into.
+///
+/// Either just the name of the texture, or a mapping with the name, the
+/// color before the first frame and the usual texture options.
+pub fn parse_previous_frame(object: &Value) -> Result<Option<PreviousFrame>, String> {
+    let section = match object.get("previous_frame") {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+
+    let (name, builder) = match section {
+        Value::String(s) => (s, TextureBuilder::parse(section, false, true)?),
+        Value::Mapping(_) => match section.get("name") {
+            Some(Value::String(s)) => (s, TextureBuilder::parse(section, false, true)?),
+            s => return Err(format!("Expected \"name\" to be a string, got {:?}", s)),
+        },
+        s => {
+            return Err(format!(
+                "Expected \"previous_frame\" to be a name or a mapping, got {:?}",
+                s
+            ))
+        }
+    };
+
+    let clear_color = match section.get("clear_color") {
+        Some(Value::Sequence(s)) => {
+            let color: Vec<_> = s
+                .iter()
+                .filter_map(Value::as_f64)
+                .map(|x| x as f32)
+                .collect();
+            match color.as_slice() {
+                &[r, g, b, a] if s.len() == 4 => [r, g, b, a],
+                &[r, g, b] if s.len() == 3 => [r, g, b, 1.0],
+                _ => {
+                    return Err(format!(
+                        "Expected \"clear_color\" to be a list of 3 or 4 numbers, got {:?}",
+                        s
+                    ))
+                }
+            }
+        }
+        Some(s) => {
+            return Err(format!(
+                "Expected \"clear_color\" to be a list of numbers, got {:?}",
+                s
+            ))
+        }
+        None => [0.0; 4],
+    };
+
+    let name = CString::new(name.as_str())
+        .map_err(|_| format!("Invalid texture name in \"previous_frame\": {:?}", name))?;
+
+    Ok(Some(PreviousFrame {
+        name,
+        clear_color,
+        builder,
+    }))
+}
+
+/// Reads the file name of the fragment shader which draws the background.
+pub fn parse_background_shader(object: &Value) -> Result<Option<String>, String> {
+    match object.get("background_shader") {
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(s) => Err(format!(
+            "Expected \"background_shader\" to be a file name, got {:?}",
+            s
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Reads the width of the raw spectrum texture from the `fft` section.
+///
+/// The width is independent of the number of analyzed samples, `pow2` picks
+/// the power of two which is closest to the number of FFT bins.
+pub fn parse_fft_width(fft: Option<&Value>, audio_samples: usize) -> Result<usize, String> {
+    let bins = fft.and_then(|fft| fft.get("bins"));
+    let width = fft.and_then(|fft| fft.get("texture_width"));
+    let s = match (bins, width) {
+        (Some(_), Some(_)) => {
+            return Err("Fields \"bins\" and \"texture_width\" cannot be used together".into())
+        }
+        (None, None) => return Ok(audio_samples / 2),
+        (Some(s), None) | (None, Some(s)) => s,
+    };
+
+    match (s.as_u64(), s.as_str()) {
+        (Some(n), _) if n > 0 => Ok(n as _),
+        (_, Some("pow2")) => Ok(nearest_power_of_two(audio_samples / 2)),
+        _ => Err(format!(
+            "Expected the texture width to be a positive number or \"pow2\", got {:?}",
+            s
+        )),
+    }
+}
+
+/// Seed of pipelines which do not set one, it is chosen once per run.
+static STARTUP_SEED: OnceLock<u32> = OnceLock::new();
+
+/// Reads the `seed` of the pipeline.
+///
+/// Without a seed, every pipeline of a run gets the same random seed, and
+/// `"random"` picks a new one on every build.
+pub fn parse_seed(object: &Value) -> Result<u32, String> {
+    match object.get("seed") {
+        None => Ok(*STARTUP_SEED.get_or_init(rand::random)),
+        Some(Value::String(s)) if s == "random" => Ok(rand::random()),
+        Some(s) => match s.as_u64() {
+            Some(n) if n <= u32::MAX as u64 => Ok(n as _),
+            _ => Err(format!(
+                "Expected \"seed\" to be a number or \"random\", got {:?}",
+                s
+            )),
+        },
+    }
+}
+
+/// Derives the seed of a stage from the seed of the pipeline.
+///
+/// This is a PCG hash of both, so neighboring stages get unrelated seeds.
+pub fn pass_seed(seed: u32, pass: usize) -> u32 {
+    let state = seed ^ (pass as u32).wrapping_mul(0x9E37_79B9);
+    let state = state.wrapping_mul(747_796_405).wrapping_add(2_891_336_453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277_803_737);
+    (word >> 22) ^ word
+}
+
+/// Maps a seed to a float from 0 up to 1, as it is passed to the shaders.
+pub fn seed_to_float(seed: u32) -> f32 {
+    (seed >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Reads the period of the `loop_time` uniform from the `loop` section.
+pub fn parse_loop_period(object: &Value) -> Result<f32, String> {
+    let section = match object.get("loop") {
+        Some(section) if section.is_mapping() => section,
+        Some(s) => return Err(format!("Expected \"loop\" to be a mapping, got {:?}", s)),
+        None => return Ok(LOOP_PERIOD),
+    };
+
+    match section.get("period") {
+        Some(s) => match s.as_f64() {
+            Some(x) if x > 0.0 => Ok(x as _),
+            _ => Err(format!(
+                "Expected \"period\" to be a positive number, got {:?}",
+                s
+            )),
+        },
+        None => Ok(LOOP_PERIOD),
+    }
+}
+
+/// Reads the envelope parameters of the `audio` section or of one of its profiles.
+///
+/// Parameters which are not listed keep their value from `base`.
+pub fn parse_audio_profile(object: &Value, base: AudioProfile) -> Result<AudioProfile, String> {
+    let attack = match object.get("attack") {
+        None => base.attack,
+        Some(s) => match s.as_f64() {
+            Some(s) => s as _,
+            _ => return Err(format!("Expected \"smoothing\" to be a float, got {:?}", s)),
+        },
+    };
+    let decay = match object.get("decay") {
+        None => base.decay,
+        Some(s) => match s.as_f64() {
+            Some(s) => s as _,
+            _ => return Err(format!("Expected \"smoothing\" to be a float, got {:?}", s)),
+        },
+    };
+
+    // peak hold of the level uniforms
+    let peak = object.get("peak");
+    let peak_hold = match peak.and_then(|peak| peak.get("hold")) {
+        None => base.peak_hold,
+        Some(s) => match s.as_f64() {
+            Some(s) if s >= 0.0 => s as _,
+            _ => {
+                return Err(format!(
+                    "Expected \"hold\" to be a non-negative float, got {:?}",
+                    s
+                ))
+            }
+        },
+    };
+    let peak_decay = match peak.and_then(|peak| peak.get("decay")) {
+        None => base.peak_decay,
+        Some(s) => match s.as_f64() {
+            Some(s) if s >= 0.0 => s as _,
+            _ => {
+                return Err(format!(
+                    "Expected \"decay\" to be a non-negative float, got {:?}",
+                    s
+                ))
+            }
+        },
+    };
+
+    Ok(AudioProfile {
+        attack,
+        decay,
+        peak_hold,
+        peak_decay,
+    })
+}
+
+/// Reads the named profiles of the `audio` section and which one is active at first.
+pub fn parse_audio_profiles(
+    object: &Value,
+    base: AudioProfile,
+) -> Result<(Vec<(String, AudioProfile)>, Option<usize>), String> {
+    let mut profiles = Vec::new();
+    match object.get("profiles") {
+        None => {}
+        Some(Value::Mapping(map)) => {
+            for (name, profile) in map {
+                let name = match name.as_str() {
+                    Some(name) => name.to_owned(),
+                    None => {
+                        return Err(format!(
+                            "Expected audio profile name to be a string, got {:?}",
+                            name
+                        ))
+                    }
+                };
+                if !profile.is_mapping() {
+                    return Err(format!(
+                        "Expected audio profile {:?} to be a mapping, got {:?}",
+                        name, profile
+                    ));
+                }
+
+                let profile = parse_audio_profile(profile, base)?;
+                profiles.push((name, profile));
+            }
+        }
+        Some(s) => {
+            return Err(format!(
+                "Expected \"profiles\" to be a mapping, got {:?}",
+                s
+            ))
+        }
+    }
+
+    let active = match object.get("profile") {
+        None => None,
+        Some(Value::String(s)) => match profiles.iter().position(|(name, _)| name == s) {
+            Some(index) => Some(index),
+            None => {
+                return Err(format!(
+                    "Expected \"profile\" to be the name of a profile, got {:?}",
+                    s
+                ))
+            }
+        },
+        Some(s) => return Err(format!("Expected \"profile\" to be a string, got {:?}", s)),
+    };
+
+    Ok((profiles, active))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn audio_profiles() {
+        let object: Value = serde_yaml::from_str(
+            r#"
+            attack: 0.8
+            profiles:
+              calm: { decay: 0.95, peak: { hold: 1.0 } }
+              aggressive: { attack: 0.1, peak: { decay: 2.0 } }
+            profile: aggressive
+            "#,
+        )
+        .unwrap();
+
+        let base = parse_audio_profile(&object, AudioProfile::default()).unwrap();
+        assert_eq!(base.attack, 0.8);
+        assert_eq!(base.decay, FFT_DECAY);
+
+        let (profiles, active) = parse_audio_profiles(&object, base).unwrap();
+        assert_eq!(active, Some(1));
+        assert_eq!(profiles[0].0, "calm");
+        assert_eq!(profiles[0].1.attack, 0.8);
+        assert_eq!(profiles[0].1.decay, 0.95);
+        assert_eq!(profiles[0].1.peak_hold, 1.0);
+        assert_eq!(profiles[1].1.attack, 0.1);
+        assert_eq!(profiles[1].1.peak_decay, 2.0);
+
+        let object: Value = serde_yaml::from_str("profile: calm").unwrap();
+        assert!(parse_audio_profiles(&object, base).is_err());
+
+        let object: Value = serde_yaml::from_str("profiles: { calm: 0.5 }").unwrap();
+        assert!(parse_audio_profiles(&object, base).is_err());
+    }
+
+    #[test]
+    fn previous_frame() {
+        let parse = |yaml: &str| parse_previous_frame(&serde_yaml::from_str(yaml).unwrap());
+        assert!(parse("stages: []").unwrap().is_none());
+
+        let frame = parse("previous_frame: last").unwrap().unwrap();
+        assert_eq!(frame.name.to_str(), Ok("last"));
+        assert_eq!(frame.clear_color, [0.0; 4]);
+
+        let yaml = "previous_frame: { name: last, clear_color: [1, 0, 0], mipmap: true }";
+        let frame = parse(yaml).unwrap().unwrap();
+        assert_eq!(frame.clear_color, [1.0, 0.0, 0.0, 1.0]);
+        assert!(frame.builder.mipmap);
+
+        assert!(parse("previous_frame: { clear_color: [0, 0, 0] }").is_err());
+        assert!(parse("previous_frame: { name: last, clear_color: black }").is_err());
+        assert!(parse("previous_frame: [last]").is_err());
+    }
+
+    #[test]
+    fn seeds() {
+        let parse = |yaml: &str| parse_seed(&serde_yaml::from_str(yaml).unwrap());
+        assert_eq!(parse("seed: 42"), Ok(42));
+        assert_eq!(parse("stages: []"), parse("stages: []"));
+        assert!(parse("seed: -1").is_err());
+        assert!(parse("seed: fixed").is_err());
+
+        // the same pipeline seed always gives the same pass seeds
+        assert_eq!(pass_seed(42, 3), pass_seed(42, 3));
+        assert_ne!(pass_seed(42, 3), pass_seed(42, 4));
+        assert_ne!(pass_seed(42, 3), pass_seed(43, 3));
+
+        assert_eq!(seed_to_float(0), 0.0);
+        assert!(seed_to_float(u32::MAX) < 1.0);
     }
 }
@@ -0,0 +1,327 @@
+use std::{ffi::CString, time::Instant};
+
+use gl::types::*;
+use lazy_static::lazy_static;
+use rosc::OscType;
+
+use super::{Pipeline, UpdateRequest, PASS_VERT, POSITION_NAME, RESOLUTION_NAME};
+use crate::util::*;
+
+const BLIT_FRAG: &str = include_str!("shaders/blit.frag");
+
+lazy_static! {
+    static ref TEX_NAME: CString = CString::new("tex").unwrap();
+}
+
+/// Selects a pipeline file from the list of pipeline files.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PresetRequest {
+    /// Position in the sorted list of pipeline files.
+    Index(usize),
+    /// File name, with or without the `.yaml` extension.
+    Name(String),
+    /// Steps through the list relative to the current file, wrapping around.
+    Offset(isize),
+}
+
+impl PresetRequest {
+    /// Converts the argument of a `/preset` OSC message.
+    pub fn from_osc(arg: &OscType) -> Option<Self> {
+        match arg {
+            OscType::Int(i) if *i >= 0 => Some(Self::Index(*i as _)),
+            OscType::Long(l) if *l >= 0 => Some(Self::Index(*l as _)),
+            OscType::Float(f) if *f >= 0.0 => Some(Self::Index(f.round() as _)),
+            OscType::Double(d) if *d >= 0.0 => Some(Self::Index(d.round() as _)),
+            OscType::String(s) => Some(Self::Name(s.clone())),
+            _ => None,
+        }
+    }
+
+    /// Finds the index of the requested file, if it exists.
+    pub fn resolve(&self, files: &[String], current: usize) -> Option<usize> {
+        match self {
+            Self::Index(index) if *index < files.len() => Some(*index),
+            Self::Index(_) => None,
+            Self::Name(name) => files
+                .iter()
+                .position(|file| file == name || file.strip_suffix(".yaml") == Some(name)),
+            Self::Offset(_) if files.is_empty() => None,
+            Self::Offset(offset) => {
+                let len = files.len() as isize;
+                Some((current as isize + offset).rem_euclid(len) as _)
+            }
+        }
+    }
+}
+
+/// Handles switching between pipeline files during a performance.
+///
+/// A pipeline which was built because of a preset switch is held back until
+/// the end of the frame. The last frame of the outgoing pipeline is then
+/// copied into a texture and faded out on top of the new output, while the
+/// old pipeline itself is dropped right away.
+pub struct PresetManager {
+    pub crossfade: f32,
+    switching: bool,
+    ready: Option<(Pipeline, UpdateRequest)>,
+    fade: Option<(FrameBuffer, Instant)>,
+    /// The fade shader, presets switch without a fade if it failed to build.
+    program: Option<(GLuint, Vec<GLuint>)>,
+}
+
+impl std::fmt::Debug for PresetManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(PresetManager))
+            .field("crossfade", &self.crossfade)
+            .field("switching", &self.switching)
+            .field("ready", &self.ready.is_some())
+            .field("fade", &self.fade)
+            .finish()
+    }
+}
+
+impl PresetManager {
+    /// Creates a new preset manager for the OpenGL context which is currently active.
+    pub fn new(crossfade: f32) -> Self {
+        let program = match Self::build_program() {
+            Ok(program) => Some(program),
+            Err(err) => {
+                log::error!("Failed to build the preset fade shader: {}", err);
+                None
+            }
+        };
+
+        Self {
+            crossfade,
+            switching: false,
+            ready: None,
+            fade: None,
+            program,
+        }
+    }
+
+    fn build_program() -> Result<(GLuint, Vec<GLuint>), String> {
+        let sh_ids = vec![
+            compile_shader(PASS_VERT, gl::VERTEX_SHADER)?,
+            compile_shader(BLIT_FRAG, gl::FRAGMENT_SHADER)?,
+        ];
+
+        let prog_id = link_program(&sh_ids)?;
+        Ok((prog_id, sh_ids))
+    }
+
+    /// Marks the next pipeline build as a preset switch.
+    pub fn begin_switch(&mut self) {
+        self.switching = true;
+    }
+
+    /// Takes over a freshly built pipeline if it belongs to a crossfaded switch.
+    ///
+    /// Returns the pipeline back to the caller if it should be used right away.
+    pub fn offer(
+        &mut self,
+        pipeline: Pipeline,
+        update: UpdateRequest,
+    ) -> Option<(Pipeline, UpdateRequest)> {
+        let switching = std::mem::take(&mut self.switching);
+        if !switching || self.crossfade <= 0.0 || self.program.is_none() {
+            return Some((pipeline, update));
+        }
+
+        self.ready = Some((pipeline, update));
+        None
+    }
+
+    /// Copies the current contents of the default framebuffer, if a new
+    /// pipeline is waiting to be swapped in.
+    pub fn capture(&mut self, width: u32, height: u32) {
+        if self.ready.is_none() {
+            return;
+        }
+
+        let snapshot = FrameBuffer::new(width, height);
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, snapshot.fb_id);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                width as _,
+                height as _,
+                0,
+                0,
+                width as _,
+                height as _,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl_debug_check!();
+        }
+
+        self.fade = Some((snapshot, Instant::now()));
+    }
+
+    /// Returns the pipeline which is waiting to be swapped in.
+    pub fn take_ready(&mut self) -> Option<(Pipeline, UpdateRequest)> {
+        self.ready.take()
+    }
+
+    /// Draws the last frame of the previous pipeline on top of the default framebuffer.
+    ///
+    /// The blend state is restored afterwards, except for `GL_BLEND` itself.
+    pub fn draw_fade(&mut self, vao: GLuint, width: u32, height: u32) {
+        let (alpha, prog_id) = match (&self.fade, &self.program) {
+            (Some((_, start)), Some((prog_id, _))) => (
+                1.0 - start.elapsed().as_secs_f32() / self.crossfade,
+                *prog_id,
+            ),
+            _ => return,
+        };
+
+        if alpha <= 0.0 {
+            self.fade = None;
+            return;
+        }
+
+        let snapshot = &self.fade.as_ref().unwrap().0;
+        let (w, h) = (width as f32, height as f32);
+
+        unsafe {
+            // the stages of the next frame expect the blend state they set themselves
+            let mut color = [0.0; 4];
+            let mut state = [0; 6];
+            gl::GetFloatv(gl::BLEND_COLOR, color.as_mut_ptr());
+            let names = [
+                gl::BLEND_SRC_RGB,
+                gl::BLEND_DST_RGB,
+                gl::BLEND_SRC_ALPHA,
+                gl::BLEND_DST_ALPHA,
+                gl::BLEND_EQUATION_RGB,
+                gl::BLEND_EQUATION_ALPHA,
+            ];
+            for (value, &name) in state.iter_mut().zip(names.iter()) {
+                gl::GetIntegerv(name, value);
+            }
+            gl_debug_check!();
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, width as _, height as _);
+            gl::UseProgram(prog_id);
+            gl_debug_check!();
+
+            let res_loc = gl::GetUniformLocation(prog_id, RESOLUTION_NAME.as_ptr());
+            gl::Uniform4f(res_loc, w, h, w / h, h / w);
+            gl_debug_check!();
+
+            let tex_loc = gl::GetUniformLocation(prog_id, TEX_NAME.as_ptr());
+            gl::ActiveTexture(gl::TEXTURE0);
+            snapshot.bind(0);
+            gl::Uniform1i(tex_loc, 0);
+            gl_debug_check!();
+
+            gl::Enable(gl::BLEND);
+            gl::BlendEquation(gl::FUNC_ADD);
+            gl::BlendFunc(gl::CONSTANT_ALPHA, gl::ONE_MINUS_CONSTANT_ALPHA);
+            gl::BlendColor(0.0, 0.0, 0.0, alpha);
+            gl_debug_check!();
+
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vao);
+            let pos_attr = gl::GetAttribLocation(prog_id, POSITION_NAME.as_ptr());
+            if pos_attr != -1 {
+                gl::EnableVertexAttribArray(pos_attr as GLuint);
+                gl::VertexAttribPointer(
+                    pos_attr as GLuint,
+                    2,
+                    gl::FLOAT,
+                    gl::FALSE as GLboolean,
+                    0,
+                    std::ptr::null(),
+                );
+            }
+            gl_debug_check!();
+
+            draw_fullscreen(vao);
+
+            let [r, g, b, a] = color;
+            gl::BlendColor(r, g, b, a);
+            gl::BlendFuncSeparate(state[0] as _, state[1] as _, state[2] as _, state[3] as _);
+            gl::BlendEquationSeparate(state[4] as _, state[5] as _);
+            gl_debug_check!();
+        }
+    }
+}
+
+impl Drop for PresetManager {
+    fn drop(&mut self) {
+        let (prog_id, sh_ids) = match &self.program {
+            Some(program) => program,
+            None => return,
+        };
+
+        unsafe {
+            for &id in sh_ids.iter() {
+                gl::DetachShader(*prog_id, id);
+                gl::DeleteShader(id);
+            }
+
+            gl::DeleteProgram(*prog_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn files() -> Vec<String> {
+        vec![
+            "ambient.yaml".into(),
+            "drop.yaml".into(),
+            "outro.yaml".into(),
+        ]
+    }
+
+    #[test]
+    fn resolve_index() {
+        let files = files();
+        assert_eq!(PresetRequest::Index(1).resolve(&files, 0), Some(1));
+        assert_eq!(PresetRequest::Index(3).resolve(&files, 0), None);
+    }
+
+    #[test]
+    fn resolve_name() {
+        let files = files();
+        let by_name = |name: &str| PresetRequest::Name(name.into()).resolve(&files, 0);
+        assert_eq!(by_name("outro"), Some(2));
+        assert_eq!(by_name("drop.yaml"), Some(1));
+        assert_eq!(by_name("intro"), None);
+    }
+
+    #[test]
+    fn resolve_offset() {
+        let files = files();
+        assert_eq!(PresetRequest::Offset(1).resolve(&files, 2), Some(0));
+        assert_eq!(PresetRequest::Offset(-1).resolve(&files, 0), Some(2));
+        assert_eq!(PresetRequest::Offset(1).resolve(&[], 0), None);
+    }
+
+    #[test]
+    fn from_osc() {
+        assert_eq!(
+            PresetRequest::from_osc(&OscType::Int(3)),
+            Some(PresetRequest::Index(3))
+        );
+        assert_eq!(
+            PresetRequest::from_osc(&OscType::Float(2.0)),
+            Some(PresetRequest::Index(2))
+        );
+        assert_eq!(
+            PresetRequest::from_osc(&OscType::String("drop".into())),
+            Some(PresetRequest::Name("drop".into()))
+        );
+        assert_eq!(PresetRequest::from_osc(&OscType::Int(-1)), None);
+    }
+}
@@ -0,0 +1,687 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde_yaml::{Mapping, Value};
+
+use super::{
+    capture_image,
+    check::{enter_pipeline_folder, headless_context},
+    Audio, Config, Engine, FrameUniforms, InputActivity, RenderInputs, SavedState, MIDI_N,
+};
+use crate::util::{CapturedFrame, FrameBuffer};
+
+/// Name of the file next to the frames of a recording which holds their uniforms.
+pub const RECORDING_SIDECAR: &str = "uniforms.yaml";
+
+/// Lists the analysis results of `Audio` which feed the shaders.
+///
+/// Takes the kind of borrow and the function which turns a borrowed scalar
+/// into a slice, so the same list serves for reading and writing.
+macro_rules! audio_fields {
+    ($audio:expr, $scalar:path, $($borrow:tt)+) => {
+        [
+            ("volume", $($borrow)+ $audio.volume[..]),
+            ("volume_integrated", $($borrow)+ $audio.volume_integrated[..]),
+            ("volume_peak", $($borrow)+ $audio.volume_peak[..]),
+            ("bass", $($borrow)+ $audio.bass[..]),
+            ("bass_smooth", $($borrow)+ $audio.bass_smooth[..]),
+            ("bass_integrated", $($borrow)+ $audio.bass_integrated[..]),
+            ("bass_smooth_integrated", $($borrow)+ $audio.bass_smooth_integrated[..]),
+            ("bass_peak", $($borrow)+ $audio.bass_peak[..]),
+            ("mid", $($borrow)+ $audio.mid[..]),
+            ("mid_smooth", $($borrow)+ $audio.mid_smooth[..]),
+            ("mid_integrated", $($borrow)+ $audio.mid_integrated[..]),
+            ("mid_smooth_integrated", $($borrow)+ $audio.mid_smooth_integrated[..]),
+            ("mid_peak", $($borrow)+ $audio.mid_peak[..]),
+            ("high", $($borrow)+ $audio.high[..]),
+            ("high_smooth", $($borrow)+ $audio.high_smooth[..]),
+            ("high_integrated", $($borrow)+ $audio.high_integrated[..]),
+            ("high_smooth_integrated", $($borrow)+ $audio.high_smooth_integrated[..]),
+            ("high_peak", $($borrow)+ $audio.high_peak[..]),
+            ("stereo_correlation", $scalar($($borrow)+ $audio.stereo_correlation)),
+            ("stereo_width", $scalar($($borrow)+ $audio.stereo_width)),
+            ("pitch", $scalar($($borrow)+ $audio.pitch)),
+            ("note", $scalar($($borrow)+ $audio.note)),
+            ("pitch_confidence", $scalar($($borrow)+ $audio.pitch_confidence)),
+            ("centroid", $($borrow)+ $audio.centroid[..]),
+            ("spread", $($borrow)+ $audio.spread[..]),
+            ("centroid_smooth", $($borrow)+ $audio.centroid_smooth[..]),
+            ("spread_smooth", $($borrow)+ $audio.spread_smooth[..]),
+            ("density", $scalar($($borrow)+ $audio.density)),
+            ("l_signal", $($borrow)+ $audio.l_signal[..]),
+            ("r_signal", $($borrow)+ $audio.r_signal[..]),
+            ("l_binned_spectrum", $($borrow)+ $audio.l_binned_spectrum[..]),
+            ("r_binned_spectrum", $($borrow)+ $audio.r_binned_spectrum[..]),
+            ("l_spectrum", $($borrow)+ $audio.l_spectrum[..]),
+            ("r_spectrum", $($borrow)+ $audio.r_spectrum[..]),
+            ("l_spectrum_integrated", $($borrow)+ $audio.l_spectrum_integrated[..]),
+            ("r_spectrum_integrated", $($borrow)+ $audio.r_spectrum_integrated[..]),
+            ("l_spectrum_smooth", $($borrow)+ $audio.l_spectrum_smooth[..]),
+            ("r_spectrum_smooth", $($borrow)+ $audio.r_spectrum_smooth[..]),
+            (
+                "l_spectrum_smooth_integrated",
+                $($borrow)+ $audio.l_spectrum_smooth_integrated[..],
+            ),
+            (
+                "r_spectrum_smooth_integrated",
+                $($borrow)+ $audio.r_spectrum_smooth_integrated[..],
+            ),
+        ]
+    };
+}
+
+/// Everything besides the pipeline which fed the uniforms of a recorded frame.
+///
+/// The resolution is not part of a snapshot, so a recording can be rendered
+/// again at any size.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameSnapshot {
+    pub time: f32,
+    pub time_since_build: f32,
+    pub loop_origin: f32,
+    pub beat: f32,
+    pub delta: f32,
+    pub frame: u32,
+    pub frame_since_build: u32,
+    pub fps: f32,
+    /// Average and longest frame time of the last second, in milliseconds.
+    pub frame_time: [f32; 2],
+    pub crossfade: f32,
+    /// Slider positions and OSC values.
+    pub controls: SavedState,
+    /// Value, seconds since the last press and release, and press count of every button.
+    pub buttons: Vec<[f32; 4]>,
+    pub pitch_bend: f32,
+    pub pressure: f32,
+    /// Audio levels and textures, by the name of their field in `Audio`.
+    pub audio: Vec<(String, Vec<f32>)>,
+    /// Which inputs were active.
+    pub activity: InputActivity,
+    /// Whether the flash limiter was on, `None` keeps the configured setting.
+    pub flash_limiter: Option<bool>,
+    /// Strength of the LUT, `None` keeps the configured strength.
+    pub lut_strength: Option<f32>,
+}
+
+impl FrameSnapshot {
+    /// Takes a snapshot of the inputs of the frame which is being rendered.
+    pub fn capture(inputs: &RenderInputs, uniforms: &FrameUniforms, loop_origin: f32) -> Self {
+        let buttons = inputs
+            .buttons
+            .iter()
+            .map(|button| {
+                [
+                    button.0,
+                    button.1.elapsed().as_secs_f32(),
+                    button.2.elapsed().as_secs_f32(),
+                    button.3 as f32,
+                ]
+            })
+            .collect();
+
+        let audio = audio_fields!(inputs.audio, std::slice::from_ref, &)
+            .iter()
+            .map(|(name, values)| (name.to_string(), values.to_vec()))
+            .collect();
+
+        Self {
+            time: uniforms.time,
+            time_since_build: uniforms.time_since_build,
+            loop_origin,
+            beat: uniforms.beat,
+            delta: uniforms.delta,
+            frame: uniforms.frame,
+            frame_since_build: uniforms.frame_since_build,
+            fps: uniforms.fps,
+            frame_time: [uniforms.frame_time, uniforms.frame_time_max],
+            crossfade: inputs.crossfade,
+            controls: SavedState {
+                sliders: inputs.sliders.to_vec(),
+                osc_values: inputs.osc.get_all_values(),
+            },
+            buttons,
+            pitch_bend: inputs.pitch_bend,
+            pressure: inputs.pressure,
+            audio,
+            activity: inputs.activity,
+            flash_limiter: None,
+            lut_strength: None,
+        }
+    }
+
+    /// Overwrites the analysis results of `audio` with the recorded ones.
+    pub fn restore_audio(&self, audio: &mut Audio) -> Result<(), String> {
+        for (name, field) in audio_fields!(audio, std::slice::from_mut, &mut) {
+            let values = match self.audio.iter().find(|(n, _)| n == name) {
+                Some((_, values)) => values,
+                None => return Err(format!("Recorded frame is missing {:?}", name)),
+            };
+
+            if values.len() != field.len() {
+                return Err(format!(
+                    "Expected {} values for {:?}, got {}",
+                    field.len(),
+                    name,
+                    values.len()
+                ));
+            }
+
+            field.copy_from_slice(values);
+        }
+
+        Ok(())
+    }
+
+    /// Restores the slider positions and reconstructs the button timings at `now`.
+    pub fn restore_midi(
+        &self,
+        sliders: &mut [f32; MIDI_N],
+        buttons: &mut [(f32, Instant, Instant, u32); MIDI_N],
+        now: Instant,
+    ) {
+        for (slider, &value) in sliders.iter_mut().zip(self.controls.sliders.iter()) {
+            *slider = value;
+        }
+
+        let ago = |secs: f32| {
+            Duration::try_from_secs_f32(secs)
+                .ok()
+                .and_then(|d| now.checked_sub(d))
+                .unwrap_or(now)
+        };
+
+        for (button, values) in buttons.iter_mut().zip(self.buttons.iter()) {
+            *button = (values[0], ago(values[1]), ago(values[2]), values[3] as _);
+        }
+    }
+
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let number = |name: &str| match object.get(name).and_then(Value::as_f64) {
+            Some(x) => Ok(x),
+            None => Err(format!(
+                "Expected {:?} to be a number, got {:?}",
+                name,
+                object.get(name)
+            )),
+        };
+
+        let buttons = match object.get("buttons") {
+            Some(value) => {
+                let values = decode_numbers("buttons", value)?;
+                if values.len() % 4 != 0 {
+                    return Err(format!(
+                        "Expected \"buttons\" to hold four numbers per button, got {}",
+                        values.len()
+                    ));
+                }
+
+                values.chunks(4).map(|c| [c[0], c[1], c[2], c[3]]).collect()
+            }
+            None => Vec::new(),
+        };
+
+        let audio = match object.get("audio") {
+            Some(Value::Mapping(map)) => {
+                let mut audio = Vec::with_capacity(map.len());
+                for (name, value) in map {
+                    let name = match name.as_str() {
+                        Some(s) => s,
+                        None => {
+                            return Err(format!(
+                                "Expected audio field to be a string, got {:?}",
+                                name
+                            ))
+                        }
+                    };
+
+                    audio.push((name.to_owned(), decode_numbers(name, value)?));
+                }
+                audio
+            }
+            s => return Err(format!("Expected \"audio\" to be a mapping, got {:?}", s)),
+        };
+
+        let mut activity = InputActivity::default();
+        match object.get("activity") {
+            Some(Value::Sequence(names)) => {
+                for name in names {
+                    match name.as_str() {
+                        Some(name) if activity.set_active(name) => {}
+                        _ => {
+                            return Err(format!(
+                                "Expected \"activity\" to list audio, osc, spout or midi, got {:?}",
+                                name
+                            ))
+                        }
+                    }
+                }
+            }
+            None => {}
+            s => return Err(format!("Expected \"activity\" to be a list, got {:?}", s)),
+        }
+
+        let flash_limiter = match object.get("flash_limiter") {
+            Some(Value::Bool(b)) => Some(*b),
+            None => None,
+            s => {
+                return Err(format!(
+                    "Expected \"flash_limiter\" to be a bool, got {:?}",
+                    s
+                ))
+            }
+        };
+
+        let lut_strength = match object.get("lut_strength") {
+            Some(_) => Some(number("lut_strength")? as f32),
+            None => None,
+        };
+
+        Ok(Self {
+            time: number("time")? as _,
+            time_since_build: number("time_since_build")? as _,
+            loop_origin: number("loop_origin")? as _,
+            beat: number("beat")? as _,
+            delta: number("delta")? as _,
+            frame: number("frame")? as _,
+            frame_since_build: number("frame_since_build")? as _,
+            fps: number("fps")? as _,
+            frame_time: [number("frame_time")? as _, number("frame_time_max")? as _],
+            crossfade: number("crossfade")? as _,
+            controls: SavedState::from_yaml(object)?,
+            buttons,
+            pitch_bend: number("pitch_bend")? as _,
+            pressure: number("pressure")? as _,
+            audio,
+            activity,
+            flash_limiter,
+            lut_strength,
+        })
+    }
+
+    pub fn to_yaml(&self) -> Value {
+        // sliders and osc values share the mapping of the frame
+        let mut object = match self.controls.to_yaml() {
+            Value::Mapping(map) => map,
+            _ => Mapping::new(),
+        };
+
+        object.insert("time".into(), Value::from(self.time as f64));
+        object.insert(
+            "time_since_build".into(),
+            Value::from(self.time_since_build as f64),
+        );
+        object.insert("loop_origin".into(), Value::from(self.loop_origin as f64));
+        object.insert("beat".into(), Value::from(self.beat as f64));
+        object.insert("delta".into(), Value::from(self.delta as f64));
+        object.insert("frame".into(), Value::from(self.frame));
+        object.insert(
+            "frame_since_build".into(),
+            Value::from(self.frame_since_build),
+        );
+        object.insert("fps".into(), Value::from(self.fps as f64));
+        object.insert("frame_time".into(), Value::from(self.frame_time[0] as f64));
+        object.insert(
+            "frame_time_max".into(),
+            Value::from(self.frame_time[1] as f64),
+        );
+        object.insert("crossfade".into(), Value::from(self.crossfade as f64));
+
+        let activity = self.activity.names().into_iter().map(Value::from).collect();
+        object.insert("activity".into(), Value::Sequence(activity));
+
+        if let Some(enabled) = self.flash_limiter {
+            object.insert("flash_limiter".into(), Value::from(enabled));
+        }
+        if let Some(strength) = self.lut_strength {
+            object.insert("lut_strength".into(), Value::from(strength as f64));
+        }
+
+        let buttons: Vec<f32> = self.buttons.iter().flatten().copied().collect();
+        object.insert("buttons".into(), encode_numbers(&buttons));
+        object.insert("pitch_bend".into(), Value::from(self.pitch_bend as f64));
+        object.insert("pressure".into(), Value::from(self.pressure as f64));
+
+        let mut audio = Mapping::new();
+        for (name, values) in &self.audio {
+            audio.insert(Value::from(name.as_str()), encode_numbers(values));
+        }
+        object.insert("audio".into(), Value::Mapping(audio));
+
+        Value::Mapping(object)
+    }
+}
+
+/// Stores a list of numbers as a single string, which keeps sidecar files compact.
+///
+/// Floats are printed with the shortest representation which reads back to
+/// the same value, so nothing is lost.
+fn encode_numbers(values: &[f32]) -> Value {
+    let strings: Vec<_> = values.iter().map(f32::to_string).collect();
+    Value::String(strings.join(" "))
+}
+
+fn decode_numbers(name: &str, value: &Value) -> Result<Vec<f32>, String> {
+    let s = match value.as_str() {
+        Some(s) => s,
+        None => {
+            return Err(format!(
+                "Expected {:?} to be a string of numbers, got {:?}",
+                name, value
+            ))
+        }
+    };
+
+    s.split_whitespace()
+        .map(|x| {
+            x.parse()
+                .map_err(|_| format!("Expected {:?} to only hold numbers, got {:?}", name, x))
+        })
+        .collect()
+}
+
+/// Reads all snapshots of a sidecar file, in the order they were recorded.
+pub fn load_sidecar(path: &Path) -> Result<Vec<FrameSnapshot>, String> {
+    let file =
+        File::open(path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+
+    let object: Value = serde_yaml::from_reader(file)
+        .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+
+    match object {
+        Value::Sequence(frames) => frames
+            .iter()
+            .enumerate()
+            .map(|(k, frame)| {
+                FrameSnapshot::from_yaml(frame).map_err(|err| format!("Frame {}: {}", k, err))
+            })
+            .collect(),
+        Value::Null => Ok(Vec::new()),
+        s => Err(format!("Expected a list of frames, got {:?}", s)),
+    }
+}
+
+/// Records the output of a live session together with the uniforms of every frame.
+///
+/// Frames are saved as numbered png files in a new folder, and their
+/// snapshots are appended to the sidecar file in the same folder. Frames are
+/// read back asynchronously, so the recorder stays around after `stop` until
+/// the last captures arrived.
+#[derive(Debug)]
+pub struct Recorder {
+    folder: PathBuf,
+    sidecar: BufWriter<File>,
+    /// Capture frame and recording index of every frame which is still being read back.
+    pending: VecDeque<(u64, u64)>,
+    next_index: u64,
+    active: bool,
+}
+
+impl Recorder {
+    /// Creates the recording folder and its sidecar file.
+    pub fn start(folder: PathBuf) -> Result<Self, String> {
+        std::fs::create_dir_all(&folder)
+            .map_err(|err| format!("Failed to create {}: {}", folder.display(), err))?;
+
+        let path = folder.join(RECORDING_SIDECAR);
+        let mut sidecar = File::create(&path)
+            .map(BufWriter::new)
+            .map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+
+        sidecar
+            .write_all(b"# This file was automatically generated by Sh4derJockey.\n")
+            .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+
+        log::info!("Started recording into {}", folder.display());
+        Ok(Self {
+            folder,
+            sidecar,
+            pending: VecDeque::new(),
+            next_index: 0,
+            active: true,
+        })
+    }
+
+    /// Whether new frames are being recorded.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Whether recording stopped and every frame has been handed off to be saved.
+    pub fn is_finished(&self) -> bool {
+        !self.active && self.pending.is_empty()
+    }
+
+    /// Stops recording new frames.
+    pub fn stop(&mut self) {
+        if !std::mem::replace(&mut self.active, false) {
+            return;
+        }
+
+        if let Err(err) = self.sidecar.flush() {
+            log::error!("Failed to write recording sidecar: {}", err);
+        }
+
+        log::info!(
+            "Stopped recording after {} frames in {}",
+            self.next_index,
+            self.folder.display()
+        );
+    }
+
+    /// Adds the frame which is read back as `capture_frame` to the recording.
+    pub fn add_frame(&mut self, capture_frame: u64, snapshot: &FrameSnapshot) {
+        if !self.active {
+            return;
+        }
+
+        // every snapshot is a list with one element, so together they form a list
+        let entry = Value::Sequence(vec![snapshot.to_yaml()]);
+        if let Err(err) = serde_yaml::to_writer(&mut self.sidecar, &entry) {
+            log::error!("Failed to write recording sidecar: {}", err);
+            self.stop();
+            return;
+        }
+
+        self.pending.push_back((capture_frame, self.next_index));
+        self.next_index += 1;
+    }
+
+    /// Saves a captured frame if it belongs to the recording, or hands it back.
+    pub fn take_capture(&mut self, capture: CapturedFrame) -> Option<CapturedFrame> {
+        // captures which failed to map never arrive, skip over them
+        while let Some(&(frame, _)) = self.pending.front() {
+            if frame >= capture.frame {
+                break;
+            }
+            self.pending.pop_front();
+        }
+
+        let index = match self.pending.front() {
+            Some(&(frame, index)) if frame == capture.frame => index,
+            _ => return Some(capture),
+        };
+        self.pending.pop_front();
+
+        let path = self.folder.join(format!("frame-{:06}.png", index));
+        std::thread::spawn(move || {
+            if let Some(img) = capture_image(capture) {
+                if let Err(err) = img.save(&path) {
+                    log::error!("Failed to save {}: {}", path.display(), err);
+                }
+            }
+        });
+
+        None
+    }
+}
+
+/// Renders a recording again, with exactly the uniforms of the recorded frames.
+///
+/// The pipeline is loaded like in `bench_pipeline`, but every frame is fed the
+/// clock, audio, MIDI and OSC values of its snapshot instead of live input.
+/// Color handling and the flash limiter are taken from `config.yaml` in the
+/// cwd, like in the output window.
+/// Frames are rendered at `size` and saved as numbered `render-*.png` files
+/// next to the sidecar file. Returns the number of rendered frames.
+pub fn replay_recording(
+    pipeline: &Path,
+    sidecar: &Path,
+    size: (u32, u32),
+) -> Result<usize, String> {
+    // the sidecar has to be found before entering the folder of the pipeline
+    let sidecar = sidecar
+        .canonicalize()
+        .map_err(|err| format!("Failed to find {}: {}", sidecar.display(), err))?;
+    let folder = sidecar.parent().unwrap_or_else(|| Path::new("."));
+    let snapshots = load_sidecar(&sidecar)?;
+
+    // grade and limit the frames like the output window, which reads
+    // config.yaml from the cwd as well
+    let mut config = Config::load_or_default();
+    if let Some(lut) = &mut config.color.lut {
+        if let Ok(path) = lut.canonicalize() {
+            *lut = path;
+        }
+    }
+
+    let file_name = enter_pipeline_folder(pipeline)?;
+
+    let events_loop = glutin::event_loop::EventLoop::new();
+    let context = headless_context(&events_loop)?;
+
+    let (width, height) = size;
+    let mut engine = Engine::new(|s| context.get_proc_address(s) as _, width, height)
+        .with_color(&config)
+        .with_flash_limiter(&config);
+    engine.load_pipeline(file_name)?;
+
+    let target = FrameBuffer::new(width, height);
+    for (k, snapshot) in snapshots.iter().enumerate() {
+        engine
+            .render_snapshot(target.fb_id, snapshot)
+            .map_err(|err| format!("Frame {}: {}", k, err))?;
+
+        // nothing else is waiting for the gpu, so the frame is read back right away
+        let mut pixels = vec![0; 3 * width as usize * height as usize];
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target.fb_id);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                width as _,
+                height as _,
+                gl::RGB,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as _,
+            );
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            gl_debug_check!();
+        }
+
+        let capture = CapturedFrame {
+            frame: k as _,
+            width,
+            height,
+            pixels,
+        };
+
+        let path = folder.join(format!("render-{:06}.png", k));
+        match capture_image(capture) {
+            Some(img) => img
+                .save(&path)
+                .map_err(|err| format!("Failed to save {}: {}", path.display(), err))?,
+            None => return Err(format!("Failed to read back frame {}", k)),
+        }
+    }
+
+    Ok(snapshots.len())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::jockey::OscUniformValue;
+
+    #[test]
+    fn numbers_round_trip() {
+        let values = [0.0, -1.5, 0.1, 1e-7, f32::MAX, 1.0 / 3.0];
+        let decoded = decode_numbers("values", &encode_numbers(&values)).unwrap();
+        assert_eq!(decoded, values);
+        assert_eq!(
+            decode_numbers("empty", &encode_numbers(&[])).unwrap(),
+            vec![]
+        );
+        assert!(decode_numbers("values", &Value::from("1 two 3")).is_err());
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut osc_values = HashMap::new();
+        osc_values.insert("speed".to_owned(), OscUniformValue::Float(0.3));
+        osc_values.insert("strobe".to_owned(), OscUniformValue::Bool(true));
+
+        let snapshot = FrameSnapshot {
+            time: 12.345,
+            time_since_build: 2.5,
+            loop_origin: 8.0,
+            beat: 3.25,
+            delta: 1.0 / 60.0,
+            frame: 740,
+            frame_since_build: 150,
+            fps: 59.5,
+            frame_time: [16.8, 21.25],
+            crossfade: 0.75,
+            controls: SavedState {
+                sliders: vec![0.1, 0.9],
+                osc_values,
+            },
+            buttons: vec![[1.0, 0.25, 3.5, 4.0], [0.0, 12.0, 11.5, 1.0]],
+            pitch_bend: -0.25,
+            pressure: 0.625,
+            audio: vec![
+                ("bass".to_owned(), vec![0.5, 0.4, 0.6]),
+                ("pitch".to_owned(), vec![440.0]),
+            ],
+            activity: InputActivity {
+                audio: true,
+                midi: true,
+                ..Default::default()
+            },
+            flash_limiter: Some(true),
+            lut_strength: Some(0.5),
+        };
+
+        let text = serde_yaml::to_string(&Value::Sequence(vec![snapshot.to_yaml()])).unwrap();
+        let twice = format!("# comment\n{}{}", text, text);
+        let frames: Value = serde_yaml::from_str(&twice).unwrap();
+
+        match frames {
+            Value::Sequence(frames) => {
+                assert_eq!(frames.len(), 2);
+                for frame in frames.iter() {
+                    assert_eq!(FrameSnapshot::from_yaml(frame).unwrap(), snapshot);
+                }
+            }
+            s => panic!("Expected a list of frames, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn snapshot_needs_audio() {
+        let mut object = FrameSnapshot::default().to_yaml();
+        assert!(FrameSnapshot::from_yaml(&object).is_ok());
+
+        if let Value::Mapping(map) = &mut object {
+            map.remove("audio");
+        }
+        assert!(FrameSnapshot::from_yaml(&object).is_err());
+    }
+}
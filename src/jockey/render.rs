@@ -0,0 +1,1334 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    mem::MaybeUninit,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use gl::types::*;
+
+use crate::jockey::*;
+
+/// Per-frame values which are shared by the stages of both decks.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameUniforms {
+    pub width: u32,
+    pub height: u32,
+    pub beat: f32,
+    pub time: f32,
+    pub time_since_build: f32,
+    pub loop_time: f32,
+    pub delta: f32,
+    pub frame: u32,
+    pub frame_since_build: u32,
+    pub fps: f32,
+    /// Average frame time of the last second, in milliseconds.
+    pub frame_time: f32,
+    /// Longest frame time of the last second, in milliseconds.
+    pub frame_time_max: f32,
+}
+
+/// Time without new data after which an input no longer counts as active.
+pub const INPUT_ACTIVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Which inputs are running and received data within `INPUT_ACTIVE_TIMEOUT`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InputActivity {
+    pub audio: bool,
+    pub osc: bool,
+    pub spout: bool,
+    pub midi: bool,
+}
+
+impl InputActivity {
+    /// Collects the state of all inputs, a missing module counts as inactive.
+    pub fn new(
+        audio: &Audio,
+        osc: &OscReceiver,
+        spout: Option<&SpoutReceiver>,
+        midi: Option<&Midi>,
+    ) -> Self {
+        Self {
+            audio: audio.is_active(),
+            osc: osc.is_active(),
+            spout: spout.map_or(false, SpoutReceiver::is_connected),
+            midi: midi.map_or(false, Midi::is_active),
+        }
+    }
+
+    /// Names of the active inputs, in the order of the uniforms.
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.audio, "audio"),
+            (self.osc, "osc"),
+            (self.spout, "spout"),
+            (self.midi, "midi"),
+        ]
+        .iter()
+        .filter(|(active, _)| *active)
+        .map(|&(_, name)| name)
+        .collect()
+    }
+
+    /// Marks the input with the given name as active, returns false for unknown names.
+    pub fn set_active(&mut self, name: &str) -> bool {
+        match name {
+            "audio" => self.audio = true,
+            "osc" => self.osc = true,
+            "spout" => self.spout = true,
+            "midi" => self.midi = true,
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+/// Returns how far `time` is into the current loop, from 0 up to 1.
+///
+/// Loops start at `origin` and repeat every `period` seconds, also for times
+/// before the origin, so the phase keeps wrapping when time runs backwards.
+pub fn loop_phase(time: f32, origin: f32, period: f32) -> f32 {
+    ((time - origin) / period).rem_euclid(1.0)
+}
+
+/// Returns the rectangle a stage draws into, clipped to its target.
+///
+/// Rectangles are `[x, y, width, height]` in pixels, without a viewport the
+/// whole target is used. Fixed size targets are checked when the pipeline is
+/// built, this only matters for targets which follow the window size.
+pub fn clip_viewport(viewport: Option<[u32; 4]>, width: u32, height: u32) -> [u32; 4] {
+    match viewport {
+        Some([x, y, w, h]) => {
+            let x = x.min(width);
+            let y = y.min(height);
+            [x, y, w.min(width - x), h.min(height - y)]
+        }
+        None => [0, 0, width, height],
+    }
+}
+
+/// Whether the pipeline uploads `name` through one of its OSC mappings.
+fn is_osc_mapped(osc_config: Option<&OscConfig>, name: &str) -> bool {
+    osc_config.map_or(false, |config| config.mappings.contains_key(name))
+}
+
+/// Uniforms of custom uniform sources which have no OSC mapping.
+///
+/// The names and their locations in every program are only looked up again
+/// when a source provides a new name.
+#[derive(Debug, Default)]
+pub struct SourceUniforms {
+    /// Number of provided names the list was built from.
+    seen: usize,
+    /// Name, uniform name and location by program of every uniform.
+    uniforms: Vec<(String, CString, HashMap<GLuint, GLint>)>,
+}
+
+impl SourceUniforms {
+    /// Rebuilds the list if sources provided new names since the last call.
+    fn refresh(&mut self, provided: &HashSet<String>, osc_config: Option<&OscConfig>) {
+        // names are never removed, so a new name always changes the count
+        if provided.len() == self.seen {
+            return;
+        }
+
+        self.seen = provided.len();
+        self.uniforms = provided
+            .iter()
+            .filter(|name| !is_osc_mapped(osc_config, name))
+            .filter_map(|name| {
+                let uniform_cstr = CString::new(name.as_str()).ok()?;
+                Some((name.clone(), uniform_cstr, HashMap::new()))
+            })
+            .collect();
+    }
+
+    /// Uploads the current values to `prog_id`, which has to be in use.
+    fn upload(&mut self, prog_id: GLuint, osc_values: &HashMap<String, OscUniformValue>) {
+        for (name, uniform_cstr, locations) in self.uniforms.iter_mut() {
+            let value = match osc_values.get(name.as_str()) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let loc = *locations.entry(prog_id).or_insert_with(|| unsafe {
+                gl::GetUniformLocation(prog_id, uniform_cstr.as_ptr())
+            });
+            if loc != -1 {
+                upload_osc_value(loc, value, None);
+            }
+        }
+    }
+}
+
+/// Everything besides the pipeline which feeds the uniforms of a frame.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderInputs<'a> {
+    pub vao: GLuint,
+    pub audio: &'a Audio,
+    pub sliders: &'a [f32; MIDI_N],
+    pub buttons: &'a [(f32, Instant, Instant, u32); MIDI_N],
+    pub pitch_bend: f32,
+    pub pressure: f32,
+    pub osc: &'a OscReceiver,
+    pub ndi: Option<&'a Ndi>,
+    pub spout: Option<&'a SpoutReceiver>,
+    pub crossfade: f32,
+    pub activity: InputActivity,
+}
+
+/// Lists the uniforms a frame feeds to the shaders, by name.
+///
+/// Every value is annotated with its source, which is one of `time`,
+/// `engine`, `midi`, `audio`, `osc`, `source` or `param`. Custom uniforms of the stages
+/// are listed together with the stage which sets them. The resolution is
+/// the one of the screen, stages with their own target see their own size.
+pub fn dump_uniforms(
+    pipeline: &Pipeline,
+    inputs: &RenderInputs,
+    uniforms: &FrameUniforms,
+) -> HashMap<String, String> {
+    fn vec3(v: [f32; 3]) -> String {
+        format!("vec3({}, {}, {})", v[0], v[1], v[2])
+    }
+
+    let mut dump = HashMap::new();
+    let mut add = |name: &str, value: String, source: &str| {
+        dump.insert(name.to_owned(), format!("{} ({})", value, source));
+    };
+
+    let (w, h) = (uniforms.width as f32, uniforms.height as f32);
+    add(
+        "resolution",
+        format!("vec4({}, {}, {}, {})", w, h, w / h, h / w),
+        "engine",
+    );
+    add("time", uniforms.time.to_string(), "time");
+    add(
+        "time_since_build",
+        uniforms.time_since_build.to_string(),
+        "time",
+    );
+    add("loop_time", uniforms.loop_time.to_string(), "time");
+    add("time_delta", uniforms.delta.to_string(), "time");
+    add("beat", uniforms.beat.to_string(), "time");
+    add("frame_count", uniforms.frame.to_string(), "time");
+    add(
+        "frame_count_since_build",
+        uniforms.frame_since_build.to_string(),
+        "time",
+    );
+    add("fps", uniforms.fps.to_string(), "engine");
+    add("frame_time_ms", uniforms.frame_time.to_string(), "engine");
+    add(
+        "frame_time_max_ms",
+        uniforms.frame_time_max.to_string(),
+        "engine",
+    );
+    add("crossfade", inputs.crossfade.to_string(), "engine");
+    add("seed", seed_to_float(pipeline.seed).to_string(), "engine");
+    add(
+        "osc_connected",
+        inputs.osc.is_connected().unwrap_or(true).to_string(),
+        "osc",
+    );
+    add("audio_active", inputs.activity.audio.to_string(), "audio");
+    add("osc_active", inputs.activity.osc.to_string(), "osc");
+    add("spout_active", inputs.activity.spout.to_string(), "engine");
+    add("midi_active", inputs.activity.midi.to_string(), "midi");
+
+    for (k, slider) in inputs.sliders.iter().enumerate() {
+        add(&format!("sliders[{}]", k), slider.to_string(), "midi");
+    }
+
+    for (k, button) in inputs.buttons.iter().enumerate() {
+        let value = format!(
+            "vec4({}, {}, {}, {})",
+            button.0,
+            button.1.elapsed().as_secs_f32(),
+            button.2.elapsed().as_secs_f32(),
+            button.3
+        );
+        add(&format!("buttons[{}]", k), value, "midi");
+    }
+
+    add("pitch_bend", inputs.pitch_bend.to_string(), "midi");
+    add("pressure", inputs.pressure.to_string(), "midi");
+
+    let audio = inputs.audio;
+    let levels = [
+        ("volume", audio.volume),
+        ("volume_integrated", audio.volume_integrated),
+        ("volume_peak", audio.volume_peak),
+        ("bass", audio.bass),
+        ("bass_smooth", audio.bass_smooth),
+        ("bass_integrated", audio.bass_integrated),
+        ("bass_smooth_integrated", audio.bass_smooth_integrated),
+        ("bass_peak", audio.bass_peak),
+        ("mid", audio.mid),
+        ("mid_smooth", audio.mid_smooth),
+        ("mid_integrated", audio.mid_integrated),
+        ("mid_smooth_integrated", audio.mid_smooth_integrated),
+        ("mid_peak", audio.mid_peak),
+        ("high", audio.high),
+        ("high_smooth", audio.high_smooth),
+        ("high_integrated", audio.high_integrated),
+        ("high_smooth_integrated", audio.high_smooth_integrated),
+        ("high_peak", audio.high_peak),
+        ("bands_left", [audio.bass[1], audio.mid[1], audio.high[1]]),
+        ("bands_right", [audio.bass[2], audio.mid[2], audio.high[2]]),
+        ("centroid", audio.centroid),
+        ("spread", audio.spread),
+        ("centroid_smooth", audio.centroid_smooth),
+        ("spread_smooth", audio.spread_smooth),
+    ];
+
+    for &(name, level) in levels.iter() {
+        add(name, vec3(level), "audio");
+    }
+    add(
+        "stereo_correlation",
+        audio.stereo_correlation.to_string(),
+        "audio",
+    );
+    add("stereo_width", audio.stereo_width.to_string(), "audio");
+    add("pitch_hz", audio.pitch.to_string(), "audio");
+    add("note", audio.note.to_string(), "audio");
+    add(
+        "pitch_confidence",
+        audio.pitch_confidence.to_string(),
+        "audio",
+    );
+    add("density", audio.density.to_string(), "audio");
+
+    if let Some(osc_config) = &pipeline.osc_config {
+        let osc_values = inputs.osc.get_all_values();
+        for (name, mapping) in &osc_config.mappings {
+            let value = match (osc_values.get(name), &mapping.data_type) {
+                (Some(value), _) => value.to_string(),
+                // rotations are stored as the elements of a quaternion
+                (None, OscDataType::Rotation(_)) => match inputs.osc.get_vec4(name) {
+                    Some([x, y, z, w]) => format!("quaternion({}, {}, {}, {})", x, y, z, w),
+                    None => "unset".into(),
+                },
+                (None, _) => "unset".into(),
+            };
+            add(name, value, &format!("osc {}", mapping.address));
+        }
+    }
+
+    let osc_values = inputs.osc.get_all_values();
+    for name in inputs.osc.provided_names() {
+        if is_osc_mapped(pipeline.osc_config.as_ref(), name) {
+            continue;
+        }
+
+        if let Some(value) = osc_values.get(name) {
+            add(name, value.to_string(), "source");
+        }
+    }
+
+    for stage in pipeline.stages.iter() {
+        for (name, uniform) in &stage.unis {
+            let value = match uniform {
+                Uniform::Float(x) => x.to_string(),
+                Uniform::Vec2(x, y) => format!("vec2({}, {})", x, y),
+                Uniform::Vec3(x, y, z) => format!("vec3({}, {}, {})", x, y, z),
+                Uniform::Vec4(x, y, z, w) => format!("vec4({}, {}, {}, {})", x, y, z, w),
+                matrix => format!("{:?}", matrix),
+            };
+
+            // stages may give the same uniform different values
+            let name = format!("{} [{}]", name.to_string_lossy(), stage.label());
+            add(&name, value, "param");
+        }
+    }
+
+    if !pipeline.uniform_blocks.is_empty() {
+        let osc_values = inputs.osc.get_all_values();
+        for block in pipeline.uniform_blocks.iter() {
+            for field in block.fields.iter() {
+                let values: Vec<_> = field
+                    .values
+                    .iter()
+                    .map(|param| param.get(inputs.sliders, &osc_values).to_string())
+                    .collect();
+                let kind = glsl_type_name(field.kind.gl_kind());
+                let value = format!("{}({})", kind, values.join(", "));
+                let name = format!("{}.{}", block.name.to_string_lossy(), field.name);
+                add(&name, value, "param");
+            }
+        }
+    }
+
+    dump
+}
+
+/// Renders all stages of the current pipeline.
+///
+/// Stages without a target draw into `screen_fb`, which is the default
+/// framebuffer unless the pipeline is one of two decks being mixed.
+pub fn render_stages(
+    pipeline: &mut Pipeline,
+    inputs: &RenderInputs,
+    screen_fb: GLuint,
+    uniforms: &FrameUniforms,
+) {
+    let FrameUniforms {
+        width,
+        height,
+        beat,
+        time,
+        time_since_build,
+        loop_time,
+        delta,
+        frame,
+        frame_since_build,
+        fps,
+        frame_time,
+        frame_time_max,
+    } = *uniforms;
+
+    // without a heartbeat, there is nothing to lose the connection to
+    let osc_connected = inputs.osc.is_connected().unwrap_or(true);
+
+    // decks may disagree on blending
+    unsafe {
+        match pipeline.blending {
+            true => gl::Enable(gl::BLEND),
+            false => gl::Disable(gl::BLEND),
+        }
+        gl_debug_check!();
+    }
+
+    {
+        // update audio and video textures
+        fn audio_tex_update(
+            buffers: &mut HashMap<CString, Rc<dyn Texture>>,
+            name: &CString,
+            left: &[f32],
+            right: &[f32],
+        ) {
+            if let Some(tex) = buffers.get_mut(name) {
+                unsafe {
+                    alloca::with_slice(left.len() + right.len(), |buffer| {
+                        let buffer = &mut *(buffer as *mut [MaybeUninit<f32>] as *mut _);
+
+                        interlace(left, right, buffer);
+                        Rc::get_mut(tex)
+                            .unwrap()
+                            .as_any_mut()
+                            .downcast_mut::<Texture1D>()
+                            .unwrap()
+                            .write(buffer.as_ptr() as _);
+                    })
+                }
+            }
+        }
+
+        for (tex_name, src_name) in pipeline.requested_ndi_sources.iter() {
+            let tex = pipeline.buffers.get_mut(tex_name).unwrap();
+            let tex = Rc::get_mut(tex)
+                .unwrap()
+                .as_any_mut()
+                .downcast_mut::<Texture2D>()
+                .unwrap();
+            if let Some(ndi) = inputs.ndi {
+                let alpha = pipeline
+                    .ndi_alpha
+                    .get(tex_name)
+                    .copied()
+                    .unwrap_or_default();
+                ndi.update_texture(src_name, alpha, tex);
+            }
+        }
+
+        if let Some(tex_name) = &pipeline.requested_spout_texture {
+            let tex = pipeline.buffers.get_mut(tex_name).unwrap();
+            let alpha = pipeline
+                .spout_config
+                .as_ref()
+                .map_or(AlphaMode::Straight, |config| config.receive_alpha);
+            match (&mut pipeline.spout_fit, inputs.spout) {
+                (Some((fit, staging)), Some(spout)) => {
+                    spout.update_texture(inputs.vao, staging, alpha);
+                    let target = (**tex).as_any().downcast_ref::<FrameBuffer>().unwrap();
+                    fit_frame(staging, target, *fit);
+                }
+                (None, Some(spout)) => {
+                    let tex = Rc::get_mut(tex)
+                        .unwrap()
+                        .as_any_mut()
+                        .downcast_mut::<Texture2D>()
+                        .unwrap();
+                    spout.update_texture(inputs.vao, tex, alpha);
+                }
+                _ => (),
+            }
+        }
+
+        audio_tex_update(
+            &mut pipeline.buffers,
+            &SAMPLES_NAME,
+            &inputs.audio.l_signal,
+            &inputs.audio.r_signal,
+        );
+        audio_tex_update(
+            &mut pipeline.buffers,
+            &SPECTRUM_RAW_NAME,
+            &inputs.audio.l_binned_spectrum,
+            &inputs.audio.r_binned_spectrum,
+        );
+        audio_tex_update(
+            &mut pipeline.buffers,
+            &SPECTRUM_NAME,
+            &inputs.audio.l_spectrum,
+            &inputs.audio.r_spectrum,
+        );
+        audio_tex_update(
+            &mut pipeline.buffers,
+            &SPECTRUM_SMOOTH_NAME,
+            &inputs.audio.l_spectrum_smooth,
+            &inputs.audio.r_spectrum_smooth,
+        );
+        audio_tex_update(
+            &mut pipeline.buffers,
+            &SPECTRUM_SMOOTH_INTEGRATED_NAME,
+            &inputs.audio.l_spectrum_smooth_integrated,
+            &inputs.audio.r_spectrum_smooth_integrated,
+        );
+        audio_tex_update(
+            &mut pipeline.buffers,
+            &SPECTRUM_INTEGRATED_NAME,
+            &inputs.audio.l_spectrum_integrated,
+            &inputs.audio.r_spectrum_integrated,
+        );
+    }
+
+    // one snapshot of the OSC values serves all stages
+    let osc_values = inputs.osc.get_all_values();
+    pipeline
+        .source_uniforms
+        .refresh(inputs.osc.provided_names(), pipeline.osc_config.as_ref());
+
+    // copy OSC values into uniform arrays
+    if !pipeline.osc_arrays.is_empty() {
+        for array in pipeline.osc_arrays.iter_mut() {
+            array.update(&osc_values);
+        }
+    }
+
+    // upload uniform blocks which changed, the other deck may have bound its own
+    if !pipeline.uniform_blocks.is_empty() {
+        for block in pipeline.uniform_blocks.iter_mut() {
+            block.update(inputs.sliders, &osc_values);
+            block.upload();
+        }
+    }
+
+    // errors from before the first stage are not the fault of any stage
+    if pipeline.gl_checks {
+        gl_ignore!();
+    }
+
+    // conditions of the stages read the controls of this frame
+    let buttons = inputs.buttons.map(|button| button.0);
+    let condition_values = match pipeline.stages.iter().any(|stage| stage.when.is_some()) {
+        true => inputs.osc.get_all_values(),
+        false => HashMap::new(),
+    };
+
+    // clear the final target, feedback outputs are never given a background
+    if let Some([r, g, b, a]) = pipeline.background {
+        let fb_id = match &pipeline.output {
+            Some(name) => pipeline
+                .buffers
+                .get(name)
+                .unwrap()
+                .framebuffer_id()
+                .expect("Output should be a framebuffer"),
+            None => screen_fb,
+        };
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fb_id);
+            gl::ClearColor(r, g, b, a);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl_debug_check!();
+        }
+    }
+
+    // render all shader stages
+    for (pass_num, stage) in pipeline.stages.iter_mut().enumerate() {
+        let stage_start = Instant::now();
+
+        // skip stage if target is never used
+        if !matches!(stage.kind, StageKind::Comp { .. }) {
+            if let Some(name) = &stage.target {
+                if pipeline.buffers.get(name).is_none() {
+                    continue;
+                }
+            }
+        }
+
+        // nothing drawn to the screen would be seen behind the output
+        if stage.target.is_none() && pipeline.output.is_some() {
+            continue;
+        }
+
+        // disabled stages keep their last output, unless asked to clear it,
+        // and so do stages whose condition does not hold in this frame
+        let active = stage.enabled
+            && stage.when.as_ref().map_or(true, |when| {
+                when.evaluate(inputs.sliders, &buttons, &condition_values)
+            });
+        if !active {
+            if stage.when_disabled == DisabledOutput::Clear {
+                unsafe {
+                    gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+                    if stage.target.is_none() {
+                        gl::BindFramebuffer(gl::FRAMEBUFFER, screen_fb);
+                        gl::Clear(gl::COLOR_BUFFER_BIT);
+                    }
+
+                    // images written by compute shaders have no framebuffer to clear
+                    for name in stage.target.iter().chain(stage.attachments.iter()) {
+                        let tex = pipeline.buffers.get(name).unwrap();
+                        if let Some(fb_id) = tex.framebuffer_id() {
+                            gl::BindFramebuffer(gl::FRAMEBUFFER, fb_id);
+                            gl::Clear(gl::COLOR_BUFFER_BIT);
+                            tex.swap();
+                        }
+                    }
+                    gl_debug_check!();
+                }
+            }
+            continue;
+        }
+
+        // get size of the render target
+        let target_res = match stage.resolution() {
+            Some(s) => s,
+            _ => [width, height, 0],
+        };
+        let viewport = clip_viewport(stage.viewport, target_res[0], target_res[1]);
+
+        // start measuring the gpu time of this stage
+        if let Some(timer) = &mut stage.gpu_timer {
+            if let Some(gpu_ms) = timer.begin() {
+                stage.gpu_perf.push(gpu_ms);
+            }
+        }
+
+        unsafe {
+            // Use shader program
+            gl::UseProgram(stage.prog_id);
+            gl_debug_check!();
+
+            {
+                // Add time, beat, resolution and volume
+                let r_loc = gl::GetUniformLocation(stage.prog_id, R_NAME.as_ptr());
+                let k_loc = gl::GetUniformLocation(stage.prog_id, K_NAME.as_ptr());
+                let res_loc = gl::GetUniformLocation(stage.prog_id, RESOLUTION_NAME.as_ptr());
+                let i_res_loc = gl::GetUniformLocation(stage.prog_id, I_RESOLUTION_NAME.as_ptr());
+                let pass_loc = gl::GetUniformLocation(stage.prog_id, PASS_INDEX_NAME.as_ptr());
+                let seed_loc = gl::GetUniformLocation(stage.prog_id, SEED_NAME.as_ptr());
+                let pass_seed_loc = gl::GetUniformLocation(stage.prog_id, PASS_SEED_NAME.as_ptr());
+                let viewport_loc = gl::GetUniformLocation(stage.prog_id, VIEWPORT_NAME.as_ptr());
+                let time_loc = gl::GetUniformLocation(stage.prog_id, TIME_NAME.as_ptr());
+                let time_since_build_loc =
+                    gl::GetUniformLocation(stage.prog_id, TIME_SINCE_BUILD_NAME.as_ptr());
+                let loop_time_loc = gl::GetUniformLocation(stage.prog_id, LOOP_TIME_NAME.as_ptr());
+                let frame_loc = gl::GetUniformLocation(stage.prog_id, FRAME_COUNT_NAME.as_ptr());
+                let frame_since_build_loc =
+                    gl::GetUniformLocation(stage.prog_id, FRAME_COUNT_SINCE_BUILD_NAME.as_ptr());
+                let delta_loc = gl::GetUniformLocation(stage.prog_id, TIME_DELTA_NAME.as_ptr());
+                let fps_loc = gl::GetUniformLocation(stage.prog_id, FPS_NAME.as_ptr());
+                let frame_time_loc =
+                    gl::GetUniformLocation(stage.prog_id, FRAME_TIME_NAME.as_ptr());
+                let frame_time_max_loc =
+                    gl::GetUniformLocation(stage.prog_id, FRAME_TIME_MAX_NAME.as_ptr());
+                let crossfade_loc = gl::GetUniformLocation(stage.prog_id, CROSSFADE_NAME.as_ptr());
+                let osc_connected_loc =
+                    gl::GetUniformLocation(stage.prog_id, OSC_CONNECTED_NAME.as_ptr());
+                let audio_active_loc =
+                    gl::GetUniformLocation(stage.prog_id, AUDIO_ACTIVE_NAME.as_ptr());
+                let osc_active_loc =
+                    gl::GetUniformLocation(stage.prog_id, OSC_ACTIVE_NAME.as_ptr());
+                let spout_active_loc =
+                    gl::GetUniformLocation(stage.prog_id, SPOUT_ACTIVE_NAME.as_ptr());
+                let midi_active_loc =
+                    gl::GetUniformLocation(stage.prog_id, MIDI_ACTIVE_NAME.as_ptr());
+                let beat_loc = gl::GetUniformLocation(stage.prog_id, BEAT_NAME.as_ptr());
+                let volume_loc = gl::GetUniformLocation(stage.prog_id, VOLUME_NAME.as_ptr());
+                let volume_integrated_loc =
+                    gl::GetUniformLocation(stage.prog_id, VOLUME_INTEGRATED_NAME.as_ptr());
+                let bass_loc = gl::GetUniformLocation(stage.prog_id, BASS_NAME.as_ptr());
+                let mid_loc = gl::GetUniformLocation(stage.prog_id, MID_NAME.as_ptr());
+                let high_loc = gl::GetUniformLocation(stage.prog_id, HIGH_NAME.as_ptr());
+                let smooth_bass_loc =
+                    gl::GetUniformLocation(stage.prog_id, BASS_SMOOTH_NAME.as_ptr());
+                let smooth_mid_loc =
+                    gl::GetUniformLocation(stage.prog_id, MID_SMOOTH_NAME.as_ptr());
+                let smooth_high_loc =
+                    gl::GetUniformLocation(stage.prog_id, HIGH_SMOOTH_NAME.as_ptr());
+
+                let bass_integrated_loc =
+                    gl::GetUniformLocation(stage.prog_id, BASS_INTEGRATED_NAME.as_ptr());
+                let mid_integrated_loc =
+                    gl::GetUniformLocation(stage.prog_id, MID_INTEGRATED_NAME.as_ptr());
+                let high_integrated_loc =
+                    gl::GetUniformLocation(stage.prog_id, HIGH_INTEGRATED_NAME.as_ptr());
+                let smooth_bass_integrated_loc =
+                    gl::GetUniformLocation(stage.prog_id, BASS_SMOOTH_INTEGRATED_NAME.as_ptr());
+                let smooth_mid_integrated_loc =
+                    gl::GetUniformLocation(stage.prog_id, MID_SMOOTH_INTEGRATED_NAME.as_ptr());
+                let smooth_high_integrated_loc =
+                    gl::GetUniformLocation(stage.prog_id, HIGH_SMOOTH_INTEGRATED_NAME.as_ptr());
+
+                let volume_peak_loc =
+                    gl::GetUniformLocation(stage.prog_id, VOLUME_PEAK_NAME.as_ptr());
+                let bass_peak_loc = gl::GetUniformLocation(stage.prog_id, BASS_PEAK_NAME.as_ptr());
+                let mid_peak_loc = gl::GetUniformLocation(stage.prog_id, MID_PEAK_NAME.as_ptr());
+                let high_peak_loc = gl::GetUniformLocation(stage.prog_id, HIGH_PEAK_NAME.as_ptr());
+
+                let bands_left_loc =
+                    gl::GetUniformLocation(stage.prog_id, BANDS_LEFT_NAME.as_ptr());
+                let bands_right_loc =
+                    gl::GetUniformLocation(stage.prog_id, BANDS_RIGHT_NAME.as_ptr());
+                let correlation_loc =
+                    gl::GetUniformLocation(stage.prog_id, STEREO_CORRELATION_NAME.as_ptr());
+                let width_loc = gl::GetUniformLocation(stage.prog_id, STEREO_WIDTH_NAME.as_ptr());
+                let pitch_loc = gl::GetUniformLocation(stage.prog_id, PITCH_NAME.as_ptr());
+                let note_loc = gl::GetUniformLocation(stage.prog_id, NOTE_NAME.as_ptr());
+                let pitch_confidence_loc =
+                    gl::GetUniformLocation(stage.prog_id, PITCH_CONFIDENCE_NAME.as_ptr());
+                let centroid_loc = gl::GetUniformLocation(stage.prog_id, CENTROID_NAME.as_ptr());
+                let spread_loc = gl::GetUniformLocation(stage.prog_id, SPREAD_NAME.as_ptr());
+                let smooth_centroid_loc =
+                    gl::GetUniformLocation(stage.prog_id, CENTROID_SMOOTH_NAME.as_ptr());
+                let smooth_spread_loc =
+                    gl::GetUniformLocation(stage.prog_id, SPREAD_SMOOTH_NAME.as_ptr());
+                let density_loc = gl::GetUniformLocation(stage.prog_id, DENSITY_NAME.as_ptr());
+
+                let (res_x, res_y) = (target_res[0] as f32, target_res[1] as f32);
+                match stage.resolution_kind {
+                    gl::FLOAT_VEC3 => gl::Uniform3f(res_loc, res_x, res_y, res_x / res_y),
+                    _ => gl::Uniform4f(res_loc, res_x, res_y, res_x / res_y, res_y / res_x),
+                }
+                gl::Uniform3f(i_res_loc, res_x, res_y, res_x / res_y);
+                gl::Uniform3f(r_loc, target_res[0] as _, target_res[1] as _, time);
+                gl::Uniform3f(
+                    volume_loc,
+                    inputs.audio.volume[0], // average L/R
+                    inputs.audio.volume[1], // L
+                    inputs.audio.volume[2], // R
+                );
+                gl::Uniform3f(
+                    bass_loc,
+                    inputs.audio.bass[0],
+                    inputs.audio.bass[1],
+                    inputs.audio.bass[2],
+                );
+                gl::Uniform3f(
+                    mid_loc,
+                    inputs.audio.mid[0],
+                    inputs.audio.mid[1],
+                    inputs.audio.mid[2],
+                );
+                gl::Uniform3f(
+                    high_loc,
+                    inputs.audio.high[0],
+                    inputs.audio.high[1],
+                    inputs.audio.high[2],
+                );
+                gl::Uniform3f(
+                    bands_left_loc,
+                    inputs.audio.bass[1],
+                    inputs.audio.mid[1],
+                    inputs.audio.high[1],
+                );
+                gl::Uniform3f(
+                    bands_right_loc,
+                    inputs.audio.bass[2],
+                    inputs.audio.mid[2],
+                    inputs.audio.high[2],
+                );
+                gl::Uniform1f(correlation_loc, inputs.audio.stereo_correlation);
+                gl::Uniform1f(width_loc, inputs.audio.stereo_width);
+                gl::Uniform1f(pitch_loc, inputs.audio.pitch);
+                gl::Uniform1f(note_loc, inputs.audio.note);
+                gl::Uniform1f(pitch_confidence_loc, inputs.audio.pitch_confidence);
+                gl::Uniform1f(density_loc, inputs.audio.density);
+                for &(loc, value) in [
+                    (centroid_loc, &inputs.audio.centroid),
+                    (spread_loc, &inputs.audio.spread),
+                    (smooth_centroid_loc, &inputs.audio.centroid_smooth),
+                    (smooth_spread_loc, &inputs.audio.spread_smooth),
+                ]
+                .iter()
+                {
+                    gl::Uniform3f(loc, value[0], value[1], value[2]);
+                }
+                gl::Uniform3f(
+                    smooth_bass_loc,
+                    inputs.audio.bass_smooth[0],
+                    inputs.audio.bass_smooth[1],
+                    inputs.audio.bass_smooth[2],
+                );
+                gl::Uniform3f(
+                    smooth_mid_loc,
+                    inputs.audio.mid_smooth[0],
+                    inputs.audio.mid_smooth[1],
+                    inputs.audio.mid_smooth[2],
+                );
+                gl::Uniform3f(
+                    smooth_high_loc,
+                    inputs.audio.high_smooth[0],
+                    inputs.audio.high_smooth[1],
+                    inputs.audio.high_smooth[2],
+                );
+                gl::Uniform3f(
+                    volume_integrated_loc,
+                    inputs.audio.volume_integrated[0], // average L/R
+                    inputs.audio.volume_integrated[1], // L
+                    inputs.audio.volume_integrated[2], // R
+                );
+                gl::Uniform3f(
+                    bass_integrated_loc,
+                    inputs.audio.bass_integrated[0],
+                    inputs.audio.bass_integrated[1],
+                    inputs.audio.bass_integrated[2],
+                );
+                gl::Uniform3f(
+                    mid_integrated_loc,
+                    inputs.audio.mid_integrated[0],
+                    inputs.audio.mid_integrated[1],
+                    inputs.audio.mid_integrated[2],
+                );
+                gl::Uniform3f(
+                    high_integrated_loc,
+                    inputs.audio.high_integrated[0],
+                    inputs.audio.high_integrated[1],
+                    inputs.audio.high_integrated[2],
+                );
+                gl::Uniform3f(
+                    smooth_bass_integrated_loc,
+                    inputs.audio.bass_smooth_integrated[0],
+                    inputs.audio.bass_smooth_integrated[1],
+                    inputs.audio.bass_smooth_integrated[2],
+                );
+                gl::Uniform3f(
+                    smooth_mid_integrated_loc,
+                    inputs.audio.mid_smooth_integrated[0],
+                    inputs.audio.mid_smooth_integrated[1],
+                    inputs.audio.mid_smooth_integrated[2],
+                );
+                gl::Uniform3f(
+                    smooth_high_integrated_loc,
+                    inputs.audio.high_smooth_integrated[0],
+                    inputs.audio.high_smooth_integrated[1],
+                    inputs.audio.high_smooth_integrated[2],
+                );
+                gl::Uniform3f(
+                    volume_peak_loc,
+                    inputs.audio.volume_peak[0],
+                    inputs.audio.volume_peak[1],
+                    inputs.audio.volume_peak[2],
+                );
+                gl::Uniform3f(
+                    bass_peak_loc,
+                    inputs.audio.bass_peak[0],
+                    inputs.audio.bass_peak[1],
+                    inputs.audio.bass_peak[2],
+                );
+                gl::Uniform3f(
+                    mid_peak_loc,
+                    inputs.audio.mid_peak[0],
+                    inputs.audio.mid_peak[1],
+                    inputs.audio.mid_peak[2],
+                );
+                gl::Uniform3f(
+                    high_peak_loc,
+                    inputs.audio.high_peak[0],
+                    inputs.audio.high_peak[1],
+                    inputs.audio.high_peak[2],
+                );
+                gl::Uniform2i(k_loc, pass_num as _, frame as _);
+                gl::Uniform1i(pass_loc, pass_num as _);
+                gl::Uniform1f(seed_loc, seed_to_float(pipeline.seed));
+                let stage_seed = pass_seed(pipeline.seed, pass_num);
+                gl::Uniform1f(pass_seed_loc, seed_to_float(stage_seed));
+                gl::Uniform4f(
+                    viewport_loc,
+                    viewport[0] as _,
+                    viewport[1] as _,
+                    viewport[2] as _,
+                    viewport[3] as _,
+                );
+                gl::Uniform1i(frame_loc, frame as _);
+                gl::Uniform1i(frame_since_build_loc, frame_since_build as _);
+                gl::Uniform1f(time_loc, time);
+                gl::Uniform1f(time_since_build_loc, time_since_build);
+                gl::Uniform1f(loop_time_loc, loop_time);
+                gl::Uniform1f(beat_loc, beat);
+                gl::Uniform1f(delta_loc, delta);
+                gl::Uniform1f(fps_loc, fps);
+                gl::Uniform1f(frame_time_loc, frame_time);
+                gl::Uniform1f(frame_time_max_loc, frame_time_max);
+                gl::Uniform1f(crossfade_loc, inputs.crossfade);
+                gl::Uniform1i(osc_connected_loc, osc_connected as _);
+                gl::Uniform1i(audio_active_loc, inputs.activity.audio as _);
+                gl::Uniform1i(osc_active_loc, inputs.activity.osc as _);
+                gl::Uniform1i(spout_active_loc, inputs.activity.spout as _);
+                gl::Uniform1i(midi_active_loc, inputs.activity.midi as _);
+                gl_debug_check!();
+            }
+
+            {
+                // Add sliders and buttons
+                let s_loc = gl::GetUniformLocation(stage.prog_id, SLIDERS_NAME.as_ptr());
+                let b_loc = gl::GetUniformLocation(stage.prog_id, BUTTONS_NAME.as_ptr());
+
+                let mut buttons = [0.0; 4 * MIDI_N];
+                for (k, button) in inputs.buttons.iter().enumerate() {
+                    buttons[k * 4 + 0] = button.0;
+                    buttons[k * 4 + 1] = button.1.elapsed().as_secs_f32();
+                    buttons[k * 4 + 2] = button.2.elapsed().as_secs_f32();
+                    buttons[k * 4 + 3] = button.3 as f32;
+                }
+
+                gl::Uniform1fv(s_loc, inputs.sliders.len() as _, inputs.sliders.as_ptr());
+                gl::Uniform4fv(b_loc, inputs.buttons.len() as _, &buttons as _);
+
+                let pitch_bend_loc =
+                    gl::GetUniformLocation(stage.prog_id, PITCH_BEND_NAME.as_ptr());
+                let pressure_loc = gl::GetUniformLocation(stage.prog_id, PRESSURE_NAME.as_ptr());
+                gl::Uniform1f(pitch_bend_loc, inputs.pitch_bend);
+                gl::Uniform1f(pressure_loc, inputs.pressure);
+                gl_debug_check!();
+            }
+
+            // Add OSC uniforms
+            if let Some(osc_config) = &pipeline.osc_config {
+                for (uniform_name, mapping) in &osc_config.mappings {
+                    // array elements are uploaded with their whole array below
+                    if mapping.writes_array() {
+                        continue;
+                    }
+
+                    // rotations are only uploaded to matrices of the right size
+                    if let OscDataType::Rotation(_) = mapping.data_type {
+                        let kind = match pipeline.osc_coercions.get(uniform_name) {
+                            Some(&kind) => kind,
+                            None => continue,
+                        };
+
+                        if let Ok(uniform_cstr) = std::ffi::CString::new(uniform_name.as_str()) {
+                            let loc = gl::GetUniformLocation(stage.prog_id, uniform_cstr.as_ptr());
+                            if loc != -1 {
+                                upload_osc_rotation(loc, &osc_values, uniform_name, kind);
+                            }
+                        }
+                        continue;
+                    }
+
+                    if let Some(value) = osc_values.get(uniform_name) {
+                        if let Ok(uniform_cstr) = std::ffi::CString::new(uniform_name.as_str()) {
+                            let loc = gl::GetUniformLocation(stage.prog_id, uniform_cstr.as_ptr());
+                            if loc != -1 {
+                                let kind = pipeline.osc_coercions.get(uniform_name).copied();
+                                upload_osc_value(loc, value, kind);
+                            }
+                        } else {
+                            log::warn!("Invalid uniform name for OSC mapping: {}", uniform_name);
+                        }
+                    }
+                }
+            }
+
+            // Add values of custom uniform sources which have no OSC mapping
+            pipeline.source_uniforms.upload(stage.prog_id, &osc_values);
+
+            for array in pipeline.osc_arrays.iter_mut() {
+                array.upload(pass_num, stage.prog_id);
+            }
+
+            // Add custom uniforms
+            for (name, uniform) in &stage.unis {
+                let loc = gl::GetUniformLocation(stage.prog_id, name.as_ptr());
+                uniform.bind(loc);
+                gl_debug_check!();
+            }
+
+            // Add vertex and instance count uniforms
+            if let StageKind::Vert {
+                count, instances, ..
+            } = stage.kind
+            {
+                let loc = gl::GetUniformLocation(stage.prog_id, VERTEX_COUNT_NAME.as_ptr());
+                gl::Uniform1i(loc, count as _);
+                gl_debug_check!();
+
+                let loc = gl::GetUniformLocation(stage.prog_id, INSTANCE_COUNT_NAME.as_ptr());
+                gl::Uniform1i(loc, instances as _);
+                gl_debug_check!();
+            }
+
+            // Add and bind uniform texture dependencies
+            for (k, name) in stage.deps.iter().enumerate() {
+                let tex = pipeline.buffers.get(name).unwrap();
+                let loc = gl::GetUniformLocation(stage.prog_id, name.as_ptr());
+                debug_assert_ne!(loc, -1);
+
+                gl::ActiveTexture(gl::TEXTURE0 + k as GLenum);
+                gl_debug_check!();
+
+                tex.bind(k as _);
+                gl_debug_check!();
+
+                gl::Uniform1i(loc, k as _);
+                gl_debug_check!();
+
+                let name_len = name.as_bytes().len();
+                let res_loc = alloca::with_bytes_zeroed(name_len + 5, |res_name| {
+                    res_name[..name_len].copy_from_slice(name.as_bytes());
+                    res_name[name_len..].copy_from_slice("_res\0".as_bytes());
+
+                    gl::GetUniformLocation(stage.prog_id, res_name.as_ptr() as _)
+                });
+
+                let res = tex.resolution();
+                gl_debug_check!();
+
+                gl::Uniform4f(
+                    res_loc,
+                    res[0] as _,
+                    res[1] as _,
+                    res[2] as _,
+                    res[0] as f32 / res[1] as f32,
+                );
+                gl_debug_check!();
+            }
+
+            // Place the layer of composite stages
+            if let StageKind::Composite { layer } = &stage.kind {
+                let unit = stage.deps.len();
+                let tex = pipeline.buffers.get(&layer.source).unwrap();
+                let loc = gl::GetUniformLocation(stage.prog_id, COMPOSITE_SOURCE_NAME.as_ptr());
+                gl::ActiveTexture(gl::TEXTURE0 + unit as GLenum);
+                tex.bind(unit as _);
+                gl::Uniform1i(loc, unit as _);
+                gl_debug_check!();
+
+                let aspect = target_res[0] as f32 / target_res[1] as f32;
+                let (transform, opacity) =
+                    layer.evaluate(inputs.sliders, &inputs.osc.get_all_values(), aspect);
+
+                let loc = gl::GetUniformLocation(stage.prog_id, COMPOSITE_TRANSFORM_NAME.as_ptr());
+                gl::UniformMatrix3fv(loc, 1, gl::FALSE, transform.as_ptr());
+                let loc = gl::GetUniformLocation(stage.prog_id, COMPOSITE_OPACITY_NAME.as_ptr());
+                gl::Uniform1f(loc, opacity);
+                gl_debug_check!();
+            }
+        }
+
+        match &stage.kind {
+            StageKind::Comp { dispatch, .. } => unsafe {
+                gl::DispatchCompute(dispatch[0], dispatch[1], dispatch[2]);
+                gl::MemoryBarrier(
+                    gl::TEXTURE_UPDATE_BARRIER_BIT
+                        | gl::TEXTURE_FETCH_BARRIER_BIT
+                        | gl::SHADER_IMAGE_ACCESS_BARRIER_BIT,
+                );
+                gl_debug_check!();
+            },
+            _ => unsafe {
+                debug_assert_eq!(target_res[2], 0);
+
+                // get render target id
+                let (target_tex, target_fb) = if let Some(name) = &stage.target {
+                    let tex = pipeline.buffers.get(name).unwrap();
+                    let tex_id = tex.texture_id();
+                    let fb_id = tex
+                        .framebuffer_id()
+                        .expect("Render target should be a framebuffer");
+                    (tex_id, fb_id)
+                } else {
+                    (0, screen_fb) // The screen is always id=0, unless the deck is mixed
+                };
+
+                // Specify render target
+                gl::BindFramebuffer(gl::FRAMEBUFFER, target_fb);
+                let [x, y, w, h] = viewport;
+                gl::Viewport(x as _, y as _, w as _, h as _);
+                gl_debug_check!();
+
+                // Keep clearing inside the viewport
+                if stage.viewport.is_some() {
+                    gl::Enable(gl::SCISSOR_TEST);
+                    gl::Scissor(x as _, y as _, w as _, h as _);
+                    gl_debug_check!();
+                }
+
+                // Attach additional render targets
+                if !stage.attachments.is_empty() {
+                    let mut draw_buffers = vec![gl::COLOR_ATTACHMENT0];
+                    for (k, name) in stage.attachments.iter().enumerate() {
+                        let tex = pipeline.buffers.get(name).unwrap();
+                        let attachment = gl::COLOR_ATTACHMENT1 + k as GLenum;
+                        gl::FramebufferTexture2D(
+                            gl::FRAMEBUFFER,
+                            attachment,
+                            gl::TEXTURE_2D,
+                            tex.texture_id(),
+                            0,
+                        );
+                        draw_buffers.push(attachment);
+                    }
+
+                    gl::DrawBuffers(draw_buffers.len() as _, draw_buffers.as_ptr());
+                    gl_debug_check!();
+                }
+
+                // Specify fragment shader color output
+                gl::BindFragDataLocation(stage.prog_id, 0, OUT_COLOR_NAME.as_ptr());
+                gl_debug_check!();
+
+                // Specify the layout of the vertex data
+                let pos_attr = gl::GetAttribLocation(stage.prog_id, POSITION_NAME.as_ptr());
+                if pos_attr != -1 {
+                    gl_debug_check!();
+                    gl::EnableVertexAttribArray(pos_attr as GLuint);
+                    gl_debug_check!();
+                    gl::VertexAttribPointer(
+                        pos_attr as GLuint,
+                        2,
+                        gl::FLOAT,
+                        gl::FALSE as GLboolean,
+                        0,
+                        std::ptr::null(),
+                    );
+                }
+                gl_debug_check!();
+
+                // Set blend mode
+                if pipeline.blending {
+                    let blend = stage.blend.unwrap_or(BlendMode {
+                        src: gl::ONE,
+                        dst: gl::ZERO,
+                        equation: gl::FUNC_ADD,
+                    });
+                    gl::BlendEquation(blend.equation);
+                    gl::BlendFunc(blend.src, blend.dst);
+                    gl_debug_check!();
+                }
+
+                // Clear render target
+                if let Some([r, g, b, a]) = stage.clear {
+                    gl::ClearColor(r, g, b, a);
+                    gl::Clear(gl::COLOR_BUFFER_BIT);
+                    gl_debug_check!();
+                }
+
+                // Draw stuff
+                if let StageKind::Vert {
+                    count,
+                    mode,
+                    instances,
+                    thickness,
+                } = stage.kind
+                {
+                    gl::PointSize(thickness);
+                    gl::LineWidth(thickness);
+                    gl_debug_check!();
+
+                    draw_vertices(inputs.vao, count, mode, instances);
+                    gl_debug_check!();
+                } else {
+                    draw_fullscreen(inputs.vao);
+                    gl_debug_check!();
+                }
+
+                if stage.viewport.is_some() {
+                    gl::Disable(gl::SCISSOR_TEST);
+                    gl_debug_check!();
+                }
+
+                // Detach additional render targets again
+                if !stage.attachments.is_empty() {
+                    for k in 0..stage.attachments.len() {
+                        let attachment = gl::COLOR_ATTACHMENT1 + k as GLenum;
+                        gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, gl::TEXTURE_2D, 0, 0);
+                    }
+
+                    gl::DrawBuffers(1, &gl::COLOR_ATTACHMENT0);
+                    gl_debug_check!();
+                }
+
+                // Generate mip maps
+                // don't do it for the screen buffer
+                if target_tex != 0 {
+                    gl::BindTexture(gl::TEXTURE_2D, target_tex);
+                    gl::GenerateMipmap(gl::TEXTURE_2D);
+                    gl_debug_check!();
+
+                    for name in stage.attachments.iter() {
+                        let tex = pipeline.buffers.get(name).unwrap();
+                        gl::BindTexture(gl::TEXTURE_2D, tex.texture_id());
+                        gl::GenerateMipmap(gl::TEXTURE_2D);
+                        gl_debug_check!();
+                    }
+                }
+
+                // swap buffers
+                for name in stage.target.iter().chain(stage.attachments.iter()) {
+                    pipeline.buffers.get(name).unwrap().swap();
+                }
+            },
+        }
+
+        if let Some(timer) = &mut stage.gpu_timer {
+            timer.end();
+        }
+
+        // attribute errors to the stage which caused them, once per pipeline
+        if pipeline.gl_checks {
+            loop {
+                let err = unsafe { gl::GetError() };
+                if err == gl::NO_ERROR {
+                    break;
+                }
+
+                if pipeline.reported_gl_errors.insert((pass_num, err)) {
+                    log::error!(
+                        "Stage {} ({}) caused OpenGL error {} (0x{:04X})",
+                        pass_num,
+                        stage.label(),
+                        gl_error_name(err),
+                        err
+                    );
+                }
+            }
+        }
+
+        // log render time
+        let stage_time = stage_start.elapsed().as_secs_f32();
+        stage.perf.push(1000.0 * stage_time);
+    }
+
+    // present the output texture, scaled to the screen
+    if let Some(name) = &pipeline.output {
+        let tex = pipeline.buffers.get(name).unwrap();
+        let [w, h, _] = tex.resolution();
+
+        // the last rendered frame has been swapped to the front,
+        // swap it back to read it through the framebuffer
+        tex.swap();
+        let fb_id = tex
+            .framebuffer_id()
+            .expect("Output should be a framebuffer");
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fb_id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, screen_fb);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                w as _,
+                h as _,
+                0,
+                0,
+                width as _,
+                height as _,
+                gl::COLOR_BUFFER_BIT,
+                gl::LINEAR,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, screen_fb);
+            gl_debug_check!();
+        }
+
+        tex.swap();
+    }
+
+    // keep the final image, so the next frame can read it. This runs before
+    // the mixer, limiter and color pass, so feedback stays in linear color
+    // and never picks up the other deck.
+    if let Some(frame) = &pipeline.previous_frame {
+        let tex = pipeline.buffers.get(&frame.name).unwrap();
+        let [w, h, _] = tex.resolution();
+        let fb_id = tex
+            .framebuffer_id()
+            .expect("Previous frame should be a framebuffer");
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, screen_fb);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, fb_id);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                width as _,
+                height as _,
+                0,
+                0,
+                w as _,
+                h as _,
+                gl::COLOR_BUFFER_BIT,
+                gl::LINEAR,
+            );
+            gl_debug_check!();
+
+            if frame.builder.mipmap {
+                gl::BindTexture(gl::TEXTURE_2D, tex.texture_id());
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+                gl_debug_check!();
+            }
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, screen_fb);
+            gl_debug_check!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn loop_wraps() {
+        assert_eq!(loop_phase(0.0, 0.0, 8.0), 0.0);
+        assert_eq!(loop_phase(2.0, 0.0, 8.0), 0.25);
+        assert_eq!(loop_phase(10.0, 0.0, 8.0), 0.25);
+        assert_eq!(loop_phase(8.0, 0.0, 8.0), 0.0);
+    }
+
+    #[test]
+    fn viewport_clipping() {
+        assert_eq!(clip_viewport(None, 640, 360), [0, 0, 640, 360]);
+        assert_eq!(
+            clip_viewport(Some([10, 20, 100, 50]), 640, 360),
+            [10, 20, 100, 50]
+        );
+
+        // windows may shrink below the viewport
+        assert_eq!(
+            clip_viewport(Some([600, 300, 100, 100]), 640, 360),
+            [600, 300, 40, 60]
+        );
+        assert_eq!(
+            clip_viewport(Some([700, 0, 100, 100]), 640, 360),
+            [640, 0, 0, 100]
+        );
+    }
+
+    #[test]
+    fn loop_origin() {
+        assert_eq!(loop_phase(5.0, 5.0, 2.0), 0.0);
+        assert_eq!(loop_phase(6.0, 5.0, 2.0), 0.5);
+
+        // scrubbing before the origin keeps wrapping
+        assert_eq!(loop_phase(4.5, 5.0, 2.0), 0.75);
+    }
+}
@@ -0,0 +1,11 @@
+#version 140
+
+out vec4 out_color;
+
+uniform sampler2D tex;
+uniform vec4 resolution;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution.xy;
+    out_color = texture(tex, uv);
+}
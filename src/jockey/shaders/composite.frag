@@ -0,0 +1,22 @@
+#version 140
+
+out vec4 out_color;
+
+uniform sampler2D composite_source;
+uniform mat3 composite_transform;
+uniform float composite_opacity;
+uniform vec4 resolution;
+
+void main() {
+    vec2 position = 2.0 * gl_FragCoord.xy / resolution.xy - 1.0;
+    vec2 uv = 0.5 * (composite_transform * vec3(position, 1.0)).xy + 0.5;
+
+    // everything outside of the layer stays transparent
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+        out_color = vec4(0.0);
+        return;
+    }
+
+    vec4 color = texture(composite_source, uv);
+    out_color = vec4(color.rgb, color.a * composite_opacity);
+}
@@ -0,0 +1,14 @@
+#version 140
+
+out vec4 out_color;
+
+uniform vec4 resolution;
+uniform float time;
+
+// slowly moving warning stripes, so a broken pipeline is never mistaken for a dark scene
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution.y;
+    float stripe = step(0.5, fract(4.0 * (uv.x + uv.y) - 0.25 * time));
+    vec3 color = mix(vec3(0.35, 0.02, 0.02), vec3(0.08, 0.0, 0.0), stripe);
+    out_color = vec4(color, 1.0);
+}
@@ -0,0 +1,27 @@
+#version 140
+
+out vec4 out_color;
+
+uniform sampler2D frame;
+uniform sampler2D previous;
+uniform vec4 resolution;
+uniform float lod;
+uniform float max_change;
+
+float luminance(vec3 x) {
+    return clamp(dot(x, vec3(0.2126, 0.7152, 0.0722)), 0.0, 1.0);
+}
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution.xy;
+    vec4 current = texture(frame, uv);
+    vec4 last = texture(previous, uv);
+
+    // the smallest mip level holds the average of the whole frame
+    float now = luminance(textureLod(frame, vec2(0.5), lod).rgb);
+    float before = luminance(textureLod(previous, vec2(0.5), lod).rgb);
+    float change = abs(now - before);
+
+    float t = change > max_change ? max_change / change : 1.0;
+    out_color = mix(last, current, t);
+}
@@ -0,0 +1,13 @@
+#version 140
+
+out vec4 out_color;
+
+uniform sampler2D deck_a;
+uniform sampler2D deck_b;
+uniform float crossfade;
+uniform vec4 resolution;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution.xy;
+    out_color = mix(texture(deck_a, uv), texture(deck_b, uv), crossfade);
+}
@@ -0,0 +1,90 @@
+#version 140
+
+out vec4 out_color;
+
+uniform sampler2D frame;
+uniform vec4 resolution;
+uniform int tonemap;
+uniform bool srgb;
+uniform sampler3D lut;
+uniform float lut_size;
+uniform float lut_strength;
+uniform vec3 lut_domain_min;
+uniform vec3 lut_domain_max;
+uniform int dither;
+
+vec3 reinhard(vec3 x) {
+    return x / (1.0 + x);
+}
+
+// fitted curve by Krzysztof Narkowicz
+vec3 aces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+vec3 encode_srgb(vec3 x) {
+    x = clamp(x, 0.0, 1.0);
+    vec3 lo = 12.92 * x;
+    vec3 hi = 1.055 * pow(x, vec3(1.0 / 2.4)) - 0.055;
+    return mix(lo, hi, step(0.0031308, x));
+}
+
+// samples the centers of the outer texels at the ends of the domain
+vec3 grade(vec3 x) {
+    vec3 t = clamp((x - lut_domain_min) / (lut_domain_max - lut_domain_min), 0.0, 1.0);
+    vec3 uvw = (t * (lut_size - 1.0) + 0.5) / lut_size;
+    return texture(lut, uvw).rgb;
+}
+
+// threshold of an 8x8 Bayer matrix, from 0 up to 1
+float bayer2(vec2 p) {
+    p = floor(p);
+    return fract(p.x * 0.5 + p.y * p.y * 0.75);
+}
+
+float bayer4(vec2 p) {
+    return bayer2(0.5 * p) * 0.25 + bayer2(p);
+}
+
+float bayer8(vec2 p) {
+    return bayer4(0.5 * p) * 0.25 + bayer2(p);
+}
+
+// interleaved gradient noise by Jorge Jimenez
+float gradient_noise(vec2 p) {
+    return fract(52.9829189 * fract(dot(floor(p), vec2(0.06711056, 0.00583715))));
+}
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution.xy;
+    vec4 color = texture(frame, uv);
+    vec3 rgb = max(color.rgb, 0.0);
+
+    if (tonemap == 1) {
+        rgb = reinhard(rgb);
+    } else if (tonemap == 2) {
+        rgb = aces(rgb);
+    }
+
+    if (srgb) {
+        rgb = encode_srgb(rgb);
+    }
+
+    if (lut_size > 0.0) {
+        rgb = mix(rgb, grade(rgb), lut_strength);
+    }
+
+    // spread the rounding to 8 bits over neighboring pixels
+    if (dither == 1) {
+        rgb += (bayer8(gl_FragCoord.xy) - 0.5) / 255.0;
+    } else if (dither == 2) {
+        rgb += (gradient_noise(gl_FragCoord.xy) - 0.5) / 255.0;
+    }
+
+    out_color = vec4(rgb, color.a);
+}
@@ -0,0 +1,29 @@
+#version 140
+
+out vec4 out_color;
+
+uniform sampler2D frame;
+uniform vec4 resolution;
+uniform bool unpremultiply;
+uniform bool tonemap;
+
+// keeps values up to the knee and rolls off the rest towards 1
+vec3 soft_clip(vec3 x) {
+    const float knee = 0.8;
+    vec3 over = max(x - knee, 0.0) / (1.0 - knee);
+    return min(x, knee) + (1.0 - knee) * over / (1.0 + over);
+}
+
+void main() {
+    vec4 color = texture(frame, gl_FragCoord.xy / resolution.xy);
+
+    if (unpremultiply && color.a > 0.0) {
+        color.rgb /= color.a;
+    }
+
+    if (tonemap) {
+        color.rgb = soft_clip(max(color.rgb, 0.0));
+    }
+
+    out_color = color;
+}
@@ -1,52 +1,229 @@
-use std::ptr;
 use gl::types::*;
+use lazy_static::lazy_static;
+use std::{
+    borrow::Cow,
+    ffi::CString,
+    ptr,
+    sync::Once,
+    time::{Duration, Instant},
+};
+
+use super::{AlphaMode, Error, POSITION_NAME, RESOLUTION_NAME};
+use crate::util::{draw_fullscreen, FrameBuffer, Texture, Texture2D, TextureFormat};
 
 #[cfg(target_os = "windows")]
 #[path = "spout_ffi.rs"]
 mod spout_ffi;
 
+/// Number of failed sends in a row after which the sender is re-initialized
+const SPOUT_MAX_FAILURES: u32 = 3;
+
+/// Delay before Spout is looked for again if it was unavailable, doubled after every attempt
+const SPOUT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay between two attempts to load Spout
+const SPOUT_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Number of attempts to load Spout, after which the sender stays unavailable
+const SPOUT_MAX_RETRIES: u32 = 10;
+
+static UNAVAILABLE_WARNING: Once = Once::new();
+
+#[cfg(target_os = "windows")]
+const SPOUT_FRAG: &str = include_str!("shaders/spout.frag");
+
+lazy_static! {
+    static ref FRAME_NAME: CString = CString::new("frame").unwrap();
+    static ref UNPREMULTIPLY_NAME: CString = CString::new("unpremultiply").unwrap();
+    static ref TONEMAP_NAME: CString = CString::new("tonemap").unwrap();
+}
+
+/// Delay before the given retry, counting from zero
+fn spout_retry_delay(retry: u32) -> Duration {
+    SPOUT_RETRY_DELAY
+        .checked_mul(1 << retry.min(16))
+        .map_or(SPOUT_RETRY_MAX_DELAY, |delay| {
+            delay.min(SPOUT_RETRY_MAX_DELAY)
+        })
+}
+
+/// Check whether SpoutLibrary.dll can be loaded
+pub fn spout_library_available() -> bool {
+    #[cfg(target_os = "windows")]
+    return spout_ffi::is_available();
+
+    #[cfg(not(target_os = "windows"))]
+    false
+}
+
+/// Health of a Spout sender
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpoutStatus {
+    /// Whether the last send succeeded
+    pub healthy: bool,
+    /// Number of failed sends in a row
+    pub failures: u32,
+    pub last_error: Option<String>,
+    /// Set once the sender kept failing after being re-initialized
+    pub gave_up: bool,
+    /// Set if SpoutLibrary.dll could not be loaded, nothing is sent then
+    pub unavailable: bool,
+}
+
+impl Default for SpoutStatus {
+    fn default() -> Self {
+        Self {
+            healthy: true,
+            failures: 0,
+            last_error: None,
+            gave_up: false,
+            unavailable: false,
+        }
+    }
+}
+
+/// Graphics adapter which SpoutLibrary creates its D3D11 device on
+///
+/// SpoutLibrary only selects adapters by the Windows graphics preference,
+/// not by index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpoutAdapter {
+    /// The default adapter of the system
+    Default,
+    /// The adapter Windows prefers for saving power, usually an integrated GPU
+    MinimumPower,
+    /// The adapter Windows prefers for performance, usually a discrete GPU
+    HighPerformance,
+}
+
+impl SpoutAdapter {
+    /// DXGI_GPU_PREFERENCE of the adapter, `None` for the default adapter
+    pub fn preference(self) -> Option<i32> {
+        match self {
+            Self::Default => None,
+            Self::MinimumPower => Some(1),
+            Self::HighPerformance => Some(2),
+        }
+    }
+}
+
 /// Spout sender for sharing OpenGL textures
 pub struct SpoutSender {
     sender_name: String,
+    adapter: SpoutAdapter,
     width: u32,
     height: u32,
     share_handle: isize,
     initialized: bool,
+    interval: Option<Duration>,
+    last_send: Option<Instant>,
+    status: SpoutStatus,
+    reinitialized: bool,
+    /// Attempts to load Spout after it was unavailable
+    retries: u32,
+    next_retry: Instant,
     #[cfg(target_os = "windows")]
     ffi_sender: Option<spout_ffi::SpoutLibrarySender>,
 }
 
+impl std::fmt::Debug for SpoutSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(SpoutSender))
+            .field("sender_name", &self.sender_name)
+            .field("adapter", &self.adapter)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("initialized", &self.initialized)
+            .field("status", &self.status)
+            .finish()
+    }
+}
+
 impl SpoutSender {
-    /// Create a new Spout sender
+    /// Create a new Spout sender on the default adapter
     pub fn new(name: &str) -> Self {
+        Self::new_on_adapter(name, SpoutAdapter::Default)
+    }
+
+    /// Create a new Spout sender whose D3D11 device lives on `adapter`
+    pub fn new_on_adapter(name: &str, adapter: SpoutAdapter) -> Self {
         log::info!("Creating Spout sender: {}", name);
 
         #[cfg(target_os = "windows")]
-        let ffi_sender = match spout_ffi::SpoutLibrarySender::new(name) {
+        let sender = spout_ffi::SpoutLibrarySender::new_on_adapter(name, adapter.preference());
+        #[cfg(target_os = "windows")]
+        let (ffi_sender, error) = match sender {
             Ok(sender) => {
                 log::info!("Using SpoutLibrary.dll for Spout sending");
-                Some(sender)
-            }
-            Err(e) => {
-                log::warn!("Failed to initialize SpoutLibrary: {}", e);
-                log::warn!("Falling back to basic OpenGL implementation");
-                None
+                (Some(sender), None)
             }
+            Err(e) => (None, Some(e.to_string())),
         };
 
+        #[cfg(not(target_os = "windows"))]
+        let error = Some("Spout is only available on Windows".to_string());
+
+        let mut status = SpoutStatus::default();
+        if let Some(error) = error {
+            UNAVAILABLE_WARNING.call_once(|| {
+                log::warn!("======================================================");
+                log::warn!("Spout is enabled, but unavailable: {}", error);
+                log::warn!("No frames will be shared until SpoutLibrary.dll is");
+                log::warn!("placed next to the executable, it is looked for");
+                log::warn!("again a few times in the background.");
+                log::warn!("======================================================");
+            });
+
+            status.healthy = false;
+            status.unavailable = true;
+            status.last_error = Some(error);
+        }
+
         Self {
             sender_name: name.to_string(),
+            adapter,
             width: 0,
             height: 0,
             share_handle: 0,
             initialized: false,
+            interval: None,
+            last_send: None,
+            status,
+            reinitialized: false,
+            // there is nothing to wait for without Windows
+            retries: if cfg!(target_os = "windows") {
+                0
+            } else {
+                SPOUT_MAX_RETRIES
+            },
+            next_retry: Instant::now() + spout_retry_delay(0),
             #[cfg(target_os = "windows")]
             ffi_sender,
         }
     }
 
+    /// Limit how often frames are sent, `None` sends every frame
+    pub fn set_fps(&mut self, fps: Option<f32>) {
+        self.interval = fps.map(|fps| Duration::from_secs_f32(1.0 / fps));
+    }
+
+    /// Check whether the next frame should be sent
+    ///
+    /// Always true for the first frame and when no fps limit is set,
+    /// always false once the sender gave up or if Spout is unavailable.
+    pub fn is_due(&self) -> bool {
+        if self.status.gave_up || self.status.unavailable {
+            return false;
+        }
+
+        match (self.interval, self.last_send) {
+            (Some(interval), Some(last_send)) => last_send.elapsed() >= interval,
+            _ => true,
+        }
+    }
+
     /// Initialize the sender with texture dimensions
-    pub fn init(&mut self, width: u32, height: u32) -> std::result::Result<(), String> {
+    pub fn init(&mut self, width: u32, height: u32) -> Result<(), Error> {
         if self.initialized && self.width == width && self.height == height {
             return Ok(());
         }
@@ -94,7 +271,137 @@ impl SpoutSender {
     }
 
     /// Send a texture to Spout
-    pub fn send_texture(&mut self, texture_id: GLuint, width: u32, height: u32) -> std::result::Result<(), String> {
+    ///
+    /// Frames which arrive before the fps interval has elapsed are skipped.
+    /// After repeated failures the sender is re-initialized once, if that
+    /// does not help, it gives up and stops sending.
+    pub fn send_texture(
+        &mut self,
+        texture_id: GLuint,
+        width: u32,
+        height: u32,
+    ) -> Result<(), Error> {
+        self.retry_library();
+        if !self.is_due() {
+            return Ok(());
+        }
+        self.last_send = Some(Instant::now());
+
+        let result = self
+            .send_frame(texture_id, width, height)
+            .and_then(|_| self.check_size(width, height));
+        let err = match result {
+            Ok(()) => {
+                self.status.healthy = true;
+                self.status.failures = 0;
+                self.reinitialized = false;
+                return Ok(());
+            }
+            Err(err) => err,
+        };
+
+        self.status.healthy = false;
+        self.status.failures += 1;
+        self.status.last_error = Some(err.to_string());
+
+        if self.status.failures >= SPOUT_MAX_FAILURES {
+            if self.reinitialized {
+                log::error!(
+                    "Spout sender '{}' keeps failing, giving up",
+                    self.sender_name
+                );
+                self.status.gave_up = true;
+            } else {
+                log::warn!(
+                    "Spout sender '{}' failed {} times in a row, re-initializing",
+                    self.sender_name,
+                    self.status.failures
+                );
+                self.reinitialized = true;
+                self.status.failures = 0;
+                self.reinit();
+            }
+        }
+
+        Err(err)
+    }
+
+    /// Look for Spout again while it is unavailable, with growing delays
+    ///
+    /// Another application may still be setting up the Spout runtime when
+    /// the sender is created. As soon as it can be loaded, frames are shared
+    /// from the same send on.
+    fn retry_library(&mut self) {
+        if !self.status.unavailable
+            || self.retries >= SPOUT_MAX_RETRIES
+            || Instant::now() < self.next_retry
+        {
+            return;
+        }
+
+        #[cfg(target_os = "windows")]
+        match spout_ffi::SpoutLibrarySender::new_on_adapter(
+            &self.sender_name,
+            self.adapter.preference(),
+        ) {
+            Ok(sender) => {
+                log::info!(
+                    "Spout became available, sender '{}' is sharing frames now",
+                    self.sender_name
+                );
+                self.release();
+                self.ffi_sender = Some(sender);
+                self.status = SpoutStatus::default();
+                return;
+            }
+            Err(e) => self.status.last_error = Some(e.to_string()),
+        }
+
+        self.retries += 1;
+        self.next_retry = Instant::now() + spout_retry_delay(self.retries);
+        if self.retries >= SPOUT_MAX_RETRIES {
+            log::warn!(
+                "Spout is still unavailable after {} attempts, sender '{}' stops looking",
+                self.retries,
+                self.sender_name
+            );
+        }
+    }
+
+    /// Get the health of the sender
+    pub fn status(&self) -> &SpoutStatus {
+        &self.status
+    }
+
+    /// Make sure the receivers actually see a texture of the sent size
+    #[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
+    fn check_size(&self, width: u32, height: u32) -> Result<(), Error> {
+        #[cfg(target_os = "windows")]
+        if let Some((shared_width, shared_height)) =
+            self.ffi_sender.as_ref().and_then(|ffi| ffi.shared_size())
+        {
+            if (shared_width, shared_height) != (width, height) {
+                return Err(Error::D3D(format!(
+                    "Shared texture is {}x{}, but the frame is {}x{}",
+                    shared_width, shared_height, width, height
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drop all shared resources, they are recreated by the next send
+    fn reinit(&mut self) {
+        #[cfg(target_os = "windows")]
+        if let Some(ffi) = &mut self.ffi_sender {
+            ffi.release();
+        }
+
+        self.release();
+    }
+
+    fn send_frame(&mut self, texture_id: GLuint, width: u32, height: u32) -> Result<(), Error> {
         // Try using FFI sender first
         #[cfg(target_os = "windows")]
         if let Some(ffi) = &mut self.ffi_sender {
@@ -139,7 +446,7 @@ impl SpoutSender {
             // Check for OpenGL errors
             let error = gl::GetError();
             if error != gl::NO_ERROR {
-                return Err(format!("OpenGL error during texture copy: 0x{:X}", error));
+                return Err(Error::Gl(error));
             }
         }
 
@@ -180,11 +487,500 @@ impl Drop for SpoutSender {
     }
 }
 
+/// Pixel format of the shared texture of a Spout sender
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SenderFormat {
+    Rgba8,
+    Rgb10A2,
+    Rgba16,
+    Rgba16F,
+    Rgba32F,
+}
+
+impl SenderFormat {
+    /// Get the format of a DXGI format code, `None` if it is not supported
+    pub fn from_dxgi(format: u32) -> Option<Self> {
+        match format {
+            // 0 is reported by senders which never set a format, Spout uses BGRA then
+            0 | 28 | 29 | 87 | 88 | 91 => Some(Self::Rgba8),
+            24 => Some(Self::Rgb10A2),
+            11 => Some(Self::Rgba16),
+            10 => Some(Self::Rgba16F),
+            2 => Some(Self::Rgba32F),
+            _ => None,
+        }
+    }
+
+    /// Whether the sender may have values outside of 0 to 1
+    pub fn is_float(self) -> bool {
+        matches!(self, Self::Rgba16F | Self::Rgba32F)
+    }
+
+    /// Format of the texture which frames are received into
+    ///
+    /// `None` for 8 bit formats, which are received as bytes directly.
+    pub fn frame_format(self) -> Option<TextureFormat> {
+        match self {
+            Self::Rgba8 => None,
+            Self::Rgb10A2 | Self::Rgba16F => Some(TextureFormat::RGBA16F),
+            Self::Rgba16 | Self::Rgba32F => Some(TextureFormat::RGBA32F),
+        }
+    }
+}
+
+/// Spout receiver which copies the frames of another application into a texture
+///
+/// Frames are copied through a CPU buffer. While no sender is connected,
+/// the texture is 1x1 black. The receiver has its own SpoutLibrary instance,
+/// separate from the sender's, whose D3D11 device lives on the chosen adapter.
+///
+/// Senders with more than 8 bits per channel are received into a float
+/// texture first, which is drawn into the receive texture on the GPU. A
+/// float receive texture keeps their full range, an 8 bit one gets the
+/// highlights of float senders rolled off instead of clipped.
+pub struct SpoutReceiver {
+    sender_name: Option<String>,
+    adapter: SpoutAdapter,
+    width: u32,
+    height: u32,
+    format: SenderFormat,
+    pixels: Vec<u8>,
+    /// Frame of a sender with more than 8 bits per channel
+    deep_frame: Option<Texture2D>,
+    /// Program which converts the deep frame, built with the first one
+    prog_id: GLuint,
+    sh_ids: Vec<GLuint>,
+    connected: bool,
+    #[cfg(target_os = "windows")]
+    ffi_receiver: Option<spout_ffi::SpoutLibraryReceiver>,
+}
+
+impl std::fmt::Debug for SpoutReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(stringify!(SpoutReceiver))
+            .field("sender_name", &self.sender_name)
+            .field("adapter", &self.adapter)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("format", &self.format)
+            .field(
+                "deep_frame",
+                &self.deep_frame.as_ref().map(|frame| frame.format),
+            )
+            .field("connected", &self.connected)
+            .finish()
+    }
+}
+
+impl SpoutReceiver {
+    /// Create a receiver for the given sender, or the active sender if `None`
+    pub fn new(sender_name: Option<&str>) -> Self {
+        Self::new_on_adapter(sender_name, SpoutAdapter::Default)
+    }
+
+    /// Create a receiver whose D3D11 device lives on `adapter`
+    pub fn new_on_adapter(sender_name: Option<&str>, adapter: SpoutAdapter) -> Self {
+        log::info!(
+            "Creating Spout receiver for {}",
+            sender_name.unwrap_or("the active sender")
+        );
+
+        #[cfg(target_os = "windows")]
+        let ffi_receiver = match spout_ffi::SpoutLibraryReceiver::new_on_adapter(
+            sender_name,
+            adapter.preference(),
+        ) {
+            Ok(receiver) => Some(receiver),
+            Err(e) => {
+                log::warn!("Spout input is unavailable: {}", e);
+                None
+            }
+        };
+
+        #[cfg(not(target_os = "windows"))]
+        log::warn!("Spout input is only available on Windows");
+
+        Self {
+            sender_name: sender_name.map(str::to_owned),
+            adapter,
+            width: 0,
+            height: 0,
+            format: SenderFormat::Rgba8,
+            pixels: Vec::new(),
+            deep_frame: None,
+            prog_id: 0,
+            sh_ids: Vec::new(),
+            connected: false,
+            #[cfg(target_os = "windows")]
+            ffi_receiver,
+        }
+    }
+
+    /// Receive the latest frame and return the size of the sender
+    ///
+    /// Returns `None` while no sender is connected. When the size or format
+    /// of the sender changes, the frame arrives with the next call.
+    pub fn check_receiver(&mut self) -> Option<(u32, u32)> {
+        #[cfg(target_os = "windows")]
+        if let Some(ffi) = &mut self.ffi_receiver {
+            let connected = match &self.deep_frame {
+                Some(frame) => ffi.receive_texture(frame.id),
+                None => ffi.receive_image(&mut self.pixels),
+            };
+            if connected != self.connected {
+                match connected {
+                    true => log::info!("Spout receiver connected"),
+                    false => log::info!("Spout receiver lost its sender"),
+                }
+            }
+            self.connected = connected;
+
+            if connected && ffi.is_updated() {
+                let (width, height) = ffi.sender_size();
+                let dxgi_format = ffi.sender_format();
+                let format = SenderFormat::from_dxgi(dxgi_format).unwrap_or_else(|| {
+                    log::warn!(
+                        "Unknown Spout sender format {}, reading it as 8 bit",
+                        dxgi_format
+                    );
+                    SenderFormat::Rgba8
+                });
+                log::info!("Spout sender is now {}x{} {:?}", width, height, format);
+                self.width = width;
+                self.height = height;
+                self.format = format;
+
+                match format.frame_format() {
+                    Some(frame_format) => {
+                        if let Err(err) = self.build_program() {
+                            log::error!("Failed to build the Spout conversion: {}", err);
+                        }
+                        self.pixels = Vec::new();
+                        self.deep_frame = Some(Texture2D::with_params(
+                            [width, height],
+                            gl::NEAREST,
+                            gl::NEAREST,
+                            gl::CLAMP_TO_EDGE,
+                            frame_format,
+                            false,
+                            ptr::null(),
+                        ));
+                    }
+                    None => {
+                        self.deep_frame = None;
+                        self.pixels = vec![0; width as usize * height as usize * 4];
+                    }
+                }
+            }
+        }
+
+        match self.connected {
+            true => Some((self.width, self.height)),
+            false => None,
+        }
+    }
+
+    /// Compile the program which converts deep frames, once per receiver
+    #[cfg(target_os = "windows")]
+    fn build_program(&mut self) -> Result<(), String> {
+        if self.prog_id != 0 {
+            return Ok(());
+        }
+
+        use crate::util::{compile_shader, link_program};
+
+        self.sh_ids = vec![
+            compile_shader(super::PASS_VERT, gl::VERTEX_SHADER)?,
+            compile_shader(SPOUT_FRAG, gl::FRAGMENT_SHADER)?,
+        ];
+        self.prog_id = link_program(&self.sh_ids)?;
+        Ok(())
+    }
+
+    /// Latest 8 bit frame as straight alpha bytes
+    fn frame_u8(&self, alpha: AlphaMode) -> Cow<'_, [u8]> {
+        match alpha {
+            AlphaMode::Premultiplied => {
+                let mut pixels = self.pixels.clone();
+                alpha.convert_u8(&mut pixels);
+                Cow::Owned(pixels)
+            }
+            AlphaMode::Straight => Cow::Borrowed(&self.pixels),
+        }
+    }
+
+    /// Draw the deep frame into `tex`, which has the size of the sender
+    ///
+    /// This stays on the GPU, float senders are rolled off above 0.8 if
+    /// `tex` has 8 bits per channel.
+    fn draw_deep_frame(&self, vao: GLuint, frame: &Texture2D, tex: &Texture2D, alpha: AlphaMode) {
+        let float_tex = matches!(tex.format, TextureFormat::RGBA16F | TextureFormat::RGBA32F);
+        let tonemap = !float_tex && self.format.is_float();
+        let (w, h) = (self.width as f32, self.height as f32);
+
+        unsafe {
+            let mut fb = 0;
+            gl::GenFramebuffers(1, &mut fb);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fb);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                tex.id,
+                0,
+            );
+            gl::Viewport(0, 0, self.width as _, self.height as _);
+            gl::Disable(gl::BLEND);
+            gl::UseProgram(self.prog_id);
+            gl_debug_check!();
+
+            let res_loc = gl::GetUniformLocation(self.prog_id, RESOLUTION_NAME.as_ptr());
+            gl::Uniform4f(res_loc, w, h, w / h, h / w);
+            let unpremultiply = alpha == AlphaMode::Premultiplied;
+            let unpremultiply_loc =
+                gl::GetUniformLocation(self.prog_id, UNPREMULTIPLY_NAME.as_ptr());
+            gl::Uniform1i(unpremultiply_loc, unpremultiply as _);
+            let tonemap_loc = gl::GetUniformLocation(self.prog_id, TONEMAP_NAME.as_ptr());
+            gl::Uniform1i(tonemap_loc, tonemap as _);
+
+            let frame_loc = gl::GetUniformLocation(self.prog_id, FRAME_NAME.as_ptr());
+            gl::ActiveTexture(gl::TEXTURE0);
+            frame.bind(0);
+            gl::Uniform1i(frame_loc, 0);
+            gl_debug_check!();
+
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vao);
+            let pos_attr = gl::GetAttribLocation(self.prog_id, POSITION_NAME.as_ptr());
+            if pos_attr != -1 {
+                gl::EnableVertexAttribArray(pos_attr as GLuint);
+                gl::VertexAttribPointer(
+                    pos_attr as GLuint,
+                    2,
+                    gl::FLOAT,
+                    gl::FALSE as GLboolean,
+                    0,
+                    std::ptr::null(),
+                );
+            }
+            gl_debug_check!();
+
+            draw_fullscreen(vao);
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::DeleteFramebuffers(1, &fb);
+            gl_debug_check!();
+
+            if tex.mipmap {
+                gl::BindTexture(gl::TEXTURE_2D, tex.id);
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+                gl_debug_check!();
+            }
+        }
+    }
+
+    /// Write the latest frame into `tex`, resizing it to the size of the sender
+    ///
+    /// Frames with premultiplied `alpha` are converted to straight alpha.
+    /// Frames of deep senders are drawn with `vao`, without leaving the GPU.
+    pub fn update_texture(&self, vao: GLuint, tex: &mut Texture2D, alpha: AlphaMode) {
+        let connected = self.connected && self.width > 0 && self.height > 0;
+        let deep_frame = self.deep_frame.as_ref().filter(|_| self.prog_id != 0);
+        if let Some(frame) = deep_frame.filter(|_| connected) {
+            if tex.resolution() != [self.width, self.height, 0] {
+                *tex = Texture2D::with_params(
+                    [self.width, self.height],
+                    tex.min_filter,
+                    tex.mag_filter,
+                    tex.wrap_mode,
+                    tex.format,
+                    tex.mipmap,
+                    ptr::null(),
+                );
+            }
+
+            self.draw_deep_frame(vao, frame, tex, alpha);
+            return;
+        }
+
+        // zero bits are black in both 8 bit and float textures
+        let black = [0_f32; 4];
+        let bytes;
+        let (size, data) = match connected && self.deep_frame.is_none() {
+            true => {
+                bytes = self.frame_u8(alpha);
+                ([self.width, self.height], bytes.as_ptr() as *const GLvoid)
+            }
+            false => ([1, 1], black.as_ptr() as *const GLvoid),
+        };
+
+        if tex.resolution() != [size[0], size[1], 0] {
+            *tex = Texture2D::with_params(
+                size,
+                tex.min_filter,
+                tex.mag_filter,
+                tex.wrap_mode,
+                tex.format,
+                tex.mipmap,
+                data,
+            );
+        } else {
+            tex.write(data);
+        }
+    }
+
+    /// Whether a sender is connected, as of the last check
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Get the name of the sender, `None` for the active sender
+    pub fn sender_name(&self) -> Option<&str> {
+        self.sender_name.as_deref()
+    }
+
+    /// Get the adapter the receiver was created on
+    pub fn adapter(&self) -> SpoutAdapter {
+        self.adapter
+    }
+}
+
+impl Drop for SpoutReceiver {
+    fn drop(&mut self) {
+        if self.prog_id == 0 {
+            return;
+        }
+
+        unsafe {
+            for &id in self.sh_ids.iter() {
+                gl::DetachShader(self.prog_id, id);
+                gl::DeleteShader(id);
+            }
+
+            gl::DeleteProgram(self.prog_id);
+        }
+    }
+}
+
+/// How received frames are placed into a receive texture of a fixed size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpoutFit {
+    /// Scale to the size of the texture, ignoring the aspect ratio
+    Stretch,
+    /// Scale to fit inside the texture, leaving black bars
+    Contain,
+    /// Scale to fill the texture, cutting off the sides of the frame
+    Cover,
+    /// Center the frame without scaling it
+    Crop,
+}
+
+impl SpoutFit {
+    /// Source and destination rectangles as `[x0, y0, x1, y1]`, to blit a
+    /// frame of size `src` into a texture of size `dst`
+    pub fn rects(self, src: [u32; 2], dst: [u32; 2]) -> ([i32; 4], [i32; 4]) {
+        let full = |[w, h]: [u32; 2]| [0, 0, w as i32, h as i32];
+        let center = |[w, h]: [u32; 2], [outer_w, outer_h]: [u32; 2]| {
+            let x = (outer_w - w) as i32 / 2;
+            let y = (outer_h - h) as i32 / 2;
+            [x, y, x + w as i32, y + h as i32]
+        };
+        let scaled = |[w, h]: [u32; 2], scale: f64, [max_w, max_h]: [u32; 2]| {
+            let w = (w as f64 * scale).round() as u32;
+            let h = (h as f64 * scale).round() as u32;
+            [w.clamp(1, max_w), h.clamp(1, max_h)]
+        };
+
+        let scale_x = dst[0] as f64 / src[0] as f64;
+        let scale_y = dst[1] as f64 / src[1] as f64;
+        match self {
+            Self::Stretch => (full(src), full(dst)),
+            Self::Contain => {
+                let size = scaled(src, scale_x.min(scale_y), dst);
+                (full(src), center(size, dst))
+            }
+            Self::Cover => {
+                let size = scaled(dst, 1.0 / scale_x.max(scale_y), src);
+                (center(size, src), full(dst))
+            }
+            Self::Crop => {
+                let size = [src[0].min(dst[0]), src[1].min(dst[1])];
+                (center(size, src), center(size, dst))
+            }
+        }
+    }
+}
+
+/// Blit a received frame into a receive texture of a fixed size
+///
+/// Parts of the texture which the frame does not cover are cleared to black.
+pub fn fit_frame(frame: &Texture2D, target: &FrameBuffer, fit: SpoutFit) {
+    let [src_w, src_h, _] = frame.resolution();
+    let [dst_w, dst_h, _] = target.resolution();
+    let (src, dst) = fit.rects([src_w, src_h], [dst_w, dst_h]);
+    let filter = match fit {
+        SpoutFit::Crop => gl::NEAREST,
+        _ => gl::LINEAR,
+    };
+
+    unsafe {
+        let mut read_fb = 0;
+        gl::GenFramebuffers(1, &mut read_fb);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, read_fb);
+        gl::FramebufferTexture2D(
+            gl::READ_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            frame.id,
+            0,
+        );
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.fb_id);
+        gl_debug_check!();
+
+        gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+        gl::BlitFramebuffer(
+            src[0],
+            src[1],
+            src[2],
+            src[3],
+            dst[0],
+            dst[1],
+            dst[2],
+            dst[3],
+            gl::COLOR_BUFFER_BIT,
+            filter,
+        );
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl::DeleteFramebuffers(1, &read_fb);
+        gl_debug_check!();
+
+        gl::BindTexture(gl::TEXTURE_2D, target.tex_id);
+        gl::GenerateMipmap(gl::TEXTURE_2D);
+        gl_debug_check!();
+    }
+}
+
 /// Spout configuration
 #[derive(Debug, Clone)]
 pub struct SpoutConfig {
     pub enabled: bool,
     pub sender_name: String,
+    pub fps: Option<f32>,
+    /// Fail to build the pipeline if SpoutLibrary.dll is missing
+    pub require_library: bool,
+    /// Adapter of the D3D11 devices of the sender and the receiver
+    pub adapter: SpoutAdapter,
+    /// Sampler which receives the frames of another application
+    pub receive_texture: Option<String>,
+    /// Sender to receive from, `None` for the active sender
+    pub receive_sender: Option<String>,
+    /// Fixed size of the receive texture, `None` follows the sender
+    pub receive_size: Option<[u32; 2]>,
+    /// How frames are placed into a receive texture of a fixed size
+    pub receive_fit: SpoutFit,
+    /// How the sender stores alpha
+    pub receive_alpha: AlphaMode,
 }
 
 impl Default for SpoutConfig {
@@ -192,13 +988,25 @@ impl Default for SpoutConfig {
         Self {
             enabled: false,
             sender_name: "Sh4derJockey".to_string(),
+            fps: None,
+            require_library: false,
+            adapter: SpoutAdapter::Default,
+            receive_texture: None,
+            receive_sender: None,
+            receive_size: None,
+            receive_fit: SpoutFit::Contain,
+            receive_alpha: AlphaMode::Straight,
         }
     }
 }
 
 impl SpoutConfig {
     /// Parse Spout configuration from YAML
-    pub fn from_yaml(value: &serde_yaml::Value) -> std::result::Result<Self, String> {
+    pub fn from_yaml(value: &serde_yaml::Value) -> Result<Self, Error> {
+        Self::parse(value).map_err(Error::Config)
+    }
+
+    fn parse(value: &serde_yaml::Value) -> Result<Self, String> {
         let mut config = Self::default();
 
         if let Some(enabled) = value.get("enabled") {
@@ -212,6 +1020,186 @@ impl SpoutConfig {
                 .to_string();
         }
 
+        if let Some(fps) = value.get("fps") {
+            match fps.as_f64() {
+                Some(fps) if fps > 0.0 => config.fps = Some(fps as f32),
+                _ => {
+                    return Err(format!(
+                        "Spout 'fps' must be a positive number, got {:?}",
+                        fps
+                    ))
+                }
+            }
+        }
+
+        if let Some(require) = value.get("require_library") {
+            config.require_library = require
+                .as_bool()
+                .ok_or("Spout 'require_library' must be a boolean")?;
+        }
+
+        if let Some(adapter) = value.get("adapter") {
+            config.adapter = match adapter.as_str() {
+                Some("default") => SpoutAdapter::Default,
+                Some("minimum_power") => SpoutAdapter::MinimumPower,
+                Some("high_performance") => SpoutAdapter::HighPerformance,
+                _ => {
+                    return Err(format!(
+                        "Spout 'adapter' must be default, minimum_power or \
+                         high_performance, got {:?}",
+                        adapter
+                    ))
+                }
+            };
+        }
+
+        if let Some(receive) = value.get("receive") {
+            config.receive_texture = Some(
+                receive
+                    .get("texture")
+                    .and_then(|texture| texture.as_str())
+                    .ok_or("Spout 'receive' must have a 'texture' name")?
+                    .to_string(),
+            );
+
+            if let Some(sender) = receive.get("sender") {
+                config.receive_sender = Some(
+                    sender
+                        .as_str()
+                        .ok_or("Spout receive 'sender' must be a string")?
+                        .to_string(),
+                );
+            }
+
+            if let Some(size) = receive.get("size") {
+                match size.as_sequence().map(Vec::as_slice) {
+                    Some([w, h]) => match (w.as_u64(), h.as_u64()) {
+                        (Some(w), Some(h)) if w > 0 && h > 0 && w.max(h) <= u32::MAX as u64 => {
+                            config.receive_size = Some([w as u32, h as u32])
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Spout receive 'size' must be two positive numbers, got {:?}",
+                                size
+                            ))
+                        }
+                    },
+                    _ => {
+                        return Err(format!(
+                            "Spout receive 'size' must be a list of width and height, got {:?}",
+                            size
+                        ))
+                    }
+                }
+            }
+
+            if let Some(fit) = receive.get("fit") {
+                config.receive_fit = match fit.as_str() {
+                    Some("stretch") => SpoutFit::Stretch,
+                    Some("contain") => SpoutFit::Contain,
+                    Some("cover") => SpoutFit::Cover,
+                    Some("crop") => SpoutFit::Crop,
+                    _ => {
+                        return Err(format!(
+                            "Spout receive 'fit' must be stretch, contain, cover or crop, got {:?}",
+                            fit
+                        ))
+                    }
+                };
+
+                if config.receive_size.is_none() {
+                    return Err("Spout receive 'fit' needs a 'size'".into());
+                }
+            }
+
+            config.receive_alpha = AlphaMode::from_yaml(receive)?;
+        }
+
         Ok(config)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(yaml: &str) -> Result<SpoutConfig, String> {
+        SpoutConfig::parse(&serde_yaml::from_str(yaml).unwrap())
+    }
+
+    #[test]
+    fn receive_size_and_fit() {
+        let config = parse("receive: {texture: a, size: [1024, 512], fit: cover}").unwrap();
+        assert_eq!(config.receive_size, Some([1024, 512]));
+        assert_eq!(config.receive_fit, SpoutFit::Cover);
+
+        let config = parse("receive: {texture: a}").unwrap();
+        assert_eq!(config.receive_size, None);
+        assert_eq!(config.receive_alpha, AlphaMode::Straight);
+
+        let config = parse("receive: {texture: a, alpha: premultiplied}").unwrap();
+        assert_eq!(config.receive_alpha, AlphaMode::Premultiplied);
+
+        assert!(parse("receive: {texture: a, size: [0, 512]}").is_err());
+        assert!(parse("receive: {texture: a, size: 512}").is_err());
+        assert!(parse("receive: {texture: a, size: [4, 4], fit: zoom}").is_err());
+        assert!(parse("receive: {texture: a, fit: crop}").is_err());
+    }
+
+    #[test]
+    fn adapters() {
+        assert_eq!(parse("{}").unwrap().adapter, SpoutAdapter::Default);
+
+        let config = parse("adapter: high_performance").unwrap();
+        assert_eq!(config.adapter, SpoutAdapter::HighPerformance);
+        assert_eq!(config.adapter.preference(), Some(2));
+
+        assert!(parse("adapter: 1").is_err());
+        assert!(parse("adapter: fastest").is_err());
+    }
+
+    #[test]
+    fn fit_rects() {
+        let full = ([0, 0, 1920, 1080], [0, 0, 1024, 1024]);
+        assert_eq!(SpoutFit::Stretch.rects([1920, 1080], [1024, 1024]), full);
+
+        // letterboxed at the top and bottom
+        assert_eq!(
+            SpoutFit::Contain.rects([1920, 1080], [1024, 1024]),
+            ([0, 0, 1920, 1080], [0, 224, 1024, 800])
+        );
+
+        // the sides of the frame are cut off
+        assert_eq!(
+            SpoutFit::Cover.rects([1920, 1080], [1024, 1024]),
+            ([420, 0, 1500, 1080], [0, 0, 1024, 1024])
+        );
+
+        assert_eq!(
+            SpoutFit::Crop.rects([1920, 1080], [1024, 1024]),
+            ([448, 28, 1472, 1052], [0, 0, 1024, 1024])
+        );
+        assert_eq!(
+            SpoutFit::Crop.rects([100, 50], [200, 200]),
+            ([0, 0, 100, 50], [50, 75, 150, 125])
+        );
+    }
+
+    #[test]
+    fn sender_formats() {
+        // B8G8R8A8_UNORM, R16G16B16A16_FLOAT, R32G32B32A32_FLOAT
+        assert_eq!(SenderFormat::from_dxgi(87), Some(SenderFormat::Rgba8));
+        assert_eq!(SenderFormat::from_dxgi(10), Some(SenderFormat::Rgba16F));
+        assert_eq!(SenderFormat::from_dxgi(2), Some(SenderFormat::Rgba32F));
+        assert_eq!(SenderFormat::from_dxgi(1), None);
+
+        assert!(SenderFormat::Rgba8.frame_format().is_none());
+        assert!(matches!(
+            SenderFormat::Rgba16F.frame_format(),
+            Some(TextureFormat::RGBA16F)
+        ));
+
+        assert!(SenderFormat::Rgba32F.is_float());
+        assert!(!SenderFormat::Rgba16.is_float());
+    }
+}
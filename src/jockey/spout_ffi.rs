@@ -1,30 +1,50 @@
 // FFI bindings for SpoutLibrary.dll
-use std::ffi::CString;
-use std::os::raw::{c_char, c_uint, c_void};
+use crate::jockey::Error;
 use libloading::{Library, Symbol};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_void};
 use std::sync::OnceLock;
 
-static SPOUT_LIB: OnceLock<Option<Library>> = OnceLock::new();
+static SPOUT_LIB: OnceLock<Library> = OnceLock::new();
 
+// Failed loads are not remembered, so a sender can retry once the DLL shows up
 fn get_spout_lib() -> Option<&'static Library> {
-    SPOUT_LIB.get_or_init(|| {
-        // Try to load SpoutLibrary.dll
-        unsafe {
-            Library::new("SpoutLibrary.dll")
-                .or_else(|_| Library::new("./SpoutLibrary.dll"))
-                .ok()
-        }
-    }).as_ref()
+    if let Some(lib) = SPOUT_LIB.get() {
+        return Some(lib);
+    }
+
+    // Try to load SpoutLibrary.dll
+    let lib = unsafe {
+        Library::new("SpoutLibrary.dll")
+            .or_else(|_| Library::new("./SpoutLibrary.dll"))
+            .ok()?
+    };
+
+    // another thread may have been faster, its library is used then
+    let _ = SPOUT_LIB.set(lib);
+    SPOUT_LIB.get()
+}
+
+pub fn is_available() -> bool {
+    get_spout_lib().is_some()
 }
 
 // SPOUTLIBRARY is an opaque handle to the Spout library instance
 type SpoutHandle = *mut c_void;
 
+// Slot of a method which is never called
+type Unused = *const c_void;
+
 // Factory function to get SPOUTLIBRARY instance
 type GetSpoutFn = unsafe extern "C" fn() -> SpoutHandle;
 
 // Virtual table for SPOUTLIBRARY methods
 // Based on exact order from SpoutLibrary.h
+//
+// The fields must match the declaration order of the virtual methods in the
+// header, a missing or swapped entry silently calls the wrong method. Entries
+// are only ever appended, so every slot up to the last one used is listed,
+// even if it is never called. Long runs of unused slots are kept as arrays.
 #[repr(C)]
 struct SpoutVTable {
     // Sender methods (in exact order from header)
@@ -32,8 +52,114 @@ struct SpoutVTable {
     set_sender_format: unsafe extern "C" fn(SpoutHandle, u32),                    // 1
     release_sender: unsafe extern "C" fn(SpoutHandle, u32),                       // 2
     send_fbo: unsafe extern "C" fn(SpoutHandle, c_uint, c_uint, c_uint, bool) -> bool, // 3
-    send_texture: unsafe extern "C" fn(SpoutHandle, c_uint, c_uint, c_uint, c_uint, bool, c_uint) -> bool, // 4
-    // ... other virtual methods omitted
+    send_texture:
+        unsafe extern "C" fn(SpoutHandle, c_uint, c_uint, c_uint, c_uint, bool, c_uint) -> bool, // 4
+    send_image:
+        unsafe extern "C" fn(SpoutHandle, *const u8, c_uint, c_uint, c_uint, bool, c_uint) -> bool, // 5, unused
+    get_name: unsafe extern "C" fn(SpoutHandle) -> *const c_char, // 6, unused
+    get_width: unsafe extern "C" fn(SpoutHandle) -> c_uint,       // 7
+    get_height: unsafe extern "C" fn(SpoutHandle) -> c_uint,      // 8
+    get_fps: unsafe extern "C" fn(SpoutHandle) -> f64,            // 9, unused
+    get_frame: unsafe extern "C" fn(SpoutHandle) -> c_long,       // 10, unused
+    get_handle: unsafe extern "C" fn(SpoutHandle) -> *mut c_void, // 11, unused
+    get_cpu: unsafe extern "C" fn(SpoutHandle) -> bool,           // 12, unused
+    get_gldx: unsafe extern "C" fn(SpoutHandle) -> bool,          // 13, unused
+    // Receiver methods
+    set_receiver_name: unsafe extern "C" fn(SpoutHandle, *const c_char), // 14
+    release_receiver: unsafe extern "C" fn(SpoutHandle),                 // 15
+    receive_texture: unsafe extern "C" fn(SpoutHandle, c_uint, c_uint, bool, c_uint) -> bool, // 16
+    receive_image: unsafe extern "C" fn(SpoutHandle, *mut u8, c_uint, bool, c_uint) -> bool, // 17
+    is_updated: unsafe extern "C" fn(SpoutHandle) -> bool,               // 18
+    is_connected: unsafe extern "C" fn(SpoutHandle) -> bool,             // 19, unused
+    is_frame_new: unsafe extern "C" fn(SpoutHandle) -> bool,             // 20
+    get_sender_name: unsafe extern "C" fn(SpoutHandle) -> *const c_char, // 21, unused
+    get_sender_width: unsafe extern "C" fn(SpoutHandle) -> c_uint,       // 22
+    get_sender_height: unsafe extern "C" fn(SpoutHandle) -> c_uint,      // 23
+    get_sender_format: unsafe extern "C" fn(SpoutHandle) -> u32,         // 24
+    // Sender details, frame count, memory buffers, logs, registry, utilities,
+    // sharing modes and formats
+    _unused_25: [Unused; 98], // 25-122
+    // Graphics adapters
+    get_num_adapters: unsafe extern "C" fn(SpoutHandle) -> c_int, // 123
+    get_adapter_name: unsafe extern "C" fn(SpoutHandle, c_int, *mut c_char, c_int) -> bool, // 124, unused
+    adapter_name: unsafe extern "C" fn(SpoutHandle) -> *mut c_char, // 125, unused
+    get_adapter: unsafe extern "C" fn(SpoutHandle) -> c_int,        // 126, unused
+    get_performance_preference: unsafe extern "C" fn(SpoutHandle, *const c_char) -> c_int, // 127, unused
+    set_performance_preference: unsafe extern "C" fn(SpoutHandle, c_int, *const c_char) -> bool, // 128, unused
+    get_preferred_adapter_name:
+        unsafe extern "C" fn(SpoutHandle, c_int, *mut c_char, c_int) -> bool, // 129
+    set_preferred_adapter: unsafe extern "C" fn(SpoutHandle, c_int) -> bool, // 130
+    is_preference_available: unsafe extern "C" fn(SpoutHandle) -> bool,      // 131
+    // OpenGL/DirectX utilities
+    _unused_132: [Unused; 16], // 132-147
+    // Deletes the instance
+    release: unsafe extern "C" fn(SpoutHandle), // 148
+}
+
+fn get_spout_handle() -> Result<SpoutHandle, Error> {
+    let lib =
+        get_spout_lib().ok_or_else(|| Error::Library("SpoutLibrary.dll not found".to_string()))?;
+    unsafe {
+        let get_spout: Symbol<GetSpoutFn> = lib
+            .get(b"GetSpout\0")
+            .map_err(|e| Error::Library(format!("Failed to get GetSpout function: {}", e)))?;
+
+        let handle = get_spout();
+        if handle.is_null() {
+            return Err(Error::Library("Failed to get Spout instance".to_string()));
+        }
+        Ok(handle)
+    }
+}
+
+/// Deletes a SpoutLibrary instance, the handle must not be used afterwards
+unsafe fn release_handle(handle: SpoutHandle) {
+    unsafe {
+        let vtable = *(handle as *const *const SpoutVTable);
+        let release = (*vtable).release;
+        release(handle);
+    }
+}
+
+/// Gets a new instance whose D3D11 device will be created on the adapter
+/// Windows prefers for `preference`, or the default adapter if `None`
+///
+/// `preference` is a DXGI_GPU_PREFERENCE, 1 for minimum power and 2 for high
+/// performance. SpoutLibrary has no way to pick an adapter by index.
+fn get_spout_handle_on(preference: Option<i32>) -> Result<SpoutHandle, Error> {
+    let handle = get_spout_handle()?;
+    let preference = match preference {
+        Some(preference) => preference,
+        None => return Ok(handle),
+    };
+
+    unsafe {
+        let vtable = *(handle as *const *const SpoutVTable);
+        if !((*vtable).is_preference_available)(handle) {
+            release_handle(handle);
+            return Err(Error::Config(
+                "Choosing the Spout adapter needs Windows 10 1803 or later".to_string(),
+            ));
+        }
+
+        // must happen before the instance opens DirectX, on the first send or receive
+        if !((*vtable).set_preferred_adapter)(handle, preference) {
+            release_handle(handle);
+            return Err(Error::D3D(format!(
+                "Failed to select the graphics adapter for preference {}",
+                preference
+            )));
+        }
+
+        let mut name = [0 as c_char; 256];
+        let count = ((*vtable).get_num_adapters)(handle);
+        if ((*vtable).get_preferred_adapter_name)(handle, preference, name.as_mut_ptr(), 256) {
+            let name = CStr::from_ptr(name.as_ptr()).to_string_lossy();
+            log::info!("Spout uses adapter '{}' of {}", name, count);
+        }
+    }
+
+    Ok(handle)
 }
 
 pub struct SpoutLibrarySender {
@@ -45,23 +171,13 @@ pub struct SpoutLibrarySender {
 }
 
 impl SpoutLibrarySender {
-    pub fn new(name: &str) -> Result<Self, String> {
-        let name_c = CString::new(name)
-            .map_err(|e| format!("Invalid sender name: {}", e))?;
+    /// Creates a sender on the adapter Windows prefers for `preference`
+    pub fn new_on_adapter(name: &str, preference: Option<i32>) -> Result<Self, Error> {
+        let name_c =
+            CString::new(name).map_err(|e| Error::Config(format!("Invalid sender name: {}", e)))?;
 
         // Get Spout instance handle
-        let lib = get_spout_lib().ok_or("SpoutLibrary.dll not found")?;
-        let spout_handle = unsafe {
-            let get_spout: Symbol<GetSpoutFn> = lib
-                .get(b"GetSpout\0")
-                .map_err(|e| format!("Failed to get GetSpout function: {}", e))?;
-
-            let handle = get_spout();
-            if handle.is_null() {
-                return Err("Failed to get Spout instance".to_string());
-            }
-            handle
-        };
+        let spout_handle = get_spout_handle_on(preference)?;
 
         log::info!("Got Spout instance handle");
 
@@ -74,12 +190,14 @@ impl SpoutLibrarySender {
         })
     }
 
-    pub fn init(&mut self, width: u32, height: u32) -> Result<(), String> {
+    pub fn init(&mut self, width: u32, height: u32) -> Result<(), Error> {
         if self.initialized && self.width == width && self.height == height {
             return Ok(());
         }
 
-        let handle = self.spout_handle.ok_or("No Spout handle")?;
+        let handle = self
+            .spout_handle
+            .ok_or_else(|| Error::Library("No Spout handle".to_string()))?;
 
         unsafe {
             let vtable = *(handle as *const *const SpoutVTable);
@@ -107,12 +225,14 @@ impl SpoutLibrarySender {
         Ok(())
     }
 
-    pub fn send_texture(&mut self, texture_id: u32, width: u32, height: u32) -> Result<(), String> {
+    pub fn send_texture(&mut self, texture_id: u32, width: u32, height: u32) -> Result<(), Error> {
         if !self.initialized || self.width != width || self.height != height {
             self.init(width, height)?;
         }
 
-        let handle = self.spout_handle.ok_or("No Spout handle")?;
+        let handle = self
+            .spout_handle
+            .ok_or_else(|| Error::Library("No Spout handle".to_string()))?;
 
         unsafe {
             let vtable = *(handle as *const *const SpoutVTable);
@@ -128,7 +248,7 @@ impl SpoutLibrarySender {
                 false, // Don't invert
                 0,     // No host FBO
             ) {
-                return Err("Failed to send texture to Spout".to_string());
+                return Err(Error::D3D("Failed to send texture to Spout".to_string()));
             }
         }
 
@@ -141,6 +261,24 @@ impl SpoutLibrarySender {
         self.name.to_str().unwrap()
     }
 
+    /// Size of the shared texture as reported by Spout
+    ///
+    /// This may differ from the size of the last frame, if Spout could not
+    /// resize the shared texture.
+    pub fn shared_size(&self) -> Option<(u32, u32)> {
+        if !self.initialized {
+            return None;
+        }
+
+        let handle = self.spout_handle?;
+        unsafe {
+            let vtable = *(handle as *const *const SpoutVTable);
+            let get_width = (*vtable).get_width;
+            let get_height = (*vtable).get_height;
+            Some((get_width(handle), get_height(handle)))
+        }
+    }
+
     pub fn is_initialized(&self) -> bool {
         self.initialized
     }
@@ -163,5 +301,130 @@ impl SpoutLibrarySender {
 impl Drop for SpoutLibrarySender {
     fn drop(&mut self) {
         self.release();
+
+        // every handle is a separate instance, which is deleted by Release
+        if let Some(handle) = self.spout_handle.take() {
+            unsafe { release_handle(handle) };
+        }
+    }
+}
+
+pub struct SpoutLibraryReceiver {
+    spout_handle: SpoutHandle,
+}
+
+impl SpoutLibraryReceiver {
+    /// Connects to the sender with the given name, or the active sender if `None`
+    ///
+    /// The instance uses the adapter Windows prefers for `preference`.
+    pub fn new_on_adapter(sender: Option<&str>, preference: Option<i32>) -> Result<Self, Error> {
+        let sender = sender
+            .map(CString::new)
+            .transpose()
+            .map_err(|e| Error::Config(format!("Invalid sender name: {}", e)))?;
+
+        let spout_handle = get_spout_handle_on(preference)?;
+        unsafe {
+            let vtable = *(spout_handle as *const *const SpoutVTable);
+            let set_receiver_name = (*vtable).set_receiver_name;
+            match &sender {
+                Some(name) => set_receiver_name(spout_handle, name.as_ptr()),
+                None => set_receiver_name(spout_handle, std::ptr::null()),
+            }
+        }
+
+        // Spout copies the name, so it does not have to outlive this call
+        Ok(Self { spout_handle })
+    }
+
+    /// Copies the latest frame of the sender into `pixels` as RGBA
+    ///
+    /// Returns false while no sender is connected. If the size of the sender
+    /// changed, nothing is copied and `is_updated` returns true until the
+    /// next call, so the buffer can be resized first.
+    ///
+    /// Panics if `pixels` is smaller than the last size of the sender.
+    pub fn receive_image(&mut self, pixels: &mut [u8]) -> bool {
+        const GL_RGBA: u32 = 0x1908;
+        let (width, height) = self.sender_size();
+        assert!(
+            pixels.len() >= width as usize * height as usize * 4,
+            "Spout receive buffer of {} bytes is too small for {}x{}",
+            pixels.len(),
+            width,
+            height
+        );
+
+        unsafe {
+            let vtable = *(self.spout_handle as *const *const SpoutVTable);
+            let receive_image = (*vtable).receive_image;
+
+            // Spout images are stored top down, textures bottom up
+            receive_image(self.spout_handle, pixels.as_mut_ptr(), GL_RGBA, true, 0)
+        }
+    }
+
+    /// Copies the latest frame of the sender into the 2D texture `texture_id`
+    ///
+    /// The texture must have the size of the sender, Spout converts the frame
+    /// to the format of the texture. Otherwise this behaves like `receive_image`.
+    pub fn receive_texture(&mut self, texture_id: u32) -> bool {
+        const GL_TEXTURE_2D: u32 = 0x0DE1;
+        unsafe {
+            let vtable = *(self.spout_handle as *const *const SpoutVTable);
+            let receive_texture = (*vtable).receive_texture;
+            receive_texture(self.spout_handle, texture_id, GL_TEXTURE_2D, true, 0)
+        }
+    }
+
+    /// Whether the sender changed size during the last receive
+    pub fn is_updated(&self) -> bool {
+        unsafe {
+            let vtable = *(self.spout_handle as *const *const SpoutVTable);
+            let is_updated = (*vtable).is_updated;
+            is_updated(self.spout_handle)
+        }
+    }
+
+    /// Whether the last receive got a frame which was not seen before
+    pub fn is_frame_new(&self) -> bool {
+        unsafe {
+            let vtable = *(self.spout_handle as *const *const SpoutVTable);
+            let is_frame_new = (*vtable).is_frame_new;
+            is_frame_new(self.spout_handle)
+        }
+    }
+
+    /// DXGI format of the shared texture of the connected sender
+    pub fn sender_format(&self) -> u32 {
+        unsafe {
+            let vtable = *(self.spout_handle as *const *const SpoutVTable);
+            let get_sender_format = (*vtable).get_sender_format;
+            get_sender_format(self.spout_handle)
+        }
+    }
+
+    /// Size of the connected sender
+    pub fn sender_size(&self) -> (u32, u32) {
+        unsafe {
+            let vtable = *(self.spout_handle as *const *const SpoutVTable);
+            let get_sender_width = (*vtable).get_sender_width;
+            let get_sender_height = (*vtable).get_sender_height;
+            (
+                get_sender_width(self.spout_handle),
+                get_sender_height(self.spout_handle),
+            )
+        }
+    }
+}
+
+impl Drop for SpoutLibraryReceiver {
+    fn drop(&mut self) {
+        unsafe {
+            let vtable = *(self.spout_handle as *const *const SpoutVTable);
+            let release_receiver = (*vtable).release_receiver;
+            release_receiver(self.spout_handle);
+            release_handle(self.spout_handle);
+        }
     }
 }
@@ -3,12 +3,76 @@ use std::{collections::HashMap, ffi::CString};
 use gl::types::*;
 use serde_yaml::Value;
 
-use super::Uniform;
+use super::{CompositeLayer, ConfigError, StageCondition, Uniform, COMPOSITE_FRAG};
 use crate::util::*;
 
 pub const PASS_VERT: &str = include_str!("shaders/pass.vert");
 pub const PASS_FRAG: &str = include_str!("shaders/pass.frag");
 
+/// The maximum number of render targets a single stage can draw to.
+///
+/// OpenGL guarantees at least 8 draw buffers on all implementations.
+pub const MAX_ATTACHMENTS: usize = 8;
+
+/// Fields of the vertex, fragment and compute shader, as a file name or as inline source.
+pub const SHADER_FIELDS: [(&str, &str); 3] =
+    [("vs", "vertex"), ("fs", "fragment"), ("cs", "compute")];
+
+/// Fields of composite stages which move the layer, a copy stage has none of them.
+const LAYER_FIELDS: [&str; 5] = ["composite", "position", "scale", "rotation", "opacity"];
+
+/// Turns a stage with `type: copy` into the composite stage which does the same.
+///
+/// The `input` texture covers the whole target and replaces its contents,
+/// unless the stage asks for blending. Other stages are returned unchanged.
+pub fn copy_as_composite(object: Value) -> Result<Value, String> {
+    match object.get("type") {
+        Some(Value::String(s)) if s == "copy" => (),
+        Some(s) => return Err(format!("Expected \"type\" to be \"copy\", got {:?}", s)),
+        None => return Ok(object),
+    }
+
+    let mut map = match object {
+        Value::Mapping(map) => map,
+        _ => unreachable!("only mappings have fields"),
+    };
+
+    if let Some(&field) = LAYER_FIELDS.iter().find(|&&field| map.contains_key(field)) {
+        return Err(format!(
+            "Copy stages have no field {:?}, use a composite stage to move the texture",
+            field
+        ));
+    }
+
+    let input = match map.remove("input") {
+        Some(Value::String(s)) => s,
+        s => {
+            return Err(format!(
+                "Expected \"input\" to be the name of a texture, got {:?}",
+                s
+            ))
+        }
+    };
+
+    map.remove("type");
+    map.insert("composite".into(), input.into());
+    if !map.contains_key("blend") && !map.contains_key("blend_mode") {
+        map.insert("blend".into(), "none".into());
+    }
+
+    Ok(Value::Mapping(map))
+}
+
+/// Returns the name under which an inline shader shows up in error messages.
+fn inline_shader_name(field: &str, label: &str) -> String {
+    format!("<{} of {}>", field, label)
+}
+
+/// Whether a source of a stage is an inline shader rather than a file.
+fn is_inline_shader(source: &str) -> bool {
+    source.starts_with('<')
+}
+
 #[derive(Debug)]
 pub enum StageKind {
     Comp {
@@ -17,9 +81,123 @@ pub enum StageKind {
     Vert {
         count: GLsizei,
         mode: GLenum,
+        instances: GLsizei,
         thickness: f32,
     },
     Frag {},
+    Composite {
+        layer: CompositeLayer,
+    },
+}
+
+/// The blend function and equation used when drawing a stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlendMode {
+    pub src: GLenum,
+    pub dst: GLenum,
+    pub equation: GLenum,
+}
+
+impl BlendMode {
+    /// Parses one of the named blend modes.
+    ///
+    /// Returns `Ok(None)` for the mode `none`, which disables blending.
+    pub fn from_alias(name: &str) -> Result<Option<Self>, String> {
+        let (src, dst) = match name {
+            "none" => return Ok(None),
+            "additive" | "add" => (gl::ONE, gl::ONE),
+            "alpha" => (gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA),
+            "multiply" => (gl::DST_COLOR, gl::ZERO),
+            s => {
+                return Err(format!(
+                    "Expected blend mode to be one of additive, alpha, multiply or none, got {:?}",
+                    s
+                ))
+            }
+        };
+
+        Ok(Some(Self {
+            src,
+            dst,
+            equation: gl::FUNC_ADD,
+        }))
+    }
+}
+
+pub fn parse_blend_factor(name: &str) -> Result<GLenum, String> {
+    match name {
+        "ZERO" => Ok(gl::ZERO),
+        "ONE" => Ok(gl::ONE),
+        "SRC_COLOR" => Ok(gl::SRC_COLOR),
+        "DST_COLOR" => Ok(gl::DST_COLOR),
+        "SRC_ALPHA" => Ok(gl::SRC_ALPHA),
+        "DST_ALPHA" => Ok(gl::DST_ALPHA),
+        "SRC1_COLOR" => Ok(gl::SRC1_COLOR),
+        "SRC1_ALPHA" => Ok(gl::SRC1_ALPHA),
+        "CONSTANT_COLOR" => Ok(gl::CONSTANT_COLOR),
+        "CONSTANT_ALPHA" => Ok(gl::CONSTANT_ALPHA),
+        "SRC_ALPHA_SATURATE" => Ok(gl::SRC_ALPHA_SATURATE),
+        "ONE_MINUS_SRC_COLOR" => Ok(gl::ONE_MINUS_SRC_COLOR),
+        "ONE_MINUS_DST_COLOR" => Ok(gl::ONE_MINUS_DST_COLOR),
+        "ONE_MINUS_SRC_ALPHA" => Ok(gl::ONE_MINUS_SRC_ALPHA),
+        "ONE_MINUS_DST_ALPHA" => Ok(gl::ONE_MINUS_DST_ALPHA),
+        "ONE_MINUS_SRC1_COLOR" => Ok(gl::ONE_MINUS_SRC1_COLOR),
+        "ONE_MINUS_SRC1_ALPHA" => Ok(gl::ONE_MINUS_SRC1_ALPHA),
+        "ONE_MINUS_CONSTANT_COLOR" => Ok(gl::ONE_MINUS_CONSTANT_COLOR),
+        "ONE_MINUS_CONSTANT_ALPHA" => Ok(gl::ONE_MINUS_CONSTANT_ALPHA),
+        s => Err(format!("Expected blend mode, got \"{:?}\"", s)),
+    }
+}
+
+pub fn parse_vertex_mode(name: &str) -> Option<GLenum> {
+    match name {
+        "LINE_LOOP" => Some(gl::LINE_LOOP),
+        "LINE_STRIP" => Some(gl::LINE_STRIP),
+        "LINES" => Some(gl::LINES),
+        "POINTS" => Some(gl::POINTS),
+        "TRIANGLE_FAN" => Some(gl::TRIANGLE_FAN),
+        "TRIANGLE_STRIP" => Some(gl::TRIANGLE_STRIP),
+        "TRIANGLES" => Some(gl::TRIANGLES),
+        _ => None,
+    }
+}
+
+pub fn parse_blend_equation(name: &str) -> Result<GLenum, String> {
+    match name {
+        "ADD" | "FUNC_ADD" => Ok(gl::FUNC_ADD),
+        "SUBTRACT" | "FUNC_SUBTRACT" => Ok(gl::FUNC_SUBTRACT),
+        "REVERSE_SUBTRACT" | "FUNC_REVERSE_SUBTRACT" => Ok(gl::FUNC_REVERSE_SUBTRACT),
+        "MIN" => Ok(gl::MIN),
+        "MAX" => Ok(gl::MAX),
+        s => Err(format!("Expected blend equation, got {:?}", s)),
+    }
+}
+
+/// Parses the rectangle `[x, y, width, height]` a stage draws into, in pixels.
+pub fn parse_viewport(value: &Value) -> Result<[u32; 4], String> {
+    let rect: Vec<_> = match value {
+        Value::Sequence(s) => s.iter().filter_map(Value::as_u64).collect(),
+        _ => Vec::new(),
+    };
+
+    match rect.as_slice() {
+        &[x, y, w, h] if w > 0 && h > 0 && rect.iter().all(|&v| v <= u32::MAX as u64) => {
+            Ok([x as u32, y as u32, w as u32, h as u32])
+        }
+        _ => Err(format!(
+            "Expected \"viewport\" to be a list of 4 unsigned integers with a nonzero size, got {:?}",
+            value
+        )),
+    }
+}
+
+/// What a disabled stage leaves in its render targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisabledOutput {
+    /// Keep the last frame the stage rendered.
+    Keep,
+    /// Clear the targets to transparent black.
+    Clear,
 }
 
 /// The stage struct
@@ -34,361 +212,701 @@ pub enum StageKind {
 pub struct Stage {
     pub prog_id: GLuint,
     pub target: Option<CString>,
+    pub attachments: Vec<CString>,
     pub kind: StageKind,
     pub sh_ids: Vec<GLuint>,
     pub deps: Vec<CString>,
     pub unis: HashMap<CString, Uniform>,
-    pub blend: Option<(GLenum, GLenum)>,
+    pub resolution_kind: GLenum,
+    pub blend: Option<BlendMode>,
+    pub clear: Option<[f32; 4]>,
+    /// Part of the target the stage draws into, the whole target if not set.
+    pub viewport: Option<[u32; 4]>,
+    pub enabled: bool,
+    /// Skips the stage while the condition does not hold, like a disabled stage.
+    pub when: Option<StageCondition>,
+    pub when_disabled: DisabledOutput,
     pub perf: RunningAverage<f32, 128>,
+    pub gpu_perf: RunningAverage<f32, 128>,
+    pub gpu_timer: Option<GpuTimer>,
     pub builder: TextureBuilder,
+    pub sources: Vec<String>,
 }
 
-impl Stage {
-    pub fn from_yaml(object: Value) -> Result<Self, String> {
-        let perf = RunningAverage::new();
-        let deps = Vec::new();
+/// Where the source of a shader comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderSource {
+    /// Path of a shader file, which is read when the stage is built.
+    File(String),
+    /// Source code written into the pipeline file.
+    Inline(String),
+}
+
+/// Everything a stage is made of, parsed without reading shaders or touching OpenGL.
+///
+/// Errors are collected per field, so the validator reports all of them at
+/// once with the same rules which are used to build the stage.
+#[derive(Debug)]
+pub struct StageConfig {
+    pub target: Option<CString>,
+    pub attachments: Vec<CString>,
+    pub kind: StageKind,
+    pub shaders: [Option<ShaderSource>; 3],
+    pub header: ShaderHeader,
+    pub unis: HashMap<CString, Uniform>,
+    pub blend: Option<BlendMode>,
+    pub clear: Option<[f32; 4]>,
+    pub viewport: Option<[u32; 4]>,
+    pub enabled: bool,
+    pub when: Option<StageCondition>,
+    pub when_disabled: DisabledOutput,
+    pub builder: TextureBuilder,
+}
+
+/// Keeps the value of a field, or records its error.
+fn checked<T>(errors: &mut Vec<ConfigError>, field: &str, result: Result<T, String>) -> Option<T> {
+    result
+        .map_err(|err| errors.push(ConfigError::new(field, err)))
+        .ok()
+}
+
+impl StageConfig {
+    /// Parses a stage, the paths of the errors are relative to the stage.
+    pub fn from_yaml(object: Value) -> Result<Self, Vec<ConfigError>> {
+        if !object.is_mapping() {
+            let err = format!("Expected stage to be a mapping, got {:?}", object);
+            return Err(vec![ConfigError::new("", err)]);
+        }
 
-        // get render target name
-        let target = match object.get("target") {
-            Some(Value::String(s)) => Some(CString::new(s.as_str()).unwrap()),
+        let object = copy_as_composite(object).map_err(|err| vec![ConfigError::new("", err)])?;
+        let mut errors = Vec::new();
+
+        let (target, attachments) =
+            parse_targets(&object, &mut errors).unwrap_or((None, Vec::new()));
+        let unis = parse_uniforms(&object, &mut errors);
+
+        let blend_key = match object.get("blend_mode") {
+            Some(_) => "blend_mode",
+            None => "blend",
+        };
+        let blend = checked(&mut errors, blend_key, parse_blend(&object)).flatten();
+        let blend = checked(
+            &mut errors,
+            "blend_equation",
+            parse_blend_equation_field(&object, blend),
+        )
+        .flatten();
+
+        let clear = checked(&mut errors, "clear_color", parse_clear_color(&object)).flatten();
+        let clear = match object.get("clear") {
+            Some(Value::Bool(true)) => Some(clear.unwrap_or([0.0; 4])),
+            Some(Value::Bool(false)) => None,
             Some(s) => {
-                return Err(format!(
-                    "Expected field \"target\" to be a string, got {:?}",
-                    s
-                ))
+                let err = format!("Expected a bool, got {:?}", s);
+                errors.push(ConfigError::new("clear", err));
+                None
             }
+            None if clear.is_some() => clear,
+            // vertex stages clear their target by default
+            None if object.get("vs").is_some() => Some([0.0; 4]),
             None => None,
         };
 
-        // parse uniforms
-        let mut unis = HashMap::new();
-        match object.get("uniforms") {
-            Some(Value::Mapping(m)) => {
-                for (key, value) in m {
-                    let mut transpose = false;
-
-                    // get uniform name
-                    let name = match key.as_str() {
-                        Some(s) => {
-                            let mut name = s;
-
-                            // check for transpose suffix
-                            if s.len() > 2 {
-                                let (prefix, suffix) = s.split_at(s.len() - 2);
-                                let suffix = suffix.as_bytes();
-                                if matches!(suffix[0], b'-' | b'^')
-                                    && matches!(suffix[1], b't' | b'T')
-                                {
-                                    name = prefix;
-                                    transpose = true;
-                                }
-                            }
-
-                            CString::new(name).unwrap()
-                        }
-                        None => {
-                            return Err(format!(
-                                "Expected uniform name to be a string, got \"{:?}\"",
-                                key
-                            ))
-                        }
-                    };
-
-                    // parse uniform value
-                    let mut uniform = Uniform::from_yaml(value).map_err(|e| e.to_string())?;
-                    if transpose && uniform.transpose().is_err() {
-                        return Err(format!("Failed to transpose value \"{:?}\"", uniform));
-                    }
+        let viewport = match object.get("viewport") {
+            Some(value) => checked(&mut errors, "viewport", parse_viewport(value)),
+            None => None,
+        };
 
-                    unis.insert(name, uniform);
-                }
+        let enabled = match object.get("enabled") {
+            Some(Value::Bool(b)) => *b,
+            Some(s) => {
+                let err = format!("Expected a bool, got {:?}", s);
+                errors.push(ConfigError::new("enabled", err));
+                true
             }
+            None => true,
+        };
+
+        let when = match object.get("when") {
+            Some(Value::String(s)) => checked(&mut errors, "when", StageCondition::parse(s)),
             Some(s) => {
-                return Err(format!(
-                    "Expected field \"uniforms\" to be a mapping, got {:?}",
-                    s
-                ))
+                let err = format!("Expected a condition like \"bloom > 0\", got {:?}", s);
+                errors.push(ConfigError::new("when", err));
+                None
             }
-            None => (),
-        }
+            None => None,
+        };
 
-        // parse blend mode
-        let blend = match object.get("blend_mode").or(object.get("blend")) {
-            Some(Value::Sequence(s)) => {
-                fn parse_blend_mode(name: &str) -> Result<GLenum, String> {
-                    match name {
-                        "ZERO" => Ok(gl::ZERO),
-                        "ONE" => Ok(gl::ONE),
-                        "SRC_COLOR" => Ok(gl::SRC_COLOR),
-                        "DST_COLOR" => Ok(gl::DST_COLOR),
-                        "SRC_ALPHA" => Ok(gl::SRC_ALPHA),
-                        "DST_ALPHA" => Ok(gl::DST_ALPHA),
-                        "SRC1_COLOR" => Ok(gl::SRC1_COLOR),
-                        "SRC1_ALPHA" => Ok(gl::SRC1_ALPHA),
-                        "CONSTANT_COLOR" => Ok(gl::CONSTANT_COLOR),
-                        "CONSTANT_ALPHA" => Ok(gl::CONSTANT_ALPHA),
-                        "SRC_ALPHA_SATURATE" => Ok(gl::SRC_ALPHA_SATURATE),
-                        "ONE_MINUS_SRC_COLOR" => Ok(gl::ONE_MINUS_SRC_COLOR),
-                        "ONE_MINUS_DST_COLOR" => Ok(gl::ONE_MINUS_DST_COLOR),
-                        "ONE_MINUS_SRC_ALPHA" => Ok(gl::ONE_MINUS_SRC_ALPHA),
-                        "ONE_MINUS_DST_ALPHA" => Ok(gl::ONE_MINUS_DST_ALPHA),
-                        "ONE_MINUS_SRC1_COLOR" => Ok(gl::ONE_MINUS_SRC1_COLOR),
-                        "ONE_MINUS_SRC1_ALPHA" => Ok(gl::ONE_MINUS_SRC1_ALPHA),
-                        "ONE_MINUS_CONSTANT_COLOR" => Ok(gl::ONE_MINUS_CONSTANT_COLOR),
-                        "ONE_MINUS_CONSTANT_ALPHA" => Ok(gl::ONE_MINUS_CONSTANT_ALPHA),
-                        s => Err(format!("Expected blend mode, got \"{:?}\"", s)),
-                    }
+        let when_disabled = match object.get("when_disabled") {
+            Some(s) => match s.as_str() {
+                Some("keep") => DisabledOutput::Keep,
+                Some("clear") => DisabledOutput::Clear,
+                _ => {
+                    let err = format!("Expected either \"keep\" or \"clear\", got {:?}", s);
+                    errors.push(ConfigError::new("when_disabled", err));
+                    DisabledOutput::Keep
                 }
+            },
+            None => DisabledOutput::Keep,
+        };
 
-                match s.as_slice() {
-                    &[Value::String(ref src), Value::String(ref dst)] => {
-                        Some((parse_blend_mode(src)?, parse_blend_mode(dst)?))
-                    }
-                    s => {
-                        return Err(format!(
-                        "Expected field \"blend_mode\" to be a list of two strings, got \"{:?}\"",
-                        s
-                    ))
-                    }
+        // directives which are added to all shaders of the stage
+        let header = checked(&mut errors, "", ShaderHeader::parse(&object)).unwrap_or_default();
+
+        let mut shaders = [None, None, None];
+        for (k, &(name, inline)) in SHADER_FIELDS.iter().enumerate() {
+            shaders[k] = match (object.get(name), object.get(inline)) {
+                (Some(file), Some(_)) => {
+                    let err = format!("Cannot be used together with \"{}\"", name);
+                    errors.push(ConfigError::new(inline, err));
+                    file.as_str().map(|f| ShaderSource::File(f.into()))
                 }
+                (Some(Value::String(f)), None) => Some(ShaderSource::File(f.clone())),
+                (Some(s), None) => {
+                    let err = format!("Expected a file name, got {:?}", s);
+                    errors.push(ConfigError::new(name, err));
+                    None
+                }
+                (None, Some(Value::String(code))) => Some(ShaderSource::Inline(code.clone())),
+                (None, Some(s)) => {
+                    let err = format!("Expected shader source, got {:?}", s);
+                    errors.push(ConfigError::new(inline, err));
+                    None
+                }
+                (None, None) => None,
             }
-            Some(Value::String(_)) => {
-                // TODO: Fix this
-                return Err("Aliases for common blend modes are currently unimplemented".into());
+        }
+
+        let is_compute = shaders[2].is_some();
+        let builder = checked(
+            &mut errors,
+            "",
+            TextureBuilder::parse(&object, true, !is_compute),
+        );
+
+        let kind = match &shaders {
+            [None, None, None] if object.get("composite").is_some() => {
+                if !attachments.is_empty() {
+                    let err = "Composite stages cannot have multiple targets";
+                    errors.push(ConfigError::new("targets", err));
+                }
+                checked(&mut errors, "", CompositeLayer::from_yaml(&object))
+                    .map(|layer| StageKind::Composite { layer })
+            }
+            [None, Some(_), None] => Some(StageKind::Frag {}),
+            [Some(_), _, None] => parse_draw_call(&object, &mut errors),
+            [None, None, Some(_)] => {
+                if target.is_none() {
+                    let err = "Required field for compute shaders not found";
+                    errors.push(ConfigError::new("target", err));
+                }
+                if !attachments.is_empty() {
+                    let err = "Compute shaders cannot have multiple targets";
+                    errors.push(ConfigError::new("targets", err));
+                }
+                if viewport.is_some() {
+                    let err = "Compute shaders cannot have a viewport";
+                    errors.push(ConfigError::new("viewport", err));
+                }
+                checked(&mut errors, "dispatch_size", parse_dispatch(&object))
+                    .map(|dispatch| StageKind::Comp { dispatch })
+            }
+            [None, None, None] => {
+                errors.push(ConfigError::new(
+                    "",
+                    concat!(
+                        "Stage needs a shader, expected \"fs\", \"vs\", \"cs\", ",
+                        "\"fragment\", \"vertex\", \"compute\" or \"composite\"",
+                    ),
+                ));
+                None
+            }
+            _ => {
+                errors.push(ConfigError::new(
+                    "",
+                    "Invalid shader combination, compute shaders cannot be combined with other shaders",
+                ));
+                None
             }
-            Some(s) => return Err(format!("Invalid blend mode value, got \"{:?}\"", s)),
-            None => None,
         };
 
-        // read all shaders to strings
-        let mut lut = Vec::new();
-        let shaders: [Option<(String, String)>; 3] = {
-            let mut out = [None, None, None];
-            for (k, &name) in ["vs", "fs", "cs"].iter().enumerate() {
-                out[k] = match object.get(name) {
-                    Some(Value::String(f)) => match std::fs::read_to_string(f) {
-                        Ok(s) => Some((s, f.into())),
-                        Err(e) => return Err(format!("{}, {}", e.to_string(), f)),
-                    },
-                    Some(s) => {
-                        return Err(format!(
-                            "Expected shader field to be a filename, got {:?}",
-                            s
-                        ))
-                    }
-                    None => None,
-                }
+        // checked on the shaders so that it runs even if the stage kind has errors
+        if let Some(builder) = &builder {
+            match (is_compute, builder.resolution.as_slice()) {
+                (true, &[]) => errors.push(ConfigError::new(
+                    "resolution",
+                    "Required field for compute shaders not found",
+                )),
+                (true, _) | (false, &[]) | (false, &[_, _]) => (),
+                _ => errors.push(ConfigError::new("resolution", "Expected a 2D resolution")),
             }
+        }
+
+        match (kind, builder, errors.is_empty()) {
+            (Some(kind), Some(builder), true) => Ok(Self {
+                target,
+                attachments,
+                kind,
+                shaders,
+                header,
+                unis,
+                blend,
+                clear,
+                viewport,
+                enabled,
+                when,
+                when_disabled,
+                builder,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+/// Parses `target` and `targets`, the first of multiple targets is the main one.
+fn parse_targets(
+    object: &Value,
+    errors: &mut Vec<ConfigError>,
+) -> Option<(Option<CString>, Vec<CString>)> {
+    let mut target = match object.get("target") {
+        Some(Value::String(s)) => Some(CString::new(s.as_str()).unwrap()),
+        Some(s) => {
+            let err = format!("Expected a string, got {:?}", s);
+            errors.push(ConfigError::new("target", err));
+            return None;
+        }
+        None => None,
+    };
+
+    let names = match object.get("targets") {
+        Some(Value::Sequence(names)) => names,
+        Some(s) => {
+            let err = format!("Expected a list of strings, got {:?}", s);
+            errors.push(ConfigError::new("targets", err));
+            return None;
+        }
+        None => return Some((target, Vec::new())),
+    };
+
+    if target.is_some() {
+        errors.push(ConfigError::new(
+            "targets",
+            "Cannot be used together with \"target\"",
+        ));
+        return None;
+    }
+
+    if names.is_empty() || names.len() > MAX_ATTACHMENTS {
+        let err = format!(
+            "Expected 1 to {} names, got {}",
+            MAX_ATTACHMENTS,
+            names.len()
+        );
+        errors.push(ConfigError::new("targets", err));
+        return None;
+    }
 
-            out
+    let mut attachments = Vec::new();
+    for (k, name) in names.iter().enumerate() {
+        let name = match name {
+            Value::String(s) => CString::new(s.as_str()).unwrap(),
+            s => {
+                let err = format!("Expected a string, got {:?}", s);
+                errors.push(ConfigError::new(format!("targets[{}]", k), err));
+                return None;
+            }
         };
 
-        match shaders {
-            // handle full screen fragment shader stages
-            [None, Some(fs), None] => {
-                let vs = PASS_VERT;
-                let fs = preprocess(&fs.0, &fs.1, &mut lut)?;
+        if target.as_ref() == Some(&name) || attachments.contains(&name) {
+            let err = format!("Target {:?} is listed more than once", name);
+            errors.push(ConfigError::new("targets", err));
+            return None;
+        }
 
-                let vs_id =
-                    compile_shader(&vs, gl::VERTEX_SHADER).map_err(|e| process_error(e, &lut))?;
-                let fs_id =
-                    compile_shader(&fs, gl::FRAGMENT_SHADER).map_err(|e| process_error(e, &lut))?;
+        match target {
+            None => target = Some(name),
+            Some(_) => attachments.push(name),
+        }
+    }
 
-                let sh_ids = vec![vs_id, fs_id];
-                let prog_id = link_program(&sh_ids)?;
+    Some((target, attachments))
+}
 
-                let builder = TextureBuilder::parse(&object, true, true)?;
+/// Parses the `uniforms` of a stage, a `-t` or `^T` suffix transposes a matrix.
+fn parse_uniforms(object: &Value, errors: &mut Vec<ConfigError>) -> HashMap<CString, Uniform> {
+    let mut unis = HashMap::new();
+    let map = match object.get("uniforms") {
+        Some(Value::Mapping(m)) => m,
+        Some(s) => {
+            let err = format!("Expected a mapping, got {:?}", s);
+            errors.push(ConfigError::new("uniforms", err));
+            return unis;
+        }
+        None => return unis,
+    };
+
+    for (key, value) in map {
+        let mut transpose = false;
 
-                if !matches!(builder.resolution.as_slice(), &[] | &[_, _]) {
-                    return Err("Expected \"resolution\" to be 2D".into());
+        // get uniform name
+        let name = match key.as_str() {
+            Some(s) => {
+                let mut name = s;
+
+                // check for transpose suffix
+                if s.len() > 2 {
+                    if let Some(prefix) = ["-t", "-T", "^t", "^T"]
+                        .iter()
+                        .find_map(|suffix| s.strip_suffix(suffix))
+                    {
+                        name = prefix;
+                        transpose = true;
+                    }
                 }
 
-                let kind = StageKind::Frag {};
-
-                Ok(Stage {
-                    prog_id,
-                    target,
-                    kind,
-                    sh_ids,
-                    deps,
-                    unis,
-                    blend,
-                    perf,
-                    builder,
-                })
+                name
+            }
+            None => {
+                let err = format!("Expected uniform name to be a string, got {:?}", key);
+                errors.push(ConfigError::new("uniforms", err));
+                continue;
             }
+        };
 
-            // handle vertex shader stages
-            [Some(vs), fs_opt, None] => {
-                let vs = preprocess(&vs.0, &vs.1, &mut lut)?;
-                let fs = match fs_opt {
-                    Some(fs) => preprocess(&fs.0, &fs.1, &mut lut)?,
-                    None => PASS_FRAG.into(),
-                };
-
-                let vs_id =
-                    compile_shader(&vs, gl::VERTEX_SHADER).map_err(|e| process_error(e, &lut))?;
-                let fs_id =
-                    compile_shader(&fs, gl::FRAGMENT_SHADER).map_err(|e| process_error(e, &lut))?;
-
-                let sh_ids = vec![vs_id, fs_id];
-                let prog_id = link_program(&sh_ids)?;
-
-                let count = match object.get("count") {
-                    Some(s) => match s.as_u64() {
-                        Some(n) => n as _,
-                        _ => {
-                            return Err(format!(
-                                "Expected vertex count to be an unsigned int, got {:?}",
-                                s
-                            ))
-                        }
-                    },
-                    _ => 1024,
-                };
-
-                let mode = match object.get("mode") {
-                    Some(s) => match s.as_str() {
-                        Some("LINE_LOOP") => gl::LINE_LOOP,
-                        Some("LINE_STRIP") => gl::LINE_STRIP,
-                        Some("LINES") => gl::LINES,
-                        Some("POINTS") => gl::POINTS,
-                        Some("TRIANGLE_FAN") => gl::TRIANGLE_FAN,
-                        Some("TRIANGLE_STRIP") => gl::TRIANGLE_STRIP,
-                        Some("TRIANGLES") => gl::TRIANGLES,
-                        _ => return Err(format!("Invalid vertex mode: {:?}", s)),
-                    },
-                    _ => gl::TRIANGLES,
-                };
-
-                let thickness = match object
-                    .get("thickness")
-                    .or(object.get("stroke_weight"))
-                    .or(object.get("point_size"))
-                    .or(object.get("line_width"))
-                    .map(Value::as_f64)
-                {
-                    Some(Some(t)) if t > 0.0 => t as f32,
-                    None => 1.0,
-                    Some(s) => {
-                        return Err(format!(
-                            "Expected \"thickness\" to be positive float, got {:?}",
-                            s
-                        ))
-                    }
-                };
+        let field = format!("uniforms.{}", name);
+        let mut uniform = match Uniform::from_yaml(value) {
+            Ok(uniform) => uniform,
+            Err(err) => {
+                errors.push(ConfigError::new(field, err.to_string()));
+                continue;
+            }
+        };
 
-                let builder = TextureBuilder::parse(&object, true, true)?;
+        if transpose && uniform.transpose().is_err() {
+            let err = format!("Failed to transpose value {:?}", uniform);
+            errors.push(ConfigError::new(field, err));
+            continue;
+        }
 
-                if !matches!(builder.resolution.as_slice(), &[] | &[_, _]) {
-                    return Err("Expected \"resolution\" to be 2D".into());
-                }
+        unis.insert(CString::new(name).unwrap(), uniform);
+    }
+
+    unis
+}
+
+/// Parses `blend` or `blend_mode`, composite layers blend with alpha by default.
+fn parse_blend(object: &Value) -> Result<Option<BlendMode>, String> {
+    match object.get("blend_mode").or(object.get("blend")) {
+        Some(Value::Sequence(s)) => match s.as_slice() {
+            [Value::String(src), Value::String(dst)] => Ok(Some(BlendMode {
+                src: parse_blend_factor(src)?,
+                dst: parse_blend_factor(dst)?,
+                equation: gl::FUNC_ADD,
+            })),
+            s => Err(format!("Expected a list of two blend factors, got {:?}", s)),
+        },
+        Some(Value::String(s)) => BlendMode::from_alias(s),
+        Some(s) => Err(format!("Expected a blend mode, got {:?}", s)),
+        // composite layers are drawn on top of the target by default
+        None if object.get("composite").is_some() => BlendMode::from_alias("alpha"),
+        None => Ok(None),
+    }
+}
+
+/// Replaces the equation of the blend mode with the one in `blend_equation`.
+fn parse_blend_equation_field(
+    object: &Value,
+    blend: Option<BlendMode>,
+) -> Result<Option<BlendMode>, String> {
+    match (blend, object.get("blend_equation")) {
+        (Some(mode), Some(Value::String(s))) => Ok(Some(BlendMode {
+            equation: parse_blend_equation(s)?,
+            ..mode
+        })),
+        (None, Some(Value::String(_))) => Err("Requires a blend mode".into()),
+        (_, Some(s)) => Err(format!("Expected a string, got {:?}", s)),
+        (blend, None) => Ok(blend),
+    }
+}
+
+/// Parses `clear_color`, the alpha may be left out.
+fn parse_clear_color(object: &Value) -> Result<Option<[f32; 4]>, String> {
+    let s = match object.get("clear_color") {
+        Some(Value::Sequence(s)) => s,
+        Some(s) => return Err(format!("Expected a list of 3 or 4 numbers, got {:?}", s)),
+        None => return Ok(None),
+    };
+
+    let color: Vec<_> = s.iter().filter_map(Value::as_f64).collect();
+    match color.as_slice() {
+        &[r, g, b, a] if s.len() == 4 => Ok(Some([r as f32, g as f32, b as f32, a as f32])),
+        &[r, g, b] if s.len() == 3 => Ok(Some([r as f32, g as f32, b as f32, 1.0])),
+        _ => Err(format!("Expected a list of 3 or 4 numbers, got {:?}", s)),
+    }
+}
 
-                let kind = StageKind::Vert {
-                    count,
-                    mode,
-                    thickness,
-                };
-
-                Ok(Stage {
-                    prog_id,
-                    target,
-                    kind,
-                    sh_ids,
-                    deps,
-                    unis,
-                    blend,
-                    perf,
-                    builder,
-                })
+/// Parses the draw call of a vertex stage, which may be grouped in `geometry`.
+fn parse_draw_call(object: &Value, errors: &mut Vec<ConfigError>) -> Option<StageKind> {
+    const KEYS: [&str; 5] = ["count", "mode", "primitive", "instances", "instance_count"];
+
+    let (prefix, geometry) = match object.get("geometry") {
+        Some(g) if g.is_mapping() => {
+            for &key in KEYS.iter().filter(|&&key| object.get(key).is_some()) {
+                let err = "Expected this inside \"geometry\" when both are given";
+                errors.push(ConfigError::new(key, err));
+            }
+            ("geometry.", g)
+        }
+        Some(s) => {
+            let err = format!("Expected a mapping, got {:?}", s);
+            errors.push(ConfigError::new("geometry", err));
+            return None;
+        }
+        None => ("", object),
+    };
+
+    let mut field_error = |key: &str, err: String| {
+        errors.push(ConfigError::new(format!("{}{}", prefix, key), err));
+    };
+
+    let count = match geometry.get("count") {
+        Some(s) => match s.as_u64() {
+            Some(n) => Some(n as _),
+            None => {
+                field_error("count", format!("Expected an unsigned int, got {:?}", s));
+                None
+            }
+        },
+        None => Some(1024),
+    };
+
+    let mode_key = match geometry.get("primitive") {
+        Some(_) if geometry.get("mode").is_none() => "primitive",
+        _ => "mode",
+    };
+    let mode = match geometry.get(mode_key) {
+        Some(s) => match s.as_str().and_then(parse_vertex_mode) {
+            Some(mode) => Some(mode),
+            None => {
+                field_error(mode_key, format!("Unknown vertex mode {:?}", s));
+                None
+            }
+        },
+        None => Some(gl::TRIANGLES),
+    };
+
+    let instances_key = match geometry.get("instance_count") {
+        Some(_) if geometry.get("instances").is_none() => "instance_count",
+        _ => "instances",
+    };
+    let instances = match geometry.get(instances_key) {
+        Some(s) => match s.as_u64() {
+            Some(n) if n > 0 => Some(n as _),
+            _ => {
+                field_error(
+                    instances_key,
+                    format!("Expected a positive int, got {:?}", s),
+                );
+                None
             }
+        },
+        None => Some(1),
+    };
+
+    let thickness = ["thickness", "stroke_weight", "point_size", "line_width"]
+        .iter()
+        .find_map(|&key| Some((key, object.get(key)?)));
+    let thickness = match thickness {
+        Some((key, s)) => match s.as_f64() {
+            Some(t) if t > 0.0 => Some(t as f32),
+            _ => {
+                let err = format!("Expected a positive float, got {:?}", s);
+                errors.push(ConfigError::new(key, err));
+                None
+            }
+        },
+        None => Some(1.0),
+    };
+
+    Some(StageKind::Vert {
+        count: count?,
+        mode: mode?,
+        instances: instances?,
+        thickness: thickness?,
+    })
+}
 
-            // handle compute shader stages
-            [None, None, Some(cs)] => {
-                let cs = preprocess(&cs.0, &cs.1, &mut lut)?;
-
-                let cs_id =
-                    compile_shader(&cs, gl::COMPUTE_SHADER).map_err(|e| process_error(e, &lut))?;
-                let sh_ids = vec![cs_id];
-                let prog_id = link_program(&sh_ids)?;
-
-                // get target resolution
-                let dispatch = match object
-                    .get("dispatch_size")
-                    .or_else(|| object.get("dispatch"))
-                {
-                    Some(Value::Sequence(dims)) => {
-                        if dims.is_empty() || dims.len() > 3 {
-                            return Err(format!(
-                                "Field \"dispatch_size\" must be a list of 1 to 3 numbers, got {} elements",
-                                dims.len()
-                            ));
-                        }
-
-                        let mut out = [1; 3];
-                        for (k, dim) in dims.iter().enumerate() {
-                            match dim.as_i64() {
-                                Some(n) if n > 0 => {
-                                    // OpenGL must allow 65535 in each dimension
-                                    // https://www.khronos.org/opengl/wiki/Compute_Shader#Limitations
-                                    if n > 65535 {
-                                        return Err(format!(
-                                            "Values of \"dispatch_size\" may not exceed 65535 in any dimension, got {:?}",
-                                            n
-                                        ))
-                                    }
-
-                                    out[k] = n as _
-                                },
-                                _ => return Err(format!(
-                                    "Expected \"dispatch_size\" to be a list of positive numbers, got {:?}",
-                                    dims
-                                )),
-                            };
-                        }
-
-                        out
-                    }
-                    Some(s) => {
-                        return Err(format!(
-                        "Expected \"dispatch_size\" to be a list of unsigned integers, got {:?}",
-                        s
-                    ))
-                    }
-                    None => {
-                        return Err(
-                            "Field \"dispatch_size\" is mandatory for compute shaders".into()
-                        )
-                    }
-                };
+/// Parses the number of work groups of a compute stage.
+fn parse_dispatch(object: &Value) -> Result<[GLuint; 3], String> {
+    let dims = match object
+        .get("dispatch_size")
+        .or_else(|| object.get("dispatch"))
+    {
+        Some(Value::Sequence(dims)) if (1..=3).contains(&dims.len()) => dims,
+        Some(s) => {
+            return Err(format!(
+                "Expected a list of 1 to 3 numbers between 1 and 65535, got {:?}",
+                s
+            ))
+        }
+        None => return Err("Required field for compute shaders not found".into()),
+    };
+
+    let mut out = [1; 3];
+    for (k, dim) in dims.iter().enumerate() {
+        // OpenGL must allow 65535 in each dimension
+        // https://www.khronos.org/opengl/wiki/Compute_Shader#Limitations
+        match dim.as_i64() {
+            Some(n) if n > 0 && n <= 65535 => out[k] = n as _,
+            _ => {
+                return Err(format!(
+                    "Expected a list of 1 to 3 numbers between 1 and 65535, got {:?}",
+                    dims
+                ))
+            }
+        }
+    }
 
-                let builder = TextureBuilder::parse(&object, true, false)?;
+    Ok(out)
+}
 
-                if builder.resolution.as_slice().is_empty() {
-                    return Err("Field \"resolution\" is mandatory for compute shaders".into());
-                }
+impl Stage {
+    pub fn from_yaml(object: Value) -> Result<Self, String> {
+        let config = StageConfig::from_yaml(object).map_err(|errors| errors[0].to_string())?;
+        Self::build(config)
+    }
 
-                if target.is_none() {
-                    return Err("Field \"target\" is mandatory for compute shaders".into());
+    /// Reads and compiles the shaders of a parsed stage.
+    pub fn build(config: StageConfig) -> Result<Self, String> {
+        let StageConfig {
+            target,
+            attachments,
+            kind,
+            shaders,
+            header,
+            unis,
+            blend,
+            clear,
+            viewport,
+            enabled,
+            when,
+            when_disabled,
+            builder,
+        } = config;
+
+        // read all shaders to strings, inline shaders are named after the stage
+        let label = target
+            .as_ref()
+            .map_or("screen".into(), |name| name.to_string_lossy().into_owned());
+        let mut lut = Vec::new();
+        let mut sources = [None, None, None];
+        for (k, shader) in shaders.iter().enumerate() {
+            sources[k] = match shader {
+                Some(ShaderSource::File(f)) => match std::fs::read_to_string(f) {
+                    Ok(s) => Some(header.apply(preprocess(&s, f, &mut lut)?)),
+                    Err(e) => return Err(format!("{}, {}", e.to_string(), f)),
+                },
+                Some(ShaderSource::Inline(code)) => {
+                    let name = inline_shader_name(SHADER_FIELDS[k].1, &label);
+                    Some(header.apply(preprocess(code, &name, &mut lut)?))
                 }
+                None => None,
+            };
+        }
+
+        let compile = |sources: &[(&str, GLenum)], lut: &[String]| {
+            let texts: Vec<_> = sources.iter().map(|&(source, _)| source).collect();
+            link_program_cached(&texts, || {
+                sources
+                    .iter()
+                    .map(|&(source, ty)| {
+                        compile_shader(source, ty).map_err(|e| process_error(e, lut))
+                    })
+                    .collect()
+            })
+        };
 
-                let kind = StageKind::Comp { dispatch };
-
-                Ok(Stage {
-                    prog_id,
-                    target,
-                    kind,
-                    sh_ids,
-                    deps,
-                    unis,
-                    blend,
-                    perf,
-                    builder,
-                })
+        let [vs, fs, cs] = &sources;
+        let (prog_id, sh_ids) = match &kind {
+            StageKind::Composite { .. } => compile(
+                &[
+                    (PASS_VERT, gl::VERTEX_SHADER),
+                    (COMPOSITE_FRAG, gl::FRAGMENT_SHADER),
+                ],
+                &lut,
+            )?,
+            StageKind::Frag {} => {
+                let fs = fs.as_deref().unwrap_or(PASS_FRAG);
+                compile(
+                    &[(PASS_VERT, gl::VERTEX_SHADER), (fs, gl::FRAGMENT_SHADER)],
+                    &lut,
+                )?
             }
+            StageKind::Vert { .. } => {
+                let vs = vs.as_deref().unwrap_or(PASS_VERT);
+                let fs = fs.as_deref().unwrap_or(PASS_FRAG);
+                compile(&[(vs, gl::VERTEX_SHADER), (fs, gl::FRAGMENT_SHADER)], &lut)?
+            }
+            StageKind::Comp { .. } => {
+                let cs = cs.as_deref().unwrap_or_default();
+                compile(&[(cs, gl::COMPUTE_SHADER)], &lut)?
+            }
+        };
 
-            // Handle everything else
-            _ => Err("Invalid shader configuration".to_string()),
+        if matches!(kind, StageKind::Frag {} | StageKind::Vert { .. }) {
+            check_output_count(prog_id, &attachments)?;
+        }
+
+        Ok(Stage {
+            prog_id,
+            target,
+            attachments,
+            kind,
+            sh_ids,
+            deps: Vec::new(),
+            unis,
+            resolution_kind: gl::FLOAT_VEC4,
+            blend,
+            clear,
+            viewport,
+            enabled,
+            when,
+            when_disabled,
+            perf: RunningAverage::new(),
+            gpu_perf: RunningAverage::new(),
+            gpu_timer: GpuTimer::new(),
+            builder,
+            sources: lut,
+        })
+    }
+
+    /// Returns the average time spent on this stage in milliseconds.
+    ///
+    /// This is the time measured on the GPU if the driver supports timer
+    /// queries, and the time spent issuing the commands on the CPU otherwise.
+    /// The second value names the clock which was used.
+    pub fn timing(&self) -> (f32, &'static str) {
+        match self.gpu_timer {
+            Some(_) => (self.gpu_perf.get(), "GPU"),
+            None => (self.perf.get(), "CPU"),
+        }
+    }
+
+    /// Files the shaders of this stage were read from, including all includes.
+    ///
+    /// Inline shaders are part of the pipeline file and library files are
+    /// built in, so both are left out.
+    pub fn files(&self) -> impl Iterator<Item = &String> {
+        self.sources
+            .iter()
+            .filter(|source| !is_inline_shader(source))
+    }
+
+    /// Returns a short name for this stage, based on its render target.
+    pub fn label(&self) -> String {
+        match &self.target {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => "screen".into(),
         }
     }
 
@@ -402,6 +920,34 @@ impl Stage {
     }
 }
 
+/// Makes sure the fragment shader writes exactly one output per render target.
+fn check_output_count(prog_id: GLuint, attachments: &[CString]) -> Result<(), String> {
+    if attachments.is_empty() {
+        return Ok(());
+    }
+
+    let mut count = 0;
+    unsafe {
+        gl::GetProgramInterfaceiv(
+            prog_id,
+            gl::PROGRAM_OUTPUT,
+            gl::ACTIVE_RESOURCES,
+            &mut count,
+        );
+        gl_debug_check!();
+    }
+
+    let expected = 1 + attachments.len();
+    if count as usize != expected {
+        return Err(format!(
+            "Stage declares {} targets, but the fragment shader writes {} outputs",
+            expected, count
+        ));
+    }
+
+    Ok(())
+}
+
 impl Drop for Stage {
     fn drop(&mut self) {
         unsafe {
@@ -0,0 +1,235 @@
+use std::{collections::HashMap, convert::TryFrom, io::Write, path::Path};
+
+use serde_yaml::{Mapping, Value};
+
+use super::{Midi, OscReceiver, OscUniformValue, MIDI_N};
+
+/// Values of live controls which are kept between sessions.
+///
+/// Only the slider positions and the OSC value store are saved. Buttons are
+/// momentary, so restoring them would only cause confusion.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SavedState {
+    pub sliders: Vec<f32>,
+    pub osc_values: HashMap<String, OscUniformValue>,
+}
+
+impl SavedState {
+    /// Takes a snapshot of the current control values.
+    pub fn capture(midi: &Midi, osc: &OscReceiver) -> Self {
+        Self {
+            sliders: midi.sliders.to_vec(),
+            osc_values: osc.get_all_values(),
+        }
+    }
+
+    /// Seeds the controls with the saved values.
+    ///
+    /// OSC mappings which already received a value are left untouched.
+    pub fn restore(&self, midi: &mut Midi, osc: &OscReceiver) {
+        for (slider, &value) in midi.sliders.iter_mut().zip(self.sliders.iter()) {
+            *slider = value;
+        }
+
+        osc.seed_values(&self.osc_values);
+    }
+
+    pub fn from_yaml(object: &Value) -> Result<Self, String> {
+        let sliders = match object.get("sliders") {
+            Some(Value::Sequence(xs)) if xs.len() <= MIDI_N => {
+                let mut sliders = Vec::with_capacity(xs.len());
+                for x in xs {
+                    match x.as_f64() {
+                        Some(x) => sliders.push(x as f32),
+                        None => {
+                            return Err(format!(
+                                "Expected \"sliders\" to be a list of numbers, got {:?}",
+                                xs
+                            ))
+                        }
+                    }
+                }
+                sliders
+            }
+            None => Vec::new(),
+            s => {
+                return Err(format!(
+                    "Expected \"sliders\" to be a list of up to {} numbers, got {:?}",
+                    MIDI_N, s
+                ))
+            }
+        };
+
+        let mut osc_values = HashMap::new();
+        match object.get("osc") {
+            Some(Value::Mapping(map)) => {
+                for (address, value) in map {
+                    let address = match address.as_str() {
+                        Some(s) => s.to_owned(),
+                        None => {
+                            return Err(format!(
+                                "Expected OSC address to be a string, got {:?}",
+                                address
+                            ))
+                        }
+                    };
+
+                    let value = match value {
+                        Value::Bool(b) => OscUniformValue::Bool(*b),
+                        // values which don't fit the common types keep their precision
+                        Value::Number(n) if n.is_i64() => match n.as_i64() {
+                            Some(i) if i32::try_from(i).is_ok() => OscUniformValue::Int(i as _),
+                            Some(i) => OscUniformValue::Long(i),
+                            None => return Err(format!("OSC value {:?} is out of range", n)),
+                        },
+                        Value::Number(n) => match n.as_f64() {
+                            Some(f) if f as f32 as f64 == f => OscUniformValue::Float(f as _),
+                            Some(f) => OscUniformValue::Double(f),
+                            None => return Err(format!("OSC value {:?} is out of range", n)),
+                        },
+                        s => {
+                            return Err(format!(
+                                "Expected value of {:?} to be a number or bool, got {:?}",
+                                address, s
+                            ))
+                        }
+                    };
+
+                    osc_values.insert(address, value);
+                }
+            }
+            None => {}
+            s => return Err(format!("Expected \"osc\" to be a mapping, got {:?}", s)),
+        }
+
+        Ok(Self {
+            sliders,
+            osc_values,
+        })
+    }
+
+    pub fn to_yaml(&self) -> Value {
+        let sliders = self
+            .sliders
+            .iter()
+            .map(|&x| Value::from(x as f64))
+            .collect();
+
+        // sort addresses to keep the file diffable
+        let mut addresses: Vec<_> = self.osc_values.keys().collect();
+        addresses.sort();
+
+        let mut osc = Mapping::new();
+        for address in addresses {
+            let value = match self.osc_values[address] {
+                OscUniformValue::Float(f) => Value::from(f as f64),
+                OscUniformValue::Int(i) => Value::from(i),
+                OscUniformValue::Bool(b) => Value::from(b),
+                OscUniformValue::Double(d) => Value::from(d),
+                OscUniformValue::Long(l) => Value::from(l),
+            };
+
+            osc.insert(Value::from(address.as_str()), value);
+        }
+
+        let mut object = Mapping::new();
+        object.insert("sliders".into(), Value::Sequence(sliders));
+        object.insert("osc".into(), Value::Mapping(osc));
+        Value::Mapping(object)
+    }
+
+    /// Reads a state file, logging any problems.
+    pub fn load(path: &Path) -> Option<Self> {
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                log::error!("Failed to open state file {}: {}", path.display(), err);
+                return None;
+            }
+        };
+
+        let object: Value = match serde_yaml::from_reader(file) {
+            Ok(object) => object,
+            Err(err) => {
+                log::error!("Failed to parse state file {}: {}", path.display(), err);
+                return None;
+            }
+        };
+
+        match Self::from_yaml(&object) {
+            Ok(state) => {
+                log::info!("Loaded saved state from {}", path.display());
+                Some(state)
+            }
+            Err(err) => {
+                log::error!("Invalid state file {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    /// Writes the state to a file, logging any problems.
+    pub fn store(&self, path: &Path) {
+        let mut file = match std::fs::File::create(path) {
+            Ok(file) => file,
+            Err(err) => {
+                log::error!("Failed to create state file {}: {}", path.display(), err);
+                return;
+            }
+        };
+
+        if let Err(err) =
+            file.write_all(b"# This file was automatically generated by Sh4derJockey.\n")
+        {
+            log::error!("Failed to store state: {:?}", err);
+            return;
+        }
+
+        match serde_yaml::to_writer(file, &self.to_yaml()) {
+            Ok(_) => log::info!("Stored state in {}", path.display()),
+            Err(err) => log::error!("Failed to store state: {:?}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut osc_values = HashMap::new();
+        osc_values.insert("/fader/1".to_owned(), OscUniformValue::Float(0.25));
+        osc_values.insert("/scene".to_owned(), OscUniformValue::Int(3));
+        osc_values.insert("/strobe".to_owned(), OscUniformValue::Bool(true));
+        osc_values.insert("/clock".to_owned(), OscUniformValue::Double(1234567.891));
+        osc_values.insert("/stamp".to_owned(), OscUniformValue::Long(1 << 40));
+
+        let state = SavedState {
+            sliders: vec![0.0, 0.5, 1.0],
+            osc_values,
+        };
+
+        let text = serde_yaml::to_string(&state.to_yaml()).unwrap();
+        let object: Value = serde_yaml::from_str(&text).unwrap();
+        assert_eq!(SavedState::from_yaml(&object), Ok(state));
+    }
+
+    #[test]
+    fn whole_floats_stay_floats() {
+        let object: Value = serde_yaml::from_str("osc: { /a: 1.0, /b: 1 }").unwrap();
+        let state = SavedState::from_yaml(&object).unwrap();
+        assert_eq!(state.osc_values["/a"], OscUniformValue::Float(1.0));
+        assert_eq!(state.osc_values["/b"], OscUniformValue::Int(1));
+    }
+
+    #[test]
+    fn invalid_state() {
+        let parse = |yaml: &str| SavedState::from_yaml(&serde_yaml::from_str(yaml).unwrap());
+        assert!(parse("sliders: [0.5, high]").is_err());
+        assert!(parse("osc: { /a: fast }").is_err());
+        assert!(parse("osc: [1, 2]").is_err());
+        assert_eq!(parse("{}"), Ok(SavedState::default()));
+    }
+}
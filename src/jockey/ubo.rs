@@ -0,0 +1,561 @@
+use std::{collections::HashMap, ffi::CString};
+
+use gl::types::*;
+use serde_yaml::Value;
+
+use super::{LayerParam, OscUniformValue};
+use crate::util::*;
+
+/// How the components of a block field are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UboScalar {
+    Float,
+    Int,
+    UInt,
+    Bool,
+}
+
+/// Type of a field in a uniform block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UboType {
+    Scalar(UboScalar),
+    /// A vector with 2 to 4 components.
+    Vec(UboScalar, usize),
+    /// A square float matrix with 2 to 4 columns.
+    Mat(usize),
+}
+
+impl UboType {
+    /// Parses the GLSL name of a type, like `float`, `ivec3` or `mat4`.
+    pub fn parse(name: &str) -> Option<Self> {
+        let scalar = |prefix: &str| match prefix {
+            "" => Some(UboScalar::Float),
+            "i" => Some(UboScalar::Int),
+            "u" => Some(UboScalar::UInt),
+            "b" => Some(UboScalar::Bool),
+            _ => None,
+        };
+
+        match name {
+            "float" => return Some(Self::Scalar(UboScalar::Float)),
+            "int" => return Some(Self::Scalar(UboScalar::Int)),
+            "uint" => return Some(Self::Scalar(UboScalar::UInt)),
+            "bool" => return Some(Self::Scalar(UboScalar::Bool)),
+            _ => (),
+        }
+
+        // the last char may be more than one byte wide in a typo
+        let (k, _) = name.char_indices().last()?;
+        let (body, n) = name.split_at(k);
+        let n = match n.parse() {
+            Ok(n) if (2..=4).contains(&n) => n,
+            _ => return None,
+        };
+
+        match body {
+            "mat" => Some(Self::Mat(n)),
+            _ => Some(Self::Vec(scalar(body.strip_suffix("vec")?)?, n)),
+        }
+    }
+
+    /// Number of values in the config, matrices are given row by row.
+    pub fn components(&self) -> usize {
+        match self {
+            Self::Scalar(_) => 1,
+            Self::Vec(_, n) => *n,
+            Self::Mat(n) => n * n,
+        }
+    }
+
+    /// Base alignment and size in bytes under the `std140` layout.
+    ///
+    /// A `vec3` is aligned like a `vec4` but only takes 12 bytes, and every
+    /// column of a matrix is padded to a `vec4`.
+    pub fn std140(&self) -> (usize, usize) {
+        match self {
+            Self::Scalar(_) => (4, 4),
+            Self::Vec(_, 2) => (8, 8),
+            Self::Vec(_, n) => (16, 4 * n),
+            Self::Mat(n) => (16, 16 * n),
+        }
+    }
+
+    /// Offset in bytes of the `k`-th value from the start of the field.
+    fn component_offset(&self, k: usize) -> usize {
+        match self {
+            Self::Mat(n) => {
+                let (row, col) = (k / n, k % n);
+                16 * col + 4 * row
+            }
+            _ => 4 * k,
+        }
+    }
+
+    fn scalar(&self) -> UboScalar {
+        match self {
+            Self::Scalar(s) | Self::Vec(s, _) => *s,
+            Self::Mat(_) => UboScalar::Float,
+        }
+    }
+
+    /// The type OpenGL reports for a uniform of this type.
+    pub fn gl_kind(&self) -> GLenum {
+        use UboScalar::*;
+        match *self {
+            Self::Scalar(Float) => gl::FLOAT,
+            Self::Scalar(Int) => gl::INT,
+            Self::Scalar(UInt) => gl::UNSIGNED_INT,
+            Self::Scalar(Bool) => gl::BOOL,
+            Self::Vec(Float, n) => [gl::FLOAT_VEC2, gl::FLOAT_VEC3, gl::FLOAT_VEC4][n - 2],
+            Self::Vec(Int, n) => [gl::INT_VEC2, gl::INT_VEC3, gl::INT_VEC4][n - 2],
+            Self::Vec(UInt, n) => [
+                gl::UNSIGNED_INT_VEC2,
+                gl::UNSIGNED_INT_VEC3,
+                gl::UNSIGNED_INT_VEC4,
+            ][n - 2],
+            Self::Vec(Bool, n) => [gl::BOOL_VEC2, gl::BOOL_VEC3, gl::BOOL_VEC4][n - 2],
+            Self::Mat(n) => [gl::FLOAT_MAT2, gl::FLOAT_MAT3, gl::FLOAT_MAT4][n - 2],
+        }
+    }
+}
+
+/// A member of a uniform block, with its place in the buffer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UboField {
+    pub name: String,
+    pub kind: UboType,
+    /// Offset in bytes from the start of the block.
+    pub offset: usize,
+    pub values: Vec<LayerParam>,
+}
+
+impl UboField {
+    fn from_yaml(object: &Value) -> Result<(String, UboType, Vec<LayerParam>), String> {
+        let name = match object.get("name") {
+            Some(Value::String(s)) => s.clone(),
+            s => return Err(format!("Expected \"name\" to be a string, got {:?}", s)),
+        };
+
+        let kind = match object.get("type").and_then(Value::as_str) {
+            Some(s) => UboType::parse(s)
+                .ok_or_else(|| format!("Unknown type {:?} of field {:?}", s, name))?,
+            None => UboType::Scalar(UboScalar::Float),
+        };
+
+        let values = match (object.get("value"), kind.components()) {
+            (None, n) => vec![LayerParam::Value(0.0); n],
+            (Some(Value::Sequence(s)), n) if s.len() == n => s
+                .iter()
+                .map(LayerParam::from_yaml)
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("{}: {}", name, e))?,
+            (Some(s), 1) => {
+                vec![LayerParam::from_yaml(s).map_err(|e| format!("{}: {}", name, e))?]
+            }
+            (Some(s), n) => {
+                return Err(format!(
+                    "Expected \"value\" of field {:?} to be a list of {} values, got {:?}",
+                    name, n, s
+                ))
+            }
+        };
+
+        Ok((name, kind, values))
+    }
+}
+
+/// A `layout(std140)` uniform block which is filled from the pipeline file.
+///
+/// The contents are kept CPU-side and only uploaded after one of the fields
+/// changed. The buffer is created on the first upload.
+#[derive(Debug)]
+pub struct UniformBlock {
+    pub name: CString,
+    /// Binding point of the buffer, shared by all stages.
+    pub binding: GLuint,
+    pub fields: Vec<UboField>,
+    data: Vec<u32>,
+    buffer: GLuint,
+    dirty: bool,
+}
+
+impl UniformBlock {
+    /// Parses the list of fields of a block and places them like `std140` does.
+    pub fn from_yaml(name: &str, binding: GLuint, object: &Value) -> Result<Self, String> {
+        let list = match object {
+            Value::Sequence(s) if !s.is_empty() => s,
+            s => {
+                return Err(format!(
+                    "Expected uniform block {:?} to be a list of fields, got {:?}",
+                    name, s
+                ))
+            }
+        };
+
+        let mut fields: Vec<UboField> = Vec::with_capacity(list.len());
+        let mut size = 0;
+        for item in list {
+            let (field, kind, values) =
+                UboField::from_yaml(item).map_err(|e| format!("{} in block {:?}", e, name))?;
+            if fields.iter().any(|f| f.name == field) {
+                return Err(format!(
+                    "Field {:?} appears twice in block {:?}",
+                    field, name
+                ));
+            }
+
+            let (align, len) = kind.std140();
+            let offset = (size + align - 1) / align * align;
+            size = offset + len;
+            fields.push(UboField {
+                name: field,
+                kind,
+                offset,
+                values,
+            });
+        }
+
+        // the block itself is padded like a struct
+        let size = (size + 15) / 16 * 16;
+        let name =
+            CString::new(name).map_err(|_| format!("Invalid uniform block name {:?}", name))?;
+
+        Ok(Self {
+            name,
+            binding,
+            fields,
+            data: vec![0; size / 4],
+            buffer: 0,
+            dirty: true,
+        })
+    }
+
+    /// Size of the block in bytes.
+    pub fn size(&self) -> usize {
+        4 * self.data.len()
+    }
+
+    /// The raw contents of the buffer, as 32 bit words.
+    pub fn data(&self) -> &[u32] {
+        &self.data
+    }
+
+    /// Returns `true` if one of the fields follows the given OSC mapping.
+    pub fn uses_osc(&self, mapping: &str) -> bool {
+        self.osc_names().any(|name| name == mapping)
+    }
+
+    /// Names of all OSC mappings the fields follow.
+    pub fn osc_names(&self) -> impl Iterator<Item = &str> {
+        self.fields.iter().flat_map(|field| {
+            field.values.iter().filter_map(|param| match param {
+                LayerParam::Osc { name, .. } => Some(name.as_str()),
+                _ => None,
+            })
+        })
+    }
+
+    /// Writes the current values into the CPU-side copy.
+    ///
+    /// Returns `true` if anything changed since the last upload.
+    pub fn update(&mut self, sliders: &[f32], osc: &HashMap<String, OscUniformValue>) -> bool {
+        for field in self.fields.iter() {
+            for (k, param) in field.values.iter().enumerate() {
+                let value = param.get(sliders, osc);
+                let word = match field.kind.scalar() {
+                    UboScalar::Float => value.to_bits(),
+                    UboScalar::Int => (value.round() as i32) as u32,
+                    UboScalar::UInt => value.round().max(0.0) as u32,
+                    UboScalar::Bool => (value != 0.0) as u32,
+                };
+
+                let index = (field.offset + field.kind.component_offset(k)) / 4;
+                if self.data[index] != word {
+                    self.data[index] = word;
+                    self.dirty = true;
+                }
+            }
+        }
+
+        self.dirty
+    }
+
+    /// Uploads the block if it changed and binds it to its binding point.
+    pub fn upload(&mut self) {
+        unsafe {
+            if self.buffer == 0 {
+                gl::GenBuffers(1, &mut self.buffer);
+                gl::BindBuffer(gl::UNIFORM_BUFFER, self.buffer);
+                gl::BufferData(
+                    gl::UNIFORM_BUFFER,
+                    self.size() as _,
+                    self.data.as_ptr() as _,
+                    gl::DYNAMIC_DRAW,
+                );
+                self.dirty = false;
+            } else if self.dirty {
+                gl::BindBuffer(gl::UNIFORM_BUFFER, self.buffer);
+                gl::BufferSubData(
+                    gl::UNIFORM_BUFFER,
+                    0,
+                    self.size() as _,
+                    self.data.as_ptr() as _,
+                );
+                self.dirty = false;
+            }
+
+            // the other deck may use the same binding point
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, self.binding, self.buffer);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+            gl_debug_check!();
+        }
+    }
+
+    /// Connects the block of a program to the binding point.
+    ///
+    /// Returns `false` if the program does not use the block, and an error
+    /// if its declaration does not match the fields.
+    pub fn attach(&self, prog_id: GLuint) -> Result<bool, String> {
+        unsafe {
+            let index = gl::GetUniformBlockIndex(prog_id, self.name.as_ptr());
+            if index == gl::INVALID_INDEX {
+                return Ok(false);
+            }
+
+            let mut size = 0;
+            gl::GetActiveUniformBlockiv(prog_id, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut size);
+            gl_debug_check!();
+
+            for field in self.fields.iter() {
+                let (kind, offset) = match member_info(prog_id, &self.name, &field.name) {
+                    Some(info) => info,
+                    None => {
+                        return Err(format!(
+                            "Field {:?} is not declared in uniform block {:?} of the shader",
+                            field.name, self.name
+                        ))
+                    }
+                };
+
+                if kind != field.kind.gl_kind() || offset != field.offset {
+                    return Err(format!(
+                        "Field {:?} of uniform block {:?} is a {} at offset {} in the shader, \
+                        expected a {} at offset {}, please declare the block with layout(std140)",
+                        field.name,
+                        self.name,
+                        glsl_type_name(kind),
+                        offset,
+                        glsl_type_name(field.kind.gl_kind()),
+                        field.offset
+                    ));
+                }
+            }
+
+            if size as usize > self.size() {
+                return Err(format!(
+                    "Uniform block {:?} has {} bytes in the shader, but its fields only fill {}",
+                    self.name,
+                    size,
+                    self.size()
+                ));
+            }
+
+            gl::UniformBlockBinding(prog_id, index, self.binding);
+            gl_debug_check!();
+            Ok(true)
+        }
+    }
+}
+
+/// Type and offset of a block member, which is named `Block.member` if
+/// the block has an instance name.
+unsafe fn member_info(prog_id: GLuint, block: &CString, member: &str) -> Option<(GLenum, usize)> {
+    let names = [
+        member.to_owned(),
+        format!("{}.{}", block.to_string_lossy(), member),
+    ];
+    for name in names.iter() {
+        let name = CString::new(name.as_str()).ok()?;
+        let mut index = gl::INVALID_INDEX;
+        unsafe { gl::GetUniformIndices(prog_id, 1, &name.as_ptr(), &mut index) };
+        if index == gl::INVALID_INDEX {
+            continue;
+        }
+
+        let (mut kind, mut offset) = (0, 0);
+        unsafe {
+            gl::GetActiveUniformsiv(prog_id, 1, &index, gl::UNIFORM_TYPE, &mut kind);
+            gl::GetActiveUniformsiv(prog_id, 1, &index, gl::UNIFORM_OFFSET, &mut offset);
+            gl_debug_check!();
+        }
+        return Some((kind as _, offset as _));
+    }
+
+    None
+}
+
+impl Drop for UniformBlock {
+    fn drop(&mut self) {
+        if self.buffer != 0 {
+            unsafe { gl::DeleteBuffers(1, &self.buffer) };
+        }
+    }
+}
+
+/// Parses the `uniform_blocks` section, binding points are given in order.
+pub fn parse_uniform_blocks(object: &Value) -> Result<Vec<UniformBlock>, String> {
+    let blocks = match object.get("uniform_blocks") {
+        Some(Value::Mapping(m)) => m,
+        None => return Ok(Vec::new()),
+        Some(s) => {
+            return Err(format!(
+                "Expected \"uniform_blocks\" to be a mapping, got {:?}",
+                s
+            ))
+        }
+    };
+
+    let mut out = Vec::with_capacity(blocks.len());
+    for (k, (name, fields)) in blocks.iter().enumerate() {
+        let name = match name.as_str() {
+            Some(s) => s,
+            None => {
+                return Err(format!(
+                    "Expected block name to be a string, got {:?}",
+                    name
+                ))
+            }
+        };
+
+        out.push(UniformBlock::from_yaml(name, k as _, fields)?);
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(yaml: &str) -> Result<Vec<UniformBlock>, String> {
+        parse_uniform_blocks(&serde_yaml::from_str(yaml).unwrap())
+    }
+
+    #[test]
+    fn types() {
+        assert_eq!(
+            UboType::parse("float"),
+            Some(UboType::Scalar(UboScalar::Float))
+        );
+        assert_eq!(
+            UboType::parse("uvec3"),
+            Some(UboType::Vec(UboScalar::UInt, 3))
+        );
+        assert_eq!(
+            UboType::parse("bvec2"),
+            Some(UboType::Vec(UboScalar::Bool, 2))
+        );
+        assert_eq!(UboType::parse("mat3"), Some(UboType::Mat(3)));
+        for name in [
+            "vec",
+            "vec5",
+            "dvec2",
+            "mat2x3",
+            "sampler2D",
+            "",
+            "vecé",
+            "mat²",
+            "ü",
+        ] {
+            assert_eq!(UboType::parse(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn std140_offsets() {
+        let yaml = r#"
+            uniform_blocks:
+              Params:
+                - { name: a, type: float }
+                - { name: b, type: vec3 }
+                - { name: c, type: float }
+                - { name: d, type: vec2 }
+                - { name: e, type: mat3 }
+                - { name: f, type: int }
+                - { name: g, type: vec4 }
+        "#;
+
+        let blocks = parse(yaml).unwrap();
+        let offsets: Vec<_> = blocks[0].fields.iter().map(|f| f.offset).collect();
+        // the float after a vec3 fills its padding, matrix columns take a vec4 each
+        assert_eq!(offsets, [0, 16, 28, 32, 48, 96, 112]);
+        assert_eq!(blocks[0].size(), 128);
+        assert_eq!(blocks[0].binding, 0);
+    }
+
+    #[test]
+    fn values() {
+        let yaml = r#"
+            uniform_blocks:
+              Params:
+                - { name: speed, value: { slider: 1, range: [0, 4] } }
+                - { name: tint, type: vec3, value: [1, { osc: hue }, 0.25] }
+                - { name: steps, type: int, value: 2.6 }
+                - { name: on, type: bool, value: { osc: gate } }
+                - { name: m, type: mat2, value: [1, 2, 3, 4] }
+              Other:
+                - { name: x }
+        "#;
+
+        let mut blocks = parse(yaml).unwrap();
+        assert_eq!(blocks[1].binding, 1);
+        assert!(blocks[0].uses_osc("hue"));
+        assert!(!blocks[0].uses_osc("speed"));
+
+        let mut osc = HashMap::new();
+        osc.insert("hue".to_owned(), OscUniformValue::Float(0.5));
+        let sliders = [0.0, 0.5];
+        let block = &mut blocks[0];
+        assert!(block.update(&sliders, &osc));
+
+        let data = block.data();
+        assert_eq!(f32::from_bits(data[0]), 2.0);
+        assert_eq!(
+            data[4..7],
+            [1.0f32.to_bits(), 0.5f32.to_bits(), 0.25f32.to_bits()]
+        );
+        assert_eq!(data[7], 3);
+        assert_eq!(data[8], 0);
+
+        // matrices are given row by row, and stored column by column
+        let column = |k: usize| data[12 + 4 * k..14 + 4 * k].to_vec();
+        assert_eq!(column(0), [1.0f32.to_bits(), 3.0f32.to_bits()]);
+        assert_eq!(column(1), [2.0f32.to_bits(), 4.0f32.to_bits()]);
+
+        // nothing to upload until a value changes
+        block.dirty = false;
+        assert!(!block.update(&sliders, &osc));
+        osc.insert("gate".to_owned(), OscUniformValue::Bool(true));
+        assert!(block.update(&sliders, &osc));
+        assert_eq!(block.data()[8], 1);
+    }
+
+    #[test]
+    fn errors() {
+        for yaml in [
+            "uniform_blocks: [a, b]",
+            "uniform_blocks: { Params: [] }",
+            "uniform_blocks: { Params: { name: a } }",
+            "uniform_blocks: { Params: [{ type: float }] }",
+            "uniform_blocks: { Params: [{ name: a, type: double }] }",
+            "uniform_blocks: { Params: [{ name: a, type: vec2, value: 1 }] }",
+            "uniform_blocks: { Params: [{ name: a, type: vec2, value: [1, 2, 3] }] }",
+            "uniform_blocks: { Params: [{ name: a }, { name: a }] }",
+            "uniform_blocks: { Params: [{ name: a, value: { osc: 3 } }] }",
+        ] {
+            assert!(parse(yaml).is_err(), "{}", yaml);
+        }
+
+        assert!(parse("stages: []").unwrap().is_empty());
+    }
+}
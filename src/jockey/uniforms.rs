@@ -12,27 +12,48 @@ lazy_static! {
 
     // miscellaneous
     pub static ref RESOLUTION_NAME: CString = CString::new("resolution").unwrap();
+    pub static ref I_RESOLUTION_NAME: CString = CString::new("iResolution").unwrap();
     pub static ref PASS_INDEX_NAME: CString = CString::new("pass_index").unwrap();
+    pub static ref SEED_NAME: CString = CString::new("seed").unwrap();
+    pub static ref PASS_SEED_NAME: CString = CString::new("pass_seed").unwrap();
+    pub static ref VIEWPORT_NAME: CString = CString::new("viewport").unwrap();
     pub static ref OUT_COLOR_NAME: CString = CString::new("out_color").unwrap();
     pub static ref POSITION_NAME: CString = CString::new("position").unwrap();
+    pub static ref INSTANCE_COUNT_NAME: CString = CString::new("instance_count").unwrap();
     pub static ref VERTEX_COUNT_NAME: CString = CString::new("vertex_count").unwrap();
     pub static ref NOISE_NAME: CString = CString::new("noise").unwrap();
 
     // time tracking
     pub static ref TIME_NAME: CString = CString::new("time").unwrap();
     pub static ref TIME_SINCE_BUILD_NAME: CString = CString::new("time_since_build").unwrap();
+    pub static ref LOOP_TIME_NAME: CString = CString::new("loop_time").unwrap();
     pub static ref TIME_DELTA_NAME: CString = CString::new("time_delta").unwrap();
     pub static ref FRAME_COUNT_NAME: CString = CString::new("frame_count").unwrap();
     pub static ref FRAME_COUNT_SINCE_BUILD_NAME: CString = CString::new("frame_count_since_build").unwrap();
+    pub static ref FPS_NAME: CString = CString::new("fps").unwrap();
+    pub static ref FRAME_TIME_NAME: CString = CString::new("frame_time_ms").unwrap();
+    pub static ref FRAME_TIME_MAX_NAME: CString = CString::new("frame_time_max_ms").unwrap();
+    pub static ref CROSSFADE_NAME: CString = CString::new("crossfade").unwrap();
+    pub static ref OSC_CONNECTED_NAME: CString = CString::new("osc_connected").unwrap();
+    pub static ref AUDIO_ACTIVE_NAME: CString = CString::new("audio_active").unwrap();
+    pub static ref OSC_ACTIVE_NAME: CString = CString::new("osc_active").unwrap();
+    pub static ref SPOUT_ACTIVE_NAME: CString = CString::new("spout_active").unwrap();
+    pub static ref MIDI_ACTIVE_NAME: CString = CString::new("midi_active").unwrap();
 
     // direct user input
     pub static ref BEAT_NAME: CString = CString::new("beat").unwrap();
     pub static ref SLIDERS_NAME: CString = CString::new("sliders").unwrap();
     pub static ref BUTTONS_NAME: CString = CString::new("buttons").unwrap();
+    pub static ref PITCH_BEND_NAME: CString = CString::new("pitch_bend").unwrap();
+    pub static ref PRESSURE_NAME: CString = CString::new("pressure").unwrap();
+}
 
+// a single block of this size exceeds the recursion limit of the macro
+lazy_static! {
     // volume input
     pub static ref VOLUME_NAME: CString = CString::new("volume").unwrap();
     pub static ref VOLUME_INTEGRATED_NAME: CString = CString::new("volume_integrated").unwrap();
+    pub static ref VOLUME_PEAK_NAME: CString = CString::new("volume_peak").unwrap();
 
     // audio textures
     pub static ref SAMPLES_NAME: CString = CString::new("samples").unwrap();
@@ -47,18 +68,41 @@ lazy_static! {
     pub static ref BASS_SMOOTH_NAME: CString = CString::new("bass_smooth").unwrap();
     pub static ref BASS_INTEGRATED_NAME: CString = CString::new("bass_integrated").unwrap();
     pub static ref BASS_SMOOTH_INTEGRATED_NAME: CString = CString::new("bass_smooth_integrated").unwrap();
+    pub static ref BASS_PEAK_NAME: CString = CString::new("bass_peak").unwrap();
 
     // mid
     pub static ref MID_NAME: CString = CString::new("mid").unwrap();
     pub static ref MID_SMOOTH_NAME: CString = CString::new("mid_smooth").unwrap();
     pub static ref MID_INTEGRATED_NAME: CString = CString::new("mid_integrated").unwrap();
     pub static ref MID_SMOOTH_INTEGRATED_NAME: CString = CString::new("mid_smooth_integrated").unwrap();
+    pub static ref MID_PEAK_NAME: CString = CString::new("mid_peak").unwrap();
 
     // high
     pub static ref HIGH_NAME: CString = CString::new("high").unwrap();
     pub static ref HIGH_SMOOTH_NAME: CString = CString::new("high_smooth").unwrap();
     pub static ref HIGH_INTEGRATED_NAME: CString = CString::new("high_integrated").unwrap();
     pub static ref HIGH_SMOOTH_INTEGRATED_NAME: CString = CString::new("high_smooth_integrated").unwrap();
+    pub static ref HIGH_PEAK_NAME: CString = CString::new("high_peak").unwrap();
+
+    // stereo
+    pub static ref BANDS_LEFT_NAME: CString = CString::new("bands_left").unwrap();
+    pub static ref BANDS_RIGHT_NAME: CString = CString::new("bands_right").unwrap();
+    pub static ref STEREO_CORRELATION_NAME: CString = CString::new("stereo_correlation").unwrap();
+    pub static ref STEREO_WIDTH_NAME: CString = CString::new("stereo_width").unwrap();
+
+    // pitch
+    pub static ref PITCH_NAME: CString = CString::new("pitch_hz").unwrap();
+    pub static ref NOTE_NAME: CString = CString::new("note").unwrap();
+    pub static ref PITCH_CONFIDENCE_NAME: CString = CString::new("pitch_confidence").unwrap();
+
+    // timbre
+    pub static ref CENTROID_NAME: CString = CString::new("centroid").unwrap();
+    pub static ref SPREAD_NAME: CString = CString::new("spread").unwrap();
+    pub static ref CENTROID_SMOOTH_NAME: CString = CString::new("centroid_smooth").unwrap();
+    pub static ref SPREAD_SMOOTH_NAME: CString = CString::new("spread_smooth").unwrap();
+
+    // onsets
+    pub static ref DENSITY_NAME: CString = CString::new("density").unwrap();
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
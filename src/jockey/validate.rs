@@ -0,0 +1,768 @@
+use std::fmt;
+
+use serde_yaml::Value;
+
+use super::*;
+
+/// An error found while validating a pipeline file.
+///
+/// The path points at the offending field, e.g. `stages[2].blend`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub path: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Moves an error of a nested section below the path of that section.
+    pub fn under(self, section: &str) -> Self {
+        let path = match self.path.is_empty() {
+            true => section.to_owned(),
+            false => format!("{}.{}", section, self.path),
+        };
+
+        Self::new(path, self.message)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path.is_empty() {
+            true => write!(f, "{}", self.message),
+            false => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+/// Walks a whole pipeline file and collects every error it can find.
+///
+/// This only looks at the structure of the file, it does not read or
+/// compile any shaders. Parsing a pipeline which passed validation may
+/// still fail, but the common mistakes are all reported at once.
+pub fn validate_pipeline(object: &Value) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+
+    if !object.is_mapping() {
+        errors.push(ConfigError::new("", "Expected pipeline to be a mapping"));
+        return errors;
+    }
+
+    match object.get("stages") {
+        Some(Value::Sequence(stages)) => {
+            for (k, stage) in stages.iter().enumerate() {
+                validate_stage(&format!("stages[{}]", k), stage, &mut errors);
+            }
+        }
+        Some(s) => errors.push(ConfigError::new(
+            "stages",
+            format!("Expected a list of stages, got {:?}", s),
+        )),
+        None => errors.push(ConfigError::new("stages", "Required field not found")),
+    }
+
+    match object.get("images") {
+        Some(Value::Sequence(images)) => {
+            for (k, image) in images.iter().enumerate() {
+                let path = format!("images[{}]", k);
+                if let Err(image_errors) = ImageConfig::from_yaml(image) {
+                    errors.extend(image_errors.into_iter().map(|err| err.under(&path)));
+                }
+            }
+        }
+        Some(s) => errors.push(ConfigError::new(
+            "images",
+            format!("Expected a list, got {:?}", s),
+        )),
+        None => (),
+    }
+    validate_named_list(object, "ndi", &["source", "name"], &mut errors);
+
+    if let Some(audio) = object.get("audio") {
+        validate_audio(audio, &mut errors);
+    }
+
+    if let Some(osc) = object.get("osc") {
+        if let Err(err) = OscConfig::from_yaml(osc) {
+            errors.push(ConfigError::new("osc", err));
+        }
+    }
+
+    if let Some(spout) = object.get("spout") {
+        if let Err(err) = SpoutConfig::from_yaml(spout) {
+            errors.push(ConfigError::new("spout", err));
+        }
+    }
+
+    if let Err(err) = parse_output(object) {
+        errors.push(ConfigError::new("output", err));
+    }
+
+    if let Err(err) = parse_background(object) {
+        errors.push(ConfigError::new("background", err));
+    }
+
+    if let Err(err) = parse_previous_frame(object) {
+        errors.push(ConfigError::new("previous_frame", err));
+    }
+
+    if let Err(err) = parse_uniform_blocks(object) {
+        errors.push(ConfigError::new("uniform_blocks", err));
+    }
+
+    if let Err(err) = parse_background_shader(object) {
+        errors.push(ConfigError::new("background_shader", err));
+    }
+
+    if let Err(err) = parse_loop_period(object) {
+        errors.push(ConfigError::new("loop", err));
+    }
+
+    if let Err(err) = parse_gl_checks(object) {
+        errors.push(ConfigError::new("debug", err));
+    }
+
+    if let Err(err) = parse_pick(object) {
+        errors.push(ConfigError::new("debug", err));
+    }
+
+    if let Err(err) = parse_seed(object) {
+        errors.push(ConfigError::new("seed", err));
+    }
+
+    errors
+}
+
+/// Checks a stage with the parser which builds it, without reading its shaders.
+fn validate_stage(path: &str, stage: &Value, errors: &mut Vec<ConfigError>) {
+    if let Err(stage_errors) = StageConfig::from_yaml(stage.clone()) {
+        errors.extend(stage_errors.into_iter().map(|err| err.under(path)));
+    }
+}
+
+fn validate_named_list(
+    object: &Value,
+    section: &str,
+    required: &[&str],
+    errors: &mut Vec<ConfigError>,
+) {
+    let list = match object.get(section) {
+        Some(Value::Sequence(list)) => list,
+        Some(s) => {
+            errors.push(ConfigError::new(
+                section,
+                format!("Expected a list, got {:?}", s),
+            ));
+            return;
+        }
+        None => return,
+    };
+
+    for (k, entry) in list.iter().enumerate() {
+        let path = format!("{}[{}]", section, k);
+        if !entry.is_mapping() {
+            errors.push(ConfigError::new(
+                path,
+                format!("Expected a mapping, got {:?}", entry),
+            ));
+            continue;
+        }
+
+        for &key in required {
+            match entry.get(key) {
+                Some(Value::String(_)) => (),
+                Some(s) => errors.push(ConfigError::new(
+                    format!("{}.{}", path, key),
+                    format!("Expected a string, got {:?}", s),
+                )),
+                None => errors.push(ConfigError::new(
+                    format!("{}.{}", path, key),
+                    "Required field not found",
+                )),
+            }
+        }
+
+        if let Err(err) = TextureBuilder::parse(entry, false, true) {
+            errors.push(ConfigError::new(path.clone(), err));
+        }
+
+        if let Err(err) = AlphaMode::from_yaml(entry) {
+            errors.push(ConfigError::new(path, err));
+        }
+    }
+}
+
+fn validate_audio(audio: &Value, errors: &mut Vec<ConfigError>) {
+    if !audio.is_mapping() {
+        errors.push(ConfigError::new(
+            "audio",
+            format!("Expected a mapping, got {:?}", audio),
+        ));
+        return;
+    }
+
+    if let Some(s) = audio.get("audio_samples") {
+        if s.as_u64().is_none() {
+            errors.push(ConfigError::new(
+                "audio.audio_samples",
+                format!("Expected an unsigned int, got {:?}", s),
+            ));
+        }
+    }
+
+    if let Some(s) = audio.get("latency_ms") {
+        if s.as_f64().is_none() {
+            errors.push(ConfigError::new(
+                "audio.latency_ms",
+                format!("Expected a float, got {:?}", s),
+            ));
+        }
+    }
+
+    validate_audio_envelope(audio, "audio", errors);
+
+    if let Some(fft) = audio.get("fft") {
+        // the number of samples does not matter for the check
+        if let Err(err) = parse_fft_width(Some(fft), AUDIO_SAMPLES) {
+            errors.push(ConfigError::new("audio.fft", err));
+        }
+
+        if let Some(s) = fft.get("scale") {
+            if !matches!(s.as_str(), Some("linear") | Some("log")) {
+                errors.push(ConfigError::new(
+                    "audio.fft.scale",
+                    format!("Expected either \"linear\" or \"log\", got {:?}", s),
+                ));
+            }
+        }
+
+        if let Some(s) = fft.get("resample") {
+            if !matches!(s.as_str(), Some("max") | Some("linear")) {
+                errors.push(ConfigError::new(
+                    "audio.fft.resample",
+                    format!("Expected either \"max\" or \"linear\", got {:?}", s),
+                ));
+            }
+        }
+    }
+
+    match audio.get("profiles") {
+        Some(Value::Mapping(map)) => {
+            for (name, profile) in map {
+                let path = format!("audio.profiles.{}", name.as_str().unwrap_or("?"));
+                match (name.as_str(), profile.is_mapping()) {
+                    (Some(_), true) => validate_audio_envelope(profile, &path, errors),
+                    (None, _) => errors.push(ConfigError::new(
+                        path,
+                        format!("Expected the name to be a string, got {:?}", name),
+                    )),
+                    (_, false) => errors.push(ConfigError::new(
+                        path,
+                        format!("Expected a mapping, got {:?}", profile),
+                    )),
+                }
+            }
+        }
+        Some(s) => errors.push(ConfigError::new(
+            "audio.profiles",
+            format!("Expected a mapping, got {:?}", s),
+        )),
+        None => {}
+    }
+
+    if let Some(s) = audio.get("profile") {
+        let profiles = audio.get("profiles");
+        if s.as_str().and_then(|name| profiles?.get(name)).is_none() {
+            errors.push(ConfigError::new(
+                "audio.profile",
+                format!("Expected the name of a profile, got {:?}", s),
+            ));
+        }
+    }
+
+    if let Some(s) = audio.get("pre_gain") {
+        let valid = match s {
+            Value::Sequence(seq) => {
+                seq.len() == 2
+                    && seq
+                        .iter()
+                        .all(|x| matches!(x.as_f64(), Some(x) if x >= 0.0))
+            }
+            s => matches!(s.as_f64(), Some(x) if x >= 0.0),
+        };
+
+        if !valid {
+            errors.push(ConfigError::new(
+                "audio.pre_gain",
+                format!(
+                    "Expected a non-negative float or one for each channel, got {:?}",
+                    s
+                ),
+            ));
+        }
+    }
+
+    if let Some(s) = audio.get("mute") {
+        if s.as_bool().is_none() {
+            errors.push(ConfigError::new(
+                "audio.mute",
+                format!("Expected a bool, got {:?}", s),
+            ));
+        }
+    }
+
+    if let Some(s) = audio.get("pitch") {
+        if s.as_bool().is_none() {
+            errors.push(ConfigError::new(
+                "audio.pitch",
+                format!("Expected a bool, got {:?}", s),
+            ));
+        }
+    }
+
+    if let Some(centroid) = audio.get("centroid") {
+        if let Some(s) = centroid.get("smoothing") {
+            if !matches!(s.as_f64(), Some(x) if (0.0..=1.0).contains(&x)) {
+                errors.push(ConfigError::new(
+                    "audio.centroid.smoothing",
+                    format!("Expected a float between 0 and 1, got {:?}", s),
+                ));
+            }
+        }
+
+        if let Some(s) = centroid.get("normalize") {
+            if s.as_bool().is_none() {
+                errors.push(ConfigError::new(
+                    "audio.centroid.normalize",
+                    format!("Expected a bool, got {:?}", s),
+                ));
+            }
+        }
+    }
+
+    if let Some(s) = audio.get("onsets").and_then(|o| o.get("window")) {
+        if !matches!(s.as_f64(), Some(x) if x > 0.0) {
+            errors.push(ConfigError::new(
+                "audio.onsets.window",
+                format!("Expected a positive float, got {:?}", s),
+            ));
+        }
+    }
+
+    if let Some(s) = audio.get("channels") {
+        if !matches!(s.as_str(), Some("mono") | Some("stereo")) {
+            errors.push(ConfigError::new(
+                "audio.channels",
+                format!("Expected either \"mono\" or \"stereo\", got {:?}", s),
+            ));
+        }
+    }
+
+    for &key in [
+        "samples",
+        "spectrum_raw",
+        "spectrum",
+        "spectrum_smooth",
+        "spectrum_integrated",
+        "spectrum_smooth_integrated",
+    ]
+    .iter()
+    {
+        if let Some(s) = audio.get(key) {
+            if let Err(err) = TextureBuilder::parse(s, false, true) {
+                errors.push(ConfigError::new(format!("audio.{}", key), err));
+            }
+        }
+    }
+}
+
+/// Checks the envelope parameters of the `audio` section or of one of its profiles.
+fn validate_audio_envelope(object: &Value, path: &str, errors: &mut Vec<ConfigError>) {
+    for &key in ["attack", "decay"].iter() {
+        if let Some(s) = object.get(key) {
+            if s.as_f64().is_none() {
+                errors.push(ConfigError::new(
+                    format!("{}.{}", path, key),
+                    format!("Expected a float, got {:?}", s),
+                ));
+            }
+        }
+    }
+
+    if let Some(peak) = object.get("peak") {
+        for &key in ["hold", "decay"].iter() {
+            if let Some(s) = peak.get(key) {
+                if !matches!(s.as_f64(), Some(x) if x >= 0.0) {
+                    errors.push(ConfigError::new(
+                        format!("{}.peak.{}", path, key),
+                        format!("Expected a non-negative float, got {:?}", s),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn validate(yaml: &str) -> Vec<String> {
+        let object = serde_yaml::from_str(yaml).unwrap();
+        validate_pipeline(&object)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn valid_pipeline() {
+        let errors = validate(
+            r#"
+            stages:
+              - cs: "sim.comp"
+                target: "particles"
+                resolution: [1024, 1]
+                dispatch: [1024, 1, 1]
+              - vs: "draw.vert"
+                geometry:
+                  mode: POINTS
+                  count: 1
+                  instances: 1024
+                blend: additive
+              - fs: "post.frag"
+                uniforms:
+                  strength: 0.5
+            "#,
+        );
+
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn inline_shaders() {
+        let errors = validate(
+            r#"
+            stages:
+              - vertex: |
+                  void main() { gl_Position = vec4(0.0); }
+                fs: "points.frag"
+                count: 1
+              - fragment: |
+                  out vec4 color;
+                  void main() { color = vec4(1.0); }
+              - fs: "a.frag"
+                fragment: "void main() {}"
+              - compute: [1, 2]
+            "#,
+        );
+
+        assert_eq!(errors.len(), 3, "{:#?}", errors);
+        assert!(errors[0].starts_with("stages[2].fragment: "));
+        assert!(errors[1].starts_with("stages[3].compute: "));
+        assert!(errors[2].starts_with("stages[3]: Stage needs a shader"));
+    }
+
+    #[test]
+    fn copy_stages() {
+        let errors = validate(
+            r#"
+            stages:
+              - fs: "feedback.frag"
+                target: "trails"
+              - type: copy
+                input: "trails"
+                resolution: [640, 360]
+                target: "small"
+              - type: copy
+                input: "trails"
+                scale: 0.5
+              - type: blit
+              - type: copy
+            "#,
+        );
+
+        assert_eq!(errors.len(), 3, "{:#?}", errors);
+        assert!(errors[0].starts_with("stages[2]: Copy stages have no field \"scale\""));
+        assert!(errors[1].starts_with("stages[3]: Expected \"type\""));
+        assert!(errors[2].starts_with("stages[4]: Expected \"input\""));
+    }
+
+    #[test]
+    fn missing_stages() {
+        let errors = validate("audio: { audio_samples: 1024 }");
+        assert_eq!(errors, vec!["stages: Required field not found"]);
+    }
+
+    #[test]
+    fn collects_multiple_errors() {
+        let errors = validate(
+            r#"
+            stages:
+              - fs: "a.frag"
+                blend: "lighten"
+              - fs: "b.frag"
+                clear: "yes"
+                uniforms:
+                  strength: "strong"
+              - cs: "c.comp"
+                target: "c"
+            "#,
+        );
+
+        assert_eq!(errors.len(), 5, "{:#?}", errors);
+        assert!(errors[0].starts_with("stages[0].blend: "));
+        assert!(errors[1].starts_with("stages[1].uniforms.strength: "));
+        assert!(errors[2].starts_with("stages[1].clear: "));
+        assert!(errors[3].starts_with("stages[2].dispatch_size: "));
+        assert!(errors[4].starts_with("stages[2].resolution: "));
+    }
+
+    #[test]
+    fn geometry_errors() {
+        let errors = validate(
+            r#"
+            stages:
+              - vs: "draw.vert"
+                geometry:
+                  mode: QUADS
+                  instances: 0
+            "#,
+        );
+
+        assert_eq!(errors.len(), 2, "{:#?}", errors);
+        assert!(errors[0].starts_with("stages[0].geometry.mode: "));
+        assert!(errors[1].starts_with("stages[0].geometry.instances: "));
+    }
+
+    #[test]
+    fn geometry_conflicts() {
+        let errors = validate(
+            r#"
+            stages:
+              - vs: "draw.vert"
+                count: 6
+                geometry:
+                  instances: 4
+            "#,
+        );
+
+        assert_eq!(errors.len(), 1, "{:#?}", errors);
+        assert!(errors[0].starts_with("stages[0].count: "));
+    }
+
+    #[test]
+    fn debug_section() {
+        let errors = validate("stages: []\ndebug: { gl_checks: true }");
+        assert_eq!(errors, Vec::<String>::new());
+
+        let errors = validate("stages: []\ndebug: { gl_checks: 1 }");
+        assert_eq!(errors.len(), 1, "{:#?}", errors);
+        assert!(errors[0].starts_with("debug: "));
+
+        let errors = validate("stages: []\ndebug: { pick: bloom, pick_at: [10, 20] }");
+        assert_eq!(errors, Vec::<String>::new());
+
+        let errors = validate("stages: []\ndebug: { pick_at: [10] }");
+        assert_eq!(errors.len(), 1, "{:#?}", errors);
+        assert!(errors[0].starts_with("debug: Expected \"pick_at\""));
+    }
+
+    #[test]
+    fn output_errors() {
+        let stages = r#"
+            stages:
+              - cs: "sim.comp"
+                target: "particles"
+                resolution: [1024, 1]
+                dispatch: [1024, 1, 1]
+              - fs: "scene.frag"
+                targets: ["color", "depth"]
+              - fs: "debug.frag"
+            "#;
+
+        for output in ["color", "depth"] {
+            let errors = validate(&format!("{}output: {}", stages, output));
+            assert_eq!(errors, Vec::<String>::new());
+        }
+
+        for output in ["particles", "screen", "[color]"] {
+            let errors = validate(&format!("{}output: {}", stages, output));
+            assert_eq!(errors.len(), 1, "{:#?}", errors);
+            assert!(errors[0].starts_with("output: "));
+        }
+    }
+
+    #[test]
+    fn background_errors() {
+        let stages = r#"
+            stages:
+              - fs: "scene.frag"
+            "#;
+
+        for background in ["[0, 0, 0]", "[0.1, 0.2, 0.3, 0.5]"] {
+            let errors = validate(&format!("{}background: {}", stages, background));
+            assert_eq!(errors, Vec::<String>::new());
+        }
+
+        for background in ["black", "[0, 0]", "[0, 0, 0, 0, 0]", "[0, red, 0]"] {
+            let errors = validate(&format!("{}background: {}", stages, background));
+            assert_eq!(errors.len(), 1, "{:#?}", errors);
+            assert!(errors[0].starts_with("background: "));
+        }
+
+        let errors = validate("stages: []\nbackground_shader: bg.frag");
+        assert_eq!(errors, Vec::<String>::new());
+
+        let errors = validate("stages: []\nbackground_shader: [bg.frag]");
+        assert_eq!(errors.len(), 1, "{:#?}", errors);
+        assert!(errors[0].starts_with("background_shader: "));
+    }
+
+    #[test]
+    fn viewport_errors() {
+        let errors = validate(
+            r#"
+            stages:
+              - fs: "a.frag"
+                viewport: [0, 0, 320, 180]
+              - fs: "b.frag"
+                viewport: [0, 0, 0, 180]
+              - fs: "c.frag"
+                viewport: [-10, 0, 320]
+            "#,
+        );
+
+        assert_eq!(errors.len(), 2, "{:#?}", errors);
+        assert!(errors[0].starts_with("stages[1].viewport: "));
+        assert!(errors[1].starts_with("stages[2].viewport: "));
+    }
+
+    #[test]
+    fn section_errors() {
+        let errors = validate(
+            r#"
+            stages:
+              - fs: "a.frag"
+            images:
+              - path: "image.png"
+              - path: "hdr.png"
+                name: "hdr"
+                exposure: "bright"
+            audio:
+              attack: "fast"
+            "#,
+        );
+
+        assert_eq!(
+            errors,
+            vec![
+                "images[0].name: Required field not found",
+                "images[1]: Expected \"exposure\" to be a positive number, got String(\"bright\")",
+                "audio.attack: Expected a float, got String(\"fast\")",
+            ]
+        );
+    }
+
+    #[test]
+    fn fft_errors() {
+        for fft in ["{ bins: 64 }", "{ texture_width: pow2, resample: linear }"] {
+            let errors = validate(&format!("stages: []\naudio: {{ fft: {} }}", fft));
+            assert_eq!(errors, Vec::<String>::new());
+        }
+
+        let errors = validate(
+            "stages: []\naudio: { fft: { bins: 64, texture_width: 64, resample: nearest } }",
+        );
+        assert_eq!(errors.len(), 2, "{:#?}", errors);
+        assert!(errors[0].starts_with("audio.fft: "));
+        assert!(errors[1].starts_with("audio.fft.resample: "));
+    }
+
+    #[test]
+    fn audio_profile_errors() {
+        let errors = validate(
+            r#"
+            stages:
+              - fs: "a.frag"
+            audio:
+              profiles:
+                calm: { attack: 0.9, peak: { hold: -1 } }
+                aggressive: fast
+              profile: wild
+            "#,
+        );
+
+        assert_eq!(errors.len(), 3, "{:#?}", errors);
+        assert!(errors[0].starts_with("audio.profiles.calm.peak.hold: "));
+        assert!(errors[1].starts_with("audio.profiles.aggressive: "));
+        assert!(errors[2].starts_with("audio.profile: "));
+    }
+
+    #[test]
+    fn composite_errors() {
+        let errors = validate(
+            r#"
+            stages:
+              - composite: "camera"
+                position: [0.5, { slider: 1 }]
+                rotation: { osc: spin, range: [0, 360] }
+              - composite: "camera"
+                scale: [1, 2, 3]
+            "#,
+        );
+
+        assert_eq!(errors.len(), 1, "{:#?}", errors);
+        assert!(errors[0].starts_with("stages[1]: "));
+    }
+
+    #[test]
+    fn image_format_errors() {
+        let errors = validate(
+            r#"
+            stages:
+              - fs: "a.frag"
+            images:
+              - path: "sky.hdr"
+                name: "sky"
+                format: rgba32f
+              - path: "studio.exr"
+                name: "studio"
+                format: rgba8
+            "#,
+        );
+
+        assert_eq!(errors.len(), 1, "{:#?}", errors);
+        assert!(errors[0].starts_with("images[1].format: "));
+    }
+
+    #[test]
+    fn sampler_errors() {
+        let errors = validate(
+            r#"
+            stages:
+              - fs: "a.frag"
+                target: "a"
+                wrap: mirror
+                filter: nearest
+              - fs: "b.frag"
+                target: "b"
+                wrap: border
+            images:
+              - path: "image.png"
+                name: "image"
+                filter: cubic
+            "#,
+        );
+
+        assert_eq!(errors.len(), 2, "{:#?}", errors);
+        assert!(errors[0].starts_with("stages[1]: "));
+        assert!(errors[1].starts_with("images[0]: "));
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+#[derive(Debug, Default)]
+struct WatchState {
+    dependencies: Option<HashSet<PathBuf>>,
+    last_change: Option<Instant>,
+}
+
+/// Watches the files the current pipeline was built from.
+///
+/// The project folder is watched recursively, but only changes to files
+/// which the pipeline actually read are reported, along with pipeline files
+/// appearing or disappearing. If the last build failed, its dependencies are
+/// unknown and every change is reported.
+///
+/// Changes are debounced, so saving several files at once only causes a
+/// single reload.
+#[derive(Debug)]
+pub struct DependencyWatcher {
+    watcher: notify::RecommendedWatcher,
+    root: PathBuf,
+    extra_dirs: HashSet<PathBuf>,
+    state: Arc<Mutex<WatchState>>,
+}
+
+impl DependencyWatcher {
+    /// Starts watching the current working directory.
+    pub fn new() -> notify::Result<Self> {
+        let root = std::fs::canonicalize(".").map_err(notify::Error::io)?;
+        let state = Arc::new(Mutex::new(WatchState::default()));
+
+        let handler_root = root.clone();
+        let handler_state = Arc::clone(&state);
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<_>| {
+            let event: notify::Event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    log::warn!("File watcher error: {}", err);
+                    return;
+                }
+            };
+
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+
+            let mut state = match handler_state.lock() {
+                Ok(state) => state,
+                Err(_) => return,
+            };
+
+            let relevant = match &state.dependencies {
+                Some(deps) => event
+                    .paths
+                    .iter()
+                    .any(|path| is_dependency(path, deps) || is_pipeline(path, &handler_root)),
+                None => true,
+            };
+
+            if relevant {
+                state.last_change = Some(Instant::now());
+            }
+        })?;
+
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            watcher,
+            root,
+            extra_dirs: HashSet::new(),
+            state,
+        })
+    }
+
+    /// Replaces the set of files which trigger a reload.
+    ///
+    /// Passing `None` reports changes to any file in the project folder.
+    pub fn set_dependencies(&mut self, dependencies: Option<&HashSet<PathBuf>>) {
+        let root = &self.root;
+        let dependencies: Option<HashSet<_>> = dependencies.map(|deps| {
+            deps.iter()
+                .map(|path| std::fs::canonicalize(path).unwrap_or_else(|_| root.join(path)))
+                .collect()
+        });
+
+        // files outside of the project folder need watches of their own
+        let extra_dirs: HashSet<_> = dependencies
+            .iter()
+            .flatten()
+            .filter_map(|path| path.parent())
+            .filter(|dir| !dir.starts_with(root))
+            .map(Path::to_path_buf)
+            .collect();
+
+        for dir in self.extra_dirs.difference(&extra_dirs) {
+            self.watcher.unwatch(dir).ok();
+        }
+
+        for dir in extra_dirs.difference(&self.extra_dirs) {
+            if let Err(err) = self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                log::warn!("Failed to watch {}: {}", dir.display(), err);
+            }
+        }
+
+        self.extra_dirs = extra_dirs;
+
+        if let Ok(mut state) = self.state.lock() {
+            state.dependencies = dependencies;
+        }
+    }
+
+    /// Returns `true` once a burst of changes has been quiet for `debounce`.
+    pub fn poll(&self, debounce: Duration) -> bool {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+
+        match state.last_change {
+            Some(time) if time.elapsed() >= debounce => {
+                state.last_change = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn is_dependency(path: &Path, deps: &HashSet<PathBuf>) -> bool {
+    deps.contains(path) || std::fs::canonicalize(path).map_or(false, |path| deps.contains(&path))
+}
+
+fn is_pipeline(path: &Path, root: &Path) -> bool {
+    path.parent() == Some(root) && path.extension().map_or(false, |ext| ext == "yaml")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pipeline_files() {
+        let root = Path::new("/show");
+        assert!(is_pipeline(Path::new("/show/drop.yaml"), root));
+        assert!(!is_pipeline(Path::new("/show/scene.frag"), root));
+        assert!(!is_pipeline(Path::new("/show/sub/drop.yaml"), root));
+    }
+}
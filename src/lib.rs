@@ -0,0 +1,12 @@
+//! The render engine behind Sh4derJockey.
+//!
+//! The `sh4der-jockey` binary is a thin wrapper around [`jockey::Jockey`],
+//! which owns all windows and the control panel. To render pipelines inside
+//! another application, use [`jockey::Engine`] instead.
+
+#![warn(unsafe_op_in_unsafe_fn)]
+#![warn(missing_debug_implementations)]
+
+#[macro_use]
+mod util;
+pub mod jockey;
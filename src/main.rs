@@ -1,19 +1,15 @@
 #![warn(unsafe_op_in_unsafe_fn)]
 #![warn(missing_debug_implementations)]
 
-#[macro_use]
-mod util;
-mod jockey;
-
 use std::{
-    path::Path,
+    path::{Path, PathBuf},
     sync::atomic::{AtomicBool, Ordering},
     time::Duration,
 };
 
 use clap::Parser;
-use jockey::Jockey;
 use lazy_static::lazy_static;
+use sh4der_jockey::jockey::{self, Jockey};
 use simplelog::*;
 
 lazy_static! {
@@ -34,6 +30,10 @@ struct Args {
     #[clap(short, long, action = clap::ArgAction::Count, global = true)]
     #[clap(help = "Use verbose output (can be applied multiple times)")]
     verbose: u8,
+
+    #[clap(long)]
+    #[clap(help = "List audio inputs, MIDI ports and monitors which the config can name")]
+    list_devices: bool,
 }
 
 #[derive(Parser)]
@@ -45,6 +45,45 @@ enum SubCommand {
     #[clap(about = "Start the tool in the current working directory (default)")]
     #[command(alias("r"))]
     Run,
+
+    #[clap(about = "Build a pipeline without opening a window and report any errors")]
+    #[command(alias("c"))]
+    Check {
+        #[clap(help = "Path to the pipeline file")]
+        file: PathBuf,
+    },
+
+    #[clap(about = "Render a pipeline offscreen as fast as possible and report its timings")]
+    #[command(alias("b"))]
+    Bench {
+        #[clap(help = "Path to the pipeline file")]
+        file: PathBuf,
+
+        #[clap(long, default_value_t = 1000)]
+        #[clap(help = "Number of frames to render")]
+        frames: u32,
+    },
+
+    #[clap(about = "Same as --list-devices")]
+    #[command(alias("devices"))]
+    ListDevices,
+
+    #[clap(about = "Render a recording again, with the uniforms saved in its sidecar file")]
+    Replay {
+        #[clap(help = "Path to the pipeline file")]
+        file: PathBuf,
+
+        #[clap(help = "Path to the uniforms.yaml file of the recording")]
+        recording: PathBuf,
+
+        #[clap(long, default_value_t = 1920)]
+        #[clap(help = "Width of the rendered frames")]
+        width: u32,
+
+        #[clap(long, default_value_t = 1080)]
+        #[clap(help = "Height of the rendered frames")]
+        height: u32,
+    },
 }
 
 fn main() {
@@ -76,6 +115,11 @@ fn main() {
         log::warn!("Failed to resolve local time, logging in UTC");
     }
 
+    if args.list_devices || matches!(args.subcmd, Some(SubCommand::ListDevices)) {
+        print!("{}", jockey::list_devices());
+        return;
+    }
+
     if let Some(SubCommand::Init) = args.subcmd {
         let plf = Path::new("./pipeline.yaml");
         let shf = Path::new("./scene.frag");
@@ -100,13 +144,56 @@ fn main() {
         return;
     }
 
+    if let Some(SubCommand::Check { file }) = &args.subcmd {
+        match jockey::check_pipeline(file) {
+            Ok(()) => println!("{}: ok", file.display()),
+            Err(err) => {
+                eprintln!("{}:\n{}", file.display(), err);
+                std::process::exit(1);
+            }
+        }
+
+        return;
+    }
+
+    if let Some(SubCommand::Bench { file, frames }) = &args.subcmd {
+        match jockey::bench_pipeline(file, *frames) {
+            Ok(report) => print!("{}:\n{}", file.display(), report),
+            Err(err) => {
+                eprintln!("{}:\n{}", file.display(), err);
+                std::process::exit(1);
+            }
+        }
+
+        return;
+    }
+
+    if let Some(SubCommand::Replay {
+        file,
+        recording,
+        width,
+        height,
+    }) = &args.subcmd
+    {
+        match jockey::replay_recording(file, recording, (*width, *height)) {
+            Ok(frames) => println!("{}: rendered {} frames", recording.display(), frames),
+            Err(err) => {
+                eprintln!("{}:\n{}", recording.display(), err);
+                std::process::exit(1);
+            }
+        }
+
+        return;
+    }
+
     // set termination signal handler
     let kill_signal: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
     ctrlc::set_handler(move || {
         log::info!("Kill signal detected, attempt to shut down gracefully...");
         kill_signal.store(true, Ordering::Release);
 
-        // give it a moment to exit peacefully
+        // give it a moment to exit peacefully,
+        // the main loop then calls `Jockey::shutdown`
         std::thread::sleep(Duration::from_secs(3));
 
         log::info!("Alright, let's kill this thing");
@@ -137,6 +224,9 @@ fn main() {
         jockey.update_ui();
     }
 
+    // stop all background threads before the windows go away
+    jockey.shutdown();
+
     log::info!("Bye bye!");
 }
 
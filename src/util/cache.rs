@@ -15,6 +15,7 @@ impl CacheEntry {
     }
 }
 
+#[derive(Debug)]
 pub struct Cache;
 
 impl Cache {
@@ -47,4 +48,13 @@ impl Cache {
     pub fn fetch(path: &str) -> Option<Rc<dyn Texture>> {
         Self::internal().get(path).map(|s| Rc::clone(&s.tex))
     }
+
+    /// Forgets all textures, each is freed once no pipeline uses it anymore.
+    pub fn clear() {
+        unsafe {
+            if let Some(cache) = CACHE_INTERNAL.as_mut() {
+                cache.clear();
+            }
+        }
+    }
 }
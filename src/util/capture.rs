@@ -0,0 +1,155 @@
+use gl::types::*;
+
+use crate::*;
+
+/// Number of frames between requesting a capture and receiving its pixels.
+///
+/// A frame requested while rendering frame `n` is returned by
+/// `FrameCapture::finish_frame` at the end of frame `n + CAPTURE_LATENCY`.
+pub const CAPTURE_LATENCY: u64 = 2;
+
+const CAPTURE_SLOTS: usize = CAPTURE_LATENCY as usize + 1;
+
+/// The pixels of a captured frame.
+///
+/// Pixels are tightly packed RGB values, starting at the bottom left.
+#[derive(Debug)]
+pub struct CapturedFrame {
+    pub frame: u64,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingCapture {
+    frame: u64,
+    width: u32,
+    height: u32,
+}
+
+/// Reads frames back from the GPU without stalling.
+///
+/// Pixels are copied into a ring of pixel buffer objects, which the GPU fills
+/// in the background. They are only mapped `CAPTURE_LATENCY` frames later,
+/// when the copy has long finished, so capturing every frame is possible
+/// without blocking the render loop.
+#[derive(Debug)]
+pub struct FrameCapture {
+    pbos: [GLuint; CAPTURE_SLOTS],
+    pending: [Option<PendingCapture>; CAPTURE_SLOTS],
+    frame: u64,
+}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        let mut pbos = [0; CAPTURE_SLOTS];
+        unsafe {
+            gl::GenBuffers(CAPTURE_SLOTS as _, pbos.as_mut_ptr());
+            gl_debug_check!();
+        }
+
+        Self {
+            pbos,
+            pending: [None; CAPTURE_SLOTS],
+            frame: 0,
+        }
+    }
+
+    /// Number of the frame which is being rendered, as in `CapturedFrame::frame`.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Starts copying the default framebuffer of the current context.
+    ///
+    /// Only one capture can be requested per frame; later requests replace
+    /// earlier ones.
+    pub fn request(&mut self, width: u32, height: u32) {
+        let slot = (self.frame % CAPTURE_SLOTS as u64) as usize;
+        let size = 3 * width as usize * height as usize;
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.pbos[slot]);
+            gl::BufferData(
+                gl::PIXEL_PACK_BUFFER,
+                size as _,
+                std::ptr::null(),
+                gl::STREAM_READ,
+            );
+            gl_debug_check!();
+
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                width as _,
+                height as _,
+                gl::RGB,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null_mut(),
+            );
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            gl_debug_check!();
+        }
+
+        self.pending[slot] = Some(PendingCapture {
+            frame: self.frame,
+            width,
+            height,
+        });
+    }
+
+    /// Ends the current frame and returns all captures which are due.
+    pub fn finish_frame(&mut self) -> Vec<CapturedFrame> {
+        self.frame += 1;
+
+        let mut done = Vec::new();
+        for slot in 0..CAPTURE_SLOTS {
+            let capture = match self.pending[slot] {
+                Some(capture) if self.frame - capture.frame >= CAPTURE_LATENCY => capture,
+                _ => continue,
+            };
+
+            self.pending[slot] = None;
+            let size = 3 * capture.width as usize * capture.height as usize;
+            let mut pixels = vec![0; size];
+
+            unsafe {
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.pbos[slot]);
+                let ptr = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, size as _, gl::MAP_READ_BIT);
+                gl_debug_check!();
+
+                if ptr.is_null() {
+                    log::error!("Failed to map pixel buffer of frame {}", capture.frame);
+                    gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+                    continue;
+                }
+
+                std::ptr::copy_nonoverlapping(ptr as *const u8, pixels.as_mut_ptr(), size);
+                gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+                gl_debug_check!();
+            }
+
+            done.push(CapturedFrame {
+                frame: capture.frame,
+                width: capture.width,
+                height: capture.height,
+                pixels,
+            });
+        }
+
+        done.sort_by_key(|capture| capture.frame);
+        done
+    }
+}
+
+impl Drop for FrameCapture {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(CAPTURE_SLOTS as _, self.pbos.as_ptr());
+        }
+    }
+}
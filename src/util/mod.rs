@@ -1,21 +1,36 @@
 use std::{
     collections::HashSet,
     ffi::{c_void, CString},
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
 };
 
 use gl::types::*;
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde_yaml::Value;
 
 mod average;
 mod cache;
+mod capture;
+mod pixel;
 mod ringbuffer;
+mod shader_cache;
+mod shader_lib;
 mod texture;
+mod timer;
 
 pub use average::*;
 pub use cache::*;
+pub use capture::*;
+pub use pixel::*;
 pub use ringbuffer::*;
+pub use shader_cache::*;
+pub use shader_lib::*;
 pub use texture::*;
+pub use timer::*;
 
 pub fn gcd(mut x: u32, mut y: u32) -> u32 {
     if x == 0 {
@@ -45,6 +60,76 @@ pub fn gcd(mut x: u32, mut y: u32) -> u32 {
     x << shift
 }
 
+/// Runs a future to completion on the current thread.
+///
+/// The thread sleeps whenever the future is pending, until it is woken.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Returns the name of an OpenGL error code, as it is written in the spec.
+pub fn gl_error_name(err: GLenum) -> &'static str {
+    match err {
+        gl::NO_ERROR => "NO_ERROR",
+        gl::INVALID_ENUM => "INVALID_ENUM",
+        gl::INVALID_VALUE => "INVALID_VALUE",
+        gl::INVALID_OPERATION => "INVALID_OPERATION",
+        gl::INVALID_FRAMEBUFFER_OPERATION => "INVALID_FRAMEBUFFER_OPERATION",
+        gl::OUT_OF_MEMORY => "OUT_OF_MEMORY",
+        gl::STACK_UNDERFLOW => "STACK_UNDERFLOW",
+        gl::STACK_OVERFLOW => "STACK_OVERFLOW",
+        gl::CONTEXT_LOST => "CONTEXT_LOST",
+        _ => "unknown",
+    }
+}
+
+/// Returns the GLSL name of a uniform type, as reported by `glGetActiveUniform`.
+pub fn glsl_type_name(kind: GLenum) -> &'static str {
+    match kind {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::UNSIGNED_INT_VEC2 => "uvec2",
+        gl::UNSIGNED_INT_VEC3 => "uvec3",
+        gl::UNSIGNED_INT_VEC4 => "uvec4",
+        gl::BOOL => "bool",
+        gl::BOOL_VEC2 => "bvec2",
+        gl::BOOL_VEC3 => "bvec3",
+        gl::BOOL_VEC4 => "bvec4",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::SAMPLER_1D => "sampler1D",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::INT_SAMPLER_2D => "isampler2D",
+        gl::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        _ => "an unsupported type",
+    }
+}
+
 #[macro_export]
 macro_rules! gl_check {
     () => {
@@ -53,15 +138,7 @@ macro_rules! gl_check {
         let err = unsafe { gl::GetError() };
 
         if err != gl::NO_ERROR {
-            let name = match err {
-                gl::INVALID_ENUM => "INVALID_ENUM",
-                gl::INVALID_VALUE => "INVALID_VALUE",
-                gl::INVALID_OPERATION => "INVALID_OPERATION",
-                gl::INVALID_FRAMEBUFFER_OPERATION => "INVALID_ENUM",
-                gl::OUT_OF_MEMORY => "OUT_OF_MEMORY",
-                _ => "unknown",
-            };
-
+            let name = $crate::util::gl_error_name(err);
             panic!("OpenGL error: {} ({})", name, err);
         }
     };
@@ -94,7 +171,7 @@ macro_rules! gl_debug_ignore {
     };
 }
 
-const FULLSCREEN_RECT: [GLfloat; 12] = [
+pub const FULLSCREEN_RECT: [GLfloat; 12] = [
     -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0,
 ];
 
@@ -119,7 +196,7 @@ pub fn draw_fullscreen(vao: GLuint) {
     }
 }
 
-pub fn draw_vertices(vao: GLuint, count: GLsizei, mode: GLenum) {
+pub fn draw_vertices(vao: GLuint, count: GLsizei, mode: GLenum, instances: GLsizei) {
     unsafe {
         gl::BindVertexArray(vao);
         gl::BindBuffer(gl::ARRAY_BUFFER, vao);
@@ -128,7 +205,11 @@ pub fn draw_vertices(vao: GLuint, count: GLsizei, mode: GLenum) {
         gl::BufferData(gl::ARRAY_BUFFER, 0, std::ptr::null(), gl::STATIC_DRAW);
         gl_debug_check!();
 
-        gl::DrawArrays(mode, 0, count);
+        if instances > 1 {
+            gl::DrawArraysInstanced(mode, 0, count, instances);
+        } else {
+            gl::DrawArrays(mode, 0, count);
+        }
         gl_debug_check!();
     }
 }
@@ -168,9 +249,12 @@ pub fn compile_shader(src: &str, ty: GLenum) -> Result<GLuint, String> {
 ///
 /// Creates a new program and attaches the given shaders to that program.
 pub fn link_program(sh: &[GLuint]) -> Result<GLuint, String> {
-    unsafe {
-        let program = gl::CreateProgram();
+    unsafe { link_into(gl::CreateProgram(), sh) }
+}
 
+/// Attaches the given shaders to an existing program and links it.
+unsafe fn link_into(program: GLuint, sh: &[GLuint]) -> Result<GLuint, String> {
+    unsafe {
         // Link program
         sh.iter().for_each(|&s| gl::AttachShader(program, s));
         gl::LinkProgram(program);
@@ -197,6 +281,53 @@ pub fn link_program(sh: &[GLuint]) -> Result<GLuint, String> {
     }
 }
 
+/// A uniform which is declared and used by a linked program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveUniform {
+    pub name: String,
+    pub kind: GLenum,
+    pub size: GLint,
+}
+
+/// Lists all active uniforms of a linked program.
+///
+/// Uniforms which are declared in the shader source, but never used, are
+/// usually optimized out by the driver and won't show up here. Array
+/// uniforms are reported by their base name without the `[0]` suffix.
+pub fn active_uniforms(program: GLuint) -> Vec<ActiveUniform> {
+    unsafe {
+        let mut count = 0;
+        let mut max_len = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_len);
+        gl_debug_check!();
+
+        let mut out = Vec::with_capacity(count as _);
+        let mut buf = vec![0_u8; max_len.max(1) as usize];
+        for index in 0..count {
+            let mut len = 0;
+            let mut size = 0;
+            let mut kind = 0;
+            gl::GetActiveUniform(
+                program,
+                index as _,
+                buf.len() as _,
+                &mut len,
+                &mut size,
+                &mut kind,
+                buf.as_mut_ptr() as _,
+            );
+            gl_debug_check!();
+
+            let name = String::from_utf8_lossy(&buf[..len as usize]);
+            let name = name.strip_suffix("[0]").unwrap_or(&name).to_string();
+            out.push(ActiveUniform { name, kind, size });
+        }
+
+        out
+    }
+}
+
 #[allow(non_snake_case)]
 pub unsafe fn gl_TexImageND(
     target: GLenum,
@@ -267,13 +398,102 @@ fn file_index_jank(n: u32) -> u32 {
     ((7 * n + 100) & lim) ^ lim
 }
 
-pub fn process_error(mut err: String, lut: &[String]) -> String {
-    for (k, file) in lut.iter().enumerate() {
-        let key = format!("{}", file_index_jank(k as u32));
-        err = err.replace(key.as_str(), file);
+/// Looks up the file name of a file id inserted by [`preprocess`].
+fn lookup_file_id(id: u32, lut: &[String]) -> Option<&str> {
+    #[cfg(not(test))]
+    let index = (0..lut.len()).find(|&k| file_index_jank(k as u32) == id)?;
+
+    #[cfg(test)]
+    let index = id as usize;
+
+    lut.get(index).map(String::as_str)
+}
+
+/// Parses a single line of a shader info log.
+///
+/// Drivers don't agree on a common format, so this tries the formats of
+/// the most common vendors and returns the file id, line number and message.
+fn parse_error_line(line: &str) -> Option<(u32, u32, String)> {
+    lazy_static! {
+        // NVIDIA: 0(12) : error C0000: syntax error, unexpected ...
+        static ref NVIDIA_RE: Regex = Regex::new(
+            r#"^\s*(?P<file>\d+)\((?P<line>\d+)\)\s*:\s*(?P<msg>.*)$"#
+        ).expect("failed to compile regex");
+
+        // Mesa: 0:12(5): error: `foo' undeclared
+        static ref MESA_RE: Regex = Regex::new(
+            r#"^\s*(?P<file>\d+):(?P<line>\d+)\(\d+\)\s*:\s*(?P<msg>.*)$"#
+        ).expect("failed to compile regex");
+
+        // AMD, Intel and Apple: ERROR: 0:12: 'foo' : undeclared identifier
+        static ref ERROR_RE: Regex = Regex::new(
+            r#"^\s*(?P<severity>ERROR|WARNING)\s*:\s*(?P<file>\d+):(?P<line>\d+)\s*:\s*(?P<msg>.*)$"#
+        ).expect("failed to compile regex");
     }
 
-    err
+    let regexes: [&Regex; 3] = [&NVIDIA_RE, &MESA_RE, &ERROR_RE];
+    for re in regexes.iter() {
+        if let Some(caps) = re.captures(line) {
+            let file = caps["file"].parse().ok()?;
+            let line = caps["line"].parse().ok()?;
+            let msg = match caps.name("severity") {
+                Some(severity) => format!(
+                    "{}: {}",
+                    severity.as_str().to_lowercase(),
+                    caps["msg"].trim()
+                ),
+                None => caps["msg"].trim().to_string(),
+            };
+
+            return Some((file, line, msg));
+        }
+    }
+
+    None
+}
+
+/// Rewrites a shader info log to use the original file names.
+///
+/// Every line of the log which can be parsed is printed as
+/// `file:line: message`. Lines in an unknown format are kept as they are.
+pub fn process_error(err: String, lut: &[String]) -> String {
+    let mut out = Vec::new();
+    for line in err.lines() {
+        let parsed = parse_error_line(line).and_then(|(id, line_num, msg)| {
+            let file = lookup_file_id(id, lut)?;
+            Some(format!("{}:{}: {}", file, line_num, msg))
+        });
+
+        match parsed {
+            Some(line) => out.push(line),
+            None => {
+                // fall back to replacing the file ids
+                let mut line = line.to_string();
+                for (k, file) in lut.iter().enumerate() {
+                    let key = format!("{}", file_index_jank(k as u32));
+                    line = line.replace(key.as_str(), file);
+                }
+                out.push(line);
+            }
+        }
+    }
+
+    out.join("\n")
+}
+
+/// Reads a file which is included by a shader.
+#[cfg(not(test))]
+fn read_include(file_name: &str) -> Result<String, String> {
+    std::fs::read_to_string(file_name).map_err(|e| format!("{}, {}", e, file_name))
+}
+
+/// Dummy for unit tests, files named `missing*` do not exist.
+#[cfg(test)]
+fn read_include(file_name: &str) -> Result<String, String> {
+    match file_name.starts_with("missing") {
+        true => Err(format!("No such file, {}", file_name)),
+        false => Ok("#pragma once\nint hoge = 0;\n".to_string()),
+    }
 }
 
 pub fn preprocess(
@@ -283,8 +503,9 @@ pub fn preprocess(
 ) -> Result<String, String> {
     lazy_static! {
         // based on the "glsl-include" crate, which almost does what we want
+        // quotes read a file from disk, angle brackets the built-in library
         static ref INCLUDE_RE: Regex = Regex::new(
-            r#"#\s*(pragma\s*)?include\s+[<"](?P<file>.*)[>"]"#
+            r#"#\s*(pragma\s*)?include\s+(<(?P<lib>[^>]*)>|"(?P<file>[^"]*)")"#
         ).expect("failed to compile regex");
 
         static ref ONCE_RE: Regex = Regex::new(
@@ -341,12 +562,7 @@ pub fn preprocess(
         for (k, line) in code.lines().enumerate() {
             let include_re: &Regex = &INCLUDE_RE;
             if let Some(include) = include_re.find(line) {
-                let file_name = include_re
-                    .captures(include.as_str())
-                    .unwrap()
-                    .name("file")
-                    .unwrap()
-                    .as_str();
+                let captures = include_re.captures(include.as_str()).unwrap();
 
                 // get line prefix
                 let offset = unsafe { include.as_str().as_ptr().offset_from(code.as_ptr()) };
@@ -354,21 +570,35 @@ pub fn preprocess(
 
                 // check for comments
                 if !(in_block(prefix, "//", "\n") || in_block(prefix, "/*", "*/")) {
-                    // fetch file
-                    #[cfg(not(test))]
-                    let file = match std::fs::read_to_string(file_name) {
-                        Ok(s) => s,
-                        Err(e) => return Err(format!("{}, {}", e.to_string(), file_name)),
+                    let (file, file_name) = match captures.name("lib") {
+                        // the brackets keep library files out of the watched files
+                        Some(lib) => match shader_lib_file(lib.as_str()) {
+                            Some((name, source)) => (source.to_string(), format!("<{}>", name)),
+                            // other names are read from disk, like quoted ones
+                            None => match read_include(lib.as_str()) {
+                                Ok(file) => (file, lib.as_str().to_string()),
+                                Err(err) => {
+                                    return Err(format!(
+                                        "{}, and there is no library file <{}>, expected one \
+                                         of {}. Use \"{}\" for local includes.",
+                                        err,
+                                        lib.as_str(),
+                                        shader_lib_names().join(", "),
+                                        lib.as_str()
+                                    ))
+                                }
+                            },
+                        },
+                        None => {
+                            let file_name = captures.name("file").unwrap().as_str();
+                            (read_include(file_name)?, file_name.to_string())
+                        }
                     };
 
-                    // dummy for unit tests
-                    #[cfg(test)]
-                    let file = "#pragma once\nint hoge = 0;\n".to_string();
-
                     // recursively process file
                     let mut file_lines = recurse(
                         &file,
-                        file_name,
+                        &file_name,
                         cycle_seen.clone(),
                         once_ignore,
                         lut,
@@ -415,6 +645,97 @@ pub fn preprocess(
     Ok(lines.join("\n"))
 }
 
+/// Version and extension directives which a stage puts in front of its shaders.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShaderHeader {
+    pub version: Option<String>,
+    pub extensions: Vec<String>,
+}
+
+impl ShaderHeader {
+    /// Reads the `version` and `extensions` fields of a stage.
+    pub fn parse(object: &Value) -> Result<Self, String> {
+        let version = match object.get("version") {
+            Some(Value::Number(n)) if n.is_u64() => Some(n.to_string()),
+            Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().to_string()),
+            Some(s) => {
+                return Err(format!(
+                    "Expected \"version\" to be a number like 430 or a string like \"300 es\", got {:?}",
+                    s
+                ))
+            }
+            None => None,
+        };
+
+        let mut extensions = Vec::new();
+        match object.get("extensions") {
+            Some(Value::Sequence(names)) => {
+                for name in names {
+                    match name.as_str() {
+                        Some(name) if !name.trim().is_empty() => {
+                            extensions.push(name.trim().to_string())
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected \"extensions\" to be a list of names, got {:?}",
+                                name
+                            ))
+                        }
+                    }
+                }
+            }
+            Some(s) => {
+                return Err(format!(
+                    "Expected \"extensions\" to be a list of names, got {:?}",
+                    s
+                ))
+            }
+            None => (),
+        }
+
+        Ok(Self {
+            version,
+            extensions,
+        })
+    }
+
+    /// Adds the directives to a preprocessed shader.
+    ///
+    /// A `#version` in the source always wins over the configured one.
+    /// Everything is inserted above the first `#line` directive, so line
+    /// numbers in error messages stay correct.
+    pub fn apply(&self, code: String) -> String {
+        if self.version.is_none() && self.extensions.is_empty() {
+            return code;
+        }
+
+        let mut lines: Vec<_> = code.lines().map(String::from).collect();
+        let position = match lines
+            .iter()
+            .position(|l| l.trim_start().starts_with("#version"))
+        {
+            Some(k) => k + 1,
+            None => {
+                if let Some(version) = &self.version {
+                    lines.insert(0, format!("#version {}", version));
+                }
+                self.version.is_some() as usize
+            }
+        };
+
+        for (k, name) in self.extensions.iter().enumerate() {
+            // names without a behavior are enabled
+            let directive = match name.contains(':') {
+                true => format!("#extension {}", name),
+                false => format!("#extension {} : enable", name),
+            };
+            lines.insert(position + k, directive);
+        }
+
+        lines.join("\n")
+    }
+}
+
 pub fn interlace<T: Clone>(first: &[T], second: &[T], out: &mut [T]) {
     debug_assert_eq!(first.len() + second.len(), out.len());
 
@@ -445,6 +766,43 @@ pub fn deinterlace<T: Clone>(slice: &[T]) -> (Vec<T>, Vec<T>) {
 mod test {
     use super::*;
 
+    #[test]
+    fn process_error_nvidia() {
+        let lut = vec!["scene.frag".to_string(), "common.glsl".to_string()];
+        let err = "1(12) : error C1008: undefined variable \"foo\"".to_string();
+        assert_eq!(
+            process_error(err, &lut),
+            "common.glsl:12: error C1008: undefined variable \"foo\""
+        );
+    }
+
+    #[test]
+    fn process_error_mesa() {
+        let lut = vec!["scene.frag".to_string()];
+        let err = "0:7(10): error: `foo' undeclared\n0:7(10): error: operands to arithmetic operators must be numeric".to_string();
+        assert_eq!(
+            process_error(err, &lut),
+            "scene.frag:7: error: `foo' undeclared\nscene.frag:7: error: operands to arithmetic operators must be numeric"
+        );
+    }
+
+    #[test]
+    fn process_error_amd_intel() {
+        let lut = vec!["scene.frag".to_string()];
+        let err = "ERROR: 0:3: 'foo' : undeclared identifier \nERROR: 1 compilation errors.  No code generated.".to_string();
+        assert_eq!(
+            process_error(err, &lut),
+            "scene.frag:3: error: 'foo' : undeclared identifier\nERROR: 1 compilation errors.  No code generated."
+        );
+    }
+
+    #[test]
+    fn process_error_unknown_file() {
+        let lut = vec!["scene.frag".to_string()];
+        let err = "5(1) : error C0000: syntax error".to_string();
+        assert_eq!(process_error(err.clone(), &lut), err);
+    }
+
     #[test]
     fn in_block_simple() {
         assert!(in_block("aa ( bb", "(", ")"));
@@ -541,6 +899,55 @@ mod test {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn preprocess_include_library() {
+        let original = "#version 123\n#include <noise.glsl>\n#include <v1/hash.glsl>\nmain(){}";
+        let mut lut = Vec::new();
+        let result = preprocess(original, "test", &mut lut).unwrap();
+        assert_eq!(lut, ["test", "<v1/noise.glsl>", "<v1/hash.glsl>"]);
+        assert_eq!(result.matches("float hash11(float p)").count(), 1);
+        assert!(result.contains("float worley(vec2 p)"));
+        assert!(result.ends_with("#line 4 0\nmain(){}"));
+
+        // other files in angle brackets are read from disk
+        let original = "#version 123\n#include <common.glsl>\nmain(){}";
+        let mut lut = Vec::new();
+        let result = preprocess(original, "test", &mut lut).unwrap();
+        assert_eq!(lut, ["test", "common.glsl"]);
+        assert!(result.contains("int hoge = 0;"));
+
+        let original = "#version 123\n#include <missing_noise.glsl>\nmain(){}";
+        let err = preprocess(original, "test", &mut Vec::new()).unwrap_err();
+        assert!(err.contains("<missing_noise.glsl>"), "{}", err);
+        assert!(err.contains("\"missing_noise.glsl\""), "{}", err);
+    }
+
+    #[test]
+    fn header_keeps_source_version() {
+        let header = ShaderHeader {
+            version: Some("430".into()),
+            extensions: vec!["GL_ARB_gpu_shader_fp64".into()],
+        };
+
+        let mut lut = Vec::new();
+        let code = preprocess("#version 123\nmain(){}", "test", &mut lut).unwrap();
+        let expected = "#version 123\n#extension GL_ARB_gpu_shader_fp64 : enable\n#define SH4DERJOCKEY 1\n#line 2 0\nmain(){}";
+        assert_eq!(header.apply(code), expected);
+    }
+
+    #[test]
+    fn header_adds_version() {
+        let header = ShaderHeader {
+            version: Some("300 es".into()),
+            extensions: vec!["GL_OES_standard_derivatives : require".into()],
+        };
+
+        let mut lut = Vec::new();
+        let code = preprocess("main(){}", "test", &mut lut).unwrap();
+        let expected = "#version 300 es\n#extension GL_OES_standard_derivatives : require\n#define SH4DERJOCKEY 1\n#line 1 0\nmain(){}";
+        assert_eq!(header.apply(code), expected);
+    }
+
     #[test]
     fn preprocess_include_pragma_once() {
         let original =
@@ -0,0 +1,122 @@
+use gl::types::*;
+
+use crate::*;
+
+/// Reads single pixels back from the GPU without stalling.
+///
+/// The pixel is copied into a pixel buffer object, and a fence tells when
+/// the copy has finished, which is usually a frame or two later. Only one
+/// pixel can be in flight at a time.
+#[derive(Debug)]
+pub struct PixelReader {
+    pbo: GLuint,
+    fence: Option<GLsync>,
+}
+
+impl PixelReader {
+    pub fn new() -> Self {
+        let mut pbo = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut pbo);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, pbo);
+            gl::BufferData(
+                gl::PIXEL_PACK_BUFFER,
+                std::mem::size_of::<[f32; 4]>() as _,
+                std::ptr::null(),
+                gl::STREAM_READ,
+            );
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            gl_debug_check!();
+        }
+
+        Self { pbo, fence: None }
+    }
+
+    /// Whether a pixel is still being copied.
+    pub fn is_busy(&self) -> bool {
+        self.fence.is_some()
+    }
+
+    /// Starts copying the pixel at `x`, `y` of a framebuffer, from the bottom left.
+    ///
+    /// The pixel is read as floats, so float targets keep values outside of
+    /// 0 to 1, while 8 bit targets read as values from 0 to 1. Framebuffer 0
+    /// reads the back buffer of the window. Returns `false` without doing
+    /// anything while the previous pixel is still being copied.
+    pub fn request(&mut self, fb_id: GLuint, x: u32, y: u32) -> bool {
+        if self.is_busy() {
+            return false;
+        }
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fb_id);
+            match fb_id {
+                0 => gl::ReadBuffer(gl::BACK),
+                _ => gl::ReadBuffer(gl::COLOR_ATTACHMENT0),
+            }
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.pbo);
+            gl::ReadPixels(
+                x as _,
+                y as _,
+                1,
+                1,
+                gl::RGBA,
+                gl::FLOAT,
+                std::ptr::null_mut(),
+            );
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            gl_debug_check!();
+
+            self.fence = Some(gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+            gl_debug_check!();
+        }
+
+        true
+    }
+
+    /// Returns the color of the requested pixel once it has been copied.
+    pub fn poll(&mut self) -> Option<[f32; 4]> {
+        let fence = self.fence?;
+
+        let mut color = [0.0; 4];
+        unsafe {
+            match gl::ClientWaitSync(fence, 0, 0) {
+                gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => (),
+                gl::WAIT_FAILED => {
+                    log::error!("Failed to wait for the pixel read back");
+                    gl::DeleteSync(fence);
+                    self.fence = None;
+                    return None;
+                }
+                _ => return None,
+            }
+
+            gl::DeleteSync(fence);
+            self.fence = None;
+
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.pbo);
+            gl::GetBufferSubData(
+                gl::PIXEL_PACK_BUFFER,
+                0,
+                std::mem::size_of::<[f32; 4]>() as _,
+                color.as_mut_ptr() as _,
+            );
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            gl_debug_check!();
+        }
+
+        Some(color)
+    }
+}
+
+impl Drop for PixelReader {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(fence) = self.fence.take() {
+                gl::DeleteSync(fence);
+            }
+            gl::DeleteBuffers(1, &self.pbo);
+        }
+    }
+}
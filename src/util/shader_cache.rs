@@ -0,0 +1,276 @@
+use std::{
+    convert::TryInto,
+    ffi::CStr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use gl::types::*;
+use lazy_static::lazy_static;
+
+use crate::{util::link_program, *};
+
+/// Number of program binaries kept in the cache, the oldest ones are deleted first
+const SHADER_CACHE_MAX_FILES: usize = 256;
+
+lazy_static! {
+    static ref SHADER_CACHE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Sets the folder linked programs are cached in, `None` turns the cache off.
+pub fn set_shader_cache_dir(dir: Option<PathBuf>) {
+    if let Ok(mut current) = SHADER_CACHE_DIR.lock() {
+        *current = dir;
+    }
+}
+
+/// Builds a program, reusing the binary of an earlier build if possible.
+///
+/// The cache is keyed by the shader sources and the OpenGL driver, so any
+/// change to either causes a fresh build. `compile` is only called if no
+/// usable binary was found, and must return the compiled shaders. Binaries
+/// which the driver rejects are deleted and rebuilt from source.
+///
+/// Returns the program and the shaders it was linked from, the latter being
+/// empty if the program was loaded from the cache. Only the newest binaries
+/// are kept, so edits of a shader do not fill the folder.
+pub fn link_program_cached<F>(sources: &[&str], compile: F) -> Result<(GLuint, Vec<GLuint>), String>
+where
+    F: FnOnce() -> Result<Vec<GLuint>, String>,
+{
+    let dir = SHADER_CACHE_DIR.lock().ok().and_then(|dir| dir.clone());
+    let dir = match dir {
+        Some(dir) => dir,
+        None => {
+            let sh_ids = compile()?;
+            return Ok((link_program(&sh_ids)?, sh_ids));
+        }
+    };
+
+    let path = dir.join(cache_file_name(sources, &driver_id()));
+    if let Ok(bytes) = std::fs::read(&path) {
+        match decode_binary(&bytes).and_then(|(format, binary)| load_binary(format, binary)) {
+            Some(program) => {
+                log::debug!("Loaded program binary from {}", path.display());
+                // pruning goes by modification time, so mark the binary as recently used
+                let touch = std::fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .and_then(|file| file.set_modified(SystemTime::now()));
+                if let Err(err) = touch {
+                    log::debug!("Failed to touch {}: {}", path.display(), err);
+                }
+                return Ok((program, Vec::new()));
+            }
+            None => {
+                log::info!(
+                    "Program binary {} was rejected, rebuilding it",
+                    path.display()
+                );
+                std::fs::remove_file(&path).ok();
+            }
+        }
+    }
+
+    let sh_ids = compile()?;
+    let program = unsafe {
+        let program = gl::CreateProgram();
+        gl::ProgramParameteri(program, gl::PROGRAM_BINARY_RETRIEVABLE_HINT, gl::TRUE as _);
+        super::link_into(program, &sh_ids)?
+    };
+
+    if let Err(err) = store_binary(program, &path) {
+        log::warn!(
+            "Failed to cache program binary in {}: {}",
+            path.display(),
+            err
+        );
+    }
+    prune_cache(&dir);
+
+    Ok((program, sh_ids))
+}
+
+/// Identifies the driver, since binaries are only valid for the driver which built them.
+fn driver_id() -> String {
+    let get = |name| unsafe {
+        let ptr = gl::GetString(name);
+        match ptr.is_null() {
+            true => String::new(),
+            false => CStr::from_ptr(ptr as _).to_string_lossy().into_owned(),
+        }
+    };
+
+    format!(
+        "{}|{}|{}",
+        get(gl::VENDOR),
+        get(gl::RENDERER),
+        get(gl::VERSION)
+    )
+}
+
+/// Names the binary after a 64 bit FNV-1a hash of the driver and the sources.
+///
+/// The hash is spelled out rather than taken from the standard library, whose
+/// hashers may change between Rust releases and would orphan every binary.
+fn cache_file_name(sources: &[&str], driver: &str) -> String {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    let mut feed = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    };
+
+    // lengths keep the parts apart, so "a", "bc" differs from "ab", "c"
+    for part in std::iter::once(driver).chain(sources.iter().copied()) {
+        feed(&(part.len() as u64).to_le_bytes());
+        feed(part.as_bytes());
+    }
+
+    format!("{:016x}.bin", hash)
+}
+
+/// Deletes the oldest binaries in `dir` beyond `SHADER_CACHE_MAX_FILES`.
+fn prune_cache(dir: &Path) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let binaries = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "bin"))
+        .filter_map(|path| {
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect();
+
+    for path in stale_binaries(binaries, SHADER_CACHE_MAX_FILES) {
+        match std::fs::remove_file(&path) {
+            Ok(()) => log::debug!("Deleted old program binary {}", path.display()),
+            Err(err) => log::warn!("Failed to delete {}: {}", path.display(), err),
+        }
+    }
+}
+
+/// Picks all but the `keep` most recently used binaries.
+fn stale_binaries(mut binaries: Vec<(PathBuf, SystemTime)>, keep: usize) -> Vec<PathBuf> {
+    binaries.sort_by(|a, b| b.1.cmp(&a.1));
+    binaries
+        .into_iter()
+        .skip(keep)
+        .map(|(path, _)| path)
+        .collect()
+}
+
+/// Prepends the binary format to the binary, since both are needed to load it.
+fn encode_binary(format: GLenum, binary: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(4 + binary.len());
+    bytes.extend_from_slice(&format.to_le_bytes());
+    bytes.extend_from_slice(binary);
+    bytes
+}
+
+fn decode_binary(bytes: &[u8]) -> Option<(GLenum, &[u8])> {
+    if bytes.len() <= 4 {
+        return None;
+    }
+
+    let (format, binary) = bytes.split_at(4);
+    Some((GLenum::from_le_bytes(format.try_into().ok()?), binary))
+}
+
+fn load_binary(format: GLenum, binary: &[u8]) -> Option<GLuint> {
+    unsafe {
+        let program = gl::CreateProgram();
+        gl::ProgramBinary(program, format, binary.as_ptr() as _, binary.len() as _);
+
+        let mut status = gl::FALSE as GLint;
+        gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
+
+        // drivers reject binaries after an update, which also sets an error
+        while gl::GetError() != gl::NO_ERROR {}
+
+        if status != (gl::TRUE as GLint) {
+            gl::DeleteProgram(program);
+            return None;
+        }
+
+        Some(program)
+    }
+}
+
+fn store_binary(program: GLuint, path: &Path) -> Result<(), String> {
+    let (format, binary) = unsafe {
+        let mut len = 0;
+        gl::GetProgramiv(program, gl::PROGRAM_BINARY_LENGTH, &mut len);
+        if len <= 0 {
+            return Err("The driver does not provide program binaries".into());
+        }
+
+        let mut format = 0;
+        let mut binary = vec![0_u8; len as usize];
+        gl::GetProgramBinary(
+            program,
+            len,
+            std::ptr::null_mut(),
+            &mut format,
+            binary.as_mut_ptr() as _,
+        );
+        gl_debug_check!();
+
+        (format, binary)
+    };
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+
+    std::fs::write(path, encode_binary(format, &binary)).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn file_names() {
+        let a = cache_file_name(&["void main() {}"], "driver");
+        assert_eq!(a, cache_file_name(&["void main() {}"], "driver"));
+        assert_ne!(a, cache_file_name(&["void main() { }"], "driver"));
+        assert_ne!(a, cache_file_name(&["void main() {}"], "other driver"));
+        assert_ne!(
+            cache_file_name(&["a", "bc"], "driver"),
+            cache_file_name(&["ab", "c"], "driver")
+        );
+
+        // names must stay the same across builds and Rust releases
+        assert_eq!(a, "8ca3c55cb0dee8d3.bin");
+    }
+
+    #[test]
+    fn pruning() {
+        let at = |secs| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        let binaries = vec![
+            (PathBuf::from("a.bin"), at(30)),
+            (PathBuf::from("b.bin"), at(10)),
+            (PathBuf::from("c.bin"), at(20)),
+        ];
+
+        assert_eq!(
+            stale_binaries(binaries.clone(), 1),
+            ["c.bin", "b.bin"].map(PathBuf::from)
+        );
+        assert!(stale_binaries(binaries, 3).is_empty());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let bytes = encode_binary(0x1234, &[1, 2, 3]);
+        assert_eq!(decode_binary(&bytes), Some((0x1234, &[1_u8, 2, 3][..])));
+        assert_eq!(decode_binary(&bytes[..4]), None);
+    }
+}
@@ -0,0 +1,73 @@
+/// Newest version of the built-in shader library.
+pub const SHADER_LIB_VERSION: u32 = 1;
+
+/// Files of the built-in shader library, by version and name.
+///
+/// Released versions are never changed, so shaders which include a file
+/// with its version can rely on the signatures. New functions go into a
+/// new version.
+const SHADER_LIB: &[(u32, &str, &str)] = &[
+    (1, "hash.glsl", include_str!("shader_lib/v1/hash.glsl")),
+    (1, "noise.glsl", include_str!("shader_lib/v1/noise.glsl")),
+    (1, "color.glsl", include_str!("shader_lib/v1/color.glsl")),
+    (
+        1,
+        "tonemap.glsl",
+        include_str!("shader_lib/v1/tonemap.glsl"),
+    ),
+];
+
+/// Looks up a file of the built-in library, like `noise.glsl` or `v1/noise.glsl`.
+///
+/// Names without a version refer to the newest one. Returns the name with
+/// its version, so both spellings of the same file are only included once,
+/// and the source of the file.
+pub fn shader_lib_file(name: &str) -> Option<(String, &'static str)> {
+    let (version, file) = match name.strip_prefix('v').and_then(|s| s.split_once('/')) {
+        Some((version, file)) => (version.parse().ok()?, file),
+        None => (SHADER_LIB_VERSION, name),
+    };
+
+    SHADER_LIB
+        .iter()
+        .find(|&&(v, f, _)| v == version && f == file)
+        .map(|&(v, f, source)| (format!("v{}/{}", v, f), source))
+}
+
+/// Names of all files of the newest version, for error messages.
+pub fn shader_lib_names() -> Vec<&'static str> {
+    SHADER_LIB
+        .iter()
+        .filter(|&&(v, _, _)| v == SHADER_LIB_VERSION)
+        .map(|&(_, f, _)| f)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let (name, source) = shader_lib_file("noise.glsl").unwrap();
+        assert_eq!(name, "v1/noise.glsl");
+        assert!(source.contains("float fbm(vec2 p, int octaves)"));
+        assert_eq!(shader_lib_file("v1/noise.glsl").unwrap().0, name);
+
+        assert_eq!(shader_lib_file("noise"), None);
+        assert_eq!(shader_lib_file("v9/noise.glsl"), None);
+        assert_eq!(shader_lib_file("vx/noise.glsl"), None);
+        assert_eq!(
+            shader_lib_names(),
+            ["hash.glsl", "noise.glsl", "color.glsl", "tonemap.glsl"]
+        );
+    }
+
+    #[test]
+    fn files_are_guarded() {
+        // every file has to survive being included twice
+        for &(_, name, source) in SHADER_LIB {
+            assert!(source.contains("#pragma once"), "{}", name);
+        }
+    }
+}
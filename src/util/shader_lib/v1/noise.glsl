@@ -0,0 +1,87 @@
+// sh4der-jockey shader library, version 1
+//
+// Value noise and fbm return values between 0 and 1, gradient noise
+// between -1 and 1, and worley the distance to the closest feature point.
+#pragma once
+#include <v1/hash.glsl>
+
+float value_noise(vec2 p) {
+  vec2 i = floor(p);
+  vec2 f = fract(p);
+  vec2 u = f * f * (3.0 - 2.0 * f);
+
+  float a = hash12(i);
+  float b = hash12(i + vec2(1.0, 0.0));
+  float c = hash12(i + vec2(0.0, 1.0));
+  float d = hash12(i + vec2(1.0, 1.0));
+  return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+float value_noise(vec3 p) {
+  vec3 i = floor(p);
+  vec3 f = fract(p);
+  vec3 u = f * f * (3.0 - 2.0 * f);
+
+  float a = mix(hash13(i), hash13(i + vec3(1, 0, 0)), u.x);
+  float b = mix(hash13(i + vec3(0, 1, 0)), hash13(i + vec3(1, 1, 0)), u.x);
+  float c = mix(hash13(i + vec3(0, 0, 1)), hash13(i + vec3(1, 0, 1)), u.x);
+  float d = mix(hash13(i + vec3(0, 1, 1)), hash13(i + vec3(1, 1, 1)), u.x);
+  return mix(mix(a, b, u.y), mix(c, d, u.y), u.z);
+}
+
+float gradient_noise(vec2 p) {
+  vec2 i = floor(p);
+  vec2 f = fract(p);
+  vec2 u = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+
+  vec2 ga = hash22(i) * 2.0 - 1.0;
+  vec2 gb = hash22(i + vec2(1.0, 0.0)) * 2.0 - 1.0;
+  vec2 gc = hash22(i + vec2(0.0, 1.0)) * 2.0 - 1.0;
+  vec2 gd = hash22(i + vec2(1.0, 1.0)) * 2.0 - 1.0;
+
+  float a = dot(ga, f);
+  float b = dot(gb, f - vec2(1.0, 0.0));
+  float c = dot(gc, f - vec2(0.0, 1.0));
+  float d = dot(gd, f - vec2(1.0, 1.0));
+  return 1.41421 * mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+float fbm(vec2 p, int octaves) {
+  float sum = 0.0;
+  float amplitude = 0.5;
+  float total = 0.0;
+  for (int k = 0; k < octaves; k++) {
+    sum += amplitude * value_noise(p);
+    total += amplitude;
+    p = p * 2.03 + 17.0;
+    amplitude *= 0.5;
+  }
+  return sum / max(total, 1e-6);
+}
+
+float fbm(vec3 p, int octaves) {
+  float sum = 0.0;
+  float amplitude = 0.5;
+  float total = 0.0;
+  for (int k = 0; k < octaves; k++) {
+    sum += amplitude * value_noise(p);
+    total += amplitude;
+    p = p * 2.03 + 17.0;
+    amplitude *= 0.5;
+  }
+  return sum / max(total, 1e-6);
+}
+
+float worley(vec2 p) {
+  vec2 i = floor(p);
+  vec2 f = fract(p);
+  float closest = 8.0;
+  for (int y = -1; y <= 1; y++) {
+    for (int x = -1; x <= 1; x++) {
+      vec2 cell = vec2(x, y);
+      vec2 point = cell + hash22(i + cell);
+      closest = min(closest, length(point - f));
+    }
+  }
+  return closest;
+}
@@ -0,0 +1,19 @@
+// sh4der-jockey shader library, version 1
+//
+// Maps linear HDR colors to 0 to 1, the result is still linear.
+#pragma once
+
+vec3 tonemap_reinhard(vec3 c) {
+  return c / (1.0 + c);
+}
+
+// colors at `white` and above become 1
+vec3 tonemap_reinhard(vec3 c, float white) {
+  return c * (1.0 + c / (white * white)) / (1.0 + c);
+}
+
+// fit of the ACES filmic curve by Krzysztof Narkowicz
+vec3 tonemap_aces(vec3 c) {
+  c *= 0.6;
+  return clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), 0.0, 1.0);
+}
@@ -214,6 +214,8 @@ pub struct TextureBuilder {
     pub wrap_mode: GLenum,
     pub channels: u8,
     pub float: bool,
+    /// Whether 8 bit colors are sRGB encoded and decoded to linear when sampled.
+    pub srgb: bool,
     pub mipmap: bool,
 }
 
@@ -226,6 +228,7 @@ impl TextureBuilder {
             wrap_mode: gl::CLAMP_TO_EDGE,
             channels: 4,
             float: false,
+            srgb: false,
             mipmap: false,
         }
     }
@@ -334,6 +337,7 @@ impl TextureBuilder {
             wrap_mode,
             channels: 4,
             float,
+            srgb: false,
             mipmap,
         })
     }
@@ -353,6 +357,11 @@ impl TextureBuilder {
         self
     }
 
+    pub fn set_srgb(&mut self, is_srgb: bool) -> &mut Self {
+        self.srgb = is_srgb;
+        self
+    }
+
     pub fn build_framebuffer(&self, screen_size: (u32, u32)) -> Rc<FrameBuffer> {
         let [width, height] = match self.resolution.as_slice() {
             &[w, h] => [w, h],
@@ -394,6 +403,7 @@ impl TextureBuilder {
             (1, false) => TextureFormat::R8,
             (2, false) => TextureFormat::RG8,
             (3, false) => TextureFormat::RGB8,
+            (4, false) if self.srgb => TextureFormat::SRGBA8,
             (4, false) => TextureFormat::RGBA8,
             (1, true) => TextureFormat::R32F,
             (2, true) => TextureFormat::RG32F,
@@ -496,9 +506,11 @@ pub enum TextureFormat {
     RG8 = gl::RG8 as _,
     RGB8 = gl::RGB8 as _,
     RGBA8 = gl::RGBA8 as _,
+    SRGBA8 = gl::SRGB8_ALPHA8 as _,
     R32F = gl::R32F as _,
     RG32F = gl::RG32F as _,
     RGB32F = gl::RGB32F as _,
+    RGBA16F = gl::RGBA16F as _,
     RGBA32F = gl::RGBA32F as _,
 }
 
@@ -569,17 +581,22 @@ macro_rules! impl_texture {
                     TextureFormat::R8 | TextureFormat::R32F => gl::RED,
                     TextureFormat::RG8 | TextureFormat::RG32F => gl::RG,
                     TextureFormat::RGB8 | TextureFormat::RGB32F => gl::RGB,
-                    TextureFormat::RGBA32F | TextureFormat::RGBA8 => gl::RGBA,
+                    TextureFormat::RGBA16F
+                    | TextureFormat::RGBA32F
+                    | TextureFormat::RGBA8
+                    | TextureFormat::SRGBA8 => gl::RGBA,
                 };
 
                 let type_ = match format {
                     TextureFormat::R8
                     | TextureFormat::RG8
                     | TextureFormat::RGB8
-                    | TextureFormat::RGBA8 => gl::UNSIGNED_BYTE,
+                    | TextureFormat::RGBA8
+                    | TextureFormat::SRGBA8 => gl::UNSIGNED_BYTE,
                     TextureFormat::R32F
                     | TextureFormat::RG32F
                     | TextureFormat::RGB32F
+                    | TextureFormat::RGBA16F
                     | TextureFormat::RGBA32F => gl::FLOAT,
                 };
 
@@ -719,10 +736,14 @@ pub fn make_texture(resolution: &[u32]) -> Rc<dyn Texture> {
     }
 }
 
-pub fn make_noise() -> Texture3D {
+/// Creates a 3D texture of random noise, which is the same for the same seed.
+pub fn make_noise(seed: u32) -> Texture3D {
+    use rand::{Rng, SeedableRng};
+
     const WIDTH: usize = 32;
     const SIZE: usize = 4 * WIDTH * WIDTH * WIDTH;
-    let data: Vec<u8> = (0..SIZE).map(|_| rand::random()).collect();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed as _);
+    let data: Vec<u8> = (0..SIZE).map(|_| rng.gen()).collect();
     let tex = Texture3D::with_params(
         [WIDTH as _; 3],
         gl::LINEAR,
@@ -0,0 +1,116 @@
+use gl::types::*;
+
+use crate::*;
+
+/// Number of frames a timer query may lag behind before it is reused.
+const GPU_TIMER_FRAMES: usize = 4;
+
+/// Measures how long the GPU spends on a range of commands.
+///
+/// Every measurement writes two timestamp queries. Reading a query result
+/// right away would stall until the GPU caught up with the CPU, so the
+/// queries are kept in a small ring and only read once they are available,
+/// a few frames later.
+#[derive(Debug)]
+pub struct GpuTimer {
+    queries: [[GLuint; 2]; GPU_TIMER_FRAMES],
+    pending: [bool; GPU_TIMER_FRAMES],
+    index: usize,
+}
+
+impl GpuTimer {
+    /// Creates a new timer, if the driver supports timestamp queries.
+    pub fn new() -> Option<Self> {
+        if !Self::is_supported() {
+            return None;
+        }
+
+        let mut queries = [[0; 2]; GPU_TIMER_FRAMES];
+        unsafe {
+            gl::GenQueries((2 * GPU_TIMER_FRAMES) as _, queries.as_mut_ptr() as _);
+            gl_debug_check!();
+        }
+
+        Some(Self {
+            queries,
+            pending: [false; GPU_TIMER_FRAMES],
+            index: 0,
+        })
+    }
+
+    /// Checks whether the current context supports timestamp queries.
+    pub fn is_supported() -> bool {
+        if !gl::QueryCounter::is_loaded() || !gl::GetQueryObjectui64v::is_loaded() {
+            return false;
+        }
+
+        let mut bits = 0;
+        unsafe {
+            gl::GetQueryiv(gl::TIMESTAMP, gl::QUERY_COUNTER_BITS, &mut bits);
+            gl_ignore!();
+        }
+
+        bits > 0
+    }
+
+    /// Starts a new measurement.
+    ///
+    /// Returns the GPU time in milliseconds of the oldest measurement
+    /// which finished since the last call, if there is one.
+    pub fn begin(&mut self) -> Option<f32> {
+        let result = self.poll();
+
+        unsafe {
+            gl::QueryCounter(self.queries[self.index][0], gl::TIMESTAMP);
+            gl_debug_check!();
+        }
+
+        result
+    }
+
+    /// Ends the measurement started by the last call to `begin`.
+    pub fn end(&mut self) {
+        unsafe {
+            gl::QueryCounter(self.queries[self.index][1], gl::TIMESTAMP);
+            gl_debug_check!();
+        }
+
+        self.pending[self.index] = true;
+        self.index = (self.index + 1) % GPU_TIMER_FRAMES;
+    }
+
+    /// Reads the result of the measurement which is about to be reused.
+    ///
+    /// If it is not available yet, it is dropped rather than waited for.
+    fn poll(&mut self) -> Option<f32> {
+        if !std::mem::take(&mut self.pending[self.index]) {
+            return None;
+        }
+
+        let [start, end] = self.queries[self.index];
+        unsafe {
+            let mut available = 0;
+            gl::GetQueryObjectiv(end, gl::QUERY_RESULT_AVAILABLE, &mut available);
+            gl_debug_check!();
+
+            if available == 0 {
+                return None;
+            }
+
+            let (mut start_ns, mut end_ns) = (0, 0);
+            gl::GetQueryObjectui64v(start, gl::QUERY_RESULT, &mut start_ns);
+            gl::GetQueryObjectui64v(end, gl::QUERY_RESULT, &mut end_ns);
+            gl_debug_check!();
+
+            Some(end_ns.saturating_sub(start_ns) as f32 / 1_000_000.0)
+        }
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteQueries((2 * GPU_TIMER_FRAMES) as _, self.queries.as_ptr() as _);
+        }
+    }
+}